@@ -6,11 +6,20 @@
 
 use bumpalo::collections::Vec as BumpVec;
 use bumpalo::Bump;
-use std::cell::RefCell;
+use std::cell::{Cell, RefCell};
 use std::fmt;
+use std::time::Instant;
 
 use super::interner::StringInterner;
+use crate::logic::{
+    CancellationToken, Clock, CustomOperatorRegistry, DecimalArithmetic, EvaluateOptions,
+    EvaluationLimits, FailureChain, LogicError, RegisteredOperator, Result, SystemClock, Token,
+    TraceNode, TraceRecorder,
+};
+use chrono::{DateTime, FixedOffset};
+use std::sync::Arc;
 use crate::value::DataValue;
+use crate::logic::Pattern;
 
 /// Maximum number of path components in the fixed-size array
 const PATH_CHAIN_CAPACITY: usize = 16;
@@ -124,6 +133,59 @@ pub struct DataArena {
     
     /// Current path chain - represents the path from root to current position
     path_chain: RefCell<PathChainVec>,
+
+    /// Custom operators available to the evaluation in progress
+    custom_operators: RefCell<Option<Arc<CustomOperatorRegistry>>>,
+
+    /// Resource limits for the evaluation in progress
+    limits: Cell<EvaluationLimits>,
+
+    /// Cancellation token for the evaluation in progress
+    cancellation: RefCell<Option<CancellationToken>>,
+
+    /// Deadline for the evaluation in progress
+    deadline: Cell<Option<Instant>>,
+
+    /// Trace recorder for the evaluation in progress
+    tracer: RefCell<Option<TraceRecorder>>,
+
+    /// Whether a trace is being recorded
+    tracing: Cell<bool>,
+
+    /// Whether any limit, cancellation token, deadline or trace is set
+    checks_enabled: Cell<bool>,
+
+    /// Current evaluation depth, tracked while limits are enabled
+    depth: Cell<usize>,
+
+    /// Evaluation steps taken, tracked while limits are enabled
+    steps: Cell<usize>,
+
+    /// Memory usage when the limits were set
+    memory_baseline: Cell<usize>,
+
+    /// Tokens that locate the last evaluation error
+    failure: Cell<FailureChain>,
+
+    /// Results of shared expressions, by slot, with the address of the
+    /// context they were evaluated in
+    memo: RefCell<Vec<Option<(usize, &'static DataValue<'static>)>>>,
+
+    /// Clock for the evaluation in progress, if not the system clock
+    clock: RefCell<Option<Arc<dyn Clock>>>,
+
+    /// The time `now` returns, once it has been read in this evaluation
+    now: Cell<Option<DateTime<FixedOffset>>>,
+
+    /// Decimal arithmetic settings, if numbers with a fractional part are
+    /// decimals
+    decimal_arithmetic: Cell<Option<DecimalArithmetic>>,
+
+    /// Compiled regular expressions, which are boxed rather than bump
+    /// allocated so they are dropped with the arena, and keep their
+    /// address as more are added
+    #[allow(clippy::vec_box)]
+    patterns: RefCell<Vec<Box<Pattern>>>,
 }
 
 impl Default for DataArena {
@@ -183,6 +245,22 @@ impl DataArena {
             current_context: RefCell::new(None),
             root_context: RefCell::new(None),
             path_chain: RefCell::new(PathChainVec::new()),
+            custom_operators: RefCell::new(None),
+            limits: Cell::new(EvaluationLimits::default()),
+            cancellation: RefCell::new(None),
+            deadline: Cell::new(None),
+            tracer: RefCell::new(None),
+            tracing: Cell::new(false),
+            checks_enabled: Cell::new(false),
+            depth: Cell::new(0),
+            steps: Cell::new(0),
+            memory_baseline: Cell::new(0),
+            failure: Cell::new(FailureChain::default()),
+            memo: RefCell::new(Vec::new()),
+            clock: RefCell::new(None),
+            now: Cell::new(None),
+            decimal_arithmetic: Cell::new(None),
+            patterns: RefCell::new(Vec::new()),
         }
     }
 
@@ -222,19 +300,16 @@ impl DataArena {
     }
 
     /// Converts a Vec into a slice allocated in the arena.
+    ///
+    /// The items are moved into the arena, so the memory is accounted for
+    /// by `memory_usage` and released together with the arena.
     #[inline]
     pub fn vec_into_slice<T>(&self, vec: Vec<T>) -> &[T] {
         if vec.is_empty() {
             return &[];
         }
 
-        let ptr = vec.as_ptr();
-        let len = vec.len();
-
-        // Forget the vector to prevent double-free (memory will be reclaimed when arena is dropped)
-        std::mem::forget(vec);
-
-        unsafe { std::slice::from_raw_parts(ptr, len) }
+        self.bump.alloc_slice_fill_iter(vec)
     }
 
     /// Allocates a value in the arena.
@@ -275,6 +350,39 @@ impl DataArena {
         self.current_context.replace(None);
         self.root_context.replace(None);
         self.path_chain.replace(PathChainVec::new());
+        self.custom_operators.replace(None);
+        self.clear_limits();
+        self.clear_interrupts();
+        self.finish_trace();
+        self.failure.take();
+        self.clear_memo();
+        self.clear_clock();
+        self.patterns.get_mut().clear();
+    }
+
+    /// Frees all allocations, keeping the settings and the per-evaluation
+    /// state of the arena.
+    ///
+    /// Unlike `reset`, this only rewinds the state an evaluation leaves
+    /// behind, since the evaluation clears its contexts, limits and
+    /// interrupts itself when it ends. The interner and the path chain keep
+    /// their capacity for the next evaluation.
+    pub(crate) fn rewind(&mut self) {
+        self.bump.reset();
+        self.interner.get_mut().clear();
+        self.patterns.get_mut().clear();
+    }
+
+    /// Returns true if `value` was allocated in this arena.
+    pub(crate) fn contains<T>(&self, value: &T) -> bool {
+        let address = value as *const T as usize;
+        // SAFETY: nothing is allocated while the iterator is alive, and only
+        // the bounds of the chunks are read
+        let mut chunks = unsafe { self.bump.iter_allocated_chunks_raw() };
+        chunks.any(|(start, len)| {
+            let start = start as usize;
+            (start..start + len).contains(&address)
+        })
     }
 
     /// Returns the current memory usage of the arena in bytes.
@@ -284,8 +392,27 @@ impl DataArena {
 
     /// Creates a new temporary arena for short-lived allocations.
     ///
+    /// The temporary arena uses the same decimal arithmetic settings.
     pub fn create_temp_arena(&self) -> DataArena {
-        DataArena::with_chunk_size(self.chunk_size)
+        let arena = DataArena::with_chunk_size(self.chunk_size);
+        arena.set_decimal_arithmetic(self.decimal_arithmetic());
+        arena
+    }
+
+    /// Sets whether numbers with a fractional part are exact decimals.
+    ///
+    /// The settings apply to values converted from JSON in this arena and to
+    /// arithmetic evaluated in it, until they are changed again. Resetting
+    /// the arena keeps them.
+    pub fn set_decimal_arithmetic(&self, decimal_arithmetic: Option<DecimalArithmetic>) {
+        self.decimal_arithmetic.set(decimal_arithmetic);
+    }
+
+    /// Returns the decimal arithmetic settings, if numbers with a fractional
+    /// part are exact decimals.
+    #[inline]
+    pub fn decimal_arithmetic(&self) -> Option<DecimalArithmetic> {
+        self.decimal_arithmetic.get()
     }
 
     /// Allocates a slice in the arena and fills it with values generated by a function.
@@ -409,14 +536,24 @@ impl DataArena {
 
     /// Sets the current context for the arena.
     pub fn set_current_context<'a>(&self, context: &'a DataValue<'a>, key: &'a DataValue<'a>) {
+        // SAFETY: evaluations clear the contexts with `clear_contexts` before
+        // the data they borrow goes away
         self.current_context.replace(Some(unsafe {
             std::mem::transmute::<&'a DataValue<'a>, &'static DataValue<'static>>(context)
         }));
         self.push_path_key(key);
     }
 
+    /// Replaces the current context without touching the path chain.
+    pub(crate) fn replace_current_context<'a>(&self, context: Option<&'a DataValue<'a>>) {
+        // SAFETY: see `set_current_context`
+        self.current_context.replace(context.map(|context| unsafe {
+            std::mem::transmute::<&'a DataValue<'a>, &'static DataValue<'static>>(context)
+        }));
+    }
+
     /// Returns the current context for the arena.
-    pub fn current_context(&self, scope_jump: usize) -> Option<&DataValue> {
+    pub fn current_context(&self, scope_jump: usize) -> Option<&DataValue<'_>> {
         // Fast path for the common case (no scope jump)
         if scope_jump == 0 {
             return *self.current_context.borrow();
@@ -427,22 +564,40 @@ impl DataArena {
     }
 
     /// Returns the root context for the arena.
-    pub fn root_context(&self) -> Option<&DataValue> {
+    pub fn root_context(&self) -> Option<&DataValue<'_>> {
         // Reset the path chain when getting root context
         self.path_chain.borrow_mut().clear();
         *self.root_context.borrow()
     }
 
     /// Sets the root context for the arena.
+    ///
+    /// A new root context starts a new evaluation, so the memoized results of
+    /// shared expressions and the time read by `now` are forgotten.
     pub fn set_root_context<'a>(&self, context: &'a DataValue<'a>) {
+        // SAFETY: see `set_current_context`
         self.root_context.replace(Some(unsafe {
             std::mem::transmute::<&'a DataValue<'a>, &'static DataValue<'static>>(context)
         }));
+        self.clear_memo();
+        self.now.set(None);
+    }
+
+    /// Clears the root and current context, the path chain and the memoized
+    /// results of shared expressions.
+    ///
+    /// These refer to the data of an evaluation, so they are cleared when the
+    /// evaluation ends.
+    pub(crate) fn clear_contexts(&self) {
+        self.current_context.replace(None);
+        self.root_context.replace(None);
+        self.path_chain.borrow_mut().clear();
+        self.clear_memo();
     }
 
     #[cold]
     #[inline(never)]
-    fn root_context_with_jump(&self, scope_jump: usize) -> Option<&DataValue> {
+    fn root_context_with_jump(&self, scope_jump: usize) -> Option<&DataValue<'_>> {
         if scope_jump == 0 {
             return *self.current_context.borrow();
         }
@@ -550,9 +705,9 @@ impl DataArena {
     }
     
     /// Returns the current path chain as a slice.
-    pub fn path_chain_as_slice(&self) -> Vec<&DataValue> {
+    pub fn path_chain_as_slice(&self) -> Vec<&DataValue<'_>> {
         let chain = self.path_chain.borrow();
-        chain.as_slice().iter().copied().collect()
+        chain.as_slice().to_vec()
     }
     
     /// Efficiently access the path chain without allocating a new vector.
@@ -566,10 +721,301 @@ impl DataArena {
     }
     
     /// Returns the last path component.
-    pub fn last_path_component(&self) -> Option<&DataValue> {
+    pub fn last_path_component(&self) -> Option<&DataValue<'_>> {
         self.path_chain.borrow().last()
     }
     
+    /// Runs `f` with the given custom operators available to evaluation.
+    ///
+    /// The arena holds a reference to the registry while `f` runs. The
+    /// operators that were available before are restored when `f` returns
+    /// or panics.
+    pub(crate) fn with_custom_operators<R>(
+        &self,
+        registry: &Arc<CustomOperatorRegistry>,
+        f: impl FnOnce() -> R,
+    ) -> R {
+        struct Restore<'s> {
+            arena: &'s DataArena,
+            previous: Option<Arc<CustomOperatorRegistry>>,
+        }
+
+        impl Drop for Restore<'_> {
+            fn drop(&mut self) {
+                self.arena.custom_operators.replace(self.previous.take());
+            }
+        }
+
+        // Without custom operators there is nothing to share with the arena
+        if registry.is_empty() && self.custom_operators.borrow().is_none() {
+            return f();
+        }
+
+        let _restore = Restore {
+            arena: self,
+            previous: self.custom_operators.replace(Some(Arc::clone(registry))),
+        };
+        f()
+    }
+
+    /// Returns the custom operator registered under the given name, if any
+    /// operators are available to evaluation.
+    pub(crate) fn custom_operator(&self, name: &str) -> Option<Arc<RegisteredOperator>> {
+        self.custom_operators.borrow().as_ref()?.get(name)
+    }
+
+    /// Sets the resource limits for the following evaluation steps.
+    ///
+    /// This also resets the depth and step counters, and measures memory
+    /// growth from the current usage.
+    pub(crate) fn set_limits(&self, limits: EvaluationLimits) {
+        self.limits.set(limits);
+        self.update_checks_enabled();
+        self.depth.set(0);
+        self.steps.set(0);
+        self.memory_baseline.set(self.memory_usage());
+    }
+
+    /// Removes the resource limits set by `set_limits`.
+    pub(crate) fn clear_limits(&self) {
+        self.set_limits(EvaluationLimits::default());
+    }
+
+    /// Sets the cancellation token and deadline for the following evaluation steps.
+    pub(crate) fn set_interrupts(&self, options: &EvaluateOptions) {
+        self.cancellation.replace(options.cancellation.clone());
+        self.deadline.set(options.deadline);
+        self.update_checks_enabled();
+    }
+
+    /// Removes the cancellation token and deadline set by `set_interrupts`.
+    pub(crate) fn clear_interrupts(&self) {
+        self.cancellation.replace(None);
+        self.deadline.set(None);
+        self.update_checks_enabled();
+    }
+
+    /// Sets the clock read by `now` during the following evaluation.
+    pub(crate) fn set_clock(&self, clock: Option<Arc<dyn Clock>>) {
+        self.clock.replace(clock);
+        self.now.set(None);
+    }
+
+    /// Restores the system clock.
+    pub(crate) fn clear_clock(&self) {
+        self.set_clock(None);
+    }
+
+    /// Returns the current time, reading the clock once per evaluation.
+    pub(crate) fn now(&self) -> DateTime<FixedOffset> {
+        if let Some(now) = self.now.get() {
+            return now;
+        }
+        let now = match &*self.clock.borrow() {
+            Some(clock) => clock.now(),
+            None => SystemClock.now(),
+        };
+        self.now.set(Some(now));
+        now
+    }
+
+    /// Starts recording a trace of the following evaluation steps.
+    pub(crate) fn start_trace(&self) {
+        self.tracer.replace(Some(TraceRecorder::default()));
+        self.tracing.set(true);
+        self.update_checks_enabled();
+    }
+
+    /// Stops recording the trace started by `start_trace` and returns its root node.
+    pub(crate) fn finish_trace(&self) -> Option<TraceNode> {
+        let tracer = self.tracer.replace(None);
+        self.tracing.set(false);
+        self.update_checks_enabled();
+        tracer.and_then(TraceRecorder::finish)
+    }
+
+    /// Returns true if a trace is being recorded.
+    #[inline]
+    pub(crate) fn tracing(&self) -> bool {
+        self.tracing.get()
+    }
+
+    /// Records the start of the evaluation of a token in the trace.
+    pub(crate) fn trace_enter(&self, token: &Token) {
+        if let Some(tracer) = self.tracer.borrow_mut().as_mut() {
+            tracer.enter(token);
+        }
+    }
+
+    /// Records the end of the evaluation of a token in the trace.
+    pub(crate) fn trace_exit(&self, token: &Token, result: &Result<&DataValue>) {
+        if let Some(tracer) = self.tracer.borrow_mut().as_mut() {
+            tracer.exit(token, result);
+        }
+    }
+
+    fn update_checks_enabled(&self) {
+        let enabled = !self.limits.get().is_unlimited()
+            || self.cancellation.borrow().is_some()
+            || self.deadline.get().is_some()
+            || self.tracing.get();
+        self.checks_enabled.set(enabled);
+    }
+
+    /// Returns true if any limit, cancellation token, deadline or trace is set.
+    #[inline]
+    pub(crate) fn checks_enabled(&self) -> bool {
+        self.checks_enabled.get()
+    }
+
+    /// Returns an error if the evaluation was cancelled or its deadline has passed.
+    ///
+    /// Iterators call this once per item, so that long loops stop promptly
+    /// even when their bodies never reach the evaluator.
+    #[inline]
+    pub(crate) fn check_interrupts(&self) -> Result<()> {
+        if self.checks_enabled.get() {
+            self.check_interrupts_slow()
+        } else {
+            Ok(())
+        }
+    }
+
+    #[cold]
+    fn check_interrupts_slow(&self) -> Result<()> {
+        if let Some(token) = self.cancellation.borrow().as_ref()
+            && token.is_cancelled()
+        {
+            return Err(LogicError::Cancelled);
+        }
+        if let Some(deadline) = self.deadline.get()
+            && Instant::now() >= deadline
+        {
+            return Err(LogicError::DeadlineExceeded);
+        }
+        Ok(())
+    }
+
+    /// Records that the evaluation of a token failed.
+    ///
+    /// Only the first token to fail is kept, as it raised the error.
+    #[cold]
+    pub(crate) fn record_failure(&self, token: &Token) {
+        let failure = self.failure.take();
+        self.failure.set(failure.raised_by(token));
+    }
+
+    /// Records that an error propagated through an occurrence of a shared
+    /// expression.
+    #[cold]
+    pub(crate) fn record_shared_failure(&self, occurrence: &Token, expr: &Token) {
+        let failure = self.failure.take();
+        self.failure.set(failure.shared(occurrence, expr));
+    }
+
+    /// Returns the tokens that locate the last evaluation error, and forgets
+    /// them.
+    pub(crate) fn take_failure(&self) -> FailureChain {
+        self.failure.take()
+    }
+
+    /// Returns the memoized result of the shared expression in `slot`, if it
+    /// was evaluated in `context`.
+    pub(crate) fn memoized(&self, slot: usize, context: Option<&DataValue>) -> Option<&DataValue<'_>> {
+        let context = context_address(context);
+        match self.memo.borrow().get(slot) {
+            Some(Some((address, value))) if *address == context => Some(*value),
+            _ => None,
+        }
+    }
+
+    /// Memoizes the result of the shared expression in `slot` for `context`.
+    pub(crate) fn memoize<'a>(
+        &self,
+        slot: usize,
+        context: Option<&DataValue>,
+        value: &'a DataValue<'a>,
+    ) {
+        let context = context_address(context);
+        let mut memo = self.memo.borrow_mut();
+        if memo.len() <= slot {
+            memo.resize(slot + 1, None);
+        }
+        // SAFETY: the memo is cleared with the contexts when the evaluation
+        // ends, and the value outlives the evaluation
+        memo[slot] = Some((context, unsafe {
+            std::mem::transmute::<&'a DataValue<'a>, &'static DataValue<'static>>(value)
+        }));
+    }
+
+    /// Forgets the memoized results of shared expressions.
+    pub(crate) fn clear_memo(&self) {
+        self.memo.borrow_mut().clear();
+    }
+
+    /// Stores a compiled pattern until the arena is reset or dropped.
+    pub(crate) fn alloc_pattern(&self, pattern: Pattern) -> &Pattern {
+        let pattern = Box::new(pattern);
+        let pointer: *const Pattern = &*pattern;
+        self.patterns.borrow_mut().push(pattern);
+        // SAFETY: the box keeps the pattern at the same address when the
+        // vector grows, and it is only dropped by `reset`, which takes
+        // `&mut self`, or with the arena
+        unsafe { &*pointer }
+    }
+
+
+    /// Records the start of an evaluation step, checking it against the limits,
+    /// the cancellation token and the deadline.
+    ///
+    /// Every successful call must be paired with a call to `exit_step`.
+    pub(crate) fn enter_step(&self) -> Result<()> {
+        self.check_interrupts_slow()?;
+
+        let limits = self.limits.get();
+
+        let depth = self.depth.get() + 1;
+        if let Some(max) = limits.max_depth
+            && depth > max
+        {
+            return Err(LogicError::LimitExceededError {
+                limit: "max_depth".to_string(),
+                max,
+                location: None,
+            });
+        }
+
+        let steps = self.steps.get() + 1;
+        if let Some(max) = limits.max_steps
+            && steps > max
+        {
+            return Err(LogicError::LimitExceededError {
+                limit: "max_steps".to_string(),
+                max,
+                location: None,
+            });
+        }
+
+        if let Some(max) = limits.max_memory_bytes
+            && self.memory_usage().saturating_sub(self.memory_baseline.get()) > max
+        {
+            return Err(LogicError::LimitExceededError {
+                limit: "max_memory_bytes".to_string(),
+                max,
+                location: None,
+            });
+        }
+
+        self.depth.set(depth);
+        self.steps.set(steps);
+        Ok(())
+    }
+
+    /// Records the end of an evaluation step started with `enter_step`.
+    pub(crate) fn exit_step(&self) {
+        self.depth.set(self.depth.get().saturating_sub(1));
+    }
+
     /// Batch appends multiple path components in one operation.
     pub fn push_path_components<'a>(&self, keys: &[&'a DataValue<'a>]) {
         if keys.is_empty() {
@@ -586,6 +1032,11 @@ impl DataArena {
     }
 }
 
+/// Identifies a context by its address, which is stable during an evaluation.
+fn context_address(context: Option<&DataValue>) -> usize {
+    context.map_or(0, |context| context as *const DataValue as usize)
+}
+
 #[cfg(test)]
 mod tests {
     use super::*;
@@ -654,4 +1105,36 @@ mod tests {
         let slice = arena.bump_vec_into_slice(vec);
         assert_eq!(slice.len(), 2);
     }
+
+    #[test]
+    fn test_custom_operators_are_scoped() {
+        struct Null;
+
+        impl crate::CustomOperator for Null {
+            fn evaluate<'a>(
+                &self,
+                _args: &'a [DataValue<'a>],
+                arena: &'a DataArena,
+            ) -> crate::Result<&'a DataValue<'a>> {
+                Ok(arena.null_value())
+            }
+        }
+
+        let arena = DataArena::new();
+        let mut registry = CustomOperatorRegistry::new();
+        registry.register("null", Box::new(Null));
+        let registry = Arc::new(registry);
+
+        arena.with_custom_operators(&registry, || {
+            assert_eq!(Arc::strong_count(&registry), 2);
+        });
+        assert_eq!(Arc::strong_count(&registry), 1);
+
+        // The registry is taken out of the arena when the evaluation panics too
+        let result = std::panic::catch_unwind(std::panic::AssertUnwindSafe(|| {
+            arena.with_custom_operators(&registry, || panic!("evaluation failed"))
+        }));
+        assert!(result.is_err());
+        assert_eq!(Arc::strong_count(&registry), 1);
+    }
 }
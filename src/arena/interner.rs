@@ -141,7 +141,7 @@ impl StringInterner {
     }
 
     /// Clears the interner, removing all interned strings.
-    fn _clear(&mut self) {
+    pub(crate) fn clear(&mut self) {
         self.strings.clear();
     }
 }
@@ -184,7 +184,7 @@ mod tests {
 
         assert_eq!(interner._len(), 2);
 
-        interner._clear();
+        interner.clear();
 
         assert_eq!(interner._len(), 0);
         assert!(interner._is_empty());
@@ -51,6 +51,41 @@ impl<'a> ArithmeticBuilder<'a> {
     pub fn max_op(&self) -> ArithmeticOperationBuilder<'a> {
         ArithmeticOperationBuilder::new(self.arena, ArithmeticOp::Max)
     }
+
+    /// Creates a rounding operation.
+    pub fn round_op(&self) -> ArithmeticOperationBuilder<'a> {
+        ArithmeticOperationBuilder::new(self.arena, ArithmeticOp::Round)
+    }
+
+    /// Creates an absolute value operation.
+    pub fn abs_op(&self) -> ArithmeticOperationBuilder<'a> {
+        ArithmeticOperationBuilder::new(self.arena, ArithmeticOp::Abs)
+    }
+
+    /// Creates a floor operation.
+    pub fn floor_op(&self) -> ArithmeticOperationBuilder<'a> {
+        ArithmeticOperationBuilder::new(self.arena, ArithmeticOp::Floor)
+    }
+
+    /// Creates a ceiling operation.
+    pub fn ceil_op(&self) -> ArithmeticOperationBuilder<'a> {
+        ArithmeticOperationBuilder::new(self.arena, ArithmeticOp::Ceil)
+    }
+
+    /// Creates a power operation.
+    pub fn pow_op(&self) -> ArithmeticOperationBuilder<'a> {
+        ArithmeticOperationBuilder::new(self.arena, ArithmeticOp::Pow)
+    }
+
+    /// Creates a square root operation.
+    pub fn sqrt_op(&self) -> ArithmeticOperationBuilder<'a> {
+        ArithmeticOperationBuilder::new(self.arena, ArithmeticOp::Sqrt)
+    }
+
+    /// Creates a logarithm operation.
+    pub fn log_op(&self) -> ArithmeticOperationBuilder<'a> {
+        ArithmeticOperationBuilder::new(self.arena, ArithmeticOp::Log)
+    }
 }
 
 /// Builder for an arithmetic operation with its operands.
@@ -153,10 +188,15 @@ impl<'a> ArithmeticOperationBuilder<'a> {
                         self.arena,
                     );
                 }
-                _ => {
-                    // For other operations, just return the operand for unary case
+                ArithmeticOp::Add
+                | ArithmeticOp::Multiply
+                | ArithmeticOp::Min
+                | ArithmeticOp::Max => {
+                    // These just return the operand for unary case
                     return self.operands[0].clone();
                 }
+                // Functions of one number, like `1 / x` for division
+                _ => {}
             }
         }
 
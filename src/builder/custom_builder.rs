@@ -0,0 +1,82 @@
+use crate::arena::DataArena;
+use crate::logic::Logic;
+
+/// Builder for a custom operator application with its arguments.
+///
+/// The operator itself must be registered with `DataLogic::register_operator`
+/// before a rule built this way is evaluated.
+pub struct CustomOperatorBuilder<'a> {
+    /// The arena in which all allocations will be made.
+    arena: &'a DataArena,
+    /// The name of the custom operator.
+    name: String,
+    /// The arguments collected so far.
+    arguments: Vec<Logic<'a>>,
+}
+
+impl<'a> CustomOperatorBuilder<'a> {
+    /// Creates a new custom operator builder.
+    pub fn new(arena: &'a DataArena, name: &str) -> Self {
+        Self {
+            arena,
+            name: name.to_string(),
+            arguments: Vec::new(),
+        }
+    }
+
+    /// Adds an argument to the custom operator.
+    pub fn operand(mut self, operand: Logic<'a>) -> Self {
+        self.arguments.push(operand);
+        self
+    }
+
+    /// Adds a variable as an argument to the custom operator.
+    pub fn var(mut self, path: &str) -> Self {
+        let var = Logic::variable(path, None, self.arena);
+        self.arguments.push(var);
+        self
+    }
+
+    /// Adds a literal value as an argument to the custom operator.
+    pub fn value<T: Into<crate::value::DataValue<'a>>>(mut self, value: T) -> Self {
+        let val = Logic::literal(value.into(), self.arena);
+        self.arguments.push(val);
+        self
+    }
+
+    /// Adds an integer value as an argument to the custom operator.
+    pub fn int(mut self, value: i64) -> Self {
+        let val = Logic::literal(crate::value::DataValue::integer(value), self.arena);
+        self.arguments.push(val);
+        self
+    }
+
+    /// Adds a float value as an argument to the custom operator.
+    pub fn float(mut self, value: f64) -> Self {
+        let val = Logic::literal(crate::value::DataValue::float(value), self.arena);
+        self.arguments.push(val);
+        self
+    }
+
+    /// Adds a string value as an argument to the custom operator.
+    pub fn string(mut self, value: &str) -> Self {
+        let val = Logic::literal(
+            crate::value::DataValue::string(self.arena, value),
+            self.arena,
+        );
+        self.arguments.push(val);
+        self
+    }
+
+    /// Adds a boolean value as an argument to the custom operator.
+    pub fn bool(mut self, value: bool) -> Self {
+        let val = Logic::literal(crate::value::DataValue::bool(value), self.arena);
+        self.arguments.push(val);
+        self
+    }
+
+    /// Builds the custom operator with the collected arguments.
+    pub fn build(self) -> Logic<'a> {
+        Logic::custom_operator(&self.name, self.arguments, self.arena)
+    }
+}
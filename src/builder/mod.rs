@@ -7,6 +7,7 @@ mod arithmetic_builder;
 mod array_builder;
 mod comparison_builder;
 mod control_builder;
+mod custom_builder;
 pub mod factory;
 mod rule_builder;
 mod string_builder;
@@ -18,6 +19,7 @@ pub use arithmetic_builder::ArithmeticBuilder;
 pub use array_builder::ArrayBuilder;
 pub use comparison_builder::ComparisonBuilder;
 pub use control_builder::ControlBuilder;
+pub use custom_builder::CustomOperatorBuilder;
 pub use factory::RuleFactory;
 pub use rule_builder::RuleBuilder;
 pub use string_builder::StringBuilder;
@@ -6,6 +6,7 @@ use super::arithmetic_builder::ArithmeticBuilder;
 use super::array_builder::ArrayBuilder;
 use super::comparison_builder::ComparisonBuilder;
 use super::control_builder::ControlBuilder;
+use super::custom_builder::CustomOperatorBuilder;
 use super::string_builder::StringBuilder;
 use super::variable_builder::VariableBuilder;
 
@@ -54,6 +55,11 @@ impl<'a> RuleBuilder<'a> {
         StringBuilder::new(self.arena)
     }
 
+    /// Creates a builder for a custom operator registered under `name`.
+    pub fn custom_op(&self, name: &str) -> CustomOperatorBuilder<'a> {
+        CustomOperatorBuilder::new(self.arena, name)
+    }
+
     /// Creates a variable reference.
     pub fn var(&self, path: &str) -> VariableBuilder<'a> {
         VariableBuilder::new(self.arena, path)
@@ -26,6 +26,63 @@ impl<'a> StringBuilder<'a> {
     pub fn substr_op(&self) -> SubstringBuilder<'a> {
         SubstringBuilder::new(self.arena)
     }
+
+    /// Creates an upper case conversion.
+    pub fn upper_op(&self) -> StringOperationBuilder<'a> {
+        StringOperationBuilder::new(self.arena, StringOp::Upper)
+    }
+
+    /// Creates a lower case conversion.
+    pub fn lower_op(&self) -> StringOperationBuilder<'a> {
+        StringOperationBuilder::new(self.arena, StringOp::Lower)
+    }
+
+    /// Creates a whitespace trimming operation.
+    pub fn trim_op(&self) -> StringOperationBuilder<'a> {
+        StringOperationBuilder::new(self.arena, StringOp::Trim)
+    }
+
+    /// Creates a prefix test.
+    pub fn starts_with_op(&self) -> StringOperationBuilder<'a> {
+        StringOperationBuilder::new(self.arena, StringOp::StartsWith)
+    }
+
+    /// Creates a suffix test.
+    pub fn ends_with_op(&self) -> StringOperationBuilder<'a> {
+        StringOperationBuilder::new(self.arena, StringOp::EndsWith)
+    }
+
+    /// Creates a substring test.
+    pub fn contains_op(&self) -> StringOperationBuilder<'a> {
+        StringOperationBuilder::new(self.arena, StringOp::Contains)
+    }
+
+    /// Creates a split operation, with the string and the separator as operands.
+    pub fn split_op(&self) -> StringOperationBuilder<'a> {
+        StringOperationBuilder::new(self.arena, StringOp::Split)
+    }
+
+    /// Creates a join operation, with the array and the separator as operands.
+    pub fn join_op(&self) -> StringOperationBuilder<'a> {
+        StringOperationBuilder::new(self.arena, StringOp::Join)
+    }
+
+    /// Creates a replace operation, with the string, the substring and its
+    /// replacement as operands.
+    pub fn replace_op(&self) -> StringOperationBuilder<'a> {
+        StringOperationBuilder::new(self.arena, StringOp::Replace)
+    }
+
+    /// Creates a length operation.
+    pub fn length_op(&self) -> StringOperationBuilder<'a> {
+        StringOperationBuilder::new(self.arena, StringOp::Length)
+    }
+
+    /// Creates a padding operation, with the string, the length and an optional
+    /// fill string as operands.
+    pub fn pad_op(&self) -> StringOperationBuilder<'a> {
+        StringOperationBuilder::new(self.arena, StringOp::Pad)
+    }
 }
 
 /// Builder for string operations with multiple operands.
@@ -94,8 +151,8 @@ impl<'a> StringOperationBuilder<'a> {
 
     /// Builds the string operation with the collected operands.
     pub fn build(self) -> Logic<'a> {
-        if self.operands.is_empty() {
-            // Default for string operations is an empty string
+        if self.operands.is_empty() && self.operation == StringOp::Cat {
+            // Default for concatenation is an empty string
             return Logic::literal(crate::value::DataValue::string(self.arena, ""), self.arena);
         }
 
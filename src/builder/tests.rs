@@ -1,7 +1,9 @@
 #[cfg(test)]
+#[allow(clippy::module_inception)]
 mod tests {
     use crate::value::DataValue;
-    use crate::DataLogic;
+    use crate::{CustomOperator, DataArena, DataLogic, Logic, LogicError, Result, ToJson};
+    use serde_json::json;
 
     #[test]
     fn test_basic_builder() {
@@ -38,4 +40,100 @@ mod tests {
         assert_eq!(result1, &DataValue::String("Premium"));
         assert_eq!(result2, &DataValue::String("Basic"));
     }
+
+    struct Sum;
+
+    impl CustomOperator for Sum {
+        fn evaluate<'a>(
+            &self,
+            args: &'a [DataValue<'a>],
+            arena: &'a DataArena,
+        ) -> Result<&'a DataValue<'a>> {
+            let mut total = 0.0;
+            for (index, arg) in args.iter().enumerate() {
+                total += arg
+                    .coerce_to_number()
+                    .ok_or_else(|| LogicError::nan("sum", index, arg))?
+                    .as_f64();
+            }
+            Ok(arena.alloc(DataValue::float(total)))
+        }
+    }
+
+    #[test]
+    fn test_custom_operator_builder() {
+        let mut logic = DataLogic::new();
+        logic.register_operator("sum", Box::new(Sum));
+
+        let rule = logic
+            .builder()
+            .custom_op("sum")
+            .var("a")
+            .int(2)
+            .float(0.5)
+            .build();
+        assert!(rule.is_custom_operator());
+
+        let data = logic.parse_data(r#"{"a": 1.5}"#).unwrap();
+        let result = logic.evaluate(&rule, &data).unwrap();
+        assert_eq!(result, &DataValue::integer(4));
+    }
+
+    #[test]
+    fn test_built_rules_to_json() {
+        let dl = DataLogic::new();
+        let builder = dl.builder();
+        let factory = crate::builder::RuleFactory::new(dl.arena());
+
+        let rule = builder
+            .control()
+            .if_op()
+            .condition(
+                builder
+                    .compare()
+                    .greater_than_op()
+                    .var("age")
+                    .int(18)
+                    .build(),
+            )
+            .then(builder.string_value("adult"))
+            .else_branch(builder.var_with_default("fallback", builder.string_value("minor")))
+            .build();
+        assert_eq!(
+            rule.to_json(),
+            json!({"if": [{">": [{"var": "age"}, 18]}, "adult", {"var": ["fallback", "minor"]}]})
+        );
+
+        let rules: Vec<Logic> = vec![
+            rule,
+            builder
+                .array()
+                .map_op()
+                .array_literal(vec![builder.var("a").build(), builder.int(2)])
+                .mapper(builder.arithmetic().multiply_op().var("").int(10).build())
+                .build(),
+            builder.val_str("name"),
+            builder.missing_vars(["a", "name", "zip"]),
+            factory.between_exclusive("age", builder.int(10), builder.int(30)),
+            factory.is_one_of("age", vec![DataValue::integer(18), DataValue::integer(20)]),
+            factory.string_template(vec![(false, "Hi "), (true, "name")]),
+            factory.coalesce(vec!["nick", "name"]),
+            factory.default_if_missing("nick", builder.string_value("anon")),
+        ];
+
+        let data = dl
+            .parse_data(r#"{"age": 20, "a": 1, "name": "Ann"}"#)
+            .unwrap();
+        for rule in rules {
+            // The JSON parses back into a rule that evaluates the same way
+            let json = rule.to_json();
+            let reparsed = dl.parse_logic(&json.to_string(), None).unwrap();
+            assert_eq!(
+                dl.evaluate(&reparsed, &data).unwrap(),
+                dl.evaluate(&rule, &data).unwrap(),
+                "rule {}",
+                json
+            );
+        }
+    }
 }
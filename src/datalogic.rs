@@ -4,11 +4,18 @@
 //! for parsing and evaluating logic expressions.
 
 use crate::arena::DataArena;
-use crate::logic::{evaluate, optimize, Logic, Result};
+use crate::logic::{
+    evaluate_rule, explain, partial_evaluate, validate, with_rule_state, CompiledLogic, CustomOperator,
+    CustomOperatorRegistry, DecimalArithmetic, Diagnostic, EvaluateOptions, EvaluationLimits,
+    FailedCondition, LazyCustomOperator, Logic, OperatorPolicy, Result, TraceNode,
+};
+use crate::parser::source::parse_json;
 use crate::parser::{ExpressionParser, ParserRegistry};
-use crate::value::{DataValue, FromJson, ToJson};
-use crate::{LogicError, RuleBuilder};
+use crate::value::{DataValue, FromJson, OwnedDataValue, ToJson};
+use crate::RuleBuilder;
 use serde_json::Value as JsonValue;
+use std::cell::RefCell;
+use std::sync::Arc;
 
 /// Main interface for the DataLogic library
 ///
@@ -27,7 +34,10 @@ use serde_json::Value as JsonValue;
 /// ```
 pub struct DataLogic {
     arena: DataArena,
+    scratch: RefCell<DataArena>,
     parsers: ParserRegistry,
+    custom_operators: Arc<CustomOperatorRegistry>,
+    limits: EvaluationLimits,
 }
 
 impl DataLogic {
@@ -35,7 +45,10 @@ impl DataLogic {
     pub fn new() -> Self {
         Self {
             arena: DataArena::new(),
+            scratch: RefCell::new(DataArena::new()),
             parsers: ParserRegistry::new(),
+            custom_operators: Arc::default(),
+            limits: EvaluationLimits::new(),
         }
     }
 
@@ -43,24 +56,45 @@ impl DataLogic {
     pub fn with_chunk_size(chunk_size: usize) -> Self {
         Self {
             arena: DataArena::with_chunk_size(chunk_size),
+            scratch: RefCell::new(DataArena::with_chunk_size(chunk_size)),
             parsers: ParserRegistry::new(),
+            custom_operators: Arc::default(),
+            limits: EvaluationLimits::new(),
         }
     }
 
     /// Get a reference to the internal arena
     ///
-    /// This is exposed for advanced usage scenarios, but most users
-    /// won't need to access this directly.
+    /// This is the arena that holds parsed rules, data parsed with
+    /// `parse_data` and the results of `evaluate`. It is exposed for advanced
+    /// usage scenarios, but most users won't need to access this directly.
     pub fn arena(&self) -> &DataArena {
         &self.arena
     }
 
-    /// Reset the internal arena to free memory
+    /// Reset the internal arenas to free memory
     ///
-    /// This clears all allocated data from the arena, invalidating any
+    /// This clears all allocated data from the arenas, invalidating any
     /// existing DataValue or Logic instances.
     pub fn reset_arena(&mut self) {
         self.arena.reset();
+        self.scratch.get_mut().reset();
+    }
+
+    /// Run `f` with the scratch arena and rewind the arena afterwards
+    ///
+    /// Values allocated in the scratch arena cannot escape `f`, so the arena
+    /// can be rewound without invalidating anything the caller holds on to.
+    fn with_scratch_arena<R>(&self, f: impl FnOnce(&DataArena) -> R) -> R {
+        match self.scratch.try_borrow_mut() {
+            Ok(mut scratch) => {
+                let result = f(&scratch);
+                scratch.rewind();
+                result
+            }
+            // Nested use falls back to a temporary arena
+            Err(_) => f(&self.arena.create_temp_arena()),
+        }
     }
 
     /// Register a parser for a specific expression format
@@ -68,72 +102,414 @@ impl DataLogic {
         self.parsers.register(parser);
     }
 
+    /// Register a custom operator under the given name
+    ///
+    /// Rules that use `name` as an operator key will call the operator with
+    /// their evaluated arguments. Built-in operators take precedence, so a
+    /// custom operator cannot replace them.
+    ///
+    /// # Examples
+    ///
+    /// ```
+    /// use datalogic_rs::{CustomOperator, DataArena, DataLogic, DataValue, Result};
+    ///
+    /// struct Answer;
+    ///
+    /// impl CustomOperator for Answer {
+    ///     fn evaluate<'a>(
+    ///         &self,
+    ///         _args: &'a [DataValue<'a>],
+    ///         arena: &'a DataArena,
+    ///     ) -> Result<&'a DataValue<'a>> {
+    ///         Ok(arena.alloc(DataValue::integer(42)))
+    ///     }
+    /// }
+    ///
+    /// let mut dl = DataLogic::new();
+    /// dl.register_operator("answer", Box::new(Answer));
+    ///
+    /// let result = dl.evaluate_str(r#"{"answer": []}"#, "{}", None).unwrap();
+    /// assert_eq!(result.to_string(), "42");
+    /// ```
+    pub fn register_operator(&mut self, name: &str, operator: Box<dyn CustomOperator>) {
+        Arc::make_mut(&mut self.custom_operators).register(name, operator);
+    }
+
+    /// Register a lazy custom operator under the given name
+    ///
+    /// Unlike [`register_operator`](Self::register_operator), the operator
+    /// receives its arguments unevaluated, along with an
+    /// [`EvaluationContext`](crate::EvaluationContext) for evaluating them.
+    /// This makes it possible to short-circuit or to iterate over arrays like
+    /// the built-in `if`, `map` and `filter` operators do.
+    pub fn register_lazy_operator(&mut self, name: &str, operator: Box<dyn LazyCustomOperator>) {
+        Arc::make_mut(&mut self.custom_operators).register_lazy(name, operator);
+    }
+
+    /// Set the policy that decides which operators parsed rules may use
+    ///
+    /// `parse_logic` rejects rules that use a forbidden operator with an
+    /// `OperatorNotAllowedError` naming the operator and its location in the
+    /// rule. Rules that were parsed before the policy was set are not affected.
+    pub fn set_operator_policy(&mut self, policy: OperatorPolicy) {
+        self.parsers.set_policy(policy);
+    }
+
+    /// Get the policy that decides which operators parsed rules may use
+    pub fn operator_policy(&self) -> &OperatorPolicy {
+        self.parsers.policy()
+    }
+
+    /// Set the resource limits enforced by each evaluation
+    ///
+    /// An evaluation that goes past a limit fails with a
+    /// `LimitExceededError` instead of overflowing the stack or exhausting
+    /// memory.
+    pub fn set_evaluation_limits(&mut self, limits: EvaluationLimits) {
+        self.limits = limits;
+    }
+
+    /// Get the resource limits enforced by each evaluation
+    pub fn evaluation_limits(&self) -> &EvaluationLimits {
+        &self.limits
+    }
+
+    /// Set the decimal arithmetic used by parsing and evaluation
+    ///
+    /// With decimal arithmetic, numbers with a fractional part in rules and
+    /// data parsed afterwards are exact decimals, and arithmetic on them is
+    /// exact up to the configured scale. `None` switches back to floating
+    /// point numbers.
+    pub fn set_decimal_arithmetic(&mut self, decimal: Option<DecimalArithmetic>) {
+        self.arena.set_decimal_arithmetic(decimal);
+        self.scratch.get_mut().set_decimal_arithmetic(decimal);
+    }
+
+    /// Get the decimal arithmetic used by parsing and evaluation, if enabled
+    pub fn decimal_arithmetic(&self) -> Option<DecimalArithmetic> {
+        self.arena.decimal_arithmetic()
+    }
+
     /// Set the default parser
     pub fn set_default_parser(&mut self, format_name: &str) -> Result<()> {
         self.parsers.set_default(format_name)
     }
 
     /// Get a rule builder for constructing rules programmatically
-    pub fn builder(&self) -> RuleBuilder {
+    pub fn builder(&self) -> RuleBuilder<'_> {
         RuleBuilder::new(&self.arena)
     }
 
     /// Parse a logic expression using the specified parser format
-    pub fn parse_logic(&self, source: &str, format: Option<&str>) -> Result<Logic> {
+    pub fn parse_logic(&self, source: &str, format: Option<&str>) -> Result<Logic<'_>> {
         let token = self.parsers.parse(source, format, &self.arena)?;
 
         // Apply static optimization
-        let optimized_token = optimize(token, &self.arena)?;
+        Logic::optimized(token, &self.arena)
+    }
 
-        Ok(Logic::new(optimized_token, &self.arena))
+    /// Compile a logic expression into a rule that can be shared between threads
+    ///
+    /// The compiled rule owns its token tree and takes a snapshot of the
+    /// custom operators and evaluation limits configured on this instance.
+    /// Unlike a [`Logic`], it does not borrow this instance's arena, so it can
+    /// be wrapped in an `Arc` and evaluated from many threads at once.
+    pub fn compile(&self, source: &str, format: Option<&str>) -> Result<CompiledLogic> {
+        let decimal = self.arena.decimal_arithmetic();
+        CompiledLogic::compile(self.custom_operators.clone(), self.limits, decimal, |arena| {
+            let token = self.parsers.parse(source, format, arena)?;
+            Logic::optimized(token, arena)
+        })
     }
 
     /// Parse a JSON data string into a DataValue
-    pub fn parse_data(&self, source: &str) -> Result<DataValue> {
-        let json = serde_json::from_str(source).map_err(|e| LogicError::ParseError {
-            reason: e.to_string(),
-        })?;
+    pub fn parse_data(&self, source: &str) -> Result<DataValue<'_>> {
+        let json = parse_json(source)?;
         Ok(DataValue::from_json(&json, &self.arena))
     }
 
     /// Evaluate a rule with the provided data
+    ///
+    /// Intermediate values are allocated in the scratch arena, which is reset
+    /// when the call returns, and only the result is copied into the main
+    /// arena, so repeated calls grow the main arena by the size of their
+    /// results.
     pub fn evaluate<'a>(
         &'a self,
         rule: &'a Logic,
         data: &'a DataValue,
     ) -> Result<&'a DataValue<'a>> {
-        // Set both current context and root context to the data
-        self.arena.set_root_context(data);
-        self.arena.set_current_context(data, &DataValue::String("$"));
-        
-        // Evaluate the rule with the data as context
-        evaluate(rule.root(), &self.arena)
+        self.evaluate_with_options(rule, data, &EvaluateOptions::default())
+    }
+
+    /// Evaluate a rule with the provided data and per-evaluation options
+    ///
+    /// The evaluation stops with `LogicError::Cancelled` once the
+    /// cancellation token is cancelled, and with
+    /// `LogicError::DeadlineExceeded` once the deadline has passed. Both are
+    /// checked before every evaluation step and for every array item that
+    /// an iterator visits.
+    pub fn evaluate_with_options<'a>(
+        &'a self,
+        rule: &'a Logic,
+        data: &'a DataValue,
+        options: &EvaluateOptions,
+    ) -> Result<&'a DataValue<'a>> {
+        self.with_scratch_arena(|scratch| {
+            let result = evaluate_rule(
+                rule,
+                data,
+                scratch,
+                &self.custom_operators,
+                self.limits,
+                options,
+            )?;
+            Ok(self.copy_result(scratch, result))
+        })
+    }
+
+    /// Copy a result out of the scratch arena into the main arena
+    ///
+    /// Only results allocated in the scratch arena are copied. A result that
+    /// refers to the data or to a literal of the rule already outlives the
+    /// call, so it is returned as is.
+    fn copy_result<'a>(&'a self, scratch: &DataArena, result: &DataValue) -> &'a DataValue<'a> {
+        if scratch.contains(result) {
+            return self.arena.alloc(result.copy_to(&self.arena));
+        }
+        // SAFETY: values that are not in the scratch arena are never built
+        // during the evaluation, so they come from the data, the rule or
+        // static storage, all of which outlive `'a`
+        unsafe { std::mem::transmute::<&DataValue, &'a DataValue<'a>>(result) }
+    }
+
+    /// Evaluate a rule with the provided data, recording a trace
+    ///
+    /// Returns the result together with the root of a tree of
+    /// [`TraceNode`]s, one for every token that was evaluated. The trace is
+    /// recorded even if the evaluation fails, and can be converted to JSON
+    /// with [`ToJson`](crate::ToJson).
+    ///
+    /// # Examples
+    ///
+    /// ```
+    /// use datalogic_rs::{DataLogic, ToJson};
+    ///
+    /// let dl = DataLogic::new();
+    /// let rule = dl.parse_logic(r#"{"and": [{"var": "a"}, {"var": "b"}]}"#, None).unwrap();
+    /// let data = dl.parse_data(r#"{"a": false, "b": true}"#).unwrap();
+    ///
+    /// let (result, trace) = dl.evaluate_traced(&rule, &data);
+    /// assert_eq!(result.unwrap().as_bool(), Some(false));
+    /// assert_eq!(trace.branch, Some(0));
+    /// assert_eq!(trace.to_json()["children"][0]["expression"]["var"], "a");
+    /// ```
+    pub fn evaluate_traced<'a>(
+        &'a self,
+        rule: &'a Logic,
+        data: &'a DataValue,
+    ) -> (Result<&'a DataValue<'a>>, TraceNode) {
+        self.with_scratch_arena(|scratch| {
+            scratch.start_trace();
+            let result = evaluate_rule(
+                rule,
+                data,
+                scratch,
+                &self.custom_operators,
+                self.limits,
+                &EvaluateOptions::default(),
+            )
+            .map(|result| self.copy_result(scratch, result));
+            let trace = scratch
+                .finish_trace()
+                .expect("the root token is always traced");
+            (result, trace)
+        })
+    }
+
+    /// Explain why a boolean rule fails for the provided data
+    ///
+    /// Returns the leaf conditions of the rule that did not hold, each with
+    /// the variable it tests, the actual value and the comparison it has to
+    /// satisfy. `and`, `or`, `!`, `!!` and comparisons are broken down into
+    /// their conditions, and for an `or` only the argument that is closest to
+    /// passing is reported. The result is empty if the rule passes.
+    ///
+    /// # Examples
+    ///
+    /// ```
+    /// use datalogic_rs::DataLogic;
+    ///
+    /// let dl = DataLogic::new();
+    /// let rule = dl.parse_logic(
+    ///     r#"{"and": [{">=": [{"var": "age"}, 18]}, {"==": [{"var": "country"}, "US"]}]}"#,
+    ///     None,
+    /// ).unwrap();
+    /// let data = dl.parse_data(r#"{"age": 16, "country": "US"}"#).unwrap();
+    ///
+    /// let failed = dl.explain(&rule, &data).unwrap();
+    /// assert_eq!(failed.len(), 1);
+    /// assert_eq!(failed[0].path.as_deref(), Some("age"));
+    /// assert_eq!(failed[0].actual.as_i64(), Some(16));
+    /// assert_eq!(failed[0].operator, ">=");
+    /// assert_eq!(failed[0].expected.as_ref().unwrap().as_i64(), Some(18));
+    /// ```
+    pub fn explain(&self, rule: &Logic, data: &DataValue) -> Result<Vec<FailedCondition>> {
+        self.with_scratch_arena(|scratch| {
+            with_rule_state(
+                data,
+                scratch,
+                &self.custom_operators,
+                self.limits,
+                &EvaluateOptions::default(),
+                || {
+                    explain(rule.root(), scratch).map_err(|error| {
+                        let source_map = rule.source_map().map(|map| &**map);
+                        scratch.take_failure().locate(rule.root(), source_map, error)
+                    })
+                },
+            )
+        })
+    }
+
+    /// Check a rule for problems without evaluating it
+    ///
+    /// Returns a diagnostic for each problem found, such as an operator
+    /// called with the wrong number of arguments, an unregistered custom
+    /// operator, an `if` branch that is never taken or a variable path that
+    /// looks like a typo. Each diagnostic has a severity and the JSON pointer
+    /// of the expression it refers to.
+    ///
+    /// # Examples
+    ///
+    /// ```
+    /// use datalogic_rs::{DataLogic, Severity};
+    ///
+    /// let dl = DataLogic::new();
+    /// let rule = dl.parse_logic(r#"{"if": [{"var": "a"}, {"substr": [{"var": "s"}]}]}"#, None).unwrap();
+    ///
+    /// let diagnostics = dl.validate(&rule);
+    /// assert_eq!(diagnostics.len(), 2);
+    /// assert_eq!(diagnostics[0].code, "missing_else");
+    /// assert_eq!(diagnostics[1].severity, Severity::Error);
+    /// assert_eq!(diagnostics[1].path, "/if/1");
+    /// ```
+    pub fn validate(&self, rule: &Logic) -> Vec<Diagnostic> {
+        validate(rule.source(), &self.custom_operators)
+    }
+
+    /// Partially evaluate a rule with the part of the data that is known
+    ///
+    /// Variables whose path is in `known` are replaced by their values and
+    /// the rule is simplified as far as that allows: operators whose
+    /// arguments are all known are evaluated, and `and`, `or` and `if` keep
+    /// only the arguments that can still change the result. Variables whose
+    /// path is not in `known` are kept, so the residual rule can be
+    /// evaluated with the rest of the data once it arrives.
+    ///
+    /// # Examples
+    ///
+    /// ```
+    /// use datalogic_rs::{DataLogic, ToJson};
+    /// use serde_json::json;
+    ///
+    /// let dl = DataLogic::new();
+    /// let rule = dl.parse_logic(
+    ///     r#"{"and": [{"==": [{"var": "country"}, "US"]}, {">": [{"var": "cart.total"}, 100]}]}"#,
+    ///     None,
+    /// ).unwrap();
+    /// let known = dl.parse_data(r#"{"country": "US"}"#).unwrap();
+    ///
+    /// let residual = dl.partial_evaluate(&rule, &known);
+    /// assert_eq!(residual.to_json(), json!({">": [{"var": "cart.total"}, 100]}));
+    ///
+    /// let cart = dl.parse_data(r#"{"cart": {"total": 120}}"#).unwrap();
+    /// assert_eq!(dl.evaluate(&residual, &cart).unwrap().as_bool(), Some(true));
+    /// ```
+    pub fn partial_evaluate<'a>(&'a self, rule: &Logic<'a>, known: &DataValue) -> Logic<'a> {
+        Logic::new(
+            partial_evaluate(rule.root(), known, &self.arena),
+            &self.arena,
+        )
+    }
+
+    /// Evaluate a rule with the provided data, returning an owned value
+    ///
+    /// Unlike [`evaluate`](Self::evaluate), the result does not borrow the
+    /// arena, so it can be returned from functions, cached or sent to other
+    /// threads. Intermediate values are allocated in the scratch arena, which
+    /// is reset when the call returns.
+    pub fn evaluate_owned(&self, rule: &Logic, data: &DataValue) -> Result<OwnedDataValue> {
+        self.with_scratch_arena(|scratch| {
+            let result = evaluate_rule(
+                rule,
+                data,
+                scratch,
+                &self.custom_operators,
+                self.limits,
+                &EvaluateOptions::default(),
+            )?;
+            Ok(result.to_owned_value())
+        })
+    }
+
+    /// Evaluate a rule with JSON data, returning JSON
+    ///
+    /// The data and all intermediate values are allocated in a scratch
+    /// arena that is reset when the call returns. Long-running services can
+    /// parse their rules once and evaluate them with this method without the
+    /// arena growing from request to request.
+    pub fn evaluate_json(&self, rule: &Logic, data: &JsonValue) -> Result<JsonValue> {
+        self.with_scratch_arena(|scratch| {
+            let data_value = DataValue::from_json(data, scratch);
+            let result = evaluate_rule(
+                rule,
+                &data_value,
+                scratch,
+                &self.custom_operators,
+                self.limits,
+                &EvaluateOptions::default(),
+            )?;
+            Ok(result.to_json())
+        })
     }
 
     /// Parse and evaluate in one step, returning JSON
+    ///
+    /// The rule and the data are parsed into the scratch arena, which is
+    /// reset when the call returns.
     pub fn apply(
         &self,
         logic_source: &str,
         data_source: &str,
         format: Option<&str>,
     ) -> Result<JsonValue> {
-        let rule = self.parse_logic(logic_source, format)?;
-        let data_value = self.parse_data(data_source)?;
-        let result = self.evaluate(&rule, &data_value)?;
-        Ok(result.to_json())
+        self.with_scratch_arena(|scratch| {
+            let token = self.parsers.parse(logic_source, format, scratch)?;
+            let rule = Logic::optimized(token, scratch)?;
+            let data_value = DataValue::from_json(&parse_json(data_source)?, scratch);
+            let result = evaluate_rule(
+                &rule,
+                &data_value,
+                scratch,
+                &self.custom_operators,
+                self.limits,
+                &EvaluateOptions::default(),
+            )?;
+            Ok(result.to_json())
+        })
     }
 
-    /// Parse and evaluate in one step, returning a DataValue
+    /// Parse and evaluate in one step, returning JSON
+    ///
+    /// This is the same as [`apply`](Self::apply).
     pub fn evaluate_str(
         &self,
         logic_source: &str,
         data_source: &str,
         format: Option<&str>,
     ) -> Result<JsonValue> {
-        let rule = self.parse_logic(logic_source, format)?;
-        let data_value = self.parse_data(data_source)?;
-        let result = self.evaluate(&rule, &data_value)?;
-        Ok(result.to_json())
+        self.apply(logic_source, data_source, format)
     }
 }
 
@@ -142,3 +518,98 @@ impl Default for DataLogic {
         Self::new()
     }
 }
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use serde_json::json;
+
+    #[test]
+    fn test_evaluate_json_uses_scratch_arena() {
+        let dl = DataLogic::new();
+        let rule = dl
+            .parse_logic(r#"{"map": [{"var": "items"}, {"cat": ["item-", {"var": ""}]}]}"#, None)
+            .unwrap();
+        let rule_memory = dl.arena().memory_usage();
+
+        for i in 0..1000 {
+            let data = json!({ "items": [i, i + 1] });
+            let result = dl.evaluate_json(&rule, &data).unwrap();
+            assert_eq!(result, json!([format!("item-{}", i), format!("item-{}", i + 1)]));
+        }
+
+        // Neither the rule arena nor the scratch arena grows across requests
+        assert_eq!(dl.arena().memory_usage(), rule_memory);
+        assert!(dl.scratch.borrow().memory_usage() < 64 * 1024);
+    }
+
+    #[test]
+    fn test_apply_uses_scratch_arena() {
+        let dl = DataLogic::new();
+        let rule_memory = dl.arena().memory_usage();
+
+        for i in 0..100 {
+            let result = dl
+                .apply(r#"{"+": [{"var": "a"}, 1]}"#, &format!(r#"{{"a": {}}}"#, i), None)
+                .unwrap();
+            assert_eq!(result, json!(i + 1));
+        }
+
+        assert_eq!(dl.arena().memory_usage(), rule_memory);
+    }
+
+    #[test]
+    fn test_evaluate_uses_scratch_arena() {
+        let dl = DataLogic::new();
+        let rule = dl
+            .parse_logic(
+                r#"{"reduce": [
+                    {"map": [{"var": "items"}, {"cat": ["item-", {"var": ""}]}]},
+                    {"+": [{"var": "accumulator"}, 1]},
+                    0
+                ]}"#,
+                None,
+            )
+            .unwrap();
+        let items: Vec<_> = (0..1000).collect();
+        let data = dl.parse_data(&json!({ "items": items }).to_string()).unwrap();
+        let rule_memory = dl.arena().memory_usage();
+
+        for _ in 0..100 {
+            let result = dl.evaluate(&rule, &data).unwrap();
+            assert_eq!(result.as_i64(), Some(1000));
+        }
+
+        // Only the results are copied into the main arena, not the mapped strings
+        assert!(dl.arena().memory_usage() - rule_memory < 256 * 1024);
+    }
+
+    #[test]
+    fn test_evaluate_returns_data_without_copying() {
+        let dl = DataLogic::new();
+        let rule = dl.parse_logic(r#"{"var": "user"}"#, None).unwrap();
+        let data = dl.parse_data(r#"{"user": {"name": "Ann", "tags": ["a", "b"]}}"#).unwrap();
+        let rule_memory = dl.arena().memory_usage();
+
+        let result = dl.evaluate(&rule, &data).unwrap();
+        let user = &data.as_object().unwrap()[0].1;
+        assert!(std::ptr::eq(result, user));
+        assert_eq!(dl.arena().memory_usage(), rule_memory);
+
+        let (traced, _) = dl.evaluate_traced(&rule, &data);
+        assert!(std::ptr::eq(traced.unwrap(), user));
+    }
+
+    #[test]
+    fn test_evaluate_json_matches_evaluate() {
+        let dl = DataLogic::new();
+        let rule = dl
+            .parse_logic(r#"{"if": [{">": [{"var": "temp"}, 100]}, "hot", "cold"]}"#, None)
+            .unwrap();
+        let data = dl.parse_data(r#"{"temp": 110}"#).unwrap();
+
+        let expected = dl.evaluate(&rule, &data).unwrap().to_json();
+        let result = dl.evaluate_json(&rule, &json!({"temp": 110})).unwrap();
+        assert_eq!(result, expected);
+    }
+}
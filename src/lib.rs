@@ -1,9 +1,15 @@
 // Core types and functionality
+pub use arena::DataArena;
 pub use builder::RuleBuilder;
 pub use datalogic::DataLogic;
 pub use error::LogicError;
-pub use logic::{Logic, Result};
-pub use value::{DataValue, FromDataValue, FromJson, IntoDataValue, ToJson};
+pub use logic::{
+    CancellationToken, Clock, CompiledLogic, CustomOperator, DecimalArithmetic, Diagnostic,
+    ErrorLocation, EvaluateOptions, EvaluationContext, EvaluationLimits, FailedCondition,
+    FixedClock, LazyCustomOperator, Logic, OperatorPolicy, ReferencedPath, Result,
+    Rounding, Severity, SourcePosition, SystemClock, TraceNode,
+};
+pub use value::{DataValue, FromDataValue, FromJson, IntoDataValue, OwnedDataValue, ToJson};
 
 // Internal modules with implementation details
 mod arena;
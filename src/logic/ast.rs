@@ -3,9 +3,16 @@
 //! This module provides the Logic struct, which represents a logic expression
 //! as an Abstract Syntax Tree (AST).
 
+use std::sync::Arc;
+
+use super::error::Result;
+use super::location::SourceMap;
+use super::optimizer;
+use super::references::{self, ReferencedPath};
 use super::token::{OperatorType, Token};
 use crate::arena::DataArena;
-use crate::value::DataValue;
+use crate::value::{DataValue, ToJson};
+use serde_json::Value as JsonValue;
 
 /// A logic expression.
 ///
@@ -17,6 +24,13 @@ pub struct Logic<'a> {
     /// The root token of the logic expression.
     root: &'a Token<'a>,
 
+    /// The root token of the expression as it was written, before it was
+    /// optimized.
+    source: &'a Token<'a>,
+
+    /// Pointers into the expression as it was written, for error locations.
+    source_map: Option<Arc<SourceMap>>,
+
     /// The arena in which the tokens are allocated.
     arena: &'a DataArena,
 }
@@ -24,13 +38,32 @@ pub struct Logic<'a> {
 impl<'a> Logic<'a> {
     /// Creates a new logic expression.
     pub fn new(root: &'a Token<'a>, arena: &'a DataArena) -> Self {
-        Self { root, arena }
+        Self {
+            root,
+            source: root,
+            source_map: None,
+            arena,
+        }
     }
 
     /// Creates a new logic expression from a token.
     pub fn from_token(token: Token<'a>, arena: &'a DataArena) -> Self {
-        let root = arena.alloc(token);
-        Self { root, arena }
+        Self::new(arena.alloc(token), arena)
+    }
+
+    /// Optimizes the expression `source`.
+    ///
+    /// Errors raised by the optimized expression point to the expression as
+    /// it was written.
+    pub(crate) fn optimized(source: &'a Token<'a>, arena: &'a DataArena) -> Result<Self> {
+        let mut source_map = SourceMap::of(source);
+        let root = optimizer::optimize_mapped(source, arena, &mut source_map)?;
+        Ok(Self {
+            root,
+            source,
+            source_map: Some(Arc::new(source_map)),
+            arena,
+        })
     }
 
     /// Returns the root token of the logic expression.
@@ -38,6 +71,20 @@ impl<'a> Logic<'a> {
         self.root
     }
 
+    /// Returns the root token of the expression as it was written.
+    ///
+    /// This is the root token itself unless the expression was optimized
+    /// when it was parsed.
+    pub(crate) fn source(&self) -> &'a Token<'a> {
+        self.source
+    }
+
+    /// Returns the pointers into the expression as it was written, if it was
+    /// optimized when it was parsed.
+    pub(crate) fn source_map(&self) -> Option<&Arc<SourceMap>> {
+        self.source_map.as_ref()
+    }
+
     /// Returns the arena in which the tokens are allocated.
     pub fn arena(&self) -> &'a DataArena {
         self.arena
@@ -67,7 +114,7 @@ impl<'a> Logic<'a> {
         }
 
         // Allocate the argument tokens in the arena
-        let array_literal = Token::ArrayLiteral(arg_tokens);
+        let array_literal = Token::ArrayLiteral(arena.vec_into_slice(arg_tokens));
         let array_token = arena.alloc(array_literal);
 
         // Create the operator token
@@ -86,7 +133,7 @@ impl<'a> Logic<'a> {
         }
 
         // Allocate the argument tokens in the arena
-        let array_literal = Token::ArrayLiteral(arg_tokens);
+        let array_literal = Token::ArrayLiteral(arena.vec_into_slice(arg_tokens));
         let array_token = arena.alloc(array_literal);
 
         // Create the custom operator token
@@ -135,6 +182,52 @@ impl<'a> Logic<'a> {
     pub fn as_custom_operator(&self) -> Option<(&'a str, &'a Token<'a>)> {
         self.root.as_custom_operator()
     }
+
+    /// Returns the paths of the data that this logic expression reads.
+    ///
+    /// References inside `map`, `filter`, `reduce`, `all`, `some` and `none`
+    /// are resolved against the array they iterate over, with a `*` segment
+    /// for its elements. Paths computed during evaluation are reported as
+    /// [`ReferencedPath::Dynamic`].
+    ///
+    /// # Examples
+    ///
+    /// ```
+    /// use datalogic_rs::{DataLogic, ReferencedPath};
+    ///
+    /// let dl = DataLogic::new();
+    /// let rule = dl
+    ///     .parse_logic(r#"{"some": [{"var": "items"}, {">": [{"var": "qty"}, 0]}]}"#, None)
+    ///     .unwrap();
+    /// assert_eq!(
+    ///     rule.referenced_paths(),
+    ///     vec![
+    ///         ReferencedPath::Static("items".to_string()),
+    ///         ReferencedPath::Static("items.*.qty".to_string()),
+    ///     ]
+    /// );
+    /// ```
+    pub fn referenced_paths(&self) -> Vec<ReferencedPath> {
+        references::referenced_paths(self.root)
+    }
+}
+
+/// Converts the logic expression back into JSONLogic.
+///
+/// # Examples
+///
+/// ```
+/// use datalogic_rs::{DataLogic, ToJson};
+/// use serde_json::json;
+///
+/// let dl = DataLogic::new();
+/// let rule = dl.builder().compare().greater_than_op().var("temp").int(100).build();
+/// assert_eq!(rule.to_json(), json!({">": [{"var": "temp"}, 100]}));
+/// ```
+impl ToJson for Logic<'_> {
+    fn to_json(&self) -> JsonValue {
+        self.root.to_json()
+    }
 }
 
 #[cfg(test)]
@@ -0,0 +1,462 @@
+//! Compiled logic expressions that can be shared between threads.
+//!
+//! This module provides `CompiledLogic`, a parsed and optimized rule that
+//! owns its token tree. Each evaluation lays the tree out in a scratch arena,
+//! together with the data and intermediate values, so one compiled rule can
+//! be evaluated by many threads at the same time.
+
+use std::cell::RefCell;
+use std::collections::HashMap;
+use std::sync::Arc;
+
+use serde_json::Value as JsonValue;
+
+use super::ast::Logic;
+use super::decimal::DecimalArithmetic;
+use super::error::Result;
+use super::evaluator::{evaluate, with_rule_state};
+use super::limits::EvaluationLimits;
+use super::location::SourceMap;
+use super::operators::custom::CustomOperatorRegistry;
+use super::operators::regex::Pattern;
+use super::options::EvaluateOptions;
+use super::token::{OperatorType, Token};
+use crate::arena::DataArena;
+use crate::value::{DataValue, FromJson, OwnedDataValue, ToJson};
+
+/// A compiled rule that is `Send + Sync`.
+///
+/// A compiled rule owns its token tree, together with the custom operators,
+/// evaluation limits and decimal arithmetic that were configured on the `DataLogic` instance
+/// when it was compiled. Each evaluation takes a scratch arena for the data
+/// and intermediate values, which every thread provides for itself.
+///
+/// # Examples
+///
+/// ```
+/// use datalogic_rs::DataLogic;
+/// use serde_json::json;
+/// use std::sync::Arc;
+/// use std::thread;
+///
+/// let dl = DataLogic::new();
+/// let rule = Arc::new(dl.compile(r#"{"*": [{"var": "n"}, 2]}"#, None).unwrap());
+///
+/// let handles: Vec<_> = (0..4)
+///     .map(|n| {
+///         let rule = Arc::clone(&rule);
+///         thread::spawn(move || rule.evaluate_json(&json!({ "n": n })).unwrap())
+///     })
+///     .collect();
+///
+/// let results: Vec<_> = handles.into_iter().map(|h| h.join().unwrap()).collect();
+/// assert_eq!(results, vec![json!(0), json!(2), json!(4), json!(6)]);
+/// ```
+pub struct CompiledLogic {
+    /// The tokens of the rule, each after the tokens it refers to, so the
+    /// root comes last.
+    nodes: Vec<Node>,
+
+    /// The pointer of each token into the rule as it was written, for error
+    /// locations.
+    paths: Vec<Option<String>>,
+
+    /// Custom operators available to the rule.
+    custom_operators: Arc<CustomOperatorRegistry>,
+
+    /// Resource limits enforced by each evaluation.
+    limits: EvaluationLimits,
+
+    /// Decimal arithmetic used by each evaluation, if enabled.
+    decimal: Option<DecimalArithmetic>,
+}
+
+/// A token of a compiled rule, which owns its data.
+///
+/// Tokens refer to other tokens by their index in the rule. A token that
+/// occurs more than once in the tree, such as the expression of a shared
+/// token, is stored once.
+#[derive(Debug)]
+enum Node {
+    Literal(OwnedDataValue),
+    ArrayLiteral(Box<[usize]>),
+    Variable {
+        path: Box<str>,
+        default: Option<usize>,
+    },
+    DynamicVariable {
+        path_expr: usize,
+        default: Option<usize>,
+    },
+    Operator {
+        op_type: OperatorType,
+        args: usize,
+    },
+    CustomOperator {
+        name: Box<str>,
+        args: usize,
+    },
+    Shared {
+        slot: usize,
+        expr: usize,
+    },
+    Pattern(Pattern),
+}
+
+// Fails to compile if a part of `CompiledLogic` stops being thread-safe.
+const _: () = {
+    const fn assert_send_sync<T: Send + Sync>() {}
+    assert_send_sync::<Node>();
+    assert_send_sync::<CustomOperatorRegistry>();
+    assert_send_sync::<CompiledLogic>();
+};
+
+thread_local! {
+    /// The scratch arena of `CompiledLogic::evaluate_json` on this thread,
+    /// reused across calls.
+    static SCRATCH: RefCell<DataArena> = RefCell::new(DataArena::new());
+}
+
+impl CompiledLogic {
+    /// Compiles a rule, copying its token tree out of the arena it was
+    /// parsed into.
+    ///
+    /// `parse` allocates the token tree in the arena it is given.
+    pub(crate) fn compile<F>(
+        custom_operators: Arc<CustomOperatorRegistry>,
+        limits: EvaluationLimits,
+        decimal: Option<DecimalArithmetic>,
+        parse: F,
+    ) -> Result<Self>
+    where
+        F: for<'a> FnOnce(&'a DataArena) -> Result<Logic<'a>>,
+    {
+        let arena = DataArena::new();
+        arena.set_decimal_arithmetic(decimal);
+        let rule = parse(&arena)?;
+        let source_map = match rule.source_map() {
+            Some(source_map) => (**source_map).clone(),
+            None => SourceMap::of(rule.root()),
+        };
+
+        let mut builder = NodeBuilder {
+            nodes: Vec::new(),
+            paths: Vec::new(),
+            indices: HashMap::new(),
+            source_map: &source_map,
+        };
+        builder.add(rule.root());
+
+        Ok(Self {
+            nodes: builder.nodes,
+            paths: builder.paths,
+            custom_operators,
+            limits,
+            decimal,
+        })
+    }
+
+    /// Lays out the token tree of the rule in the given arena, returning its
+    /// root.
+    ///
+    /// The tokens borrow the rule, so they cannot outlive it:
+    ///
+    /// ```compile_fail
+    /// use datalogic_rs::{DataArena, DataLogic};
+    ///
+    /// let dl = DataLogic::new();
+    /// let arena = DataArena::new();
+    /// let root = {
+    ///     let rule = dl.compile(r#"{"var": "a"}"#, None).unwrap();
+    ///     rule.root(&arena)
+    /// };
+    /// println!("{:?}", root);
+    /// ```
+    pub fn root<'a>(&'a self, arena: &'a DataArena) -> &'a Token<'a> {
+        let tokens = self.tokens(arena);
+        tokens[tokens.len() - 1]
+    }
+
+    /// Returns the resource limits enforced by each evaluation.
+    pub fn evaluation_limits(&self) -> &EvaluationLimits {
+        &self.limits
+    }
+
+    /// Returns the decimal arithmetic used by each evaluation, if enabled.
+    pub fn decimal_arithmetic(&self) -> Option<DecimalArithmetic> {
+        self.decimal
+    }
+
+    /// Evaluates the rule with data allocated in the given scratch arena.
+    ///
+    /// The result is allocated in the scratch arena, or borrowed from the
+    /// rule itself.
+    pub fn evaluate<'a>(
+        &'a self,
+        data: &'a DataValue<'a>,
+        arena: &'a DataArena,
+    ) -> Result<&'a DataValue<'a>> {
+        self.evaluate_with_options(data, arena, &EvaluateOptions::default())
+    }
+
+    /// Evaluates the rule with data allocated in the given scratch arena and
+    /// per-evaluation options.
+    pub fn evaluate_with_options<'a>(
+        &'a self,
+        data: &'a DataValue<'a>,
+        arena: &'a DataArena,
+        options: &EvaluateOptions,
+    ) -> Result<&'a DataValue<'a>> {
+        arena.set_decimal_arithmetic(self.decimal);
+        let tokens = self.tokens(arena);
+        let root = tokens[tokens.len() - 1];
+        with_rule_state(data, arena, &self.custom_operators, self.limits, options, || {
+            evaluate(root, arena).map_err(|error| {
+                let source_map = self.source_map(&tokens);
+                arena.take_failure().locate(root, Some(&source_map), error)
+            })
+        })
+    }
+
+    /// Evaluates the rule with JSON data in a scratch arena, returning JSON.
+    ///
+    /// Each thread reuses one scratch arena, which is rewound after each call.
+    pub fn evaluate_json(&self, data: &JsonValue) -> Result<JsonValue> {
+        SCRATCH.with(|scratch| match scratch.try_borrow_mut() {
+            Ok(mut scratch) => {
+                let result = self.evaluate_json_in(data, &scratch);
+                scratch.rewind();
+                result
+            }
+            // A custom operator evaluating a compiled rule uses a fresh arena
+            Err(_) => self.evaluate_json_in(data, &DataArena::new()),
+        })
+    }
+
+    /// Evaluates the rule with JSON data in the given arena, returning JSON.
+    fn evaluate_json_in(&self, data: &JsonValue, arena: &DataArena) -> Result<JsonValue> {
+        arena.set_decimal_arithmetic(self.decimal);
+        let data = arena.alloc(DataValue::from_json(data, arena));
+        let result = self.evaluate(data, arena)?;
+        Ok(result.to_json())
+    }
+
+    /// Allocates a token for each node in the given arena, borrowing the
+    /// strings and patterns of the rule.
+    fn tokens<'a>(&'a self, arena: &'a DataArena) -> Vec<&'a Token<'a>> {
+        let mut tokens: Vec<&'a Token<'a>> = Vec::with_capacity(self.nodes.len());
+        for node in &self.nodes {
+            let token = match node {
+                Node::Literal(value) => Token::Literal(value.borrow_in(arena)),
+                Node::ArrayLiteral(items) => Token::ArrayLiteral(
+                    arena.alloc_slice_fill_with(items.len(), |i| tokens[items[i]]),
+                ),
+                Node::Variable { path, default } => Token::Variable {
+                    path,
+                    default: default.map(|default| tokens[default]),
+                },
+                Node::DynamicVariable { path_expr, default } => Token::DynamicVariable {
+                    path_expr: tokens[*path_expr],
+                    default: default.map(|default| tokens[default]),
+                },
+                Node::Operator { op_type, args } => Token::Operator {
+                    op_type: *op_type,
+                    args: tokens[*args],
+                },
+                Node::CustomOperator { name, args } => Token::CustomOperator {
+                    name,
+                    args: tokens[*args],
+                },
+                Node::Shared { slot, expr } => Token::Shared {
+                    slot: *slot,
+                    expr: tokens[*expr],
+                },
+                Node::Pattern(pattern) => Token::Pattern(pattern),
+            };
+            tokens.push(arena.alloc(token));
+        }
+        tokens
+    }
+
+    /// Maps the tokens allocated for the nodes to their pointers.
+    fn source_map(&self, tokens: &[&Token]) -> SourceMap {
+        let mut source_map = SourceMap::default();
+        for (token, path) in tokens.iter().zip(&self.paths) {
+            if let Some(path) = path {
+                source_map.insert(token, path.clone());
+            }
+        }
+        source_map
+    }
+}
+
+/// Copies a token tree into the nodes of a compiled rule.
+struct NodeBuilder<'s> {
+    nodes: Vec<Node>,
+    paths: Vec<Option<String>>,
+    /// The index of each token added so far, by address.
+    indices: HashMap<*const Token<'s>, usize>,
+    source_map: &'s SourceMap,
+}
+
+impl<'s> NodeBuilder<'s> {
+    /// Adds `token` after the tokens it refers to, returning its index.
+    fn add(&mut self, token: &'s Token<'s>) -> usize {
+        if let Some(&index) = self.indices.get(&(token as *const Token)) {
+            return index;
+        }
+
+        let node = match token {
+            Token::Literal(value) => Node::Literal(OwnedDataValue::from_data_value(value)),
+            Token::ArrayLiteral(items) => {
+                Node::ArrayLiteral(items.iter().map(|item| self.add(item)).collect())
+            }
+            Token::Variable { path, default } => Node::Variable {
+                path: Box::from(*path),
+                default: default.map(|default| self.add(default)),
+            },
+            Token::DynamicVariable { path_expr, default } => Node::DynamicVariable {
+                path_expr: self.add(path_expr),
+                default: default.map(|default| self.add(default)),
+            },
+            Token::Operator { op_type, args } => Node::Operator {
+                op_type: *op_type,
+                args: self.add(args),
+            },
+            Token::CustomOperator { name, args } => Node::CustomOperator {
+                name: Box::from(*name),
+                args: self.add(args),
+            },
+            Token::Shared { slot, expr } => Node::Shared {
+                slot: *slot,
+                expr: self.add(expr),
+            },
+            Token::Pattern(pattern) => Node::Pattern((*pattern).clone()),
+        };
+
+        let index = self.nodes.len();
+        self.nodes.push(node);
+        self.paths.push(self.source_map.path(token).map(String::from));
+        self.indices.insert(token, index);
+        index
+    }
+}
+
+impl std::fmt::Debug for CompiledLogic {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        f.debug_struct("CompiledLogic")
+            .field("root", self.root(&DataArena::new()))
+            .field("custom_operators", &self.custom_operators)
+            .field("limits", &self.limits)
+            .field("decimal", &self.decimal)
+            .finish()
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::logic::error::LogicError;
+    use crate::{CustomOperator, DataLogic};
+    use serde_json::json;
+    use std::sync::Arc;
+    use std::thread;
+
+    struct Double;
+
+    impl CustomOperator for Double {
+        fn evaluate<'a>(
+            &self,
+            args: &'a [DataValue<'a>],
+            arena: &'a DataArena,
+        ) -> Result<&'a DataValue<'a>> {
+            let n = args.first().and_then(|v| v.as_i64()).unwrap_or(0);
+            Ok(arena.alloc(DataValue::integer(n * 2)))
+        }
+    }
+
+    fn assert_send_sync<T: Send + Sync>() {}
+
+    #[test]
+    fn test_compiled_logic_is_send_sync() {
+        assert_send_sync::<CompiledLogic>();
+        assert_send_sync::<Arc<CompiledLogic>>();
+    }
+
+    #[test]
+    fn test_evaluate_in_scratch_arena() {
+        let dl = DataLogic::new();
+        let rule = dl
+            .compile(r#"{"if": [{">": [{"var": "a"}, 1]}, "big", "small"]}"#, None)
+            .unwrap();
+
+        let arena = DataArena::new();
+        let data = DataValue::from_json(&json!({"a": 5}), &arena);
+        let result = rule.evaluate(&data, &arena).unwrap();
+        assert_eq!(result.as_str(), Some("big"));
+
+        assert_eq!(rule.evaluate_json(&json!({"a": 0})).unwrap(), json!("small"));
+    }
+
+    #[test]
+    fn test_concurrent_evaluation() {
+        let dl = DataLogic::new();
+        let rule = dl
+            .compile(
+                r#"{"reduce": [{"var": "items"}, {"+": [{"var": "current"}, {"var": "accumulator"}]}, 0]}"#,
+                None,
+            )
+            .unwrap();
+
+        thread::scope(|scope| {
+            let handles: Vec<_> = (0..8)
+                .map(|n| {
+                    let rule = &rule;
+                    scope.spawn(move || {
+                        let arena = DataArena::new();
+                        let items: Vec<i64> = (0..=n * 100).collect();
+                        let data = DataValue::from_json(&json!({ "items": items }), &arena);
+                        for _ in 0..50 {
+                            let result = rule.evaluate(&data, &arena).unwrap();
+                            assert_eq!(result.as_i64(), Some((0..=n * 100).sum::<i64>()));
+                        }
+                    })
+                })
+                .collect();
+            for handle in handles {
+                handle.join().unwrap();
+            }
+        });
+    }
+
+    #[test]
+    fn test_snapshot_of_settings() {
+        let mut dl = DataLogic::new();
+        dl.register_operator("double", Box::new(Double));
+        dl.set_evaluation_limits(EvaluationLimits::new().with_max_steps(2));
+        let rule = dl.compile(r#"{"double": [{"var": "n"}]}"#, None).unwrap();
+
+        // The compiled rule does not depend on the instance it was compiled with
+        drop(dl);
+
+        assert_eq!(rule.evaluate_json(&json!({"n": 21})).unwrap(), json!(42));
+        assert_eq!(rule.evaluation_limits().max_steps, Some(2));
+
+        let rule = DataLogic::new()
+            .compile(r#"{"double": [{"var": "n"}]}"#, None)
+            .unwrap();
+        assert_eq!(
+            rule.evaluate_json(&json!({"n": 21})).map_err(LogicError::without_location),
+            Err(LogicError::operator_not_found("double"))
+        );
+    }
+
+    #[test]
+    fn test_compile_errors() {
+        let dl = DataLogic::new();
+        assert!(matches!(
+            dl.compile(r#"{"unknown_op": [1, 2]"#, None),
+            Err(LogicError::ParseError { .. })
+        ));
+    }
+}
@@ -0,0 +1,390 @@
+//! Common subexpression elimination.
+//!
+//! Generated rules often repeat an expensive expression, such as a total
+//! computed with `reduce`. This pass finds the operators that occur more than
+//! once in a rule and puts a `Token::Shared` in place of each of their
+//! occurrences, all with the same slot and expression. The evaluator memoizes the result of a shared expression for
+//! the context it was evaluated in, so inside an iterator it is evaluated once
+//! per element.
+//!
+//! Only expressions whose result depends on nothing but the current context
+//! are shared. Scope jumps, the `index` of an iteration, `try` and custom
+//! operators keep an expression from being shared.
+
+use std::collections::HashMap;
+
+use super::location::{SourceMap, for_each_child};
+use super::token::{OperatorType, Token};
+use crate::arena::DataArena;
+use crate::value::DataValue;
+
+/// Shares the operators that occur more than once in a rule.
+///
+/// The tokens rebuilt around the shared ones take the pointers of the tokens
+/// they replace in the source map.
+pub(crate) fn share_subexpressions<'a>(
+    root: &'a Token<'a>,
+    arena: &'a DataArena,
+    source_map: &mut SourceMap,
+) -> &'a Token<'a> {
+    let mut sharing = Sharing {
+        arena,
+        source_map,
+        forms: HashMap::new(),
+        nodes: HashMap::new(),
+        counts: HashMap::new(),
+        shared: HashMap::new(),
+        next_slot: 0,
+    };
+
+    sharing.identify(root);
+    sharing.count(root);
+    if sharing.counts.values().all(|&count| count < 2) {
+        return root;
+    }
+    sharing.rebuild(root)
+}
+
+#[derive(Clone, Copy)]
+struct Node {
+    /// Identifies the structure of the subtree.
+    id: usize,
+    /// Whether the subtree depends on nothing but the current context.
+    in_scope: bool,
+    /// Whether the subtree can be replaced by a shared token.
+    shareable: bool,
+}
+
+struct Sharing<'a, 'm> {
+    arena: &'a DataArena,
+    /// Pointers into the rule as it was written.
+    source_map: &'m mut SourceMap,
+    /// Ids of the distinct subtrees, by their canonical form.
+    forms: HashMap<String, usize>,
+    /// The node of each token, by address.
+    nodes: HashMap<usize, Node>,
+    /// Occurrences of each shareable subtree, by id. Occurrences within a
+    /// later occurrence of a shared subtree are not counted, as they are
+    /// replaced along with it.
+    counts: HashMap<usize, usize>,
+    /// The slot and the expression of each subtree that occurs more than
+    /// once, by id.
+    shared: HashMap<usize, (usize, &'a Token<'a>)>,
+    /// The next free slot for a memoized result.
+    next_slot: usize,
+}
+
+impl<'a> Sharing<'a, '_> {
+    /// Assigns each token the id of its structure and finds the ones that can be shared.
+    fn identify(&mut self, token: &'a Token<'a>) -> Node {
+        if let Some(node) = self.nodes.get(&token_address(token)) {
+            return *node;
+        }
+
+        let (form, in_scope) = match token {
+            Token::Literal(value) => (format!("L{:?}", value), true),
+            Token::Pattern(pattern) => (format!("P{:?}", pattern.as_str()), true),
+            Token::ArrayLiteral(items) => {
+                let (ids, in_scope) = self.identify_all(items);
+                (format!("A{:?}", ids), in_scope)
+            }
+            Token::Variable { path, default } => {
+                let default = default.map(|default| self.identify(default));
+                (
+                    format!("V{:?}{:?}", path, default.map(|node| node.id)),
+                    default.is_none_or(|node| node.in_scope),
+                )
+            }
+            Token::DynamicVariable { path_expr, default } => {
+                let path_expr = self.identify(path_expr);
+                let default = default.map(|default| self.identify(default));
+                (
+                    format!("D{}{:?}", path_expr.id, default.map(|node| node.id)),
+                    path_expr.in_scope && default.is_none_or(|node| node.in_scope),
+                )
+            }
+            Token::Operator { op_type, args } => {
+                let args_node = self.identify(args);
+                let in_scope = args_node.in_scope
+                    && match op_type {
+                        OperatorType::Val => val_stays_in_scope(args),
+                        // try changes the context and the path chain for its fallbacks
+                        OperatorType::Try => false,
+                        _ => true,
+                    };
+                (format!("O{:?}{}", op_type, args_node.id), in_scope)
+            }
+            // Custom operators may depend on more than their arguments
+            Token::CustomOperator { name, args } => {
+                let args_node = self.identify(args);
+                (format!("C{:?}{}", name, args_node.id), false)
+            }
+            // A rule that was shared before keeps its slots
+            Token::Shared { slot, expr } => {
+                self.next_slot = self.next_slot.max(slot + 1);
+                let node = Node {
+                    shareable: false,
+                    ..self.identify(expr)
+                };
+                self.nodes.insert(token_address(token), node);
+                return node;
+            }
+        };
+
+        let next_id = self.forms.len();
+        let node = Node {
+            id: *self.forms.entry(form).or_insert(next_id),
+            in_scope,
+            // Variables are looked up faster than a result is memoized
+            shareable: in_scope
+                && matches!(token, Token::Operator { op_type, .. } if *op_type != OperatorType::Val),
+        };
+        self.nodes.insert(token_address(token), node);
+        node
+    }
+
+    fn identify_all(&mut self, items: &[&'a Token<'a>]) -> (Vec<usize>, bool) {
+        let nodes: Vec<Node> = items.iter().map(|item| self.identify(item)).collect();
+        (
+            nodes.iter().map(|node| node.id).collect(),
+            nodes.iter().all(|node| node.in_scope),
+        )
+    }
+
+    /// Counts the occurrences of the shareable subtrees.
+    fn count(&mut self, token: &'a Token<'a>) {
+        let node = self.nodes[&token_address(token)];
+        if node.shareable {
+            let count = self.counts.entry(node.id).or_insert(0);
+            *count += 1;
+            // The subtrees of later occurrences are shared along with them
+            if *count > 1 {
+                return;
+            }
+        }
+
+        for_each_child(token, &mut |_, child| {
+            self.count(child);
+            false
+        });
+    }
+
+    /// Puts the shared tokens in place of the subtrees that occur more than once.
+    fn rebuild(&mut self, token: &'a Token<'a>) -> &'a Token<'a> {
+        let rebuilt = self.rebuild_token(token);
+        self.source_map.replace(token, rebuilt);
+        rebuilt
+    }
+
+    fn rebuild_token(&mut self, token: &'a Token<'a>) -> &'a Token<'a> {
+        let node = self.nodes[&token_address(token)];
+        if !node.shareable || self.counts[&node.id] < 2 {
+            return self.rebuild_children(token);
+        }
+        // Each occurrence gets a token of its own, so errors can tell them apart
+        let (slot, expr) = match self.shared.get(&node.id) {
+            Some(&shared) => shared,
+            None => {
+                let expr = self.rebuild_children(token);
+                self.source_map.replace(token, expr);
+                let shared = (self.next_slot, expr);
+                self.next_slot += 1;
+                self.shared.insert(node.id, shared);
+                shared
+            }
+        };
+        self.arena.alloc(Token::Shared { slot, expr })
+    }
+
+    fn rebuild_children(&mut self, token: &'a Token<'a>) -> &'a Token<'a> {
+        match token {
+            Token::Literal(_) | Token::Shared { .. } => token,
+            Token::Pattern(_) => token,
+            Token::ArrayLiteral(items) => {
+                let rebuilt: Vec<_> = items.iter().map(|item| self.rebuild(item)).collect();
+                if unchanged(items, &rebuilt) {
+                    token
+                } else {
+                    self.arena
+                        .alloc(Token::ArrayLiteral(self.arena.vec_into_slice(rebuilt)))
+                }
+            }
+            Token::Variable { path, default } => match default {
+                Some(default) => {
+                    let rebuilt = self.rebuild(default);
+                    if std::ptr::eq(rebuilt, *default) {
+                        token
+                    } else {
+                        self.arena.alloc(Token::variable(path, Some(rebuilt)))
+                    }
+                }
+                None => token,
+            },
+            Token::DynamicVariable { path_expr, default } => {
+                let rebuilt_path = self.rebuild(path_expr);
+                let rebuilt_default = default.map(|default| self.rebuild(default));
+                if std::ptr::eq(rebuilt_path, *path_expr)
+                    && unchanged(default.as_slice(), rebuilt_default.as_slice())
+                {
+                    token
+                } else {
+                    self.arena
+                        .alloc(Token::dynamic_variable(rebuilt_path, rebuilt_default))
+                }
+            }
+            Token::Operator { op_type, args } => {
+                let rebuilt = self.rebuild(args);
+                if std::ptr::eq(rebuilt, *args) {
+                    token
+                } else {
+                    self.arena.alloc(Token::operator(*op_type, rebuilt))
+                }
+            }
+            Token::CustomOperator { name, args } => {
+                let rebuilt = self.rebuild(args);
+                if std::ptr::eq(rebuilt, *args) {
+                    token
+                } else {
+                    self.arena.alloc(Token::custom_operator(name, rebuilt))
+                }
+            }
+        }
+    }
+}
+
+/// Returns true if `val` reads a plain path from the current context.
+///
+/// Scope jumps read an enclosing context, `index` reads the position in the
+/// enclosing iteration and computed paths may turn out to be either.
+fn val_stays_in_scope(args: &Token) -> bool {
+    let segments = match args {
+        Token::ArrayLiteral(items) => items.iter().map(|item| item.as_literal()).collect(),
+        Token::Literal(DataValue::Array(items)) => items.iter().map(Some).collect(),
+        token => vec![token.as_literal()],
+    };
+
+    segments.iter().all(|segment| match segment {
+        Some(DataValue::String(key)) => *key != "index",
+        Some(DataValue::Number(_)) => true,
+        _ => false,
+    })
+}
+
+fn unchanged(tokens: &[&Token], rebuilt: &[&Token]) -> bool {
+    tokens
+        .iter()
+        .zip(rebuilt)
+        .all(|(token, rebuilt)| std::ptr::eq(*token, *rebuilt))
+}
+
+fn token_address(token: &Token) -> usize {
+    token as *const Token as usize
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::{DataLogic, EvaluationLimits, ToJson};
+    use serde_json::json;
+
+    /// Returns the slots of the shared expressions in a rule.
+    fn shared_slots(token: &Token, found: &mut Vec<usize>) {
+        match token {
+            Token::Shared { slot, expr } => {
+                if !found.contains(slot) {
+                    found.push(*slot);
+                }
+                shared_slots(expr, found);
+            }
+            Token::ArrayLiteral(items) => items.iter().for_each(|item| shared_slots(item, found)),
+            Token::Operator { args, .. } | Token::CustomOperator { args, .. } => {
+                shared_slots(args, found)
+            }
+            _ => {}
+        }
+    }
+
+    fn shared_count(rule: &str) -> usize {
+        let dl = DataLogic::new();
+        let logic = dl.parse_logic(rule, None).unwrap();
+        let mut found = Vec::new();
+        shared_slots(logic.root(), &mut found);
+        found.len()
+    }
+
+    const DOUBLED: &str = r#"{"map": [{"var": "items"}, {"*": [{"var": ""}, 2]}]}"#;
+
+    #[test]
+    fn test_shares_repeated_expressions() {
+        let rule = format!(r#"{{"cat": [{DOUBLED}, {{"merge": [{DOUBLED}, {DOUBLED}]}}]}}"#);
+        assert_eq!(shared_count(&rule), 1);
+
+        // The rule reads the same, and evaluates to the same result
+        let dl = DataLogic::new();
+        let logic = dl.parse_logic(&rule, None).unwrap();
+        let doubled: serde_json::Value = serde_json::from_str(DOUBLED).unwrap();
+        assert_eq!(
+            logic.to_json(),
+            json!({"cat": [doubled, {"merge": [doubled, doubled]}]})
+        );
+        assert_eq!(
+            dl.evaluate_str(&rule, r#"{"items": [1, 2]}"#, None)
+                .unwrap(),
+            json!("242424")
+        );
+
+        // Only the outermost of nested repeated expressions is shared
+        let rule = format!(r#"{{"+": [{{"max": {DOUBLED}}}, {{"max": {DOUBLED}}}]}}"#);
+        assert_eq!(shared_count(&rule), 1);
+        assert_eq!(shared_count(&format!(r#"{{"cat": [{DOUBLED}, 1]}}"#)), 0);
+    }
+
+    #[test]
+    fn test_evaluates_once() {
+        let items: Vec<usize> = (0..50).collect();
+        let data = json!({ "items": items }).to_string();
+
+        // Each evaluation of the map takes more than half of the steps
+        let mut dl = DataLogic::new();
+        dl.set_evaluation_limits(EvaluationLimits::new().with_max_steps(200));
+        let single = format!(r#"{{"+": [{{"max": {DOUBLED}}}, 1]}}"#);
+        assert_eq!(dl.evaluate_str(&single, &data, None).unwrap(), json!(99));
+
+        let repeated = format!(r#"{{"+": [{{"max": {DOUBLED}}}, {{"min": {DOUBLED}}}]}}"#);
+        assert_eq!(dl.evaluate_str(&repeated, &data, None).unwrap(), json!(98));
+    }
+
+    #[test]
+    fn test_memoized_per_element() {
+        let rule = r#"{"map": [{"var": "rows"}, {"-": [{"*": [{"var": "x"}, {"var": "x"}]}, {"*": [{"var": "x"}, {"var": "x"}]}, {"*": [{"var": "x"}, 2]}]}]}"#;
+        assert_eq!(shared_count(rule), 1);
+
+        let dl = DataLogic::new();
+        let result = dl
+            .evaluate_str(rule, r#"{"rows": [{"x": 1}, {"x": 2}, {"x": 3}]}"#, None)
+            .unwrap();
+        assert_eq!(result, json!([-2, -4, -6]));
+
+        // The same rule evaluated against other data does not reuse results
+        let result = dl
+            .evaluate_str(rule, r#"{"rows": [{"x": 5}]}"#, None)
+            .unwrap();
+        assert_eq!(result, json!([-10]));
+    }
+
+    #[test]
+    fn test_expressions_not_shared() {
+        for expr in [
+            // Scope jumps and the index depend on the enclosing iteration
+            r#"{"+": [{"val": [[1], "x"]}, 1]}"#,
+            r#"{"+": [{"val": "index"}, 1]}"#,
+            r#"{"+": [{"val": {"var": "key"}}, 1]}"#,
+            r#"{"try": [{"var": "x"}, 1]}"#,
+            // Variables are not worth memoizing
+            r#"{"val": "x"}"#,
+        ] {
+            let rule = format!(r#"{{"map": [{{"var": "rows"}}, {{"cat": [{expr}, {expr}]}}]}}"#);
+            assert_eq!(shared_count(&rule), 0, "{}", expr);
+        }
+    }
+}
@@ -3,7 +3,7 @@ use crate::arena::DataArena;
 use crate::builder::factory::RuleFactory;
 use crate::builder::RuleBuilder;
 use crate::logic::{evaluate, Logic};
-use crate::value::{DataValue, FromJson, ToJson};
+use crate::value::{DataValue, FromJson, OwnedDataValue, ToJson};
 
 /// The main engine implementation for DataLogic expressions.
 ///
@@ -28,27 +28,47 @@ impl DataLogicCore {
     }
 
     /// Creates a rule builder that allocates in this instance's arena.
-    pub fn builder(&self) -> RuleBuilder {
+    pub fn builder(&self) -> RuleBuilder<'_> {
         RuleBuilder::new(&self.arena)
     }
 
     /// Creates a rule factory that allocates in this instance's arena.
-    pub fn factory(&self) -> RuleFactory {
+    pub fn factory(&self) -> RuleFactory<'_> {
         RuleFactory::new(&self.arena)
     }
 
     /// Evaluates a rule created with the builder API.
     pub fn apply(&self, logic: &Logic, data: &serde_json::Value) -> Result<serde_json::Value> {
-        // Convert input data to DataValue
-        let data_value = DataValue::from_json(data, &self.arena);
-        self.arena.set_current_context(&data_value, &DataValue::String("$"));
-        self.arena.set_root_context(&data_value);
-        
-        // Evaluate the rule
-        let result = evaluate(logic.root(), &self.arena)?;
+        self.evaluate_data(logic, data, |result| result.to_json())
+    }
+
+    /// Evaluates a rule created with the builder API, returning an owned value.
+    ///
+    /// The result does not borrow the arena, so it stays valid after the
+    /// arena is dropped.
+    pub fn evaluate_owned(
+        &self,
+        logic: &Logic,
+        data: &serde_json::Value,
+    ) -> Result<OwnedDataValue> {
+        self.evaluate_data(logic, data, |result| result.to_owned_value())
+    }
+
+    /// Evaluates a rule with JSON data as the context, and converts the result.
+    fn evaluate_data<T>(
+        &self,
+        logic: &Logic,
+        data: &serde_json::Value,
+        convert: impl FnOnce(&DataValue) -> T,
+    ) -> Result<T> {
+        // The data lives in the arena, like the contexts that refer to it
+        let data_value = self.arena.alloc(DataValue::from_json(data, &self.arena));
+        self.arena.clear_path_chain();
+        self.arena.set_current_context(data_value, &DataValue::String("$"));
+        self.arena.set_root_context(data_value);
 
-        // Convert the result back to a JSON value
-        Ok(result.to_json())
+        let result = evaluate(logic.root(), &self.arena)?;
+        Ok(convert(result))
     }
 }
 
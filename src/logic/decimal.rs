@@ -0,0 +1,140 @@
+//! Decimal arithmetic for logic expressions.
+//!
+//! This module provides the `DecimalArithmetic` configuration, which makes
+//! numbers with a fractional part exact decimals rather than floating point
+//! numbers, and the `Rounding` strategies it uses.
+
+use rust_decimal::{Decimal, RoundingStrategy};
+
+/// Settings for exact decimal arithmetic.
+///
+/// With decimal arithmetic, numbers with a fractional part are parsed as
+/// decimals, so `0.1` is exactly one tenth, and `+`, `-`, `*`, `/`, `%`,
+/// `reduce` and `round` compute with decimals. Results with more than
+/// `scale` decimal places, such as the result of `{"/": [1, 3]}`, are
+/// rounded with `rounding`.
+///
+/// # Examples
+///
+/// ```
+/// use datalogic_rs::{DataLogic, DecimalArithmetic, Rounding};
+/// use serde_json::json;
+///
+/// let mut dl = DataLogic::new();
+/// assert_eq!(
+///     dl.evaluate_str(r#"{"+": [0.1, 0.2]}"#, "null", None).unwrap(),
+///     json!(0.30000000000000004)
+/// );
+///
+/// dl.set_decimal_arithmetic(Some(
+///     DecimalArithmetic::new().with_scale(4).with_rounding(Rounding::HalfUp),
+/// ));
+/// assert_eq!(
+///     dl.evaluate_str(r#"{"+": [0.1, 0.2]}"#, "null", None).unwrap(),
+///     json!(0.3)
+/// );
+/// assert_eq!(
+///     dl.evaluate_str(r#"{"/": [2, 3]}"#, "null", None).unwrap(),
+///     json!(0.6667)
+/// );
+/// ```
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub struct DecimalArithmetic {
+    /// Maximum number of decimal places of a result, at most 28.
+    pub scale: u32,
+    /// How results with more decimal places are rounded.
+    pub rounding: Rounding,
+}
+
+impl Default for DecimalArithmetic {
+    fn default() -> Self {
+        Self {
+            scale: Decimal::MAX_SCALE,
+            rounding: Rounding::HalfEven,
+        }
+    }
+}
+
+impl DecimalArithmetic {
+    /// Creates settings that keep up to 28 decimal places and round half
+    /// to even.
+    pub fn new() -> Self {
+        Self::default()
+    }
+
+    /// Sets the maximum number of decimal places of a result.
+    pub fn with_scale(mut self, scale: u32) -> Self {
+        self.scale = scale.min(Decimal::MAX_SCALE);
+        self
+    }
+
+    /// Sets how results with more decimal places are rounded.
+    pub fn with_rounding(mut self, rounding: Rounding) -> Self {
+        self.rounding = rounding;
+        self
+    }
+
+    /// Rounds a result to the maximum number of decimal places.
+    pub(crate) fn round(&self, value: Decimal) -> Decimal {
+        if value.scale() > self.scale {
+            value.round_dp_with_strategy(self.scale, self.rounding.strategy())
+        } else {
+            value
+        }
+    }
+}
+
+/// How a decimal is rounded to fewer decimal places.
+#[derive(Debug, Clone, Copy, Default, PartialEq, Eq)]
+pub enum Rounding {
+    /// Round to the nearest value, and halves to the even neighbour.
+    ///
+    /// This is also known as banker's rounding.
+    #[default]
+    HalfEven,
+    /// Round to the nearest value, and halves away from zero.
+    HalfUp,
+    /// Round to the nearest value, and halves towards zero.
+    HalfDown,
+    /// Round away from zero.
+    Up,
+    /// Round towards zero, which truncates.
+    Down,
+    /// Round towards positive infinity.
+    Ceiling,
+    /// Round towards negative infinity.
+    Floor,
+}
+
+impl Rounding {
+    pub(crate) fn strategy(self) -> RoundingStrategy {
+        match self {
+            Rounding::HalfEven => RoundingStrategy::MidpointNearestEven,
+            Rounding::HalfUp => RoundingStrategy::MidpointAwayFromZero,
+            Rounding::HalfDown => RoundingStrategy::MidpointTowardZero,
+            Rounding::Up => RoundingStrategy::AwayFromZero,
+            Rounding::Down => RoundingStrategy::ToZero,
+            Rounding::Ceiling => RoundingStrategy::ToPositiveInfinity,
+            Rounding::Floor => RoundingStrategy::ToNegativeInfinity,
+        }
+    }
+
+    /// Rounds a decimal to `places` decimal places, or to a multiple of a
+    /// power of ten if `places` is negative.
+    ///
+    /// Returns `None` if the result is out of the range of decimals.
+    pub(crate) fn round(self, value: Decimal, places: i64) -> Option<Decimal> {
+        if places >= 0 {
+            let places = places.min(Decimal::MAX_SCALE as i64) as u32;
+            return Some(value.round_dp_with_strategy(places, self.strategy()));
+        }
+
+        let mut factor = Decimal::ONE;
+        for _ in 0..places.unsigned_abs() {
+            factor = factor.checked_mul(Decimal::TEN)?;
+        }
+        (value / factor)
+            .round_dp_with_strategy(0, self.strategy())
+            .checked_mul(factor)
+    }
+}
@@ -2,10 +2,13 @@
 //!
 //! This module provides error types for operations involving logic expressions.
 
+use serde::ser::{Serialize, SerializeMap, Serializer};
 use std::error::Error;
 use std::fmt;
 use std::result;
 
+use crate::value::DataValue;
+
 /// A specialized Result type for logic operations.
 pub type Result<T> = result::Result<T, LogicError>;
 
@@ -16,6 +19,11 @@ pub enum LogicError {
     ParseError {
         /// The reason for the parsing failure.
         reason: String,
+        /// The position of the offending fragment, if the error was found in
+        /// a source string.
+        position: Option<SourcePosition>,
+        /// Where in the rule the error was raised, if known.
+        location: Option<Box<ErrorLocation>>,
     },
 
     /// Error accessing a variable.
@@ -24,58 +32,310 @@ pub enum LogicError {
         path: String,
         /// The reason for the variable access failure.
         reason: String,
+        /// Where in the rule the error was raised, if known.
+        location: Option<Box<ErrorLocation>>,
     },
 
     /// Error indicating that an operator is not found.
     OperatorNotFoundError {
         /// The operator that was not found.
         operator: String,
+        /// Where in the rule the error was raised, if known.
+        location: Option<Box<ErrorLocation>>,
+    },
+
+    /// Error indicating that an operator is forbidden by the operator policy.
+    OperatorNotAllowedError {
+        /// The operator that is not allowed.
+        operator: String,
+        /// JSON pointer to the operator's position in the rule.
+        path: String,
+    },
+
+    /// Error indicating that an evaluation limit was exceeded.
+    LimitExceededError {
+        /// The name of the limit, such as `max_depth`.
+        limit: String,
+        /// The configured value of the limit.
+        max: usize,
+        /// Where in the rule the error was raised, if known.
+        location: Option<Box<ErrorLocation>>,
     },
 
-    NaNError,
+    /// Error indicating that the evaluation was cancelled.
+    Cancelled,
 
-    InvalidArgumentsError,
+    /// Error indicating that the evaluation deadline has passed.
+    DeadlineExceeded,
+
+    /// Error indicating that an operator could not turn an argument into a number.
+    NaNError {
+        /// The operator that raised the error.
+        operator: String,
+        /// The index of the offending argument, if a single argument is at fault.
+        index: Option<usize>,
+        /// The value the operator expected, such as `number`.
+        expected: String,
+        /// The type of the value it got, as returned by `DataValue::type_name`.
+        actual: String,
+        /// Where in the rule the error was raised, if known.
+        location: Option<Box<ErrorLocation>>,
+    },
+
+    /// Error indicating that an operator got the wrong number or type of arguments.
+    InvalidArgumentsError {
+        /// The operator that raised the error.
+        operator: String,
+        /// The index of the offending argument, or `None` if the number of
+        /// arguments is wrong.
+        index: Option<usize>,
+        /// The argument the operator expected, such as `array` or `2 arguments`.
+        expected: String,
+        /// The type of the argument it got, as returned by
+        /// `DataValue::type_name`, or the number of arguments.
+        actual: String,
+        /// Where in the rule the error was raised, if known.
+        location: Option<Box<ErrorLocation>>,
+    },
 
     /// Error thrown by the throw operator.
     ThrownError {
         /// The type or value of the error.
         r#type: String,
+        /// Where in the rule the error was raised, if known.
+        location: Option<Box<ErrorLocation>>,
     },
 
     /// A custom error with a message.
     Custom(String),
 }
 
+/// Where in a rule an error was raised.
+///
+/// Evaluating a rule with `DataLogic` or `CompiledLogic` sets the location
+/// of the errors that have a `location` field, as does parsing a rule.
+/// Operators such as `try` see the error before it has a location.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct ErrorLocation {
+    /// JSON pointer to the expression in the rule, such as `/and/2/map/1`.
+    /// The pointer is empty for the root of the rule.
+    pub path: String,
+    /// The position of the expression, if the error was found while parsing
+    /// a source string.
+    pub position: Option<SourcePosition>,
+}
+
+/// The position of a fragment in the source string of a rule.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct SourcePosition {
+    /// The line of the start of the fragment, starting at 1.
+    pub line: usize,
+    /// The column of the start of the fragment in characters, starting at 1.
+    pub column: usize,
+    /// The offending fragment of the source, which is empty at the end of
+    /// the input.
+    pub fragment: String,
+    /// The source line containing the start of the fragment.
+    pub source_line: String,
+}
+
+impl SourcePosition {
+    /// Renders the source line with the fragment underlined by carets.
+    ///
+    /// ```text
+    ///  --> line 2, column 11
+    ///   |
+    /// 2 |   {"var": true}
+    ///   |           ^^^^
+    /// ```
+    pub fn render(&self) -> String {
+        let number = self.line.to_string();
+        let gutter = " ".repeat(number.len());
+
+        // Keep tabs so the carets line up with the source line
+        let indent: String = self
+            .source_line
+            .chars()
+            .take(self.column - 1)
+            .map(|c| if c == '\t' { '\t' } else { ' ' })
+            .collect();
+        let rest = self.source_line.chars().count().saturating_sub(self.column - 1);
+        let width = self
+            .fragment
+            .lines()
+            .next()
+            .map_or(0, |line| line.chars().count())
+            .min(rest)
+            .max(1);
+
+        format!(
+            "{gutter}--> line {}, column {}\n{gutter} |\n{number} | {}\n{gutter} | {indent}{}",
+            self.line,
+            self.column,
+            self.source_line,
+            "^".repeat(width),
+        )
+    }
+}
+
 impl fmt::Display for LogicError {
     fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        self.fmt_message(f)?;
+        match self.location_field() {
+            Some(location) if location.path.is_empty() => write!(f, " at the rule root"),
+            Some(location) => write!(f, " at '{}'", location.path),
+            None => Ok(()),
+        }
+    }
+}
+
+impl LogicError {
+    /// Writes the message of the error, without its location.
+    fn fmt_message(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
         match self {
-            LogicError::ParseError { reason } => {
-                write!(f, "Parse error: {}", reason)
+            LogicError::ParseError {
+                reason, position, ..
+            } => {
+                write!(f, "Parse error: {}", reason)?;
+                if let Some(position) = position {
+                    write!(f, " at line {}, column {}", position.line, position.column)?;
+                }
+                Ok(())
             }
-            LogicError::VariableError { path, reason } => {
+            LogicError::VariableError { path, reason, .. } => {
                 write!(f, "Variable '{}' error: {}", path, reason)
             }
-            LogicError::NaNError => {
-                write!(f, "NaN error")
+            LogicError::NaNError {
+                operator,
+                index,
+                expected,
+                actual,
+                ..
+            } => {
+                write!(f, "NaN error in '{}': ", operator)?;
+                write_mismatch(f, *index, expected, actual)
             }
-            LogicError::InvalidArgumentsError => {
-                write!(f, "Invalid arguments error")
+            LogicError::InvalidArgumentsError {
+                operator,
+                index,
+                expected,
+                actual,
+                ..
+            } => {
+                write!(f, "Invalid arguments error in '{}': ", operator)?;
+                write_mismatch(f, *index, expected, actual)
             }
-            LogicError::ThrownError { r#type } => {
+            LogicError::ThrownError { r#type, .. } => {
                 write!(f, "Thrown error: {}", r#type)
             }
             LogicError::Custom(msg) => {
                 write!(f, "{}", msg)
             }
-            LogicError::OperatorNotFoundError { operator } => {
+            LogicError::OperatorNotFoundError { operator, .. } => {
                 write!(f, "Operator '{}' not found", operator)
             }
+            LogicError::Cancelled => {
+                write!(f, "Evaluation cancelled")
+            }
+            LogicError::DeadlineExceeded => {
+                write!(f, "Evaluation deadline exceeded")
+            }
+            LogicError::LimitExceededError { limit, max, .. } => {
+                write!(f, "Evaluation limit exceeded: {} ({})", limit, max)
+            }
+            LogicError::OperatorNotAllowedError { operator, path } => {
+                if path.is_empty() {
+                    write!(f, "Operator '{}' is not allowed at the rule root", operator)
+                } else {
+                    write!(f, "Operator '{}' is not allowed at '{}'", operator, path)
+                }
+            }
         }
     }
 }
 
+/// Writes what an operator expected and what it got instead.
+fn write_mismatch(
+    f: &mut fmt::Formatter<'_>,
+    index: Option<usize>,
+    expected: &str,
+    actual: &str,
+) -> fmt::Result {
+    match index {
+        Some(index) => write!(f, "argument {} expected {}, got {}", index, expected, actual),
+        None => write!(f, "expected {}, got {}", expected, actual),
+    }
+}
+
 impl Error for LogicError {}
 
+/// Serializes the error as an object with its `code`, its `message` and the
+/// fields of the variant.
+///
+/// JSON pointers into the rule are serialized as `location`.
+impl Serialize for LogicError {
+    fn serialize<S: Serializer>(&self, serializer: S) -> result::Result<S::Ok, S::Error> {
+        let mut map = serializer.serialize_map(None)?;
+        map.serialize_entry("code", self.code())?;
+        map.serialize_entry("message", &self.to_string())?;
+        if let Some(location) = self.location() {
+            map.serialize_entry("location", location)?;
+        }
+        if let Some(position) = self.position() {
+            map.serialize_entry("line", &position.line)?;
+            map.serialize_entry("column", &position.column)?;
+            map.serialize_entry("fragment", &position.fragment)?;
+        }
+
+        match self {
+            LogicError::ParseError { reason, .. } => {
+                map.serialize_entry("reason", reason)?;
+            }
+            LogicError::VariableError { path, reason, .. } => {
+                map.serialize_entry("path", path)?;
+                map.serialize_entry("reason", reason)?;
+            }
+            LogicError::OperatorNotFoundError { operator, .. } => {
+                map.serialize_entry("operator", operator)?;
+            }
+            // The path is serialized as the location above
+            LogicError::OperatorNotAllowedError { operator, .. } => {
+                map.serialize_entry("operator", operator)?;
+            }
+            LogicError::LimitExceededError { limit, max, .. } => {
+                map.serialize_entry("limit", limit)?;
+                map.serialize_entry("max", max)?;
+            }
+            LogicError::NaNError {
+                operator,
+                index,
+                expected,
+                actual,
+                ..
+            }
+            | LogicError::InvalidArgumentsError {
+                operator,
+                index,
+                expected,
+                actual,
+                ..
+            } => {
+                map.serialize_entry("operator", operator)?;
+                if let Some(index) = index {
+                    map.serialize_entry("index", index)?;
+                }
+                map.serialize_entry("expected", expected)?;
+                map.serialize_entry("actual", actual)?;
+            }
+            LogicError::ThrownError { r#type, .. } => {
+                map.serialize_entry("type", r#type)?;
+            }
+            LogicError::Cancelled | LogicError::DeadlineExceeded | LogicError::Custom(_) => {}
+        }
+        map.end()
+    }
+}
+
 /// Extension methods for Result<T, LogicError>.
 pub trait LogicResultExt<T> {
     /// Adds context to an error with a custom message.
@@ -101,6 +361,8 @@ impl LogicError {
     pub fn parse_error(reason: impl Into<String>) -> Self {
         LogicError::ParseError {
             reason: reason.into(),
+            position: None,
+            location: None,
         }
     }
 
@@ -109,6 +371,15 @@ impl LogicError {
         LogicError::VariableError {
             path: path.into(),
             reason: reason.into(),
+            location: None,
+        }
+    }
+
+    /// Creates an error for an operator that is neither built in nor registered.
+    pub fn operator_not_found(operator: impl Into<String>) -> Self {
+        LogicError::OperatorNotFoundError {
+            operator: operator.into(),
+            location: None,
         }
     }
 
@@ -116,6 +387,7 @@ impl LogicError {
     pub fn thrown_error(r#type: impl Into<String>) -> Self {
         LogicError::ThrownError {
             r#type: r#type.into(),
+            location: None,
         }
     }
 
@@ -123,6 +395,189 @@ impl LogicError {
     pub fn custom(message: impl Into<String>) -> Self {
         LogicError::Custom(message.into())
     }
+
+    /// Creates a NaN error for an argument that cannot be turned into a number.
+    pub fn nan(operator: impl Into<String>, index: usize, value: &DataValue) -> Self {
+        LogicError::NaNError {
+            operator: operator.into(),
+            index: Some(index),
+            expected: "number".to_string(),
+            actual: value.type_name().to_string(),
+            location: None,
+        }
+    }
+
+    /// Creates a NaN error for a division or modulo by zero.
+    pub fn division_by_zero(operator: impl Into<String>, index: usize) -> Self {
+        LogicError::NaNError {
+            operator: operator.into(),
+            index: Some(index),
+            expected: "non-zero number".to_string(),
+            actual: "zero".to_string(),
+            location: None,
+        }
+    }
+
+    /// Creates a NaN error for a result that is not a finite number, such as
+    /// the square root of a negative number or a power that overflows.
+    pub fn not_finite(operator: impl Into<String>, index: Option<usize>, result: f64) -> Self {
+        LogicError::NaNError {
+            operator: operator.into(),
+            index,
+            expected: "finite number".to_string(),
+            actual: if result.is_nan() { "NaN" } else { "infinity" }.to_string(),
+            location: None,
+        }
+    }
+
+    /// Creates an invalid arguments error for an argument of the wrong type.
+    pub fn invalid_argument(
+        operator: impl Into<String>,
+        index: usize,
+        expected: impl Into<String>,
+        value: &DataValue,
+    ) -> Self {
+        LogicError::InvalidArgumentsError {
+            operator: operator.into(),
+            index: Some(index),
+            expected: expected.into(),
+            actual: value.type_name().to_string(),
+            location: None,
+        }
+    }
+
+    /// Creates an invalid arguments error for a wrong number of arguments.
+    ///
+    /// `expected` describes the accepted counts, such as `"2 arguments"`.
+    pub fn argument_count(
+        operator: impl Into<String>,
+        expected: impl Into<String>,
+        actual: usize,
+    ) -> Self {
+        LogicError::InvalidArgumentsError {
+            operator: operator.into(),
+            index: None,
+            expected: expected.into(),
+            actual: match actual {
+                1 => "1 argument".to_string(),
+                n => format!("{} arguments", n),
+            },
+            location: None,
+        }
+    }
+
+    /// Returns a stable, machine-readable code for the kind of error.
+    pub fn code(&self) -> &'static str {
+        match self {
+            LogicError::ParseError { .. } => "parse_error",
+            LogicError::VariableError { .. } => "variable_error",
+            LogicError::OperatorNotFoundError { .. } => "operator_not_found",
+            LogicError::OperatorNotAllowedError { .. } => "operator_not_allowed",
+            LogicError::LimitExceededError { .. } => "limit_exceeded",
+            LogicError::Cancelled => "cancelled",
+            LogicError::DeadlineExceeded => "deadline_exceeded",
+            LogicError::NaNError { .. } => "nan",
+            LogicError::InvalidArgumentsError { .. } => "invalid_arguments",
+            LogicError::ThrownError { .. } => "thrown",
+            LogicError::Custom(_) => "custom",
+        }
+    }
+
+    /// Returns the JSON pointer to the expression that raised the error, if
+    /// the error has a location.
+    ///
+    /// Errors without a `location` field, such as `Cancelled` or `Custom`,
+    /// have no location, except `OperatorNotAllowedError`, whose location is
+    /// its path.
+    pub fn location(&self) -> Option<&str> {
+        match self {
+            LogicError::OperatorNotAllowedError { path, .. } => Some(path),
+            error => error.location_field().map(|location| location.path.as_str()),
+        }
+    }
+
+    /// Returns the position in the source string of an error raised while
+    /// parsing it, if known.
+    pub fn position(&self) -> Option<&SourcePosition> {
+        match self {
+            LogicError::ParseError {
+                position: Some(position),
+                ..
+            } => Some(position),
+            error => error.location_field()?.position.as_ref(),
+        }
+    }
+
+    /// Returns the error message, followed by the offending source line with
+    /// the fragment underlined if the error has a source position.
+    pub fn diagnostic(&self) -> String {
+        match (self, self.position()) {
+            (LogicError::ParseError { reason, .. }, Some(position)) => {
+                format!("Parse error: {}\n{}", reason, position.render())
+            }
+            (error, Some(position)) => {
+                let error = error.clone().without_location();
+                format!("{}\n{}", error, position.render())
+            }
+            (_, None) => self.to_string(),
+        }
+    }
+
+    /// Sets the location of an error that has none yet to the JSON pointer
+    /// of the expression that raised it.
+    ///
+    /// Errors without a `location` field are returned unchanged.
+    pub(crate) fn located(mut self, path: String) -> Self {
+        if let Some(location @ None) = self.location_slot() {
+            *location = Some(Box::new(ErrorLocation {
+                path,
+                position: None,
+            }));
+        }
+        self
+    }
+
+    /// Returns the error without its location.
+    pub fn without_location(mut self) -> LogicError {
+        if let Some(location) = self.location_slot() {
+            *location = None;
+        }
+        self
+    }
+
+    /// Returns the location field of the error, if it is set.
+    fn location_field(&self) -> Option<&ErrorLocation> {
+        match self {
+            LogicError::ParseError { location, .. }
+            | LogicError::VariableError { location, .. }
+            | LogicError::OperatorNotFoundError { location, .. }
+            | LogicError::LimitExceededError { location, .. }
+            | LogicError::NaNError { location, .. }
+            | LogicError::InvalidArgumentsError { location, .. }
+            | LogicError::ThrownError { location, .. } => location.as_deref(),
+            LogicError::OperatorNotAllowedError { .. }
+            | LogicError::Cancelled
+            | LogicError::DeadlineExceeded
+            | LogicError::Custom(_) => None,
+        }
+    }
+
+    /// Returns the location field of the error, if it has one.
+    pub(crate) fn location_slot(&mut self) -> Option<&mut Option<Box<ErrorLocation>>> {
+        match self {
+            LogicError::ParseError { location, .. }
+            | LogicError::VariableError { location, .. }
+            | LogicError::OperatorNotFoundError { location, .. }
+            | LogicError::LimitExceededError { location, .. }
+            | LogicError::NaNError { location, .. }
+            | LogicError::InvalidArgumentsError { location, .. }
+            | LogicError::ThrownError { location, .. } => Some(location),
+            LogicError::OperatorNotAllowedError { .. }
+            | LogicError::Cancelled
+            | LogicError::DeadlineExceeded
+            | LogicError::Custom(_) => None,
+        }
+    }
 }
 
 #[cfg(test)]
@@ -133,6 +588,8 @@ mod tests {
     fn test_parse_error() {
         let err = LogicError::ParseError {
             reason: "unexpected token".to_string(),
+            position: None,
+            location: None,
         };
         assert_eq!(err.to_string(), "Parse error: unexpected token");
     }
@@ -142,14 +599,112 @@ mod tests {
         let err = LogicError::VariableError {
             path: "user.age".to_string(),
             reason: "not found".to_string(),
+            location: None,
         };
         assert_eq!(err.to_string(), "Variable 'user.age' error: not found");
     }
 
+    #[test]
+    fn test_operator_not_allowed_error() {
+        let err = LogicError::OperatorNotAllowedError {
+            operator: "throw".to_string(),
+            path: "/and/1".to_string(),
+        };
+        assert_eq!(err.to_string(), "Operator 'throw' is not allowed at '/and/1'");
+
+        let err = LogicError::OperatorNotAllowedError {
+            operator: "throw".to_string(),
+            path: String::new(),
+        };
+        assert_eq!(err.to_string(), "Operator 'throw' is not allowed at the rule root");
+    }
+
+    #[test]
+    fn test_argument_errors() {
+        let err = LogicError::nan("+", 1, &DataValue::String("abc"));
+        assert_eq!(err.code(), "nan");
+        assert_eq!(err.to_string(), "NaN error in '+': argument 1 expected number, got string");
+
+        let err = LogicError::argument_count("substr", "2 or 3 arguments", 1);
+        assert_eq!(err.code(), "invalid_arguments");
+        assert_eq!(
+            err.to_string(),
+            "Invalid arguments error in 'substr': expected 2 or 3 arguments, got 1 argument"
+        );
+    }
+
+    #[test]
+    fn test_operator_error_fields() {
+        let dl = crate::DataLogic::new();
+        let err = dl
+            .evaluate_str(r#"{"map": [{"var": "n"}, 1]}"#, r#"{"n": 5}"#, None)
+            .unwrap_err();
+        assert_eq!(err.code(), "invalid_arguments");
+        assert_eq!(err.location(), Some(""));
+        assert_eq!(
+            err.without_location(),
+            LogicError::InvalidArgumentsError {
+                operator: "map".to_string(),
+                index: Some(0),
+                expected: "array".to_string(),
+                actual: "number".to_string(),
+                location: None,
+            }
+        );
+
+        // The error raised by the operator is matchable as it is
+        let err = dl.evaluate_str(r#"{"*": [2, "x"]}"#, "{}", None).unwrap_err();
+        assert!(matches!(
+            &err,
+            LogicError::NaNError { operator, index: Some(1), .. } if operator == "*"
+        ));
+        assert_eq!(err.without_location(), LogicError::nan("*", 1, &DataValue::String("x")));
+    }
+
+    #[test]
+    fn test_serialize() {
+        let err = LogicError::nan("+", 1, &DataValue::Bool(true)).located("/+/1".to_string());
+        assert_eq!(
+            serde_json::to_value(&err).unwrap(),
+            serde_json::json!({
+                "code": "nan",
+                "message": "NaN error in '+': argument 1 expected number, got boolean at '/+/1'",
+                "location": "/+/1",
+                "operator": "+",
+                "index": 1,
+                "expected": "number",
+                "actual": "boolean"
+            })
+        );
+
+        let err = LogicError::thrown_error("bad");
+        assert_eq!(
+            serde_json::to_value(&err).unwrap(),
+            serde_json::json!({"code": "thrown", "message": "Thrown error: bad", "type": "bad"})
+        );
+
+        // The path of a forbidden operator is its location
+        let err = LogicError::OperatorNotAllowedError {
+            operator: "throw".to_string(),
+            path: "/and/1".to_string(),
+        };
+        assert_eq!(
+            serde_json::to_value(&err).unwrap(),
+            serde_json::json!({
+                "code": "operator_not_allowed",
+                "message": "Operator 'throw' is not allowed at '/and/1'",
+                "location": "/and/1",
+                "operator": "throw"
+            })
+        );
+    }
+
     #[test]
     fn test_with_context() {
         let result: Result<()> = Err(LogicError::ParseError {
             reason: "unexpected token".to_string(),
+            position: None,
+            location: None,
         });
 
         let result_with_context =
@@ -2,10 +2,18 @@
 //!
 //! This module provides functions for evaluating logic expressions.
 
+use std::sync::Arc;
+
+use super::ast::Logic;
 use super::error::Result;
+use super::limits::EvaluationLimits;
+use super::operators::custom::{CustomOperatorRegistry, EvaluationContext, RegisteredOperator};
+use super::options::EvaluateOptions;
 use super::operators::{
-    arithmetic, array, comparison, control, missing, r#try, string, throw, val, variable,
+    arithmetic, array, comparison, control, datetime, missing, r#try, string, throw, val, variable,
 };
+#[cfg(feature = "regex")]
+use super::operators::regex;
 use super::token::{OperatorType, Token};
 use crate::arena::DataArena;
 use crate::value::DataValue;
@@ -13,12 +21,13 @@ use crate::value::DataValue;
 /// Helper function to convert a token to a TokenRefs wrapper
 /// This avoids cloning tokens for lazy evaluation
 #[inline]
-fn convert_to_token_refs<'a>(args: &'a Token<'a>, arena: &'a DataArena) -> &'a [&'a Token<'a>] {
+pub(crate) fn convert_to_token_refs<'a>(
+    args: &'a Token<'a>,
+    arena: &'a DataArena,
+) -> &'a [&'a Token<'a>] {
     match args {
-        // Fast path for ArrayLiteral with 0 items
-        Token::ArrayLiteral(items) if items.is_empty() => &[],
-        // For ArrayLiteral with items, just use the references directly
-        Token::ArrayLiteral(items) => items.as_slice(),
+        // For ArrayLiteral, just use the references directly
+        Token::ArrayLiteral(items) => items,
         // Fast path for the single argument case
         _ => arena.alloc_slice_copy(&[args]),
     }
@@ -30,11 +39,214 @@ pub fn evaluate<'a>(
     token: &'a Token<'a>,
     arena: &'a DataArena,
 ) -> Result<&'a DataValue<'a>> {
+    // Count the step against the resource limits, check for interruption and
+    // trace it. This is the only check on the fast path, as the evaluation
+    // decides once whether any of them applies.
+    if arena.checks_enabled() {
+        return evaluate_checked(token, arena);
+    }
+
     // Fast path for literals - most common case
     if let Token::Literal(value) = token {
         return Ok(value);
     }
 
+    let result = evaluate_token(token, arena);
+    if result.is_err() {
+        arena.record_failure(token);
+    }
+    result
+}
+
+/// Evaluates a token while limits, interrupts or tracing are enabled.
+#[inline(never)]
+fn evaluate_checked<'a>(
+    token: &'a Token<'a>,
+    arena: &'a DataArena,
+) -> Result<&'a DataValue<'a>> {
+    // Shared expressions are transparent to the limits and the trace
+    if let Token::Shared { slot, expr } = token {
+        return evaluate_shared(token, *slot, expr, arena);
+    }
+
+    let result = if arena.tracing() {
+        arena.trace_enter(token);
+        let result = evaluate_limited(token, arena);
+        arena.trace_exit(token, &result);
+        result
+    } else {
+        evaluate_limited(token, arena)
+    };
+
+    if result.is_err() {
+        arena.record_failure(token);
+    }
+    result
+}
+
+/// Evaluates a token, counting non-literals as steps against the resource limits.
+#[inline]
+fn evaluate_limited<'a>(
+    token: &'a Token<'a>,
+    arena: &'a DataArena,
+) -> Result<&'a DataValue<'a>> {
+    if let Token::Literal(value) = token {
+        return Ok(value);
+    }
+
+    arena.enter_step()?;
+    let result = evaluate_token(token, arena);
+    arena.exit_step();
+    result
+}
+
+/// Evaluates a rule against the given data.
+///
+/// This sets up the per-evaluation state of the arena, namely the root and
+/// current context, the custom operators, the limits and the interrupts,
+/// evaluates the rule and clears that state again. Errors get the location
+/// of the token that raised them.
+pub(crate) fn evaluate_rule<'a>(
+    rule: &Logic<'a>,
+    data: &'a DataValue<'a>,
+    arena: &'a DataArena,
+    custom_operators: &Arc<CustomOperatorRegistry>,
+    limits: EvaluationLimits,
+    options: &EvaluateOptions,
+) -> Result<&'a DataValue<'a>> {
+    let root = rule.root();
+    with_rule_state(data, arena, custom_operators, limits, options, || {
+        evaluate(root, arena).map_err(|error| {
+            let source_map = rule.source_map().map(|map| &**map);
+            arena.take_failure().locate(root, source_map, error)
+        })
+    })
+}
+
+/// Runs `f` with the per-evaluation state of the arena set up for the given data.
+///
+/// The state is cleared when `f` returns or panics, so the arena does not keep
+/// references to the data or the custom operators after they are borrowed.
+pub(crate) fn with_rule_state<'a, R>(
+    data: &'a DataValue<'a>,
+    arena: &'a DataArena,
+    custom_operators: &Arc<CustomOperatorRegistry>,
+    limits: EvaluationLimits,
+    options: &EvaluateOptions,
+    f: impl FnOnce() -> R,
+) -> R {
+    // Start from an empty path chain, in case a previous evaluation left keys behind
+    arena.clear_path_chain();
+    // Forget errors raised outside of this evaluation, such as by the optimizer
+    arena.take_failure();
+
+    // Set both current context and root context to the data
+    arena.set_root_context(data);
+    arena.set_current_context(data, &DataValue::String("$"));
+
+    // Make the limits, interrupts and clock apply for the duration of the
+    // evaluation. Whether any of them is set is decided once per evaluation,
+    // so evaluations without them neither set them up nor clear them.
+    let checked = !limits.is_unlimited()
+        || options.cancellation.is_some()
+        || options.deadline.is_some()
+        || options.clock.is_some();
+    let _state = RuleState { arena, checked };
+    if checked {
+        arena.set_limits(limits);
+        arena.set_interrupts(options);
+        arena.set_clock(options.clock.clone());
+    }
+
+    arena.with_custom_operators(custom_operators, f)
+}
+
+/// Clears the per-evaluation state of an arena when dropped.
+struct RuleState<'a> {
+    arena: &'a DataArena,
+    checked: bool,
+}
+
+impl Drop for RuleState<'_> {
+    fn drop(&mut self) {
+        if self.checked {
+            self.arena.clear_clock();
+            self.arena.clear_interrupts();
+            self.arena.clear_limits();
+        }
+        self.arena.clear_contexts();
+    }
+}
+
+/// Evaluates a shared expression, reusing its result in the same context.
+///
+/// While a trace is recorded, the expression is evaluated at each of its
+/// occurrences, so each of them shows up in the trace. Errors record the
+/// occurrence they propagated through.
+#[inline(never)]
+fn evaluate_shared<'a>(
+    occurrence: &'a Token<'a>,
+    slot: usize,
+    expr: &'a Token<'a>,
+    arena: &'a DataArena,
+) -> Result<&'a DataValue<'a>> {
+    let result = if arena.tracing() {
+        evaluate(expr, arena)
+    } else {
+        evaluate_memoized(slot, expr, arena)
+    };
+
+    if result.is_err() {
+        arena.record_shared_failure(occurrence, expr);
+    }
+    result
+}
+
+/// Evaluates a shared expression, or returns its result in the current
+/// context if it was evaluated in that context before.
+#[inline]
+fn evaluate_memoized<'a>(
+    slot: usize,
+    expr: &'a Token<'a>,
+    arena: &'a DataArena,
+) -> Result<&'a DataValue<'a>> {
+    let context = arena.current_context(0);
+    if let Some(value) = arena.memoized(slot, context) {
+        return Ok(value);
+    }
+
+    let value = evaluate(expr, arena)?;
+    arena.memoize(slot, context, value);
+    Ok(value)
+}
+
+/// Evaluates an operator that changes the current context for its arguments.
+///
+/// The current context and the path chain are restored afterwards, so the
+/// expressions after the operator see the context they would see without it.
+#[inline]
+fn in_scope<'a>(
+    arena: &'a DataArena,
+    f: impl FnOnce() -> Result<&'a DataValue<'a>>,
+) -> Result<&'a DataValue<'a>> {
+    let context = arena.current_context(0);
+    let chain_len = arena.path_chain_len();
+
+    let result = f();
+
+    arena.replace_current_context(context);
+    while arena.path_chain_len() > chain_len {
+        arena.pop_path_component();
+    }
+    result
+}
+
+/// Evaluates a non-literal token.
+#[inline]
+fn evaluate_token<'a>(
+    token: &'a Token<'a>,
+    arena: &'a DataArena,
+) -> Result<&'a DataValue<'a>> {
     // Fast path for variables - second most common case
     if let Token::Variable { path, default } = token {
         return variable::evaluate_variable(path, default, arena);
@@ -43,7 +255,15 @@ pub fn evaluate<'a>(
     // Handle other token types
     match token {
         // Already handled above
-        Token::Literal(_) | Token::Variable { .. } => unreachable!(),
+        Token::Variable { .. } => unreachable!(),
+
+        Token::Shared { slot, expr } => evaluate_shared(token, *slot, expr, arena),
+
+        Token::Literal(value) => Ok(value),
+
+        // A compiled pattern evaluates to its source when it is not used
+        // as the pattern of a regex operator
+        Token::Pattern(pattern) => Ok(arena.alloc(DataValue::String(pattern.as_str()))),
 
         // Dynamic variables evaluate the path expression first
         Token::DynamicVariable { path_expr, default } => {
@@ -74,6 +294,7 @@ pub fn evaluate<'a>(
                             "Dynamic variable path must evaluate to a scalar value, got: {:?}",
                             path_value
                         ),
+                        location: None,
                     })
                 }
             };
@@ -88,7 +309,7 @@ pub fn evaluate<'a>(
             let mut values = arena.get_data_value_vec_with_capacity(items.len());
 
             // Evaluate each item in the array
-            for item in items {
+            for item in items.iter() {
                 let value = evaluate(item, arena)?;
                 values.push(value.clone());
             }
@@ -107,7 +328,7 @@ pub fn evaluate<'a>(
             // Convert args to a vector of token references
             let tokens_refs = if let Token::ArrayLiteral(items) = args {
                 // For ArrayLiteral, we can use the items directly
-                items.as_slice()
+                items
             } else {
                 // For single argument (not in an ArrayLiteral), create a one-element slice
                 std::slice::from_ref(args)
@@ -120,14 +341,36 @@ pub fn evaluate<'a>(
 }
 
 /// Evaluates a custom operator application.
+///
+/// The operator is looked up in the registry attached to the arena. Eager
+/// operators get their arguments evaluated first, lazy operators get the
+/// argument tokens and evaluate them as needed.
 fn evaluate_custom_operator<'a>(
     name: &'a str,
-    _args: &'a [&'a Token<'a>],
-    _arena: &'a DataArena,
+    args: &'a [&'a Token<'a>],
+    arena: &'a DataArena,
 ) -> Result<&'a DataValue<'a>> {
-    Err(super::error::LogicError::OperatorNotFoundError {
-        operator: name.to_string(),
-    })
+    match arena.custom_operator(name).as_deref() {
+        Some(RegisteredOperator::Eager(operator)) => {
+            // Evaluate each argument in the current context
+            let mut values = arena.get_data_value_vec_with_capacity(args.len());
+            for arg in args {
+                let value = evaluate(arg, arena)?;
+                values.push(value.clone());
+            }
+
+            operator.evaluate(arena.bump_vec_into_slice(values), arena)
+        }
+        Some(RegisteredOperator::Lazy(operator)) => {
+            let result = operator.evaluate(args, &EvaluationContext::new(arena));
+            // Forget the errors of arguments that the operator caught
+            if result.is_ok() {
+                arena.take_failure();
+            }
+            result
+        }
+        None => Err(super::error::LogicError::operator_not_found(name)),
+    }
 }
 
 /// Evaluates arguments and returns them as a slice of DataValues
@@ -149,7 +392,7 @@ fn evaluate_arguments<'a>(
             let mut values = arena.get_data_value_vec_with_capacity(items.len());
 
             // Evaluate each item in the array
-            for item in items {
+            for item in items.iter() {
                 let value = evaluate(item, arena)?;
                 values.push(value.clone());
             }
@@ -176,6 +419,18 @@ fn evaluate_arguments<'a>(
     }
 }
 
+/// Returns the error for a control operator whose arguments are not an array.
+#[cold]
+fn not_an_argument_array(operator: &str) -> super::error::LogicError {
+    super::error::LogicError::InvalidArgumentsError {
+        operator: operator.to_string(),
+        index: None,
+        expected: "an array of arguments".to_string(),
+        actual: "a single argument".to_string(),
+        location: None,
+    }
+}
+
 /// Helper function to evaluate an operator with a token argument
 #[inline]
 fn evaluate_operator<'a>(
@@ -188,75 +443,47 @@ fn evaluate_operator<'a>(
 
     match op_type {
         // Comparison operators
-        OperatorType::Comparison(comp_op) => match comp_op {
-            comparison::ComparisonOp::Equal => comparison::eval_equal(token_refs, arena),
-            comparison::ComparisonOp::StrictEqual => {
-                comparison::eval_strict_equal(token_refs, arena)
-            }
-            comparison::ComparisonOp::NotEqual => {
-                comparison::eval_not_equal(token_refs, arena)
-            }
-            comparison::ComparisonOp::StrictNotEqual => {
-                comparison::eval_strict_not_equal(token_refs, arena)
-            }
-            comparison::ComparisonOp::GreaterThan => {
-                comparison::eval_greater_than(token_refs, arena)
-            }
-            comparison::ComparisonOp::GreaterThanOrEqual => {
-                comparison::eval_greater_than_or_equal(token_refs, arena)
-            }
-            comparison::ComparisonOp::LessThan => {
-                comparison::eval_less_than(token_refs, arena)
-            }
-            comparison::ComparisonOp::LessThanOrEqual => {
-                comparison::eval_less_than_or_equal(token_refs, arena)
-            }
-        },
+        OperatorType::Comparison(comp_op) => {
+            comparison::eval_comparison(comp_op, token_refs, arena)
+        }
 
         // Array operators
         OperatorType::Array(array_op) => match array_op {
-            array::ArrayOp::Map => array::eval_map(token_refs, arena),
-            array::ArrayOp::Filter => array::eval_filter(token_refs, arena),
-            array::ArrayOp::Reduce => array::eval_reduce(token_refs, arena),
-            array::ArrayOp::All => array::eval_all(token_refs, arena),
-            array::ArrayOp::Some => array::eval_some(token_refs, arena),
-            array::ArrayOp::None => array::eval_none(token_refs, arena),
+            array::ArrayOp::Map => in_scope(arena, || array::eval_map(token_refs, arena)),
+            array::ArrayOp::Filter => in_scope(arena, || array::eval_filter(token_refs, arena)),
+            array::ArrayOp::Reduce => in_scope(arena, || array::eval_reduce(token_refs, arena)),
+            array::ArrayOp::All => in_scope(arena, || array::eval_all(token_refs, arena)),
+            array::ArrayOp::Some => in_scope(arena, || array::eval_some(token_refs, arena)),
+            array::ArrayOp::None => in_scope(arena, || array::eval_none(token_refs, arena)),
             array::ArrayOp::Merge => array::eval_merge(token_refs, arena),
             array::ArrayOp::In => array::eval_in(token_refs, arena),
+            array::ArrayOp::InSet => array::eval_in_set(token_refs, arena),
         },
 
         // Arithmetic operators
         OperatorType::Arithmetic(arith_op) => {
             // Evaluate arguments once and pass to the appropriate function
             let args_result = evaluate_arguments(args, arena)?;
-            match arith_op {
-                arithmetic::ArithmeticOp::Add => arithmetic::eval_add(args_result, arena),
-                arithmetic::ArithmeticOp::Subtract => arithmetic::eval_sub(args_result, arena),
-                arithmetic::ArithmeticOp::Multiply => arithmetic::eval_mul(args_result, arena),
-                arithmetic::ArithmeticOp::Divide => arithmetic::eval_div(args_result, arena),
-                arithmetic::ArithmeticOp::Modulo => arithmetic::eval_mod(args_result, arena),
-                arithmetic::ArithmeticOp::Min => arithmetic::eval_min(args_result),
-                arithmetic::ArithmeticOp::Max => arithmetic::eval_max(args_result),
-            }
+            arithmetic::eval_arithmetic(arith_op, args_result, arena)
         }
 
         // Logical operators
         OperatorType::Control(control_op) => match control_op {
             control::ControlOp::If => {
                 if !args.is_array_literal() {
-                    return Err(super::error::LogicError::InvalidArgumentsError);
+                    return Err(not_an_argument_array("if"));
                 }
                 control::eval_if(token_refs, arena)
             }
             control::ControlOp::And => {
                 if !args.is_array_literal() {
-                    return Err(super::error::LogicError::InvalidArgumentsError);
+                    return Err(not_an_argument_array("and"));
                 }
                 control::eval_and(token_refs, arena)
             }
             control::ControlOp::Or => {
                 if !args.is_array_literal() {
-                    return Err(super::error::LogicError::InvalidArgumentsError);
+                    return Err(not_an_argument_array("or"));
                 }
                 control::eval_or(token_refs, arena)
             }
@@ -270,8 +497,42 @@ fn evaluate_operator<'a>(
         OperatorType::String(string_op) => match string_op {
             string::StringOp::Cat => string::eval_cat(token_refs, arena),
             string::StringOp::Substr => string::eval_substr(token_refs, arena),
+            string::StringOp::Upper => string::eval_upper(token_refs, arena),
+            string::StringOp::Lower => string::eval_lower(token_refs, arena),
+            string::StringOp::Trim => string::eval_trim(token_refs, arena),
+            string::StringOp::StartsWith => string::eval_starts_with(token_refs, arena),
+            string::StringOp::EndsWith => string::eval_ends_with(token_refs, arena),
+            string::StringOp::Contains => string::eval_contains(token_refs, arena),
+            string::StringOp::Split => string::eval_split(token_refs, arena),
+            string::StringOp::Join => string::eval_join(token_refs, arena),
+            string::StringOp::Replace => string::eval_replace(token_refs, arena),
+            string::StringOp::Length => string::eval_length(token_refs, arena),
+            string::StringOp::Pad => string::eval_pad(token_refs, arena),
+        },
+
+        // Datetime operators
+        OperatorType::DateTime(datetime_op) => match datetime_op {
+            datetime::DateTimeOp::Now => datetime::eval_now(token_refs, arena),
+            datetime::DateTimeOp::DateTime => datetime::eval_datetime(token_refs, arena),
+            datetime::DateTimeOp::Duration => datetime::eval_duration(token_refs, arena),
+            datetime::DateTimeOp::Diff => datetime::eval_date_diff(token_refs, arena),
+            datetime::DateTimeOp::Add => datetime::eval_date_add(token_refs, arena),
+            datetime::DateTimeOp::Format => datetime::eval_format_date(token_refs, arena),
+            datetime::DateTimeOp::Part => datetime::eval_date_part(token_refs, arena),
         },
 
+        // Regular expression operators
+        #[cfg(feature = "regex")]
+        OperatorType::Regex(regex_op) => match regex_op {
+            regex::RegexOp::Match => regex::eval_match(token_refs, arena),
+            regex::RegexOp::Extract => regex::eval_extract(token_refs, arena),
+            regex::RegexOp::Replace => regex::eval_replace(token_refs, arena),
+        },
+        #[cfg(not(feature = "regex"))]
+        OperatorType::Regex(_) => Err(super::error::LogicError::operator_not_found(
+            op_type.as_str(),
+        )),
+
         OperatorType::Missing => missing::eval_missing(token_refs, arena),
 
         OperatorType::MissingSome => missing::eval_missing_some(token_refs, arena),
@@ -287,7 +548,7 @@ fn evaluate_operator<'a>(
         OperatorType::Throw => throw::eval_throw(token_refs, arena),
 
         // Try operator
-        OperatorType::Try => r#try::eval_try(token_refs, arena),
+        OperatorType::Try => in_scope(arena, || r#try::eval_try(token_refs, arena)),
 
         // Val operator
         OperatorType::Val => val::eval_val(token_refs, arena),
@@ -436,4 +697,20 @@ mod tests {
         let result = evaluate(arena.alloc(empty_val_token), &arena).unwrap();
         assert_eq!(*result, data);
     }
+
+    #[test]
+    fn test_iterators_restore_context() {
+        let dl = crate::DataLogic::new();
+        let data = r#"{"y": 2, "items": [{"y": 1}]}"#;
+
+        for rule in [
+            r#"{"cat": [{"map": [{"var": "items"}, {"var": "y"}]}, {"var": "y"}]}"#,
+            r#"{"cat": [{"all": [{"var": "items"}, {"var": "y"}]}, {"var": "y"}]}"#,
+            r#"{"cat": [{"reduce": [{"var": "items"}, {"var": "current.y"}, 0]}, {"var": "y"}]}"#,
+            r#"{"cat": [{"try": [{"throw": "x"}, {"var": "type"}]}, {"var": "y"}]}"#,
+        ] {
+            let result = dl.evaluate_str(rule, data, None).unwrap();
+            assert!(result.as_str().unwrap().ends_with('2'), "{}", rule);
+        }
+    }
 }
@@ -0,0 +1,388 @@
+//! Explanations of failed boolean rules.
+//!
+//! This module provides the `FailedCondition` type and the search for the
+//! leaf conditions that made a boolean rule evaluate to false.
+
+use serde_json::{Value as JsonValue, json};
+
+use super::error::Result;
+use super::evaluator::{convert_to_token_refs, evaluate};
+use super::operators::array::ArrayOp;
+use super::operators::comparison::{ComparisonOp, eval_comparison};
+use super::operators::control::ControlOp;
+use super::token::{OperatorType, Token};
+use crate::arena::DataArena;
+use crate::value::{OwnedDataValue, ToJson};
+
+/// A leaf condition of a boolean rule that did not hold.
+///
+/// The condition reads as `actual operator expected`. The operator is the
+/// comparison the value has to satisfy for the rule to pass, so it accounts
+/// for negation with `!` and for operands written in the other order: the
+/// failure of `{"!": {"<": [18, {"var": "age"}]}}` has the path `"age"`, the
+/// operator `"<="` and the expected value `18`.
+#[derive(Debug, Clone, PartialEq)]
+pub struct FailedCondition {
+    /// The condition as written in the rule, as JSONLogic.
+    pub expression: JsonValue,
+    /// The path of the variable the condition tests, if it tests one.
+    pub path: Option<String>,
+    /// The tested value: the value of the variable, or the left operand if
+    /// neither operand is a variable.
+    pub actual: OwnedDataValue,
+    /// The comparison `actual` has to satisfy.
+    ///
+    /// This is a comparison operator such as `">="`, `"in"` or `"not in"`
+    /// when `actual` has to be a member of `expected`, `"contains"` or
+    /// `"does not contain"` when `actual` is the array or string searched,
+    /// and `"truthy"` or `"falsy"` for other conditions.
+    pub operator: String,
+    /// The value `actual` is compared with, or `None` for `"truthy"` and `"falsy"`.
+    pub expected: Option<OwnedDataValue>,
+}
+
+impl ToJson for FailedCondition {
+    fn to_json(&self) -> JsonValue {
+        let mut condition = json!({
+            "expression": self.expression,
+            "actual": self.actual.to_json(),
+            "operator": self.operator,
+        });
+        let map = condition.as_object_mut().unwrap();
+
+        if let Some(path) = &self.path {
+            map.insert("path".to_string(), json!(path));
+        }
+        if let Some(expected) = &self.expected {
+            map.insert("expected".to_string(), expected.to_json());
+        }
+        condition
+    }
+}
+
+/// Finds the leaf conditions that made a rule evaluate to a falsy value.
+///
+/// `and`, `or`, `!`, `!!` and chained comparisons are broken down into their
+/// conditions. Every failed argument of an `and` is reported, while for an
+/// `or` only the argument with the fewest failed conditions is, as passing
+/// that one is enough for the rule to pass. The result is empty if the rule
+/// evaluates to a truthy value.
+pub(crate) fn explain<'a>(
+    token: &'a Token<'a>,
+    arena: &'a DataArena,
+) -> Result<Vec<FailedCondition>> {
+    failures(token, true, arena)
+}
+
+/// Finds the failed conditions of a token that has to evaluate to `wanted`.
+///
+/// The result is empty exactly when the truthiness of the token is `wanted`.
+fn failures<'a>(
+    token: &'a Token<'a>,
+    wanted: bool,
+    arena: &'a DataArena,
+) -> Result<Vec<FailedCondition>> {
+    if let Token::Operator { op_type, args } = token.unshared() {
+        let args = convert_to_token_refs(args, arena);
+
+        match op_type {
+            OperatorType::Control(op @ (ControlOp::And | ControlOp::Or)) if !args.is_empty() => {
+                // A passing `and` or a failing `or` needs every argument to agree
+                let every = (*op == ControlOp::And) == wanted;
+                return combine(args.iter().map(|arg| failures(arg, wanted, arena)), every);
+            }
+            OperatorType::Control(ControlOp::Not) if args.len() == 1 => {
+                return failures(args[0], !wanted, arena);
+            }
+            OperatorType::Control(ControlOp::DoubleNegation) if args.len() == 1 => {
+                return failures(args[0], wanted, arena);
+            }
+            OperatorType::Comparison(op) if args.len() >= 2 => {
+                // A chained comparison holds when every adjacent pair does
+                let pairs = args
+                    .windows(2)
+                    .map(|pair| compare(*op, pair, wanted, arena));
+                return combine(pairs, wanted);
+            }
+            OperatorType::Array(ArrayOp::In | ArrayOp::InSet) if args.len() == 2 => {
+                return membership(token, args, wanted, arena);
+            }
+            _ => {}
+        }
+    }
+
+    let value = evaluate(token, arena)?;
+    if value.coerce_to_bool() == wanted {
+        return Ok(Vec::new());
+    }
+    Ok(vec![FailedCondition {
+        expression: token.to_json(),
+        path: variable_path(token),
+        actual: value.to_owned_value(),
+        operator: if wanted { "truthy" } else { "falsy" }.to_string(),
+        expected: None,
+    }])
+}
+
+/// Combines the failed conditions of the arguments of a conjunction or disjunction.
+///
+/// If `every` argument has to pass, all their failed conditions are needed.
+/// Otherwise passing one argument is enough, so the smallest set is kept,
+/// and nothing failed if one of the arguments passed.
+fn combine(
+    arguments: impl Iterator<Item = Result<Vec<FailedCondition>>>,
+    every: bool,
+) -> Result<Vec<FailedCondition>> {
+    let mut combined: Option<Vec<FailedCondition>> = None;
+
+    for failed in arguments {
+        let failed = failed?;
+        if every {
+            combined.get_or_insert_with(Vec::new).extend(failed);
+        } else if failed.is_empty() {
+            return Ok(failed);
+        } else if combined
+            .as_ref()
+            .is_none_or(|best| failed.len() < best.len())
+        {
+            combined = Some(failed);
+        }
+    }
+    Ok(combined.unwrap_or_default())
+}
+
+/// Explains a comparison of two operands.
+fn compare<'a>(
+    op: ComparisonOp,
+    pair: &'a [&'a Token<'a>],
+    wanted: bool,
+    arena: &'a DataArena,
+) -> Result<Vec<FailedCondition>> {
+    if eval_comparison(op, pair, arena)?.coerce_to_bool() == wanted {
+        return Ok(Vec::new());
+    }
+
+    let (left, right) = (pair[0], pair[1]);
+    let expression =
+        json!({ OperatorType::Comparison(op).as_str(): [left.to_json(), right.to_json()] });
+    let required = if wanted { op } else { negate(op) };
+
+    // Put the variable on the left, so the condition reads as a requirement on it
+    let (tested, other, required) =
+        if variable_path(left).is_none() && variable_path(right).is_some() {
+            (right, left, mirror(required))
+        } else {
+            (left, right, required)
+        };
+
+    Ok(vec![FailedCondition {
+        expression,
+        path: variable_path(tested),
+        actual: evaluate(tested, arena)?.to_owned_value(),
+        operator: OperatorType::Comparison(required).as_str().to_string(),
+        expected: Some(evaluate(other, arena)?.to_owned_value()),
+    }])
+}
+
+/// Explains an `in` condition.
+fn membership<'a>(
+    token: &'a Token<'a>,
+    args: &'a [&'a Token<'a>],
+    wanted: bool,
+    arena: &'a DataArena,
+) -> Result<Vec<FailedCondition>> {
+    if evaluate(token, arena)?.coerce_to_bool() == wanted {
+        return Ok(Vec::new());
+    }
+
+    let (needle, haystack) = (args[0], args[1]);
+    // Report a variable searched for a constant as the one that has to contain it
+    let (tested, other, operator) =
+        if variable_path(needle).is_none() && variable_path(haystack).is_some() {
+            let operator = if wanted {
+                "contains"
+            } else {
+                "does not contain"
+            };
+            (haystack, needle, operator)
+        } else {
+            (needle, haystack, if wanted { "in" } else { "not in" })
+        };
+
+    Ok(vec![FailedCondition {
+        expression: token.to_json(),
+        path: variable_path(tested),
+        actual: evaluate(tested, arena)?.to_owned_value(),
+        operator: operator.to_string(),
+        expected: Some(evaluate(other, arena)?.to_owned_value()),
+    }])
+}
+
+/// Returns the path of a variable with a static path.
+fn variable_path(token: &Token) -> Option<String> {
+    match token {
+        Token::Variable { path, .. } => Some(path.to_string()),
+        _ => None,
+    }
+}
+
+/// Returns the comparison that holds exactly when `op` does not.
+fn negate(op: ComparisonOp) -> ComparisonOp {
+    match op {
+        ComparisonOp::Equal => ComparisonOp::NotEqual,
+        ComparisonOp::StrictEqual => ComparisonOp::StrictNotEqual,
+        ComparisonOp::NotEqual => ComparisonOp::Equal,
+        ComparisonOp::StrictNotEqual => ComparisonOp::StrictEqual,
+        ComparisonOp::GreaterThan => ComparisonOp::LessThanOrEqual,
+        ComparisonOp::GreaterThanOrEqual => ComparisonOp::LessThan,
+        ComparisonOp::LessThan => ComparisonOp::GreaterThanOrEqual,
+        ComparisonOp::LessThanOrEqual => ComparisonOp::GreaterThan,
+    }
+}
+
+/// Returns the comparison that holds for swapped operands.
+fn mirror(op: ComparisonOp) -> ComparisonOp {
+    match op {
+        ComparisonOp::GreaterThan => ComparisonOp::LessThan,
+        ComparisonOp::GreaterThanOrEqual => ComparisonOp::LessThanOrEqual,
+        ComparisonOp::LessThan => ComparisonOp::GreaterThan,
+        ComparisonOp::LessThanOrEqual => ComparisonOp::GreaterThanOrEqual,
+        op => op,
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::DataLogic;
+
+    fn explain(rule: &str, data: &str) -> Vec<FailedCondition> {
+        let dl = DataLogic::new();
+        let rule = dl.parse_logic(rule, None).unwrap();
+        let data = dl.parse_data(data).unwrap();
+        dl.explain(&rule, &data).unwrap()
+    }
+
+    fn summary(failed: &[FailedCondition]) -> Vec<JsonValue> {
+        failed
+            .iter()
+            .map(|f| {
+                json!([
+                    f.path,
+                    f.actual.to_json(),
+                    f.operator,
+                    f.expected.as_ref().map(ToJson::to_json)
+                ])
+            })
+            .collect()
+    }
+
+    #[test]
+    fn test_and_reports_every_failure() {
+        let rule = r#"{"and": [
+            {">=": [{"var": "age"}, 18]},
+            {"==": [{"var": "country"}, "US"]},
+            {"<": [{"var": "debt"}, 1000]}
+        ]}"#;
+
+        let failed = explain(rule, r#"{"age": 16, "country": "US", "debt": 5000}"#);
+        assert_eq!(
+            summary(&failed),
+            vec![
+                json!(["age", 16, ">=", 18]),
+                json!(["debt", 5000, "<", 1000]),
+            ]
+        );
+        assert_eq!(failed[0].expression, json!({">=": [{"var": "age"}, 18]}));
+
+        assert!(explain(rule, r#"{"age": 30, "country": "US", "debt": 0}"#).is_empty());
+    }
+
+    #[test]
+    fn test_or_reports_smallest_failure() {
+        let rule = r#"{"or": [
+            {"and": [{"var": "member"}, {">": [{"var": "points"}, 100]}]},
+            {"==": [{"var": "tier"}, "gold"]}
+        ]}"#;
+
+        let failed = explain(rule, r#"{"member": false, "points": 10, "tier": "silver"}"#);
+        assert_eq!(
+            summary(&failed),
+            vec![json!(["tier", "silver", "==", "gold"])]
+        );
+
+        let failed = explain(rule, r#"{"member": true, "points": 10, "tier": "silver"}"#);
+        assert_eq!(summary(&failed), vec![json!(["points", 10, ">", 100])]);
+
+        assert!(explain(rule, r#"{"member": false, "points": 0, "tier": "gold"}"#).is_empty());
+    }
+
+    #[test]
+    fn test_negation_and_operand_order() {
+        let failed = explain(
+            r#"{"!": {"or": [{"==": [{"var": "status"}, "banned"]}, {"<": [18, {"var": "age"}]}]}}"#,
+            r#"{"status": "banned", "age": 30}"#,
+        );
+        assert_eq!(
+            summary(&failed),
+            vec![
+                json!(["status", "banned", "!=", "banned"]),
+                json!(["age", 30, "<=", 18]),
+            ]
+        );
+
+        let failed = explain(r#"{"!!": [{"var": "email"}]}"#, r#"{"email": ""}"#);
+        assert_eq!(summary(&failed), vec![json!(["email", "", "truthy", null])]);
+    }
+
+    #[test]
+    fn test_chained_comparison_and_membership() {
+        let failed = explain(r#"{"<=": [0, {"var": "score"}, 10]}"#, r#"{"score": 12}"#);
+        assert_eq!(summary(&failed), vec![json!(["score", 12, "<=", 10])]);
+        assert_eq!(failed[0].expression, json!({"<=": [{"var": "score"}, 10]}));
+
+        let failed = explain(
+            r#"{"and": [{"in": [{"var": "country"}, ["US", "CA"]]}, {"in": ["admin", {"var": "roles"}]}]}"#,
+            r#"{"country": "FR", "roles": ["user"]}"#,
+        );
+        assert_eq!(
+            summary(&failed),
+            vec![
+                json!(["country", "FR", "in", ["CA", "US"]]),
+                json!(["roles", ["user"], "contains", "admin"]),
+            ]
+        );
+    }
+
+    #[test]
+    fn test_repeated_condition() {
+        // The optimizer shares the repeated comparison
+        let failed = explain(
+            r#"{"or": [{"and": [{">=": [{"var": "age"}, 18]}, {"var": "a"}]}, {"and": [{">=": [{"var": "age"}, 18]}, {"var": "b"}]}]}"#,
+            r#"{"age": 16, "a": true, "b": true}"#,
+        );
+        assert_eq!(summary(&failed), vec![json!(["age", 16, ">=", 18])]);
+        assert_eq!(failed[0].expression, json!({">=": [{"var": "age"}, 18]}));
+    }
+
+    #[test]
+    fn test_failed_condition_to_json() {
+        let failed = explain(r#"{">": [{"var": "a.b"}, 1]}"#, r#"{"a": {"b": 0}}"#);
+        assert_eq!(
+            failed[0].to_json(),
+            json!({
+                "expression": {">": [{"var": "a.b"}, 1]},
+                "path": "a.b",
+                "actual": 0,
+                "operator": ">",
+                "expected": 1
+            })
+        );
+
+        let failed = explain(r#"{"var": "flag"}"#, "{}");
+        assert_eq!(
+            failed[0].to_json(),
+            json!({"expression": {"var": "flag"}, "path": "flag", "actual": null, "operator": "truthy"})
+        );
+    }
+}
@@ -0,0 +1,158 @@
+//! Resource limits for evaluating logic expressions.
+//!
+//! This module provides the `EvaluationLimits` configuration, which bounds
+//! the recursion depth, the number of evaluation steps and the memory used
+//! by a single evaluation.
+
+/// Limits enforced while evaluating a rule.
+///
+/// Every limit is optional, and `None` means the resource is not limited.
+/// When a limit is exceeded, evaluation stops with a `LimitExceededError`.
+///
+/// # Examples
+///
+/// ```
+/// use datalogic_rs::{DataLogic, EvaluationLimits, LogicError};
+///
+/// let mut dl = DataLogic::new();
+/// dl.set_evaluation_limits(EvaluationLimits::new().with_max_steps(100));
+///
+/// let result = dl.evaluate_str(
+///     r#"{"map": [{"var": "items"}, {"+": [{"var": ""}, 1]}]}"#,
+///     &format!(r#"{{"items": {:?}}}"#, (0..1000).collect::<Vec<_>>()),
+///     None,
+/// );
+/// assert!(matches!(result, Err(LogicError::LimitExceededError { .. })));
+/// ```
+#[derive(Debug, Clone, Copy, Default, PartialEq, Eq)]
+pub struct EvaluationLimits {
+    /// Maximum nesting depth of expressions being evaluated at the same time.
+    pub max_depth: Option<usize>,
+    /// Maximum number of non-literal expressions evaluated.
+    pub max_steps: Option<usize>,
+    /// Maximum number of bytes the arena may grow by during the evaluation.
+    ///
+    /// The arena grows in chunks, so the check is approximate.
+    pub max_memory_bytes: Option<usize>,
+}
+
+impl EvaluationLimits {
+    /// Creates limits that do not restrict anything.
+    pub fn new() -> Self {
+        Self::default()
+    }
+
+    /// Sets the maximum nesting depth.
+    pub fn with_max_depth(mut self, max_depth: usize) -> Self {
+        self.max_depth = Some(max_depth);
+        self
+    }
+
+    /// Sets the maximum number of evaluation steps.
+    pub fn with_max_steps(mut self, max_steps: usize) -> Self {
+        self.max_steps = Some(max_steps);
+        self
+    }
+
+    /// Sets the maximum number of bytes the arena may grow by.
+    pub fn with_max_memory_bytes(mut self, max_memory_bytes: usize) -> Self {
+        self.max_memory_bytes = Some(max_memory_bytes);
+        self
+    }
+
+    /// Returns true if no limit is set.
+    pub fn is_unlimited(&self) -> bool {
+        self.max_depth.is_none() && self.max_steps.is_none() && self.max_memory_bytes.is_none()
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::logic::error::LogicError;
+    use crate::DataLogic;
+    use serde_json::json;
+
+    fn nested_rule(depth: usize) -> String {
+        let mut rule = r#"{"!!": [{"var": "a"}]}"#.to_string();
+        for _ in 1..depth {
+            rule = format!(r#"{{"===": [{}, true]}}"#, rule);
+        }
+        rule
+    }
+
+    #[test]
+    fn test_unlimited_by_default() {
+        let dl = DataLogic::new();
+        assert!(dl.evaluation_limits().is_unlimited());
+
+        let result = dl.evaluate_str(&nested_rule(50), r#"{"a": 1}"#, None).unwrap();
+        assert_eq!(result, json!(true));
+    }
+
+    #[test]
+    fn test_max_depth() {
+        let mut dl = DataLogic::new();
+        dl.set_evaluation_limits(EvaluationLimits::new().with_max_depth(10));
+
+        // Nine operators plus the variable fit in the limit
+        let result = dl.evaluate_str(&nested_rule(9), r#"{"a": 1}"#, None).unwrap();
+        assert_eq!(result, json!(true));
+
+        let result = dl.evaluate_str(&nested_rule(10), r#"{"a": 1}"#, None);
+        assert_eq!(
+            result.map_err(LogicError::without_location),
+            Err(LogicError::LimitExceededError {
+                limit: "max_depth".to_string(),
+                max: 10,
+                location: None,
+            })
+        );
+    }
+
+    #[test]
+    fn test_max_steps() {
+        let mut dl = DataLogic::new();
+        dl.set_evaluation_limits(EvaluationLimits::new().with_max_steps(10));
+
+        // map, the array variable and one variable per item
+        let rule = r#"{"map": [{"var": "items"}, {"var": ""}]}"#;
+        let result = dl.evaluate_str(rule, r#"{"items": [1, 2, 3]}"#, None).unwrap();
+        assert_eq!(result, json!([1, 2, 3]));
+
+        let result = dl.evaluate_str(rule, r#"{"items": [1, 2, 3, 4, 5, 6, 7, 8, 9]}"#, None);
+        assert_eq!(
+            result.map_err(LogicError::without_location),
+            Err(LogicError::LimitExceededError {
+                limit: "max_steps".to_string(),
+                max: 10,
+                location: None,
+            })
+        );
+
+        // The step counter starts over with every evaluation
+        let result = dl.evaluate_str(rule, r#"{"items": [1, 2, 3]}"#, None).unwrap();
+        assert_eq!(result, json!([1, 2, 3]));
+    }
+
+    #[test]
+    fn test_max_memory_bytes() {
+        let mut dl = DataLogic::new();
+        dl.set_evaluation_limits(EvaluationLimits::new().with_max_memory_bytes(64 * 1024));
+
+        // Each level of merge copies the growing array
+        let rule = r#"{"reduce": [{"var": "items"}, {"merge": [{"var": "accumulator"}, {"var": "accumulator"}, [1]]}, []]}"#;
+        let items: Vec<u32> = (0..64).collect();
+        let data = json!({ "items": items }).to_string();
+
+        let result = dl.evaluate_str(rule, &data, None);
+        assert_eq!(
+            result.map_err(LogicError::without_location),
+            Err(LogicError::LimitExceededError {
+                limit: "max_memory_bytes".to_string(),
+                max: 64 * 1024,
+                location: None,
+            })
+        );
+    }
+}
@@ -0,0 +1,483 @@
+//! Locations of evaluation errors in the rule.
+//!
+//! This module tracks the token whose evaluation raised an error while the
+//! error propagates to the root, and turns it into a JSON pointer into the
+//! rule. A `SourceMap` keeps the pointers into the rule as it was written
+//! for the tokens the optimizer has rewritten.
+
+use std::collections::HashMap;
+use std::fmt;
+
+use super::error::LogicError;
+use super::policy::push_segment;
+use super::token::{OperatorType, Token};
+
+/// The tokens that locate an error in the rule.
+///
+/// Tokens are identified by their address. The chain starts at the token
+/// that raised the error, followed by each occurrence of a shared expression
+/// the error has propagated through, preceded by the expression. The pointer
+/// of any other token the error passes through follows from the pointer of
+/// the token that raised it, so those are not recorded. Operators that catch
+/// errors, like `try`, forget the chain of the errors they catch.
+#[derive(Debug, Clone, Default, PartialEq, Eq)]
+pub(crate) struct FailureChain {
+    tokens: Vec<usize>,
+}
+
+impl FailureChain {
+    /// Returns the chain after the evaluation of `token` failed.
+    ///
+    /// An empty chain starts at `token`, which raised the error. Otherwise
+    /// the error came from one of the arguments of `token`, and the chain is
+    /// kept as it is.
+    pub(crate) fn raised_by(mut self, token: &Token) -> Self {
+        if self.tokens.is_empty() {
+            self.tokens.push(token_address(token));
+        }
+        self
+    }
+
+    /// Returns the chain after the error propagated through `occurrence`, a
+    /// shared token with the expression `expr`.
+    pub(crate) fn shared(mut self, occurrence: &Token, expr: &Token) -> Self {
+        self.tokens.push(token_address(expr));
+        self.tokens.push(token_address(occurrence));
+        self
+    }
+
+    /// Sets the location of an error that reached the root to the JSON
+    /// pointer of the token that raised it.
+    ///
+    /// The pointer is taken from the source map of the rule if it has one,
+    /// and otherwise from the tree under `root`.
+    pub(crate) fn locate(
+        self,
+        root: &Token,
+        source_map: Option<&SourceMap>,
+        error: LogicError,
+    ) -> LogicError {
+        if self.tokens.is_empty() || error.location().is_some() {
+            return error;
+        }
+
+        let tree_map;
+        let source_map = match source_map {
+            Some(source_map) => source_map,
+            None => {
+                tree_map = SourceMap::of(root);
+                &tree_map
+            }
+        };
+        match source_map.path_along(&self.tokens) {
+            Some(path) => error.located(path),
+            None => error,
+        }
+    }
+}
+
+/// The JSON pointers of the tokens of a rule into the rule as it was written.
+///
+/// The optimizer replaces operators with simplified ones, which may end up
+/// at another position in the optimized rule, such as the arguments of a
+/// flattened `and`. It gives each replacement the pointer of the token it
+/// replaces, so errors point to the expression the author wrote.
+#[derive(Clone, Default)]
+pub(crate) struct SourceMap {
+    paths: HashMap<usize, String>,
+}
+
+impl SourceMap {
+    /// Maps each token of a rule as it was written to its JSON pointer.
+    pub(crate) fn of(root: &Token) -> Self {
+        let mut map = Self::default();
+        map.insert_tree(root, &mut String::new());
+        map
+    }
+
+    /// Maps `token` to `path`, and its children to the pointers below it.
+    ///
+    /// A token that occurs more than once, such as the expression of a
+    /// shared token, keeps its first pointer.
+    fn insert_tree(&mut self, token: &Token, path: &mut String) {
+        self.paths
+            .entry(token_address(token))
+            .or_insert_with(|| path.clone());
+        for_each_raw_child(token, &mut |segments, child| {
+            let len = path.len();
+            push_segments(path, segments);
+            self.insert_tree(child, path);
+            path.truncate(len);
+            false
+        });
+    }
+
+    /// Returns the pointer of `token`, if it has one.
+    pub(crate) fn path(&self, token: &Token) -> Option<&str> {
+        self.paths.get(&token_address(token)).map(String::as_str)
+    }
+
+    /// Maps `token` to `path`.
+    pub(crate) fn insert(&mut self, token: &Token, path: String) {
+        self.paths.insert(token_address(token), path);
+    }
+
+    /// Gives `replacement` the pointer of the token it replaces.
+    ///
+    /// A replacement that already has a pointer keeps it, as it is then an
+    /// argument of the replaced token that took its place.
+    pub(crate) fn replace(&mut self, original: &Token, replacement: &Token) {
+        if let Some(path) = self.paths.get(&token_address(original)) {
+            let path = path.clone();
+            self.paths.entry(token_address(replacement)).or_insert(path);
+        }
+    }
+
+    /// Returns the pointer to the token at the start of a failure chain.
+    ///
+    /// The tokens of a shared expression have the pointers of its first
+    /// occurrence. Going down the chain, their pointers relative to the
+    /// expression are moved below the occurrence the chain passes through.
+    fn path_along(&self, chain: &[usize]) -> Option<String> {
+        // The pointer of the last token with one, and where the chain put it
+        let mut last: Option<(&str, String)> = None;
+        for address in chain.iter().rev() {
+            let Some(path) = self.paths.get(address) else {
+                continue;
+            };
+            let along = match &last {
+                None => path.clone(),
+                Some((parent, parent_along)) => match relative_path(path, parent) {
+                    Some(rest) => format!("{}{}", parent_along, rest),
+                    // A shared expression, at the position of its occurrence
+                    None => parent_along.clone(),
+                },
+            };
+            last = Some((path, along));
+        }
+        last.map(|(_, along)| along)
+    }
+}
+
+/// Returns the rest of a pointer below the pointer `parent`.
+fn relative_path<'p>(path: &'p str, parent: &str) -> Option<&'p str> {
+    let rest = path.strip_prefix(parent)?;
+    (rest.is_empty() || rest.starts_with('/')).then_some(rest)
+}
+
+impl fmt::Debug for SourceMap {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        f.debug_struct("SourceMap")
+            .field("tokens", &self.paths.len())
+            .finish()
+    }
+}
+
+impl LogicError {
+    /// Moves the location of a parse-time error into the value at `segment`.
+    ///
+    /// The parser calls this while an error propagates out of a nested value,
+    /// so the error ends up with the JSON pointer of the value that caused it.
+    pub(crate) fn within(mut self, segment: &str) -> LogicError {
+        let mut path = String::new();
+        push_segment(&mut path, segment);
+        match self.location_slot() {
+            Some(Some(location)) => {
+                path.push_str(&location.path);
+                location.path = path;
+                self
+            }
+            _ => self.located(path),
+        }
+    }
+}
+
+fn token_address(token: &Token) -> usize {
+    token as *const Token as usize
+}
+
+/// A segment of the JSON pointer from a token to one of its children.
+pub(super) enum Segment<'t> {
+    Key(&'t str),
+    Index(usize),
+}
+
+/// Calls `f` with each child of a token and the segments leading to it, until `f` returns true.
+///
+/// The segments follow the JSONLogic form of the token, so they can be
+/// joined into a JSON pointer into the rule. Shared expressions are
+/// transparent: each occurrence is passed to `f` as the expression itself.
+pub(super) fn for_each_child<'t>(
+    token: &'t Token<'t>,
+    f: &mut impl FnMut(&[Segment<'t>], &'t Token<'t>) -> bool,
+) -> bool {
+    if let Token::Shared { expr, .. } = token {
+        return f(&[], expr);
+    }
+
+    for_each_raw_child(token, &mut |segments, child| match child {
+        Token::Shared { expr, .. } => f(segments, expr),
+        child => f(segments, child),
+    })
+}
+
+/// Calls `f` with each child of a token like `for_each_child`, but passes
+/// the occurrences of shared expressions to `f` as they are.
+///
+/// The expression of a shared token is its only child, with no segments.
+fn for_each_raw_child<'t>(
+    token: &'t Token<'t>,
+    f: &mut impl FnMut(&[Segment<'t>], &'t Token<'t>) -> bool,
+) -> bool {
+    match token {
+        Token::Literal(_) => false,
+        Token::Pattern(_) => false,
+        Token::ArrayLiteral(items) => items
+            .iter()
+            .enumerate()
+            .any(|(index, item)| f(&[Segment::Index(index)], item)),
+        Token::Variable { default, .. } => {
+            default.is_some_and(|default| f(&[Segment::Key("var"), Segment::Index(1)], default))
+        }
+        Token::DynamicVariable { path_expr, default } => match default {
+            Some(default) => {
+                f(&[Segment::Key("var"), Segment::Index(0)], path_expr)
+                    || f(&[Segment::Key("var"), Segment::Index(1)], default)
+            }
+            None => f(&[Segment::Key("var")], path_expr),
+        },
+        // An array of expressions is written as a plain JSON array
+        Token::Operator {
+            op_type: OperatorType::ArrayLiteral,
+            args,
+        } => for_each_argument(None, args, f),
+        Token::Operator { op_type, args } => for_each_argument(Some(op_type.as_str()), args, f),
+        Token::CustomOperator { name, args } => for_each_argument(Some(name), args, f),
+        Token::Shared { expr, .. } => f(&[], expr),
+    }
+}
+
+fn for_each_argument<'t>(
+    key: Option<&'t str>,
+    args: &'t Token<'t>,
+    f: &mut impl FnMut(&[Segment<'t>], &'t Token<'t>) -> bool,
+) -> bool {
+    match (key, args) {
+        (Some(key), Token::ArrayLiteral(items)) => items
+            .iter()
+            .enumerate()
+            .any(|(index, item)| f(&[Segment::Key(key), Segment::Index(index)], item)),
+        (None, Token::ArrayLiteral(items)) => items
+            .iter()
+            .enumerate()
+            .any(|(index, item)| f(&[Segment::Index(index)], item)),
+        (Some(key), _) => f(&[Segment::Key(key)], args),
+        (None, _) => f(&[], args),
+    }
+}
+
+/// Appends the segments leading to a child to a JSON pointer.
+pub(super) fn push_segments(path: &mut String, segments: &[Segment]) {
+    for segment in segments {
+        match segment {
+            Segment::Key(key) => push_segment(path, key),
+            Segment::Index(index) => push_segment(path, &index.to_string()),
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use crate::{DataLogic, LogicError};
+
+    fn error(rule: &str, data: &str) -> LogicError {
+        let dl = DataLogic::new();
+        let rule = dl.parse_logic(rule, None).unwrap();
+        let data = dl.parse_data(data).unwrap();
+        dl.evaluate(&rule, &data).unwrap_err()
+    }
+
+    #[test]
+    fn test_error_location() {
+        let err = error(
+            r#"{"and": [{"var": "ok"}, {"var": "ok"}, {"map": [{"var": "items"}, {"+": [{"throw": "bad"}, 1]}]}]}"#,
+            r#"{"ok": true, "items": [1]}"#,
+        );
+        assert_eq!(err.location(), Some("/and/2/map/1/+/0"));
+        assert_eq!(
+            err.clone().without_location(),
+            LogicError::thrown_error("bad")
+        );
+        assert_eq!(err.to_string(), "Thrown error: bad at '/and/2/map/1/+/0'");
+
+        let err = error(r#"{"!": {"throw": "bad"}}"#, "{}");
+        assert_eq!(err.location(), Some("/!"));
+
+        let err = error(r#"{"throw": "bad"}"#, "{}");
+        assert_eq!(err.location(), Some(""));
+        assert_eq!(err.to_string(), "Thrown error: bad at the rule root");
+    }
+
+    #[test]
+    fn test_error_location_of_operator() {
+        // The operator itself rejects its arguments
+        let err = error(r#"{"if": [{"!": {"var": "x"}}, {"map": [1, 2, 3]}]}"#, "{}");
+        assert_eq!(err.location(), Some("/if/1"));
+
+        let err = error(r#"[1, {"throw": "bad"}]"#, "{}");
+        assert_eq!(err.location(), Some("/1"));
+
+        let err = error(r#"{"var": [{"cat": ["a", {"throw": "bad"}]}, 0]}"#, "{}");
+        assert_eq!(err.location(), Some("/var/0/cat/1"));
+    }
+
+    #[test]
+    fn test_error_location_in_optimized_rule() {
+        // The pointers refer to the rule as written, not as the optimizer rewrote it
+        let err = error(
+            r#"{"and": [true, {"var": "ok"}, {"throw": "bad"}]}"#,
+            r#"{"ok": 1}"#,
+        );
+        assert_eq!(err.location(), Some("/and/2"));
+
+        let err = error(
+            r#"{"or": [{"var": "no"}, {"or": [false, {"*": [2, "x"]}]}]}"#,
+            "{}",
+        );
+        assert_eq!(err.location(), Some("/or/1/or/1"));
+
+        let err = error(
+            r#"{"if": [false, 1, {"var": "ok"}, {"throw": "bad"}, 2]}"#,
+            r#"{"ok": true}"#,
+        );
+        assert_eq!(err.location(), Some("/if/3"));
+        let err = error(r#"{"if": [true, {"throw": "bad"}, 1]}"#, "{}");
+        assert_eq!(err.location(), Some("/if/1"));
+
+        let err = error(r#"{"!": {"!": {"*": [2, {"var": "x"}]}}}"#, r#"{"x": "y"}"#);
+        assert_eq!(err.location(), Some("/!/!"));
+
+        let err = error(r#"{"in": [{"throw": "bad"}, [1, 2]]}"#, "{}");
+        assert_eq!(err.location(), Some("/in/0"));
+
+        // Compiled rules point to the rule as written too
+        let dl = DataLogic::new();
+        let source = r#"{"and": [true, {"throw": "bad"}]}"#;
+        let compiled = dl.compile(source, None).unwrap();
+        let err = compiled.evaluate_json(&serde_json::json!({})).unwrap_err();
+        assert_eq!(err.location(), Some("/and/1"));
+    }
+
+    #[test]
+    fn test_error_location_in_shared_expression() {
+        // The occurrence that was evaluated raised the error
+        let doubled = r#"{"*": [{"var": "x"}, 2]}"#;
+        let err = error(
+            &format!(r#"{{"if": [{{"var": "c"}}, {doubled}, {{"+": [{doubled}, 1]}}]}}"#),
+            r#"{"c": false, "x": "y"}"#,
+        );
+        assert_eq!(err.location(), Some("/if/2/+/0"));
+
+        // Errors inside a later occurrence point inside it
+        let inner = r#"{"+": [1, {"*": [{"var": "x"}, 2]}]}"#;
+        let rule = format!(r#"{{"if": [{{"var": "c"}}, {inner}, {{"-": [{inner}, 1]}}]}}"#);
+        let err = error(&rule, r#"{"c": false, "x": "y"}"#);
+        assert_eq!(err.location(), Some("/if/2/-/0/+/1"));
+        let err = error(&rule, r#"{"c": true, "x": "y"}"#);
+        assert_eq!(err.location(), Some("/if/1/+/1"));
+
+        // Compiled rules tell the occurrences apart too
+        let compiled = DataLogic::new().compile(&rule, None).unwrap();
+        let data = serde_json::json!({"c": false, "x": "y"});
+        let err = compiled.evaluate_json(&data).unwrap_err();
+        assert_eq!(err.location(), Some("/if/2/-/0/+/1"));
+    }
+
+    #[test]
+    fn test_error_location_across_try() {
+        // The caught error does not affect the location of a later one
+        let err = error(
+            r#"{"+": [{"try": [{"throw": "caught"}, 1]}, {"throw": "bad"}]}"#,
+            "{}",
+        );
+        assert_eq!(err.location(), Some("/+/1"));
+
+        // An error that every alternative raises points to the last one
+        let err = error(
+            r#"{"try": [{"throw": "first"}, {"throw": "second"}]}"#,
+            "{}",
+        );
+        assert_eq!(err.location(), Some("/try/1"));
+        assert_eq!(err.without_location(), LogicError::thrown_error("second"));
+
+        let err = error(
+            r#"{"map": [[1, 2], {"if": [{"==": [{"var": ""}, 1]}, {"try": [{"throw": "x"}, 0]}, {"throw": "y"}]}]}"#,
+            "{}",
+        );
+        assert_eq!(err.location(), Some("/map/1/if/2"));
+    }
+
+    #[test]
+    fn test_error_location_across_lazy_operator() {
+        use crate::logic::token::Token;
+        use crate::{DataValue, EvaluationContext, LazyCustomOperator, Result};
+
+        // Returns the value of the first argument that does not fail
+        struct FirstOk;
+
+        impl LazyCustomOperator for FirstOk {
+            fn evaluate<'a>(
+                &self,
+                args: &'a [&'a Token<'a>],
+                context: &EvaluationContext<'a>,
+            ) -> Result<&'a DataValue<'a>> {
+                let mut last = Err(LogicError::custom("no arguments"));
+                for arg in args {
+                    last = context.evaluate(arg);
+                    if last.is_ok() {
+                        break;
+                    }
+                }
+                last
+            }
+        }
+
+        // Returns null if its argument fails
+        struct OrNull;
+
+        impl LazyCustomOperator for OrNull {
+            fn evaluate<'a>(
+                &self,
+                args: &'a [&'a Token<'a>],
+                context: &EvaluationContext<'a>,
+            ) -> Result<&'a DataValue<'a>> {
+                let null = context.arena().null_value();
+                Ok(context.evaluate(args[0]).unwrap_or(null))
+            }
+        }
+
+        let mut dl = DataLogic::new();
+        dl.register_lazy_operator("first_ok", Box::new(FirstOk));
+        dl.register_lazy_operator("or_null", Box::new(OrNull));
+        let data = dl.parse_data("{}").unwrap();
+
+        // Caught errors do not affect the location of a later one
+        for caught in [
+            r#"{"first_ok": [{"throw": "x"}, 1]}"#,
+            r#"{"or_null": [{"throw": "x"}]}"#,
+        ] {
+            let rule = format!(r#"{{"+": [{caught}, {{"throw": "bad"}}]}}"#);
+            let rule = dl.parse_logic(&rule, None).unwrap();
+            let err = dl.evaluate(&rule, &data).unwrap_err();
+            assert_eq!(err.location(), Some("/+/1"), "{}", caught);
+        }
+
+        // An error that every argument raises points to the last one
+        let rule = r#"{"first_ok": [{"throw": "first"}, {"throw": "second"}]}"#;
+        let rule = dl.parse_logic(rule, None).unwrap();
+        assert_eq!(
+            dl.evaluate(&rule, &data).unwrap_err().location(),
+            Some("/first_ok/1")
+        );
+    }
+}
@@ -4,24 +4,60 @@
 //! logic expressions using arena allocation for improved performance.
 
 mod ast;
+mod compiled;
+mod cse;
 mod datalogic_core;
+mod decimal;
 pub mod error;
 mod evaluator;
+mod explain;
+mod limits;
+mod location;
 mod operators;
 mod optimizer;
+mod options;
+mod partial;
+mod policy;
+mod references;
 pub mod token;
+mod trace;
+mod validate;
 
 pub use ast::Logic;
+pub use compiled::CompiledLogic;
 pub use datalogic_core::DataLogicCore;
-pub use error::{LogicError, Result};
+pub use decimal::{DecimalArithmetic, Rounding};
+pub use error::{ErrorLocation, LogicError, Result, SourcePosition};
+pub use policy::OperatorPolicy;
+#[cfg(feature = "decimal")]
+pub(crate) use policy::push_segment;
+pub use references::ReferencedPath;
 pub use evaluator::evaluate;
+pub(crate) use evaluator::{evaluate_rule, with_rule_state};
+pub use explain::FailedCondition;
+pub(crate) use explain::explain;
+pub use limits::EvaluationLimits;
+pub(crate) use location::FailureChain;
+pub use options::{CancellationToken, Clock, EvaluateOptions, FixedClock, SystemClock};
+pub(crate) use partial::partial_evaluate;
 pub use token::{OperatorType, Token};
+pub use trace::TraceNode;
+pub(crate) use trace::TraceRecorder;
+pub use validate::{Diagnostic, Severity};
+pub(crate) use validate::validate;
 
 // Re-export operator types
 pub use operators::arithmetic::ArithmeticOp;
 pub use operators::array::ArrayOp;
 pub use operators::comparison::ComparisonOp;
 pub use operators::control::ControlOp;
+pub use operators::custom::{
+    CustomOperator, CustomOperatorRegistry, EvaluationContext, LazyCustomOperator,
+};
+pub(crate) use operators::custom::RegisteredOperator;
+pub use operators::datetime::DateTimeOp;
+pub(crate) use operators::regex::compile_pattern;
+pub use operators::regex::{Pattern, RegexOp};
 pub use operators::string::StringOp;
 
 /// Make optimizer function public
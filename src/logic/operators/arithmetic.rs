@@ -5,9 +5,14 @@
 
 use core::f64;
 
+use rust_decimal::Decimal;
+use rust_decimal::prelude::ToPrimitive;
+
 use crate::arena::DataArena;
+use crate::logic::decimal::{DecimalArithmetic, Rounding};
+use crate::logic::operators::Arity;
 use crate::logic::error::{LogicError, Result};
-use crate::value::DataValue;
+use crate::value::{DataValue, NumberValue};
 
 /// Enumeration of arithmetic operators.
 #[derive(Debug, Clone, Copy, PartialEq, Eq)]
@@ -26,27 +31,142 @@ pub enum ArithmeticOp {
     Min,
     /// Maximum value
     Max,
+    /// Rounding to a number of decimal places
+    Round,
+    /// Absolute value
+    Abs,
+    /// Rounding down to an integer
+    Floor,
+    /// Rounding up to an integer
+    Ceil,
+    /// Power (base raised to an exponent)
+    Pow,
+    /// Square root
+    Sqrt,
+    /// Logarithm, natural or to a given base
+    Log,
+}
+
+impl ArithmeticOp {
+    /// Returns the number of arguments the operator accepts.
+    pub(crate) const fn arity(self) -> Arity {
+        match self {
+            ArithmeticOp::Add | ArithmeticOp::Multiply => Arity::ANY,
+            ArithmeticOp::Subtract | ArithmeticOp::Divide | ArithmeticOp::Min | ArithmeticOp::Max => {
+                Arity::at_least(1)
+            }
+            ArithmeticOp::Modulo => Arity::at_least(2),
+            ArithmeticOp::Round | ArithmeticOp::Log => Arity::between(1, 2),
+            ArithmeticOp::Abs | ArithmeticOp::Floor | ArithmeticOp::Ceil | ArithmeticOp::Sqrt => {
+                Arity::exactly(1)
+            }
+            ArithmeticOp::Pow => Arity::exactly(2),
+        }
+    }
 }
 
-/// Helper function to safely convert a DataValue to f64
-fn safe_to_f64(value: &DataValue) -> Result<f64> {
+/// Helper function to safely convert an argument of `operator` to f64
+fn safe_to_f64(operator: &str, index: usize, value: &DataValue) -> Result<f64> {
     value
         .coerce_to_number()
-        .ok_or(LogicError::NaNError)
+        .ok_or_else(|| LogicError::nan(operator, index, value))
         .map(|n| n.as_f64())
 }
 
+/// Converts an argument to a decimal, for decimal arithmetic.
+///
+/// Returns `None` if the argument is not a number or is out of the range
+/// of decimals, such as an infinity. The operation then falls back to
+/// floats, which report the error or compute the result.
+fn to_decimal(value: &DataValue) -> Option<Decimal> {
+    let number = value.coerce_to_number()?;
+    // Strings are parsed exactly rather than through a float
+    if let DataValue::String(s) = value
+        && let Ok(decimal) = Decimal::from_str_exact(s)
+    {
+        return Some(decimal);
+    }
+    number.as_decimal()
+}
+
+/// Converts the arguments to decimals if decimal arithmetic is enabled.
+fn decimal_arguments(
+    args: &[DataValue],
+    arena: &DataArena,
+) -> Option<(DecimalArithmetic, Vec<Decimal>)> {
+    let settings = arena.decimal_arithmetic()?;
+    let decimals = args.iter().map(to_decimal).collect::<Option<Vec<_>>>()?;
+    Some((settings, decimals))
+}
+
+/// Allocates the result of decimal arithmetic, rounded to the scale of the
+/// settings.
+fn decimal_value<'a>(
+    settings: DecimalArithmetic,
+    value: Decimal,
+    arena: &'a DataArena,
+) -> &'a DataValue<'a> {
+    arena.alloc(DataValue::decimal(settings.round(value)))
+}
+
+/// Applies `operation` to the first decimal and each of the others in turn.
+///
+/// A single decimal is the second operand of `operation` with `unit` as the
+/// first, like `{"-": 5}` is `0 - 5`. Returns `None` without decimals, on
+/// overflow and on division by zero, which are left to the float operation.
+fn fold_decimals(
+    decimals: &[Decimal],
+    unit: Decimal,
+    operation: fn(Decimal, Decimal) -> Option<Decimal>,
+) -> Option<Decimal> {
+    match decimals {
+        [] => None,
+        [only] => operation(unit, *only),
+        [first, rest @ ..] => rest.iter().try_fold(*first, |result, d| operation(result, *d)),
+    }
+}
+
+/// Evaluates an arithmetic operator with its evaluated arguments.
+pub fn eval_arithmetic<'a>(
+    op: ArithmeticOp,
+    args: &'a [DataValue<'a>],
+    arena: &'a DataArena,
+) -> Result<&'a DataValue<'a>> {
+    match op {
+        ArithmeticOp::Add => eval_add(args, arena),
+        ArithmeticOp::Subtract => eval_sub(args, arena),
+        ArithmeticOp::Multiply => eval_mul(args, arena),
+        ArithmeticOp::Divide => eval_div(args, arena),
+        ArithmeticOp::Modulo => eval_mod(args, arena),
+        ArithmeticOp::Min => eval_min(args),
+        ArithmeticOp::Max => eval_max(args),
+        ArithmeticOp::Round => eval_round(args, arena),
+        ArithmeticOp::Abs => eval_abs(args, arena),
+        ArithmeticOp::Floor => eval_floor(args, arena),
+        ArithmeticOp::Ceil => eval_ceil(args, arena),
+        ArithmeticOp::Pow => eval_pow(args, arena),
+        ArithmeticOp::Sqrt => eval_sqrt(args, arena),
+        ArithmeticOp::Log => eval_log(args, arena),
+    }
+}
+
 pub fn eval_add<'a>(args: &'a [DataValue<'a>], arena: &'a DataArena) -> Result<&'a DataValue<'a>> {
+    if let Some((settings, decimals)) = decimal_arguments(args, arena)
+        && let Some(result) = fold_decimals(&decimals, Decimal::ZERO, Decimal::checked_add)
+    {
+        return Ok(decimal_value(settings, result, arena));
+    }
+
     match args.len() {
         0 => Ok(arena.alloc(DataValue::float(0.0))),
         1 => {
-            let result = safe_to_f64(&args[0])?;
+            let result = safe_to_f64("+", 0, &args[0])?;
             Ok(arena.alloc(DataValue::float(result)))
         }
         _ => {
             let mut result = 0.0;
-            for value in args {
-                result += safe_to_f64(value)?;
+            for (index, value) in args.iter().enumerate() {
+                result += safe_to_f64("+", index, value)?;
             }
 
             Ok(arena.alloc(DataValue::float(result)))
@@ -56,18 +176,24 @@ pub fn eval_add<'a>(args: &'a [DataValue<'a>], arena: &'a DataArena) -> Result<&
 
 /// Evaluates a subtraction operation with a single argument.
 pub fn eval_sub<'a>(args: &'a [DataValue<'a>], arena: &'a DataArena) -> Result<&'a DataValue<'a>> {
+    if let Some((settings, decimals)) = decimal_arguments(args, arena)
+        && let Some(result) = fold_decimals(&decimals, Decimal::ZERO, Decimal::checked_sub)
+    {
+        return Ok(decimal_value(settings, result, arena));
+    }
+
     match args.len() {
-        0 => Err(LogicError::InvalidArgumentsError),
+        0 => Err(ArithmeticOp::Subtract.arity().error("-", 0)),
         1 => {
-            let result = safe_to_f64(&args[0])?;
+            let result = safe_to_f64("-", 0, &args[0])?;
             Ok(arena.alloc(DataValue::float(-result)))
         }
         _ => {
-            let first = safe_to_f64(&args[0])?;
+            let first = safe_to_f64("-", 0, &args[0])?;
             let mut result = first;
 
-            for value in &args[1..] {
-                result -= safe_to_f64(value)?;
+            for (index, value) in args.iter().enumerate().skip(1) {
+                result -= safe_to_f64("-", index, value)?;
             }
 
             Ok(arena.alloc(DataValue::float(result)))
@@ -77,23 +203,29 @@ pub fn eval_sub<'a>(args: &'a [DataValue<'a>], arena: &'a DataArena) -> Result<&
 
 /// Evaluates a division operation with a single argument.
 pub fn eval_div<'a>(args: &'a [DataValue<'a>], arena: &'a DataArena) -> Result<&'a DataValue<'a>> {
+    if let Some((settings, decimals)) = decimal_arguments(args, arena)
+        && let Some(result) = fold_decimals(&decimals, Decimal::ONE, Decimal::checked_div)
+    {
+        return Ok(decimal_value(settings, result, arena));
+    }
+
     match args.len() {
-        0 => Err(LogicError::InvalidArgumentsError),
+        0 => Err(ArithmeticOp::Divide.arity().error("/", 0)),
         1 => {
-            let value = safe_to_f64(&args[0])?;
+            let value = safe_to_f64("/", 0, &args[0])?;
             if value == 0.0 {
-                return Err(LogicError::NaNError);
+                return Err(LogicError::division_by_zero("/", 0));
             }
             Ok(arena.alloc(DataValue::float(1.0 / value)))
         }
         _ => {
-            let first = safe_to_f64(&args[0])?;
+            let first = safe_to_f64("/", 0, &args[0])?;
             let mut result = first;
 
-            for value in &args[1..] {
-                let divisor = safe_to_f64(value)?;
+            for (index, value) in args.iter().enumerate().skip(1) {
+                let divisor = safe_to_f64("/", index, value)?;
                 if divisor == 0.0 {
-                    return Err(LogicError::NaNError);
+                    return Err(LogicError::division_by_zero("/", index));
                 }
                 result /= divisor;
             }
@@ -106,19 +238,24 @@ pub fn eval_div<'a>(args: &'a [DataValue<'a>], arena: &'a DataArena) -> Result<&
 /// Evaluates a modulo operation with a single argument.
 pub fn eval_mod<'a>(args: &'a [DataValue<'a>], arena: &'a DataArena) -> Result<&'a DataValue<'a>> {
     match args.len() {
-        0 => Err(LogicError::InvalidArgumentsError),
-        1 => {
+        0 | 1 => {
             // Can't do modulo with a single value
-            Err(LogicError::InvalidArgumentsError)
+            Err(ArithmeticOp::Modulo.arity().error("%", args.len()))
         }
         _ => {
-            let first = safe_to_f64(&args[0])?;
+            if let Some((settings, decimals)) = decimal_arguments(args, arena)
+                && let Some(result) = fold_decimals(&decimals, Decimal::ZERO, Decimal::checked_rem)
+            {
+                return Ok(decimal_value(settings, result, arena));
+            }
+
+            let first = safe_to_f64("%", 0, &args[0])?;
             let mut result = first;
 
-            for value in &args[1..] {
-                let divisor = safe_to_f64(value)?;
+            for (index, value) in args.iter().enumerate().skip(1) {
+                let divisor = safe_to_f64("%", index, value)?;
                 if divisor == 0.0 {
-                    return Err(LogicError::NaNError);
+                    return Err(LogicError::division_by_zero("%", index));
                 }
                 result %= divisor;
             }
@@ -130,16 +267,22 @@ pub fn eval_mod<'a>(args: &'a [DataValue<'a>], arena: &'a DataArena) -> Result<&
 
 /// Evaluates a multiplication operation with a single argument.
 pub fn eval_mul<'a>(args: &'a [DataValue<'a>], arena: &'a DataArena) -> Result<&'a DataValue<'a>> {
+    if let Some((settings, decimals)) = decimal_arguments(args, arena)
+        && let Some(result) = fold_decimals(&decimals, Decimal::ONE, Decimal::checked_mul)
+    {
+        return Ok(decimal_value(settings, result, arena));
+    }
+
     match args.len() {
         0 => Ok(arena.alloc(DataValue::float(1.0))),
         1 => {
-            let result = safe_to_f64(&args[0])?;
+            let result = safe_to_f64("*", 0, &args[0])?;
             Ok(arena.alloc(DataValue::float(result)))
         }
         _ => {
             let mut result = 1.0;
-            for value in args {
-                result *= safe_to_f64(value)?;
+            for (index, value) in args.iter().enumerate() {
+                result *= safe_to_f64("*", index, value)?;
             }
 
             Ok(arena.alloc(DataValue::float(result)))
@@ -147,70 +290,234 @@ pub fn eval_mul<'a>(args: &'a [DataValue<'a>], arena: &'a DataArena) -> Result<&
     }
 }
 
-/// Evaluates a min operation with a single argument.
+/// Evaluates a min operation.
+///
+/// Decimals are compared exactly, and other numbers as floats.
 pub fn eval_min<'a>(args: &'a [DataValue<'a>]) -> Result<&'a DataValue<'a>> {
-    match args.len() {
-        0 => Err(LogicError::InvalidArgumentsError),
-        1 => {
-            if !args[0].is_number() {
-                return Err(LogicError::InvalidArgumentsError);
-            }
-            Ok(&args[0])
+    if args.is_empty() {
+        return Err(ArithmeticOp::Min.arity().error("min", 0));
+    }
+
+    let mut min_value = &args[0];
+    for (index, value) in args.iter().enumerate() {
+        let (DataValue::Number(number), DataValue::Number(min_num)) = (value, min_value) else {
+            return Err(LogicError::invalid_argument("min", index, "number", value));
+        };
+        if number < min_num {
+            min_value = value;
         }
-        _ => {
-            let mut min_value = &args[0];
-            let mut min_num = f64::INFINITY;
+    }
 
-            for value in args {
-                if !value.is_number() {
-                    return Err(LogicError::InvalidArgumentsError);
-                }
-                let val_num = value.as_f64().unwrap();
+    Ok(min_value)
+}
 
-                if val_num < min_num {
-                    min_value = value;
-                    min_num = val_num;
-                }
-            }
+/// Evaluates a max operation.
+///
+/// Decimals are compared exactly, and other numbers as floats.
+pub fn eval_max<'a>(args: &'a [DataValue<'a>]) -> Result<&'a DataValue<'a>> {
+    if args.is_empty() {
+        return Err(ArithmeticOp::Max.arity().error("max", 0));
+    }
 
-            Ok(min_value)
+    let mut max_value = &args[0];
+    for (index, value) in args.iter().enumerate() {
+        let (DataValue::Number(number), DataValue::Number(max_num)) = (value, max_value) else {
+            return Err(LogicError::invalid_argument("max", index, "number", value));
+        };
+        if number > max_num {
+            max_value = value;
         }
     }
+
+    Ok(max_value)
 }
 
-/// Evaluates a max operation with a single argument.
-pub fn eval_max<'a>(args: &'a [DataValue<'a>]) -> Result<&'a DataValue<'a>> {
-    match args.len() {
-        0 => Err(LogicError::InvalidArgumentsError),
-        1 => {
-            if !args[0].is_number() {
-                return Err(LogicError::InvalidArgumentsError);
+/// Evaluates the rounding of a number to a number of decimal places.
+///
+/// The number of decimal places is 0 by default, and rounds to tens,
+/// hundreds and so on if it is negative. With decimal arithmetic, the
+/// result is a decimal rounded as configured. Otherwise it is a float
+/// rounded as written in decimal, with halves away from zero, so `1.005`
+/// rounds to `1.01` even though the nearest float is slightly less.
+pub fn eval_round<'a>(args: &'a [DataValue<'a>], arena: &'a DataArena) -> Result<&'a DataValue<'a>> {
+    ArithmeticOp::Round.arity().check("round", args.len())?;
+
+    let value = safe_to_f64("round", 0, &args[0])?;
+    let places = match args.get(1) {
+        Some(places) => places
+            .coerce_to_number()
+            .and_then(|n| n.as_i64())
+            .ok_or_else(|| LogicError::invalid_argument("round", 1, "integer", places))?,
+        None => 0,
+    };
+
+    let settings = arena.decimal_arithmetic();
+    let rounding = settings.map_or(Rounding::HalfUp, |settings| settings.rounding);
+    match to_decimal(&args[0]).and_then(|d| rounding.round(d, places)) {
+        Some(rounded) => match settings {
+            Some(settings) => Ok(decimal_value(settings, rounded, arena)),
+            None => Ok(arena.alloc(DataValue::float(rounded.to_f64().unwrap_or(value)))),
+        },
+        // Numbers out of the range of decimals have no fractional part
+        None if places >= 0 => Ok(arena.alloc(DataValue::float(value))),
+        None => {
+            let factor = 10f64.powi(places.unsigned_abs().min(400) as i32);
+            if factor.is_infinite() {
+                // Every float is less than half of such a power of ten
+                return Ok(arena.alloc(DataValue::integer(0)));
             }
-            Ok(&args[0])
+            Ok(arena.alloc(DataValue::float((value / factor).round() * factor)))
         }
-        _ => {
-            let mut max_value = &args[0];
-            let mut max_num = f64::NEG_INFINITY;
+    }
+}
 
-            for value in args {
-                if !value.is_number() {
-                    return Err(LogicError::InvalidArgumentsError);
-                }
-                let val_num = value.as_f64().unwrap();
+/// Allocates the result of a float operation, or a NaN error if it is not a
+/// finite number.
+///
+/// With decimal arithmetic, the result is converted to a decimal rounded to
+/// the scale of the settings.
+fn float_value<'a>(
+    operator: &str,
+    index: Option<usize>,
+    result: f64,
+    arena: &'a DataArena,
+) -> Result<&'a DataValue<'a>> {
+    if !result.is_finite() {
+        return Err(LogicError::not_finite(operator, index, result));
+    }
+    let decimal = arena
+        .decimal_arithmetic()
+        .zip(NumberValue::from_f64(result).as_decimal());
+    match decimal {
+        Some((settings, decimal)) => Ok(decimal_value(settings, decimal, arena)),
+        None => Ok(arena.alloc(DataValue::float(result))),
+    }
+}
 
-                if val_num > max_num {
-                    max_value = value;
-                    max_num = val_num;
-                }
-            }
+/// Evaluates a function of one number.
+///
+/// With decimal arithmetic, `decimal` computes the result unless it returns
+/// `None`, and `float` computes it otherwise.
+fn eval_unary<'a>(
+    op: ArithmeticOp,
+    operator: &str,
+    args: &'a [DataValue<'a>],
+    arena: &'a DataArena,
+    decimal: fn(Decimal) -> Option<Decimal>,
+    float: fn(f64) -> f64,
+) -> Result<&'a DataValue<'a>> {
+    op.arity().check(operator, args.len())?;
+
+    let value = safe_to_f64(operator, 0, &args[0])?;
+    if let Some((settings, decimals)) = decimal_arguments(args, arena)
+        && let Some(result) = decimal(decimals[0])
+    {
+        return Ok(decimal_value(settings, result, arena));
+    }
+    float_value(operator, Some(0), float(value), arena)
+}
+
+/// Evaluates the absolute value of a number.
+pub fn eval_abs<'a>(args: &'a [DataValue<'a>], arena: &'a DataArena) -> Result<&'a DataValue<'a>> {
+    eval_unary(ArithmeticOp::Abs, "abs", args, arena, |d| Some(d.abs()), f64::abs)
+}
+
+/// Evaluates the largest integer less than or equal to a number.
+pub fn eval_floor<'a>(args: &'a [DataValue<'a>], arena: &'a DataArena) -> Result<&'a DataValue<'a>> {
+    eval_unary(ArithmeticOp::Floor, "floor", args, arena, |d| Some(d.floor()), f64::floor)
+}
+
+/// Evaluates the smallest integer greater than or equal to a number.
+pub fn eval_ceil<'a>(args: &'a [DataValue<'a>], arena: &'a DataArena) -> Result<&'a DataValue<'a>> {
+    eval_unary(ArithmeticOp::Ceil, "ceil", args, arena, |d| Some(d.ceil()), f64::ceil)
+}
+
+/// Evaluates the square root of a number.
+///
+/// The square root of a negative number is a NaN error.
+pub fn eval_sqrt<'a>(args: &'a [DataValue<'a>], arena: &'a DataArena) -> Result<&'a DataValue<'a>> {
+    eval_unary(ArithmeticOp::Sqrt, "sqrt", args, arena, |_| None, f64::sqrt)
+}
+
+/// Evaluates a base raised to an exponent.
+///
+/// With decimal arithmetic, powers with an integer exponent are exact.
+/// Results that are not finite numbers, such as `{"pow": [-8, 0.5]}` or
+/// `{"pow": [0, -1]}`, are NaN errors.
+pub fn eval_pow<'a>(args: &'a [DataValue<'a>], arena: &'a DataArena) -> Result<&'a DataValue<'a>> {
+    ArithmeticOp::Pow.arity().check("pow", args.len())?;
+
+    let base = safe_to_f64("pow", 0, &args[0])?;
+    let exponent = safe_to_f64("pow", 1, &args[1])?;
+    if let Some((settings, decimals)) = decimal_arguments(args, arena)
+        && let Some(result) = decimal_power(decimals[0], decimals[1])
+    {
+        return Ok(decimal_value(settings, result, arena));
+    }
+    float_value("pow", None, base.powf(exponent), arena)
+}
 
-            Ok(max_value)
+/// Raises a decimal to an integer exponent by repeated squaring.
+///
+/// Returns `None` if the exponent is not an integer, or on overflow and
+/// division by zero.
+fn decimal_power(base: Decimal, exponent: Decimal) -> Option<Decimal> {
+    if !exponent.is_integer() {
+        return None;
+    }
+    let mut remaining = exponent.abs().to_u64()?;
+    let mut square = base;
+    let mut result = Decimal::ONE;
+    while remaining > 0 {
+        if remaining & 1 == 1 {
+            result = result.checked_mul(square)?;
+        }
+        remaining >>= 1;
+        if remaining > 0 {
+            square = square.checked_mul(square)?;
         }
     }
+    if exponent.is_sign_negative() {
+        Decimal::ONE.checked_div(result)
+    } else {
+        Some(result)
+    }
+}
+
+/// Evaluates the natural logarithm of a number, or its logarithm to the base
+/// given as second argument.
+///
+/// The logarithm of zero or a negative number, and to a base of 1 or less
+/// than or equal to zero, is a NaN error.
+pub fn eval_log<'a>(args: &'a [DataValue<'a>], arena: &'a DataArena) -> Result<&'a DataValue<'a>> {
+    ArithmeticOp::Log.arity().check("log", args.len())?;
+
+    let value = safe_to_f64("log", 0, &args[0])?;
+    if !value.ln().is_finite() {
+        return Err(LogicError::not_finite("log", Some(0), value.ln()));
+    }
+    let result = match args.get(1) {
+        None => value.ln(),
+        Some(base) => match safe_to_f64("log", 1, base)? {
+            // Exact for powers of 2 and 10
+            2.0 => value.log2(),
+            10.0 => value.log10(),
+            base => {
+                let result = value.ln() / base.ln();
+                if !result.is_finite() {
+                    return Err(LogicError::not_finite("log", Some(1), result));
+                }
+                result
+            }
+        },
+    };
+    float_value("log", Some(0), result, arena)
 }
 
 #[cfg(test)]
 mod tests {
+    use super::*;
+    use crate::DataLogic;
     use crate::logic::datalogic_core::DataLogicCore;
     use serde_json::json;
 
@@ -366,4 +673,174 @@ mod tests {
         let result = core.apply(&rule, &data_json).unwrap();
         assert_eq!(result, json!(3));
     }
+
+    #[test]
+    fn test_decimal_arithmetic() {
+        let mut dl = DataLogic::new();
+        dl.set_decimal_arithmetic(Some(DecimalArithmetic::new().with_scale(2)));
+
+        let cases = [
+            (r#"{"+": [0.1, 0.2]}"#, "null", json!(0.3)),
+            (r#"{"-": [0.3, 0.1]}"#, "null", json!(0.2)),
+            (r#"{"*": [1.1, 1.1]}"#, "null", json!(1.21)),
+            (r#"{"%": [1.5, 0.4]}"#, "null", json!(0.3)),
+            // Results are rounded to the scale, half to even by default
+            (r#"{"/": [2, 3]}"#, "null", json!(0.67)),
+            (r#"{"*": [0.125, 1]}"#, "null", json!(0.12)),
+            (r#"{"+": ["0.1", {"var": "x"}]}"#, r#"{"x": 0.2}"#, json!(0.3)),
+            (
+                r#"{"reduce": [{"var": "prices"}, {"+": [{"var": "current"}, {"var": "accumulator"}]}, 0]}"#,
+                r#"{"prices": [0.1, 0.1, 0.1]}"#,
+                json!(0.3),
+            ),
+            (r#"{"==": [{"+": [0.1, 0.2]}, 0.3]}"#, "null", json!(true)),
+            (r#"{"max": [0.1, 0.3, 0.2]}"#, "null", json!(0.3)),
+            (r#"{"round": [2.345, 2]}"#, "null", json!(2.34)),
+        ];
+        for (rule, data, expected) in cases {
+            assert_eq!(dl.evaluate_str(rule, data, None).unwrap(), expected, "{rule}");
+        }
+
+        // Errors are the same as with floats
+        assert!(dl.evaluate_str(r#"{"/": [0.1, 0]}"#, "null", None).is_err());
+
+        // With the decimal feature, numbers are read and written with all
+        // their digits
+        dl.set_decimal_arithmetic(Some(DecimalArithmetic::new()));
+        #[cfg(feature = "decimal")]
+        let cases = [
+            (r#"{"/": [1, 3]}"#, "null", "0.3333333333333333333333333333"),
+            (
+                r#"{"+": [{"var": "x"}, 0.00000001]}"#,
+                r#"{"x": 1234567890.12345678}"#,
+                "1234567890.12345679",
+            ),
+        ];
+        #[cfg(not(feature = "decimal"))]
+        let cases = [
+            (r#"{"/": [1, 3]}"#, "null", "0.3333333333333333"),
+            (
+                r#"{"+": [{"var": "x"}, 0.001]}"#,
+                r#"{"x": 1234567890.123}"#,
+                "1234567890.124",
+            ),
+        ];
+        for (rule, data, expected) in cases {
+            let result = dl.evaluate_str(rule, data, None).unwrap();
+            assert_eq!(result.to_string(), expected, "{rule}");
+        }
+        dl.set_decimal_arithmetic(Some(DecimalArithmetic::new().with_scale(2)));
+        assert!(dl.evaluate_str(r#"{"+": [0.1, "abc"]}"#, "null", None).is_err());
+
+        // Compiled rules keep the settings they were compiled with
+        let rule = dl.compile(r#"{"+": [0.1, {"var": "x"}]}"#, None).unwrap();
+        dl.set_decimal_arithmetic(None);
+        assert_eq!(rule.evaluate_json(&json!({"x": 0.2})).unwrap(), json!(0.3));
+        assert_eq!(
+            dl.evaluate_str(r#"{"+": [0.1, 0.2]}"#, "null", None).unwrap(),
+            json!(0.30000000000000004)
+        );
+    }
+
+    #[test]
+    fn test_round() {
+        let mut dl = DataLogic::new();
+        let cases = [
+            (r#"{"round": 2.5}"#, json!(3)),
+            (r#"{"round": -2.5}"#, json!(-3)),
+            (r#"{"round": [1.005, 2]}"#, json!(1.01)),
+            (r#"{"round": [1234.5, -2]}"#, json!(1200)),
+            (r#"{"round": [1.5, -400]}"#, json!(0)),
+            (r#"{"round": ["1.23456", 3]}"#, json!(1.235)),
+        ];
+        for (rule, expected) in cases {
+            assert_eq!(dl.evaluate_str(rule, "null", None).unwrap(), expected, "{rule}");
+        }
+        assert!(dl.evaluate_str(r#"{"round": ["abc"]}"#, "null", None).is_err());
+        assert!(dl.evaluate_str(r#"{"round": [1.5, "x"]}"#, "null", None).is_err());
+
+        // Decimal arithmetic rounds as configured
+        dl.set_decimal_arithmetic(Some(DecimalArithmetic::new().with_rounding(Rounding::Floor)));
+        assert_eq!(dl.evaluate_str(r#"{"round": [2.57, 1]}"#, "null", None).unwrap(), json!(2.5));
+        assert_eq!(dl.evaluate_str(r#"{"round": -2.5}"#, "null", None).unwrap(), json!(-3));
+    }
+
+    #[test]
+    fn test_math_functions() {
+        let core = DataLogicCore::new();
+        let builder = core.builder();
+        let data_json = json!({"x": -7.5});
+
+        let rule = builder.arithmetic().abs_op().var("x").build();
+        assert_eq!(core.apply(&rule, &data_json).unwrap(), json!(7.5));
+
+        let rule = builder.arithmetic().floor_op().var("x").build();
+        assert_eq!(core.apply(&rule, &data_json).unwrap(), json!(-8));
+
+        let rule = builder.arithmetic().ceil_op().var("x").build();
+        assert_eq!(core.apply(&rule, &data_json).unwrap(), json!(-7));
+
+        let rule = builder.arithmetic().pow_op().int(2).float(0.5).build();
+        assert_eq!(core.apply(&rule, &data_json).unwrap(), json!(2f64.sqrt()));
+
+        let rule = builder.arithmetic().sqrt_op().string("2.25").build();
+        assert_eq!(core.apply(&rule, &data_json).unwrap(), json!(1.5));
+
+        let rule = builder.arithmetic().log_op().int(1000).int(10).build();
+        assert_eq!(core.apply(&rule, &data_json).unwrap(), json!(3));
+
+        let rule = builder.arithmetic().round_op().var("x").build();
+        assert_eq!(core.apply(&rule, &data_json).unwrap(), json!(-8));
+
+        // Results out of the range of integers stay floats
+        let rule = builder.arithmetic().abs_op().int(i64::MIN).build();
+        assert_eq!(core.apply(&rule, &data_json).unwrap(), json!(2f64.powi(63)));
+
+        let rule = builder.arithmetic().pow_op().int(2).int(63).build();
+        assert_eq!(core.apply(&rule, &data_json).unwrap(), json!(2f64.powi(63)));
+    }
+
+    #[test]
+    fn test_math_function_errors() {
+        let dl = DataLogic::new();
+        let not_finite = |operator: &str, index: Option<usize>, actual: &str| LogicError::NaNError {
+            operator: operator.to_string(),
+            index,
+            expected: "finite number".to_string(),
+            actual: actual.to_string(),
+            location: None,
+        };
+
+        let cases = [
+            (r#"{"sqrt": -4}"#, not_finite("sqrt", Some(0), "NaN")),
+            (r#"{"log": 0}"#, not_finite("log", Some(0), "infinity")),
+            (r#"{"log": [8, 1]}"#, not_finite("log", Some(1), "infinity")),
+            (r#"{"pow": [-8, 0.5]}"#, not_finite("pow", None, "NaN")),
+            (r#"{"pow": [10, 400]}"#, not_finite("pow", None, "infinity")),
+            (r#"{"abs": "abc"}"#, LogicError::nan("abs", 0, &DataValue::String("abc"))),
+        ];
+        for (rule, expected) in cases {
+            let error = dl.evaluate_str(rule, "null", None).unwrap_err();
+            assert_eq!(error.without_location(), expected, "{rule}");
+        }
+        assert!(dl.evaluate_str(r#"{"pow": [2]}"#, "null", None).is_err());
+    }
+
+    #[test]
+    fn test_decimal_math_functions() {
+        let mut dl = DataLogic::new();
+        dl.set_decimal_arithmetic(Some(DecimalArithmetic::new().with_scale(4)));
+
+        let cases = [
+            (r#"{"abs": -0.1}"#, json!(0.1)),
+            (r#"{"floor": -2.5}"#, json!(-3)),
+            (r#"{"pow": [1.1, 2]}"#, json!(1.21)),
+            (r#"{"pow": [2, -2]}"#, json!(0.25)),
+            (r#"{"sqrt": 3}"#, json!(1.7321)),
+            (r#"{"pow": [4, 0.5]}"#, json!(2)),
+        ];
+        for (rule, expected) in cases {
+            assert_eq!(dl.evaluate_str(rule, "null", None).unwrap(), expected, "{rule}");
+        }
+    }
 }
@@ -4,12 +4,14 @@
 //! such as map, filter, reduce, etc.
 
 use crate::arena::DataArena;
+use crate::logic::operators::Arity;
 use crate::logic::error::{LogicError, Result};
 use crate::logic::evaluator::evaluate;
-use crate::logic::operators::arithmetic::ArithmeticOp;
+use crate::logic::operators::arithmetic::{self, ArithmeticOp};
 use crate::logic::token::OperatorType;
 use crate::logic::token::Token;
 use crate::value::DataValue;
+use std::cmp::Ordering;
 
 /// Enumeration of array operators.
 #[derive(Debug, Clone, Copy, PartialEq, Eq)]
@@ -30,6 +32,23 @@ pub enum ArrayOp {
     Merge,
     /// In operator
     In,
+    /// In operator against a literal array, whose members the optimizer
+    /// has sorted for a binary search. It is written as `in`.
+    InSet,
+}
+
+impl ArrayOp {
+    /// Returns the number of arguments the operator accepts.
+    pub(crate) const fn arity(self) -> Arity {
+        match self {
+            ArrayOp::Map | ArrayOp::Filter | ArrayOp::All | ArrayOp::Some | ArrayOp::None => {
+                Arity::exactly(2)
+            }
+            ArrayOp::Reduce => Arity::between(2, 3),
+            ArrayOp::Merge => Arity::ANY,
+            ArrayOp::In | ArrayOp::InSet => Arity::exactly(2),
+        }
+    }
 }
 
 /// Evaluates an all operation.
@@ -38,9 +57,7 @@ pub fn eval_all<'a>(
     arena: &'a DataArena,
 ) -> Result<&'a DataValue<'a>> {
     // Fast path for invalid arguments
-    if args.len() != 2 {
-        return Err(LogicError::InvalidArgumentsError);
-    }
+    ArrayOp::All.arity().check("all", args.len())?;
 
     // Evaluate the first argument to get the array
     let array = evaluate(args[0], arena)?;
@@ -54,7 +71,7 @@ pub fn eval_all<'a>(
         DataValue::Array(items) => items,
         // Fast path for common case of null (treat as empty array)
         DataValue::Null => return Ok(arena.false_value()),
-        _ => return Err(LogicError::InvalidArgumentsError),
+        _ => return Err(LogicError::invalid_argument("all", 0, "array", array)),
     };
 
     // If the array is empty, return false (vacuously false)
@@ -67,11 +84,14 @@ pub fn eval_all<'a>(
 
     // Check if all items satisfy the condition
     for (index, item) in items.iter().enumerate() {
+        // Stop early if the evaluation was cancelled or timed out
+        arena.check_interrupts()?;
+
         // Store the current path chain length to preserve parent contexts
         let current_chain_len = arena.path_chain_len();
         
         let key = DataValue::Number(crate::value::NumberValue::from_f64(index as f64));
-        arena.set_current_context(&item, arena.alloc(key));
+        arena.set_current_context(item, arena.alloc(key));
         
         // Evaluate the function with the item as context
         if !evaluate(condition, arena)?.coerce_to_bool() {
@@ -94,9 +114,7 @@ pub fn eval_some<'a>(
     arena: &'a DataArena,
 ) -> Result<&'a DataValue<'a>> {
     // Fast path for invalid arguments
-    if args.len() != 2 {
-        return Err(LogicError::InvalidArgumentsError);
-    }
+    ArrayOp::Some.arity().check("some", args.len())?;
 
     // Evaluate the first argument to get the array
     let array = evaluate(args[0], arena)?;
@@ -110,7 +128,7 @@ pub fn eval_some<'a>(
         DataValue::Array(items) => items,
         // Fast path for common case of null (treat as empty array)
         DataValue::Null => return Ok(arena.false_value()),
-        _ => return Err(LogicError::InvalidArgumentsError),
+        _ => return Err(LogicError::invalid_argument("some", 0, "array", array)),
     };
 
     // If the array is empty, return false (vacuously false)
@@ -123,11 +141,14 @@ pub fn eval_some<'a>(
 
     // Check if any item satisfies the condition
     for (index, item) in items.iter().enumerate() {
+        // Stop early if the evaluation was cancelled or timed out
+        arena.check_interrupts()?;
+
         // Store the current path chain length to preserve parent contexts
         let current_chain_len = arena.path_chain_len();
         
         let key = DataValue::Number(crate::value::NumberValue::from_f64(index as f64));
-        arena.set_current_context(&item, arena.alloc(key));
+        arena.set_current_context(item, arena.alloc(key));
         
         // Evaluate the function with the item as context
         if evaluate(condition, arena)?.coerce_to_bool() {
@@ -150,9 +171,7 @@ pub fn eval_none<'a>(
     arena: &'a DataArena,
 ) -> Result<&'a DataValue<'a>> {
     // Fast path for invalid arguments
-    if args.len() != 2 {
-        return Err(LogicError::InvalidArgumentsError);
-    }
+    ArrayOp::None.arity().check("none", args.len())?;
 
     // Evaluate the first argument to get the array
     let array = evaluate(args[0], arena)?;
@@ -166,7 +185,7 @@ pub fn eval_none<'a>(
         DataValue::Array(items) => items,
         // Fast path for common case of null (treat as empty array)
         DataValue::Null => return Ok(arena.true_value()),
-        _ => return Err(LogicError::InvalidArgumentsError),
+        _ => return Err(LogicError::invalid_argument("none", 0, "array", array)),
     };
 
     // If the array is empty, return true (vacuously true)
@@ -179,11 +198,14 @@ pub fn eval_none<'a>(
 
     // Check if no items satisfy the condition
     for (index, item) in items.iter().enumerate() {
+        // Stop early if the evaluation was cancelled or timed out
+        arena.check_interrupts()?;
+
         // Store the current path chain length to preserve parent contexts
         let current_chain_len = arena.path_chain_len();
         
         let key = DataValue::Number(crate::value::NumberValue::from_f64(index as f64));
-        arena.set_current_context(&item, arena.alloc(key));
+        arena.set_current_context(item, arena.alloc(key));
         
         // Evaluate the function with the item as context
         if evaluate(condition, arena)?.coerce_to_bool() {
@@ -218,9 +240,7 @@ pub fn eval_map<'a>(
     arena: &'a DataArena,
 ) -> Result<&'a DataValue<'a>> {
     // Fast path for invalid arguments
-    if args.len() != 2 {
-        return Err(LogicError::InvalidArgumentsError);
-    }
+    ArrayOp::Map.arity().check("map", args.len())?;
 
     // Evaluate the first argument to get the array
     let array = evaluate(args[0], arena)?;
@@ -234,7 +254,7 @@ pub fn eval_map<'a>(
         DataValue::Array(items) => items,
         // Fast path for common case of null (treat as empty array)
         DataValue::Null => return Ok(arena.empty_array_value()),
-        _ => return Err(LogicError::InvalidArgumentsError),
+        _ => return Err(LogicError::invalid_argument("map", 0, "array", array)),
     };
 
     // Fast path for empty array
@@ -251,13 +271,16 @@ pub fn eval_map<'a>(
 
     // Apply the function to each item
     for (index, item) in items.iter().enumerate() {
+        // Stop early if the evaluation was cancelled or timed out
+        arena.check_interrupts()?;
+
         // Store the current path chain length to preserve parent contexts
         let current_chain_len = arena.path_chain_len();
         
         // Set the current item as context
         // Use the index as the key for the current context
         let key = DataValue::Number(crate::value::NumberValue::from_f64(index as f64));
-        arena.set_current_context(&item, arena.alloc(key));
+        arena.set_current_context(item, arena.alloc(key));
         
         // Evaluate the function with the item as context
         let result = evaluate(function, arena)?;
@@ -294,9 +317,7 @@ pub fn eval_filter<'a>(
     arena: &'a DataArena,
 ) -> Result<&'a DataValue<'a>> {
     // Fast path for invalid arguments
-    if args.len() != 2 {
-        return Err(LogicError::InvalidArgumentsError);
-    }
+    ArrayOp::Filter.arity().check("filter", args.len())?;
 
     // Evaluate the first argument to get the array
     let array = evaluate(args[0], arena)?;
@@ -310,7 +331,7 @@ pub fn eval_filter<'a>(
         DataValue::Array(items) => items,
         // Fast path for common case of null (treat as empty array)
         DataValue::Null => return Ok(arena.empty_array_value()),
-        _ => return Err(LogicError::InvalidArgumentsError),
+        _ => return Err(LogicError::invalid_argument("filter", 0, "array", array)),
     };
 
     // Fast path for empty array
@@ -327,11 +348,14 @@ pub fn eval_filter<'a>(
 
     // Filter the array
     for (index, item) in items.iter().enumerate() {
+        // Stop early if the evaluation was cancelled or timed out
+        arena.check_interrupts()?;
+
         // Store the current path chain length to preserve parent contexts
         let current_chain_len = arena.path_chain_len();
         
         let key = DataValue::Number(crate::value::NumberValue::from_f64(index as f64));
-        arena.set_current_context(&item, arena.alloc(key));
+        arena.set_current_context(item, arena.alloc(key));
         
         // Evaluate the condition with the item as context
         if evaluate(condition, arena)?.coerce_to_bool() {
@@ -359,6 +383,9 @@ fn is_var_with_path(token: &Token, path: &str) -> bool {
     }
 }
 
+// The reduce helpers report errors like the operator they replace would, with
+// the current item as argument 0 and the accumulator as argument 1.
+
 /// Performs a reduce operation with addition
 fn reduce_add<'a>(
     items: &'a [DataValue<'a>],
@@ -368,14 +395,14 @@ fn reduce_add<'a>(
 ) -> Result<&'a DataValue<'a>> {
     let initial_val = initial
         .coerce_to_number()
-        .ok_or(LogicError::NaNError)?
+        .ok_or_else(|| LogicError::nan("+", 1, initial))?
         .as_f64();
     let mut sum = initial_val;
 
     for item in items.iter().skip(start_idx) {
         sum += item
             .coerce_to_number()
-            .ok_or(LogicError::NaNError)?
+            .ok_or_else(|| LogicError::nan("+", 0, item))?
             .as_f64();
     }
 
@@ -391,14 +418,14 @@ fn reduce_multiply<'a>(
 ) -> Result<&'a DataValue<'a>> {
     let initial_val = initial
         .coerce_to_number()
-        .ok_or(LogicError::NaNError)?
+        .ok_or_else(|| LogicError::nan("*", 1, initial))?
         .as_f64();
     let mut product = initial_val;
 
     for item in items.iter().skip(start_idx) {
         product *= item
             .coerce_to_number()
-            .ok_or(LogicError::NaNError)?
+            .ok_or_else(|| LogicError::nan("*", 0, item))?
             .as_f64();
     }
 
@@ -414,14 +441,14 @@ fn reduce_subtract<'a>(
 ) -> Result<&'a DataValue<'a>> {
     let initial_val = initial
         .coerce_to_number()
-        .ok_or(LogicError::NaNError)?
+        .ok_or_else(|| LogicError::nan("-", 1, initial))?
         .as_f64();
     let mut result = initial_val;
 
     for item in items.iter().skip(start_idx) {
         result -= item
             .coerce_to_number()
-            .ok_or(LogicError::NaNError)?
+            .ok_or_else(|| LogicError::nan("-", 0, item))?
             .as_f64();
     }
 
@@ -437,17 +464,17 @@ fn reduce_divide<'a>(
 ) -> Result<&'a DataValue<'a>> {
     let initial_val = initial
         .coerce_to_number()
-        .ok_or(LogicError::NaNError)?
+        .ok_or_else(|| LogicError::nan("/", 1, initial))?
         .as_f64();
     let mut result = initial_val;
 
     for item in items.iter().skip(start_idx) {
         let divisor = item
             .coerce_to_number()
-            .ok_or(LogicError::NaNError)?
+            .ok_or_else(|| LogicError::nan("/", 0, item))?
             .as_f64();
         if divisor == 0.0 {
-            return Err(LogicError::NaNError);
+            return Err(LogicError::division_by_zero("/", 0));
         }
         result /= divisor;
     }
@@ -464,17 +491,17 @@ fn reduce_modulo<'a>(
 ) -> Result<&'a DataValue<'a>> {
     let initial_val = initial
         .coerce_to_number()
-        .ok_or(LogicError::NaNError)?
+        .ok_or_else(|| LogicError::nan("%", 1, initial))?
         .as_f64();
     let mut result = initial_val;
 
     for item in items.iter().skip(start_idx) {
         let divisor = item
             .coerce_to_number()
-            .ok_or(LogicError::NaNError)?
+            .ok_or_else(|| LogicError::nan("%", 0, item))?
             .as_f64();
         if divisor == 0.0 {
-            return Err(LogicError::NaNError);
+            return Err(LogicError::division_by_zero("%", 0));
         }
         result %= divisor;
     }
@@ -491,14 +518,14 @@ fn reduce_min<'a>(
 ) -> Result<&'a DataValue<'a>> {
     let initial_val = initial
         .coerce_to_number()
-        .ok_or(LogicError::NaNError)?
+        .ok_or_else(|| LogicError::nan("min", 1, initial))?
         .as_f64();
     let mut min_val = initial_val;
 
     for item in items.iter().skip(start_idx) {
         let val = item
             .coerce_to_number()
-            .ok_or(LogicError::NaNError)?
+            .ok_or_else(|| LogicError::nan("min", 0, item))?
             .as_f64();
         min_val = min_val.min(val);
     }
@@ -515,14 +542,14 @@ fn reduce_max<'a>(
 ) -> Result<&'a DataValue<'a>> {
     let initial_val = initial
         .coerce_to_number()
-        .ok_or(LogicError::NaNError)?
+        .ok_or_else(|| LogicError::nan("max", 1, initial))?
         .as_f64();
     let mut max_val = initial_val;
 
     for item in items.iter().skip(start_idx) {
         let val = item
             .coerce_to_number()
-            .ok_or(LogicError::NaNError)?
+            .ok_or_else(|| LogicError::nan("max", 0, item))?
             .as_f64();
         max_val = max_val.max(val);
     }
@@ -530,20 +557,37 @@ fn reduce_max<'a>(
     Ok(arena.alloc(DataValue::float(max_val)))
 }
 
+/// Performs a reduce operation with any arithmetic operator, one item at a time
+///
+/// This keeps decimal arithmetic exact, and rounds like `round` does.
+fn reduce_arithmetic<'a>(
+    op: ArithmeticOp,
+    items: &'a [DataValue<'a>],
+    initial: &'a DataValue<'a>,
+    start_idx: usize,
+    arena: &'a DataArena,
+) -> Result<&'a DataValue<'a>> {
+    let mut acc = initial;
+    for item in items.iter().skip(start_idx) {
+        let args = arena.vec_into_slice(vec![item.clone(), acc.clone()]);
+        acc = arithmetic::eval_arithmetic(op, args, arena)?;
+    }
+    Ok(acc)
+}
+
 /// Checks if an operator token matches the expected pattern for optimized arithmetic operations
 fn is_arithmetic_reduce_pattern<'a>(function: &'a Token<'a>) -> Option<ArithmeticOp> {
     if let Token::Operator {
         op_type: OperatorType::Arithmetic(arith_op),
         args: Token::ArrayLiteral(fn_args_tokens),
     } = function
+        && fn_args_tokens.len() == 2
     {
-        if fn_args_tokens.len() == 2 {
-            let is_var_current = is_var_with_path(fn_args_tokens[0], "current");
-            let is_var_acc = is_var_with_path(fn_args_tokens[1], "accumulator");
+        let is_var_current = is_var_with_path(fn_args_tokens[0], "current");
+        let is_var_acc = is_var_with_path(fn_args_tokens[1], "accumulator");
 
-            if is_var_current && is_var_acc {
-                return Some(*arith_op);
-            }
+        if is_var_current && is_var_acc {
+            return Some(*arith_op);
         }
     }
     None
@@ -568,9 +612,7 @@ pub fn eval_reduce<'a>(
     arena: &'a DataArena,
 ) -> Result<&'a DataValue<'a>> {
     // Fast path for invalid arguments
-    if args.len() < 2 || args.len() > 3 {
-        return Err(LogicError::InvalidArgumentsError);
-    }
+    ArrayOp::Reduce.arity().check("reduce", args.len())?;
 
     // Evaluate the first argument to get the array
     let array = evaluate(args[0], arena)?;
@@ -588,9 +630,14 @@ pub fn eval_reduce<'a>(
             if args.len() == 3 {
                 return evaluate(args[2], arena);
             }
-            return Err(LogicError::InvalidArgumentsError);
+            return Err(LogicError::invalid_argument(
+                "reduce",
+                0,
+                "non-empty array or an initial value",
+                array,
+            ));
         }
-        _ => return Err(LogicError::InvalidArgumentsError),
+        _ => return Err(LogicError::invalid_argument("reduce", 0, "array", array)),
     };
 
     // Fast path for empty array
@@ -599,7 +646,12 @@ pub fn eval_reduce<'a>(
         if args.len() == 3 {
             return evaluate(args[2], arena);
         }
-        return Err(LogicError::InvalidArgumentsError);
+        return Err(LogicError::invalid_argument(
+            "reduce",
+            0,
+            "non-empty array or an initial value",
+            array,
+        ));
     }
 
     // Get the initial value
@@ -618,6 +670,12 @@ pub fn eval_reduce<'a>(
 
     // Optimization for arithmetic operators - desugar reduce to direct arithmetic operation
     if let Some(arith_op) = is_arithmetic_reduce_pattern(function) {
+        arena.check_interrupts()?;
+
+        if arena.decimal_arithmetic().is_some() {
+            return reduce_arithmetic(arith_op, items, initial, start_idx, arena);
+        }
+
         // Use our specialized helper functions for each arithmetic operation
         return match arith_op {
             ArithmeticOp::Add => reduce_add(items, initial, start_idx, arena),
@@ -627,6 +685,7 @@ pub fn eval_reduce<'a>(
             ArithmeticOp::Modulo => reduce_modulo(items, initial, start_idx, arena),
             ArithmeticOp::Min => reduce_min(items, initial, start_idx, arena),
             ArithmeticOp::Max => reduce_max(items, initial, start_idx, arena),
+            _ => reduce_arithmetic(arith_op, items, initial, start_idx, arena),
         };
     }
 
@@ -641,6 +700,9 @@ pub fn eval_reduce<'a>(
 
     // Reduce the array using the generic approach
     for (index, item) in items.iter().enumerate() {
+        // Stop early if the evaluation was cancelled or timed out
+        arena.check_interrupts()?;
+
         // Store the current path chain length to preserve parent contexts
         let current_chain_len = arena.path_chain_len();
         
@@ -653,7 +715,7 @@ pub fn eval_reduce<'a>(
 
         // Create the context object
         let context = arena.alloc(DataValue::Object(context_entries));
-        arena.set_current_context(&context, &key);
+        arena.set_current_context(context, &key);
 
         // Evaluate the function with the context
         acc = evaluate(function, arena)?;
@@ -708,9 +770,7 @@ pub fn eval_in<'a>(
     args: &'a [&'a Token<'a>],
     arena: &'a DataArena,
 ) -> Result<&'a DataValue<'a>> {
-    if args.len() != 2 {
-        return Err(LogicError::InvalidArgumentsError);
-    }
+    ArrayOp::In.arity().check("in", args.len())?;
 
     let needle = evaluate(args[0], arena)?;
     let haystack = evaluate(args[1], arena)?;
@@ -749,6 +809,65 @@ pub fn eval_in<'a>(
     }
 }
 
+/// Evaluates an in operation against a set of literals.
+///
+/// The second argument is a literal array sorted by [`set_order`] that only
+/// holds booleans, numbers and strings, the only values `in` matches.
+pub fn eval_in_set<'a>(
+    args: &'a [&'a Token<'a>],
+    arena: &'a DataArena,
+) -> Result<&'a DataValue<'a>> {
+    ArrayOp::InSet.arity().check("in", args.len())?;
+
+    let Token::Literal(DataValue::Array(members)) = args[1] else {
+        return eval_in(args, arena);
+    };
+    let needle = evaluate(args[0], arena)?;
+
+    let found = matches!(
+        needle,
+        DataValue::Bool(_) | DataValue::Number(_) | DataValue::String(_)
+    ) && {
+        let start = members.partition_point(|member| set_order(member, needle).is_lt());
+        members[start..]
+            .iter()
+            .take_while(|member| set_order(member, needle).is_eq())
+            .any(|member| member == needle)
+    };
+
+    if found {
+        Ok(arena.true_value())
+    } else {
+        Ok(arena.false_value())
+    }
+}
+
+/// Orders the members of a set for `in`: booleans, then numbers, then
+/// strings.
+///
+/// Numbers are ordered by their floating point value, so equal numbers are
+/// next to each other whether they are integers or floats.
+pub(crate) fn set_order(a: &DataValue, b: &DataValue) -> Ordering {
+    fn rank(value: &DataValue) -> u8 {
+        match value {
+            DataValue::Bool(_) => 0,
+            DataValue::Number(_) => 1,
+            _ => 2,
+        }
+    }
+
+    match (a, b) {
+        (DataValue::Bool(a), DataValue::Bool(b)) => a.cmp(b),
+        (DataValue::Number(a), DataValue::Number(b)) => {
+            let (a, b) = (a.as_f64(), b.as_f64());
+            a.partial_cmp(&b)
+                .unwrap_or_else(|| a.is_nan().cmp(&b.is_nan()))
+        }
+        (DataValue::String(a), DataValue::String(b)) => a.cmp(b),
+        _ => rank(a).cmp(&rank(b)),
+    }
+}
+
 #[cfg(test)]
 mod tests {
     use crate::logic::datalogic_core::DataLogicCore;
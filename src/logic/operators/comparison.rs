@@ -3,7 +3,11 @@
 //! This module provides implementations for comparison operators
 //! such as equal, not equal, greater than, etc.
 
+use std::cmp::Ordering;
+
 use crate::arena::DataArena;
+use crate::logic::operators::Arity;
+use crate::logic::operators::datetime::{as_datetime, as_duration};
 use crate::logic::error::{LogicError, Result};
 use crate::logic::evaluator::evaluate;
 use crate::logic::token::Token;
@@ -30,119 +34,43 @@ pub enum ComparisonOp {
     LessThanOrEqual,
 }
 
-/// Evaluates an equality comparison.
-pub fn eval_equal<'a>(
-    args: &'a [&'a Token<'a>],
-    arena: &'a DataArena,
-) -> Result<&'a DataValue<'a>> {
-    if args.len() < 2 {
-        return Err(LogicError::InvalidArgumentsError);
-    }
-
-    for i in 0..args.len() - 1 {
-        let left = evaluate(args[i], arena)?;
-        let right = evaluate(args[i + 1], arena)?;
-
-        // Fast path for identical references
-        if std::ptr::eq(left as *const DataValue, right as *const DataValue) {
-            continue;
-        }
-
-        match (left, right) {
-            (DataValue::Number(a), DataValue::Number(b)) => {
-                if a.as_f64() != b.as_f64() {
-                    return Ok(arena.false_value());
-                }
-            }
-            (DataValue::String(a), DataValue::String(b)) => {
-                if a != b {
-                    return Ok(arena.false_value());
-                }
-            }
-            (DataValue::Bool(a), DataValue::Bool(b)) => {
-                if a != b {
-                    return Ok(arena.false_value());
-                }
-            }
-            (DataValue::Null, DataValue::Null) => {
-                continue;
-            }
-            (DataValue::Number(_), DataValue::String(s)) => {
-                // Try to parse the string as a number
-                if let Ok(num) = s.parse::<f64>() {
-                    let left_num = left.coerce_to_number().unwrap();
-                    if left_num.as_f64() != num {
-                        return Ok(arena.false_value());
-                    }
-                } else {
-                    // String is not a valid number
-                    return Err(LogicError::NaNError);
-                }
-            }
-            (DataValue::String(s), DataValue::Number(_)) => {
-                // Try to parse the string as a number
-                if let Ok(num) = s.parse::<f64>() {
-                    let right_num = right.coerce_to_number().unwrap();
-                    if num != right_num.as_f64() {
-                        return Ok(arena.false_value());
-                    }
-                } else {
-                    // String is not a valid number
-                    return Err(LogicError::NaNError);
-                }
-            }
-            (DataValue::Array(_), DataValue::Array(_)) => {
-                // Arrays should be compared by reference, not by value
-                return Err(LogicError::NaNError);
-            }
-            (DataValue::Array(_), _) | (_, DataValue::Array(_)) => {
-                // Arrays can't be compared with non-arrays
-                return Err(LogicError::NaNError);
-            }
-            (DataValue::Object(_), _) | (_, DataValue::Object(_)) => {
-                // Objects can't be compared with anything else
-                return Err(LogicError::NaNError);
-            }
-            _ => {
-                // Try numeric coercion for other cases
-                if let (Some(a), Some(b)) = (left.coerce_to_number(), right.coerce_to_number()) {
-                    if a.as_f64() != b.as_f64() {
-                        return Ok(arena.false_value());
-                    }
-                } else {
-                    // If numeric coercion fails, fall back to string comparison
-                    let left_str = left.coerce_to_string(arena);
-                    let right_str = right.coerce_to_string(arena);
-
-                    if let (DataValue::String(a), DataValue::String(b)) = (&left_str, &right_str) {
-                        if a != b {
-                            return Ok(arena.false_value());
-                        }
-                    } else {
-                        return Ok(arena.false_value());
-                    }
-                }
-            }
+impl ComparisonOp {
+    /// Returns the name of the operator.
+    pub(crate) const fn as_str(self) -> &'static str {
+        match self {
+            ComparisonOp::Equal => "==",
+            ComparisonOp::StrictEqual => "===",
+            ComparisonOp::NotEqual => "!=",
+            ComparisonOp::StrictNotEqual => "!==",
+            ComparisonOp::GreaterThan => ">",
+            ComparisonOp::GreaterThanOrEqual => ">=",
+            ComparisonOp::LessThan => "<",
+            ComparisonOp::LessThanOrEqual => "<=",
         }
     }
 
-    Ok(arena.true_value())
+    /// Returns the number of arguments the operator accepts.
+    pub(crate) const fn arity(self) -> Arity {
+        Arity::at_least(2)
+    }
 }
 
-/// Evaluates a strict equality comparison.
-pub fn eval_strict_equal<'a>(
+/// Evaluates a comparison with the given operator.
+///
+/// Each argument is compared with the next one, and the comparison holds if
+/// all of these do.
+pub fn eval_comparison<'a>(
+    op: ComparisonOp,
     args: &'a [&'a Token<'a>],
     arena: &'a DataArena,
 ) -> Result<&'a DataValue<'a>> {
-    if args.len() < 2 {
-        return Err(LogicError::InvalidArgumentsError);
-    }
+    op.arity().check(op.as_str(), args.len())?;
 
     for i in 0..args.len() - 1 {
         let left = evaluate(args[i], arena)?;
         let right = evaluate(args[i + 1], arena)?;
 
-        if !left.strict_equals(right) {
+        if !compare(op, i, left, right, arena)? {
             return Ok(arena.false_value());
         }
     }
@@ -150,250 +78,187 @@ pub fn eval_strict_equal<'a>(
     Ok(arena.true_value())
 }
 
-/// Evaluates a not equal comparison.
-pub fn eval_not_equal<'a>(
-    args: &'a [&'a Token<'a>],
-    arena: &'a DataArena,
-) -> Result<&'a DataValue<'a>> {
-    if args.len() < 2 {
-        return Err(LogicError::InvalidArgumentsError);
-    }
-
-    for i in 0..args.len() - 1 {
-        let left = evaluate(args[i], arena)?;
-        let right = evaluate(args[i + 1], arena)?;
-
-        match (left, right) {
-            (DataValue::Number(a), DataValue::Number(b)) => {
-                if a.as_f64() == b.as_f64() {
-                    return Ok(arena.false_value());
-                }
-            }
-            (DataValue::String(a), DataValue::String(b)) => {
-                if a == b {
-                    return Ok(arena.false_value());
-                }
-            }
-            (DataValue::Bool(a), DataValue::Bool(b)) => {
-                if a == b {
-                    return Ok(arena.false_value());
-                }
-            }
-            (DataValue::Null, DataValue::Null) => {
-                return Ok(arena.false_value());
-            }
-            _ => {
-                let left_num = left.coerce_to_number().ok_or(LogicError::NaNError)?;
-                let right_num = right.coerce_to_number().ok_or(LogicError::NaNError)?;
-                if left_num.as_f64() == right_num.as_f64() {
-                    return Ok(arena.false_value());
-                }
-            }
-        }
+/// Compares two adjacent arguments of a comparison, `left` being the
+/// argument at `index`.
+#[inline]
+pub(crate) fn compare(
+    op: ComparisonOp,
+    index: usize,
+    left: &DataValue,
+    right: &DataValue,
+    arena: &DataArena,
+) -> Result<bool> {
+    match op {
+        ComparisonOp::Equal => equal(index, left, right, arena),
+        ComparisonOp::StrictEqual => Ok(left.strict_equals(right)),
+        ComparisonOp::NotEqual => not_equal(index, left, right),
+        ComparisonOp::StrictNotEqual => Ok(!left.strict_equals(right)),
+        ComparisonOp::GreaterThan
+        | ComparisonOp::GreaterThanOrEqual
+        | ComparisonOp::LessThan
+        | ComparisonOp::LessThanOrEqual => ordered(op, index, left, right),
     }
-
-    Ok(arena.true_value())
 }
 
-/// Evaluates a strict not-equal comparison.
-pub fn eval_strict_not_equal<'a>(
-    args: &'a [&'a Token<'a>],
-    arena: &'a DataArena,
-) -> Result<&'a DataValue<'a>> {
-    if args.len() < 2 {
-        return Err(LogicError::InvalidArgumentsError);
-    }
-
-    for i in 0..args.len() - 1 {
-        let left = evaluate(args[i], arena)?;
-        let right = evaluate(args[i + 1], arena)?;
-
-        if left.strict_equals(right) {
-            return Ok(arena.false_value());
-        }
+/// Compares two values for loose equality.
+fn equal(index: usize, left: &DataValue, right: &DataValue, arena: &DataArena) -> Result<bool> {
+    // Fast path for identical references
+    if std::ptr::eq(left, right) {
+        return Ok(true);
     }
 
-    Ok(arena.true_value())
-}
-
-/// Evaluates a greater-than comparison.
-pub fn eval_greater_than<'a>(
-    args: &'a [&'a Token<'a>],
-    arena: &'a DataArena,
-) -> Result<&'a DataValue<'a>> {
-    if args.len() < 2 {
-        return Err(LogicError::InvalidArgumentsError);
+    if let Some(ordering) = temporal_ordering("==", index, left, right) {
+        return Ok(matches!(ordering, Ok(Ordering::Equal)));
     }
 
-    for i in 0..args.len() - 1 {
-        let left = evaluate(args[i], arena)?;
-        let right = evaluate(args[i + 1], arena)?;
-
-        match (left, right) {
-            (DataValue::Number(a), DataValue::Number(b)) => {
-                if a.as_f64() <= b.as_f64() {
-                    return Ok(arena.false_value());
-                }
-            }
-            (DataValue::String(a), DataValue::String(b)) => {
-                if a <= b {
-                    return Ok(arena.false_value());
-                }
-            }
-            (DataValue::Bool(a), DataValue::Bool(b)) => {
-                if a <= b {
-                    return Ok(arena.false_value());
-                }
-            }
-            (DataValue::Null, DataValue::Null) => {
-                return Ok(arena.false_value());
+    match (left, right) {
+        // Decimals are compared exactly
+        (DataValue::Number(a), DataValue::Number(b)) if a.is_decimal() || b.is_decimal() => {
+            Ok(a == b)
+        }
+        (DataValue::Number(a), DataValue::Number(b)) => Ok(a.as_f64() == b.as_f64()),
+        (DataValue::String(a), DataValue::String(b)) => Ok(a == b),
+        (DataValue::Bool(a), DataValue::Bool(b)) => Ok(a == b),
+        (DataValue::Null, DataValue::Null) => Ok(true),
+        (DataValue::Number(_), DataValue::String(s)) => match s.parse::<f64>() {
+            // Compare with the string parsed as a number
+            Ok(num) => Ok(left.coerce_to_number().unwrap().as_f64() == num),
+            // String is not a valid number
+            Err(_) => Err(LogicError::nan("==", index + 1, right)),
+        },
+        (DataValue::String(s), DataValue::Number(_)) => match s.parse::<f64>() {
+            Ok(num) => Ok(num == right.coerce_to_number().unwrap().as_f64()),
+            Err(_) => Err(LogicError::nan("==", index, left)),
+        },
+        // Arrays should be compared by reference, not by value
+        (DataValue::Array(_), DataValue::Array(_)) => Err(LogicError::nan("==", index, left)),
+        (DataValue::Array(_), _) | (_, DataValue::Array(_)) => {
+            // Arrays can't be compared with non-arrays
+            let (index, value) = if left.is_array() {
+                (index, left)
+            } else {
+                (index + 1, right)
+            };
+            Err(LogicError::nan("==", index, value))
+        }
+        (DataValue::Object(_), _) | (_, DataValue::Object(_)) => {
+            // Objects can't be compared with anything else
+            let (index, value) = if left.is_object() {
+                (index, left)
+            } else {
+                (index + 1, right)
+            };
+            Err(LogicError::nan("==", index, value))
+        }
+        _ => {
+            // Try numeric coercion for other cases
+            if let (Some(a), Some(b)) = (left.coerce_to_number(), right.coerce_to_number()) {
+                return Ok(a.as_f64() == b.as_f64());
             }
-            _ => {
-                let left_num = left.coerce_to_number().ok_or(LogicError::NaNError)?;
-                let right_num = right.coerce_to_number().ok_or(LogicError::NaNError)?;
-                if left_num.as_f64() <= right_num.as_f64() {
-                    return Ok(arena.false_value());
-                }
+
+            // If numeric coercion fails, fall back to string comparison
+            match (left.coerce_to_string(arena), right.coerce_to_string(arena)) {
+                (DataValue::String(a), DataValue::String(b)) => Ok(a == b),
+                _ => Ok(false),
             }
         }
     }
-
-    Ok(arena.true_value())
 }
 
-/// Evaluates a greater-than-or-equal comparison.
-pub fn eval_greater_than_or_equal<'a>(
-    args: &'a [&'a Token<'a>],
-    arena: &'a DataArena,
-) -> Result<&'a DataValue<'a>> {
-    if args.len() < 2 {
-        return Err(LogicError::InvalidArgumentsError);
+/// Compares two values for loose inequality.
+fn not_equal(index: usize, left: &DataValue, right: &DataValue) -> Result<bool> {
+    if let Some(ordering) = temporal_ordering("!=", index, left, right) {
+        return Ok(!matches!(ordering, Ok(Ordering::Equal)));
     }
 
-    for i in 0..args.len() - 1 {
-        let left = evaluate(args[i], arena)?;
-        let right = evaluate(args[i + 1], arena)?;
-
-        match (left, right) {
-            (DataValue::Number(a), DataValue::Number(b)) => {
-                if a.as_f64() < b.as_f64() {
-                    return Ok(arena.false_value());
-                }
-            }
-            (DataValue::String(a), DataValue::String(b)) => {
-                if a < b {
-                    return Ok(arena.false_value());
-                }
-            }
-            (DataValue::Bool(a), DataValue::Bool(b)) => {
-                if a < b {
-                    return Ok(arena.false_value());
-                }
-            }
-            (DataValue::Null, DataValue::Null) => {
-                return Ok(arena.false_value());
-            }
-            _ => {
-                let left_num = left.coerce_to_number().ok_or(LogicError::NaNError)?;
-                let right_num = right.coerce_to_number().ok_or(LogicError::NaNError)?;
-                if left_num.as_f64() < right_num.as_f64() {
-                    return Ok(arena.false_value());
-                }
-            }
+    match (left, right) {
+        (DataValue::Number(a), DataValue::Number(b)) if a.is_decimal() || b.is_decimal() => {
+            Ok(a != b)
+        }
+        (DataValue::Number(a), DataValue::Number(b)) => Ok(a.as_f64() != b.as_f64()),
+        (DataValue::String(a), DataValue::String(b)) => Ok(a != b),
+        (DataValue::Bool(a), DataValue::Bool(b)) => Ok(a != b),
+        (DataValue::Null, DataValue::Null) => Ok(false),
+        _ => {
+            let left_num = left
+                .coerce_to_number()
+                .ok_or_else(|| LogicError::nan("!=", index, left))?;
+            let right_num = right
+                .coerce_to_number()
+                .ok_or_else(|| LogicError::nan("!=", index + 1, right))?;
+            Ok(left_num.as_f64() != right_num.as_f64())
         }
     }
-
-    Ok(arena.true_value())
 }
 
-/// Evaluates a less-than comparison.
-pub fn eval_less_than<'a>(
-    args: &'a [&'a Token<'a>],
-    arena: &'a DataArena,
-) -> Result<&'a DataValue<'a>> {
-    if args.len() < 2 {
-        return Err(LogicError::InvalidArgumentsError);
+/// Compares the order of two values with `>`, `>=`, `<` or `<=`.
+fn ordered(op: ComparisonOp, index: usize, left: &DataValue, right: &DataValue) -> Result<bool> {
+    if let Some(ordering) = temporal_ordering(op.as_str(), index, left, right) {
+        return Ok(holds(op, ordering?, Ordering::Equal));
     }
 
-    for i in 0..args.len() - 1 {
-        let left = evaluate(args[i], arena)?;
-        let right = evaluate(args[i + 1], arena)?;
-
-        match (left, right) {
-            (DataValue::Number(a), DataValue::Number(b)) => {
-                if a.as_f64() >= b.as_f64() {
-                    return Ok(arena.false_value());
-                }
-            }
-            (DataValue::String(a), DataValue::String(b)) => {
-                if a >= b {
-                    return Ok(arena.false_value());
-                }
-            }
-            (DataValue::Bool(a), DataValue::Bool(b)) => {
-                if a >= b {
-                    return Ok(arena.false_value());
-                }
-            }
-            (DataValue::Null, DataValue::Null) => {
-                return Ok(arena.false_value());
-            }
-            _ => {
-                let left_num = left.coerce_to_number().ok_or(LogicError::NaNError)?;
-                let right_num = right.coerce_to_number().ok_or(LogicError::NaNError)?;
-                if left_num.as_f64() >= right_num.as_f64() {
-                    return Ok(arena.false_value());
-                }
-            }
+    match (left, right) {
+        (DataValue::Number(a), DataValue::Number(b)) if a.is_decimal() || b.is_decimal() => {
+            Ok(holds(op, a, b))
+        }
+        (DataValue::Number(a), DataValue::Number(b)) => Ok(holds(op, a.as_f64(), b.as_f64())),
+        (DataValue::String(a), DataValue::String(b)) => Ok(holds(op, a, b)),
+        (DataValue::Bool(a), DataValue::Bool(b)) => Ok(holds(op, a, b)),
+        (DataValue::Null, DataValue::Null) => Ok(false),
+        _ => {
+            let left_num = left
+                .coerce_to_number()
+                .ok_or_else(|| LogicError::nan(op.as_str(), index, left))?;
+            let right_num = right
+                .coerce_to_number()
+                .ok_or_else(|| LogicError::nan(op.as_str(), index + 1, right))?;
+            Ok(holds(op, left_num.as_f64(), right_num.as_f64()))
         }
     }
-
-    Ok(arena.true_value())
 }
 
-/// Evaluates a less-than-or-equal comparison.
-pub fn eval_less_than_or_equal<'a>(
-    args: &'a [&'a Token<'a>],
-    arena: &'a DataArena,
-) -> Result<&'a DataValue<'a>> {
-    if args.len() < 2 {
-        return Err(LogicError::InvalidArgumentsError);
-    }
-
-    for i in 0..args.len() - 1 {
-        let left = evaluate(args[i], arena)?;
-        let right = evaluate(args[i + 1], arena)?;
-
-        match (left, right) {
-            (DataValue::Number(a), DataValue::Number(b)) => {
-                if a.as_f64() > b.as_f64() {
-                    return Ok(arena.false_value());
-                }
-            }
-            (DataValue::String(a), DataValue::String(b)) => {
-                if a > b {
-                    return Ok(arena.false_value());
-                }
-            }
-            (DataValue::Bool(a), DataValue::Bool(b)) => {
-                if a > b {
-                    return Ok(arena.false_value());
-                }
-            }
-            (DataValue::Null, DataValue::Null) => {
-                return Ok(arena.false_value());
-            }
-            _ => {
-                let left_num = left.coerce_to_number().ok_or(LogicError::NaNError)?;
-                let right_num = right.coerce_to_number().ok_or(LogicError::NaNError)?;
-                if left_num.as_f64() > right_num.as_f64() {
-                    return Ok(arena.false_value());
-                }
-            }
+/// Orders two values if either of them is a datetime or a duration.
+///
+/// A string is parsed to compare it with a datetime or a duration. Returns
+/// `None` if neither value is temporal, and a NaN error for the other value
+/// if it cannot be compared.
+fn temporal_ordering(
+    op: &str,
+    index: usize,
+    left: &DataValue,
+    right: &DataValue,
+) -> Option<Result<Ordering>> {
+    let ordering = match (left, right) {
+        (DataValue::DateTime(a), _) => as_datetime(right).map(|b| a.cmp(&b)),
+        (_, DataValue::DateTime(b)) => as_datetime(left).map(|a| a.cmp(b)),
+        (DataValue::Duration(a), _) => as_duration(right).map(|b| a.cmp(&b)),
+        (_, DataValue::Duration(b)) => as_duration(left).map(|a| a.cmp(b)),
+        _ => return None,
+    };
+
+    Some(ordering.ok_or_else(|| {
+        if matches!(left, DataValue::DateTime(_) | DataValue::Duration(_)) {
+            LogicError::nan(op, index + 1, right)
+        } else {
+            LogicError::nan(op, index, left)
         }
-    }
+    }))
+}
 
-    Ok(arena.true_value())
+/// Applies an ordering comparison.
+///
+/// Incomparable values, such as `NaN`, only fail a comparison that holds
+/// for the opposite ordering.
+#[inline]
+fn holds<T: PartialOrd>(op: ComparisonOp, a: T, b: T) -> bool {
+    let ordering = a.partial_cmp(&b);
+    match op {
+        ComparisonOp::GreaterThan => !matches!(ordering, Some(Ordering::Less | Ordering::Equal)),
+        ComparisonOp::GreaterThanOrEqual => ordering != Some(Ordering::Less),
+        ComparisonOp::LessThan => !matches!(ordering, Some(Ordering::Greater | Ordering::Equal)),
+        ComparisonOp::LessThanOrEqual => ordering != Some(Ordering::Greater),
+        _ => unreachable!("not an ordering comparison"),
+    }
 }
 
 #[cfg(test)]
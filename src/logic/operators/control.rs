@@ -4,7 +4,8 @@
 //! such as and, or, not, etc.
 
 use crate::arena::DataArena;
-use crate::logic::error::{LogicError, Result};
+use crate::logic::operators::Arity;
+use crate::logic::error::Result;
 use crate::logic::evaluator::evaluate;
 use crate::logic::token::Token;
 use crate::value::DataValue;
@@ -24,6 +25,16 @@ pub enum ControlOp {
     DoubleNegation,
 }
 
+impl ControlOp {
+    /// Returns the number of arguments the operator accepts.
+    pub(crate) const fn arity(self) -> Arity {
+        match self {
+            ControlOp::If | ControlOp::And | ControlOp::Or => Arity::ANY,
+            ControlOp::Not | ControlOp::DoubleNegation => Arity::exactly(1),
+        }
+    }
+}
+
 /// Evaluates an if operation.
 pub fn eval_if<'a>(
     args: &'a [&'a Token<'a>],
@@ -127,9 +138,7 @@ pub fn eval_not<'a>(
     args: &'a [&'a Token<'a>],
     arena: &'a DataArena,
 ) -> Result<&'a DataValue<'a>> {
-    if args.len() != 1 {
-        return Err(LogicError::InvalidArgumentsError);
-    }
+    ControlOp::Not.arity().check("!", args.len())?;
 
     let value = evaluate(args[0], arena)?;
     Ok(arena.alloc(DataValue::Bool(!value.coerce_to_bool())))
@@ -140,9 +149,7 @@ pub fn eval_double_negation<'a>(
     args: &'a [&'a Token<'a>],
     arena: &'a DataArena,
 ) -> Result<&'a DataValue<'a>> {
-    if args.len() != 1 {
-        return Err(LogicError::InvalidArgumentsError);
-    }
+    ControlOp::DoubleNegation.arity().check("!!", args.len())?;
 
     let value = evaluate(args[0], arena)?;
     Ok(arena.alloc(DataValue::Bool(value.coerce_to_bool())))
@@ -0,0 +1,521 @@
+//! Custom operators for logic expressions.
+//!
+//! This module provides the traits that user-defined operators implement,
+//! and the registry that maps operator names to their implementations.
+
+use std::collections::HashMap;
+use std::sync::Arc;
+use std::fmt;
+
+use crate::arena::DataArena;
+use crate::logic::error::Result;
+use crate::logic::evaluator::evaluate;
+use crate::logic::token::Token;
+use crate::value::DataValue;
+
+/// Trait for user-defined operators.
+///
+/// Custom operators receive their arguments already evaluated against the
+/// current data context. The result must be allocated in the provided arena.
+///
+/// # Examples
+///
+/// ```
+/// use datalogic_rs::{CustomOperator, DataArena, DataLogic, DataValue, LogicError, Result};
+///
+/// struct HypotOperator;
+///
+/// impl CustomOperator for HypotOperator {
+///     fn evaluate<'a>(
+///         &self,
+///         args: &'a [DataValue<'a>],
+///         arena: &'a DataArena,
+///     ) -> Result<&'a DataValue<'a>> {
+///         if args.len() != 2 {
+///             return Err(LogicError::argument_count("hypot", "2 arguments", args.len()));
+///         }
+///         let a = args[0].coerce_to_number().ok_or_else(|| LogicError::nan("hypot", 0, &args[0]))?;
+///         let b = args[1].coerce_to_number().ok_or_else(|| LogicError::nan("hypot", 1, &args[1]))?;
+///         Ok(arena.alloc(DataValue::float(a.as_f64().hypot(b.as_f64()))))
+///     }
+/// }
+///
+/// let mut dl = DataLogic::new();
+/// dl.register_operator("hypot", Box::new(HypotOperator));
+///
+/// let result = dl.evaluate_str(r#"{"hypot": [3, {"var": "b"}]}"#, r#"{"b": 4}"#, None).unwrap();
+/// assert_eq!(result.as_f64(), Some(5.0));
+/// ```
+pub trait CustomOperator: Send + Sync {
+    /// Evaluates the operator with the given evaluated arguments.
+    fn evaluate<'a>(
+        &self,
+        args: &'a [DataValue<'a>],
+        arena: &'a DataArena,
+    ) -> Result<&'a DataValue<'a>>;
+}
+
+/// Trait for user-defined operators that control the evaluation of their arguments.
+///
+/// Lazy operators receive their arguments as unevaluated tokens, together with
+/// an [`EvaluationContext`] that evaluates them on demand. This allows
+/// short-circuiting and iteration in the same way as the built-in `if`, `map`
+/// or `filter` operators.
+///
+/// # Examples
+///
+/// ```
+/// use datalogic_rs::logic::Token;
+/// use datalogic_rs::{DataLogic, DataValue, EvaluationContext, LazyCustomOperator, LogicError, Result};
+///
+/// /// Returns the number of array items that satisfy a condition.
+/// struct CountIf;
+///
+/// impl LazyCustomOperator for CountIf {
+///     fn evaluate<'a>(
+///         &self,
+///         args: &'a [&'a Token<'a>],
+///         context: &EvaluationContext<'a>,
+///     ) -> Result<&'a DataValue<'a>> {
+///         if args.len() != 2 {
+///             return Err(LogicError::argument_count("count_if", "2 arguments", args.len()));
+///         }
+///         let items = match context.evaluate(args[0])? {
+///             DataValue::Array(items) => *items,
+///             other => return Err(LogicError::invalid_argument("count_if", 0, "array", other)),
+///         };
+///
+///         let mut count = 0;
+///         for (index, item) in items.iter().enumerate() {
+///             if context.evaluate_with_item(args[1], item, index)?.coerce_to_bool() {
+///                 count += 1;
+///             }
+///         }
+///         Ok(context.arena().alloc(DataValue::integer(count)))
+///     }
+/// }
+///
+/// let mut dl = DataLogic::new();
+/// dl.register_lazy_operator("count_if", Box::new(CountIf));
+///
+/// let result = dl.evaluate_str(
+///     r#"{"count_if": [{"var": "scores"}, {">": [{"var": ""}, 50]}]}"#,
+///     r#"{"scores": [10, 60, 80]}"#,
+///     None,
+/// ).unwrap();
+/// assert_eq!(result.as_i64(), Some(2));
+/// ```
+pub trait LazyCustomOperator: Send + Sync {
+    /// Evaluates the operator with the given unevaluated arguments.
+    fn evaluate<'a>(
+        &self,
+        args: &'a [&'a Token<'a>],
+        context: &EvaluationContext<'a>,
+    ) -> Result<&'a DataValue<'a>>;
+}
+
+/// Handle given to lazy custom operators for evaluating their arguments.
+///
+/// The handle evaluates tokens against the current data context and can
+/// switch the current context while evaluating, in the same way the
+/// built-in array operators do for each item.
+#[derive(Debug, Clone, Copy)]
+pub struct EvaluationContext<'a> {
+    arena: &'a DataArena,
+}
+
+impl<'a> EvaluationContext<'a> {
+    /// Creates a new evaluation context over the given arena.
+    pub fn new(arena: &'a DataArena) -> Self {
+        Self { arena }
+    }
+
+    /// Returns the arena used for the evaluation.
+    pub fn arena(&self) -> &'a DataArena {
+        self.arena
+    }
+
+    /// Evaluates a token against the current context.
+    pub fn evaluate(&self, token: &'a Token<'a>) -> Result<&'a DataValue<'a>> {
+        // The operator may have caught the error of a previous evaluation
+        self.arena.take_failure();
+        evaluate(token, self.arena)
+    }
+
+    /// Returns the current data context.
+    pub fn current_context(&self) -> &'a DataValue<'a> {
+        self.arena
+            .current_context(0)
+            .unwrap_or_else(|| self.arena.null_value())
+    }
+
+    /// Appends a key to the path chain used for scope jumps.
+    ///
+    /// The key stays in place until the enclosing [`scoped`](Self::scoped)
+    /// call returns.
+    pub fn push_path_key(&self, key: &'a DataValue<'a>) {
+        self.arena.push_path_key(key);
+    }
+
+    /// Runs `f` and then restores the current context and path chain.
+    ///
+    /// Both are restored when `f` returns or panics.
+    pub fn scoped<R>(&self, f: impl FnOnce(&Self) -> R) -> R {
+        struct Restore<'a> {
+            arena: &'a DataArena,
+            previous_context: Option<&'a DataValue<'a>>,
+            chain_len: usize,
+        }
+
+        impl Drop for Restore<'_> {
+            fn drop(&mut self) {
+                // Restore the path chain to its original state
+                while self.arena.path_chain_len() > self.chain_len {
+                    self.arena.pop_path_component();
+                }
+                self.arena.replace_current_context(self.previous_context);
+            }
+        }
+
+        let _restore = Restore {
+            arena: self.arena,
+            previous_context: self.arena.current_context(0),
+            chain_len: self.arena.path_chain_len(),
+        };
+        f(self)
+    }
+
+    /// Runs `f` with `context` as the current context, identified by `key`
+    /// in the path chain.
+    pub fn with_context<R>(
+        &self,
+        context: &'a DataValue<'a>,
+        key: &'a DataValue<'a>,
+        f: impl FnOnce(&Self) -> R,
+    ) -> R {
+        self.scoped(|ctx| {
+            ctx.arena.set_current_context(context, key);
+            f(ctx)
+        })
+    }
+
+    /// Evaluates a token with an array item as the current context.
+    pub fn evaluate_with_item(
+        &self,
+        token: &'a Token<'a>,
+        item: &'a DataValue<'a>,
+        index: usize,
+    ) -> Result<&'a DataValue<'a>> {
+        let key = self.arena.alloc(DataValue::integer(index as i64));
+        self.with_context(item, key, |ctx| ctx.evaluate(token))
+    }
+}
+
+/// A registered custom operator.
+pub(crate) enum RegisteredOperator {
+    /// Operator that receives evaluated arguments.
+    Eager(Box<dyn CustomOperator>),
+    /// Operator that receives unevaluated arguments.
+    Lazy(Box<dyn LazyCustomOperator>),
+}
+
+/// Registry of custom operators, keyed by operator name.
+///
+/// Operators are reference counted, so cloning a registry is cheap and the
+/// clone shares the operator implementations with the original.
+#[derive(Default, Clone)]
+pub struct CustomOperatorRegistry {
+    operators: HashMap<String, Arc<RegisteredOperator>>,
+}
+
+impl fmt::Debug for CustomOperatorRegistry {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        f.debug_struct("CustomOperatorRegistry")
+            .field("operators", &self.operators.keys().collect::<Vec<_>>())
+            .finish()
+    }
+}
+
+impl CustomOperatorRegistry {
+    /// Creates a new empty registry.
+    pub fn new() -> Self {
+        Self {
+            operators: HashMap::new(),
+        }
+    }
+
+    /// Registers an operator, replacing any previous operator with the same name.
+    pub fn register(&mut self, name: &str, operator: Box<dyn CustomOperator>) {
+        self.operators
+            .insert(name.to_string(), Arc::new(RegisteredOperator::Eager(operator)));
+    }
+
+    /// Registers a lazy operator, replacing any previous operator with the same name.
+    pub fn register_lazy(&mut self, name: &str, operator: Box<dyn LazyCustomOperator>) {
+        self.operators
+            .insert(name.to_string(), Arc::new(RegisteredOperator::Lazy(operator)));
+    }
+
+    /// Returns the operator registered under the given name.
+    pub(crate) fn get(&self, name: &str) -> Option<Arc<RegisteredOperator>> {
+        self.operators.get(name).cloned()
+    }
+
+    /// Returns true if an operator is registered under the given name.
+    pub fn contains(&self, name: &str) -> bool {
+        self.operators.contains_key(name)
+    }
+
+    /// Returns true if no operator is registered.
+    pub(crate) fn is_empty(&self) -> bool {
+        self.operators.is_empty()
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::logic::error::LogicError;
+    use crate::DataLogic;
+    use serde_json::json;
+
+    struct Double;
+
+    impl CustomOperator for Double {
+        fn evaluate<'a>(
+            &self,
+            args: &'a [DataValue<'a>],
+            arena: &'a DataArena,
+        ) -> Result<&'a DataValue<'a>> {
+            match args {
+                [value] => {
+                    let n = value
+                        .coerce_to_number()
+                        .ok_or_else(|| LogicError::nan("double", 0, value))?;
+                    Ok(arena.alloc(DataValue::float(n.as_f64() * 2.0)))
+                }
+                _ => Err(LogicError::argument_count("double", "1 argument", args.len())),
+            }
+        }
+    }
+
+    struct Greet;
+
+    impl CustomOperator for Greet {
+        fn evaluate<'a>(
+            &self,
+            args: &'a [DataValue<'a>],
+            arena: &'a DataArena,
+        ) -> Result<&'a DataValue<'a>> {
+            let name = args.first().and_then(|v| v.as_str()).unwrap_or("world");
+            let greeting = format!("hello {}", name);
+            Ok(arena.alloc(DataValue::string(arena, &greeting)))
+        }
+    }
+
+    /// Returns the first truthy argument without evaluating the rest.
+    struct FirstTruthy;
+
+    impl LazyCustomOperator for FirstTruthy {
+        fn evaluate<'a>(
+            &self,
+            args: &'a [&'a Token<'a>],
+            context: &EvaluationContext<'a>,
+        ) -> Result<&'a DataValue<'a>> {
+            for arg in args {
+                let value = context.evaluate(arg)?;
+                if value.coerce_to_bool() {
+                    return Ok(value);
+                }
+            }
+            Ok(context.arena().null_value())
+        }
+    }
+
+    /// Maps each item of an array to `[item, index, parent]`.
+    struct Annotate;
+
+    impl LazyCustomOperator for Annotate {
+        fn evaluate<'a>(
+            &self,
+            args: &'a [&'a Token<'a>],
+            context: &EvaluationContext<'a>,
+        ) -> Result<&'a DataValue<'a>> {
+            let arena = context.arena();
+            let items = match context.evaluate(args[0])? {
+                DataValue::Array(items) => *items,
+                other => return Err(LogicError::invalid_argument("annotate", 0, "array", other)),
+            };
+
+            context.scoped(|context| {
+                // Record the array path like the built-in iterators do
+                if let Token::Variable { path, .. } = args[0] {
+                    context.push_path_key(arena.alloc(DataValue::String(path)));
+                }
+
+                let mut results = arena.get_data_value_vec_with_capacity(items.len());
+                for (index, item) in items.iter().enumerate() {
+                    let value = context.evaluate_with_item(args[1], item, index)?;
+                    results.push(value.clone());
+                }
+                Ok(arena.alloc(DataValue::Array(arena.bump_vec_into_slice(results))))
+            })
+        }
+    }
+
+    #[test]
+    fn test_registry() {
+        let mut registry = CustomOperatorRegistry::new();
+        assert!(!registry.contains("double"));
+
+        registry.register("double", Box::new(Double));
+        assert!(registry.contains("double"));
+        assert!(matches!(
+            registry.get("double").as_deref(),
+            Some(RegisteredOperator::Eager(_))
+        ));
+        assert!(registry.get("triple").is_none());
+
+        registry.register_lazy("first_truthy", Box::new(FirstTruthy));
+        assert!(matches!(
+            registry.get("first_truthy").as_deref(),
+            Some(RegisteredOperator::Lazy(_))
+        ));
+    }
+
+    #[test]
+    fn test_custom_operator_evaluation() {
+        let mut dl = DataLogic::new();
+        dl.register_operator("double", Box::new(Double));
+        dl.register_operator("greet", Box::new(Greet));
+
+        // Single argument
+        let result = dl.evaluate_str(r#"{"double": 4}"#, "{}", None).unwrap();
+        assert_eq!(result, json!(8));
+
+        // Arguments are evaluated against the data
+        let result = dl
+            .evaluate_str(r#"{"double": {"var": "x"}}"#, r#"{"x": 2.5}"#, None)
+            .unwrap();
+        assert_eq!(result, json!(5));
+
+        // Nested inside built-in operators
+        let result = dl
+            .evaluate_str(
+                r#"{"+": [{"double": [{"var": "x"}]}, 1]}"#,
+                r#"{"x": 3}"#,
+                None,
+            )
+            .unwrap();
+        assert_eq!(result, json!(7));
+
+        // Inside an iterator, the current item is the context
+        let result = dl
+            .evaluate_str(
+                r#"{"map": [{"var": "names"}, {"greet": {"var": ""}}]}"#,
+                r#"{"names": ["ann", "bob"]}"#,
+                None,
+            )
+            .unwrap();
+        assert_eq!(result, json!(["hello ann", "hello bob"]));
+
+        // No arguments
+        let result = dl.evaluate_str(r#"{"greet": []}"#, "{}", None).unwrap();
+        assert_eq!(result, json!("hello world"));
+    }
+
+    #[test]
+    fn test_lazy_custom_operator() {
+        let mut dl = DataLogic::new();
+        dl.register_lazy_operator("first_truthy", Box::new(FirstTruthy));
+        dl.register_lazy_operator("annotate", Box::new(Annotate));
+
+        // Arguments after the first truthy one are never evaluated
+        let result = dl
+            .evaluate_str(
+                r#"{"first_truthy": [0, {"var": "x"}, {"throw": "unreachable"}]}"#,
+                r#"{"x": "found"}"#,
+                None,
+            )
+            .unwrap();
+        assert_eq!(result, json!("found"));
+
+        // Errors from evaluated arguments are propagated
+        let result = dl.evaluate_str(r#"{"first_truthy": [0, {"throw": "boom"}]}"#, "{}", None);
+        assert_eq!(
+            result.map_err(LogicError::without_location),
+            Err(LogicError::thrown_error("boom"))
+        );
+
+        // Items become the current context, with index and scope jumps available
+        let result = dl
+            .evaluate_str(
+                r#"{"annotate": [{"var": "items"}, [{"var": ""}, {"val": [[-1], "index"]}, {"val": [[-3], "label"]}]]}"#,
+                r#"{"items": ["a", "b"], "label": "L"}"#,
+                None,
+            )
+            .unwrap();
+        assert_eq!(result, json!([["a", 0, "L"], ["b", 1, "L"]]));
+
+        // The outer context is restored once the operator returns
+        let result = dl
+            .evaluate_str(
+                r#"{"cat": [{"annotate": [{"var": "items"}, {"var": ""}]}, {"var": "label"}]}"#,
+                r#"{"items": ["a", "b"], "label": "L"}"#,
+                None,
+            )
+            .unwrap();
+        assert_eq!(result, json!("abL"));
+    }
+
+    #[test]
+    fn test_scoped_restores_on_panic() {
+        use std::panic::{catch_unwind, AssertUnwindSafe};
+
+        let arena = DataArena::new();
+        let outer = arena.alloc(DataValue::string(&arena, "outer"));
+        let inner = arena.alloc(DataValue::string(&arena, "inner"));
+        let key = arena.alloc(DataValue::integer(0));
+        arena.set_current_context(outer, key);
+        let chain_len = arena.path_chain_len();
+
+        let context = EvaluationContext::new(&arena);
+        let result = catch_unwind(AssertUnwindSafe(|| {
+            context.with_context(inner, key, |context| {
+                context.push_path_key(key);
+                assert_eq!(context.current_context().as_str(), Some("inner"));
+                panic!("operator failed");
+            })
+        }));
+
+        assert!(result.is_err());
+        assert_eq!(context.current_context().as_str(), Some("outer"));
+        assert_eq!(arena.path_chain_len(), chain_len);
+    }
+
+    #[test]
+    fn test_custom_operator_errors() {
+        let mut dl = DataLogic::new();
+        dl.register_operator("double", Box::new(Double));
+
+        // Errors from the operator are propagated
+        let result = dl.evaluate_str(r#"{"double": [1, 2]}"#, "{}", None);
+        assert_eq!(
+            result.map_err(LogicError::without_location),
+            Err(LogicError::InvalidArgumentsError {
+                operator: "double".to_string(),
+                index: None,
+                expected: "1 argument".to_string(),
+                actual: "2 arguments".to_string(),
+                location: None,
+            })
+        );
+
+        // Unregistered operators are still reported as not found
+        let result = dl.evaluate_str(r#"{"triple": 1}"#, "{}", None);
+        assert_eq!(
+            result.map_err(LogicError::without_location),
+            Err(LogicError::operator_not_found("triple"))
+        );
+    }
+}
@@ -0,0 +1,619 @@
+//! Datetime operators for logic expressions.
+//!
+//! This module provides implementations for the datetime operators
+//! such as now, datetime, date_diff, date_add, etc.
+//!
+//! The operators take datetimes, or strings they parse as datetimes, such
+//! as `2024-05-17`, `2024-05-17T09:30:00` or `2024-05-17T09:30:00+02:00`.
+//! Datetimes without an offset are in UTC. Durations can be given as ISO
+//! 8601 strings, such as `P30D` or `PT1H30M`.
+
+use chrono::format::StrftimeItems;
+use chrono::{
+    DateTime, Datelike, FixedOffset, Months, NaiveDate, NaiveDateTime, TimeDelta, Timelike,
+};
+
+use crate::arena::DataArena;
+use crate::logic::error::{LogicError, Result};
+use crate::logic::evaluator::evaluate;
+use crate::logic::operators::Arity;
+use crate::logic::token::Token;
+use crate::value::{DataValue, parse_datetime, parse_duration};
+
+/// Enumeration of datetime operators.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum DateTimeOp {
+    /// The current time
+    Now,
+    /// Conversion to a datetime
+    DateTime,
+    /// Conversion to a duration
+    Duration,
+    /// Difference between two datetimes
+    Diff,
+    /// Addition of a duration to a datetime
+    Add,
+    /// Formatting of a datetime
+    Format,
+    /// Extraction of a component of a datetime
+    Part,
+}
+
+impl DateTimeOp {
+    /// Returns the number of arguments the operator accepts.
+    pub(crate) const fn arity(self) -> Arity {
+        match self {
+            DateTimeOp::Now => Arity::exactly(0),
+            DateTimeOp::DateTime => Arity::between(1, 2),
+            DateTimeOp::Duration => Arity::exactly(1),
+            DateTimeOp::Diff | DateTimeOp::Add => Arity::between(2, 3),
+            DateTimeOp::Format | DateTimeOp::Part => Arity::exactly(2),
+        }
+    }
+}
+
+/// A unit of time for `date_diff` and `date_add`.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+enum Unit {
+    Years,
+    Months,
+    Weeks,
+    Days,
+    Hours,
+    Minutes,
+    Seconds,
+    Milliseconds,
+}
+
+impl Unit {
+    fn parse(name: &str) -> Option<Self> {
+        match name {
+            "year" | "years" => Some(Unit::Years),
+            "month" | "months" => Some(Unit::Months),
+            "week" | "weeks" => Some(Unit::Weeks),
+            "day" | "days" => Some(Unit::Days),
+            "hour" | "hours" => Some(Unit::Hours),
+            "minute" | "minutes" => Some(Unit::Minutes),
+            "second" | "seconds" => Some(Unit::Seconds),
+            "millisecond" | "milliseconds" => Some(Unit::Milliseconds),
+            _ => None,
+        }
+    }
+
+    /// Returns the length of the unit in milliseconds, unless it varies.
+    fn milliseconds(self) -> Option<i64> {
+        match self {
+            Unit::Years | Unit::Months => None,
+            Unit::Weeks => Some(7 * 24 * 60 * 60 * 1000),
+            Unit::Days => Some(24 * 60 * 60 * 1000),
+            Unit::Hours => Some(60 * 60 * 1000),
+            Unit::Minutes => Some(60 * 1000),
+            Unit::Seconds => Some(1000),
+            Unit::Milliseconds => Some(1),
+        }
+    }
+}
+
+/// Evaluates the current time.
+///
+/// The clock is read once per evaluation, so every `now` in a rule returns
+/// the same time.
+pub fn eval_now<'a>(args: &'a [&'a Token<'a>], arena: &'a DataArena) -> Result<&'a DataValue<'a>> {
+    DateTimeOp::Now.arity().check("now", args.len())?;
+
+    Ok(arena.alloc(DataValue::DateTime(arena.now())))
+}
+
+/// Evaluates the conversion of a value to a datetime.
+///
+/// Strings are parsed with the `strftime` format given as second argument,
+/// or otherwise as an ISO 8601 date or datetime. Numbers are seconds since
+/// the Unix epoch.
+pub fn eval_datetime<'a>(
+    args: &'a [&'a Token<'a>],
+    arena: &'a DataArena,
+) -> Result<&'a DataValue<'a>> {
+    DateTimeOp::DateTime.arity().check("datetime", args.len())?;
+
+    let value = evaluate(args[0], arena)?;
+    let datetime = if args.len() == 2 {
+        let format = format_argument("datetime", args, 1, arena)?;
+        match value {
+            DataValue::String(s) => parse_with_format(s, format),
+            _ => None,
+        }
+        .ok_or_else(|| {
+            LogicError::invalid_argument(
+                "datetime",
+                0,
+                format!("datetime in the format '{}'", format),
+                value,
+            )
+        })?
+    } else {
+        match value {
+            DataValue::Number(n) => from_timestamp(n.as_f64()),
+            _ => as_datetime(value),
+        }
+        .ok_or_else(|| LogicError::invalid_argument("datetime", 0, "datetime", value))?
+    };
+
+    Ok(arena.alloc(DataValue::DateTime(datetime)))
+}
+
+/// Evaluates the conversion of a value to a duration.
+///
+/// Strings are parsed as ISO 8601 durations and numbers are seconds.
+pub fn eval_duration<'a>(
+    args: &'a [&'a Token<'a>],
+    arena: &'a DataArena,
+) -> Result<&'a DataValue<'a>> {
+    DateTimeOp::Duration.arity().check("duration", args.len())?;
+
+    let value = evaluate(args[0], arena)?;
+    let duration = match value {
+        DataValue::Number(n) => milliseconds((n.as_f64() * 1000.0).round()),
+        _ => as_duration(value),
+    }
+    .ok_or_else(|| LogicError::invalid_argument("duration", 0, "duration", value))?;
+
+    Ok(arena.alloc(DataValue::Duration(duration)))
+}
+
+/// Evaluates the difference between two datetimes.
+///
+/// Without a unit, the difference is a duration. With a unit, it is the
+/// number of whole units from the second datetime to the first, so it is
+/// negative if the first datetime is earlier. Months and years are counted
+/// on the calendar, so `date_diff` in years of a birthdate is an age.
+pub fn eval_date_diff<'a>(
+    args: &'a [&'a Token<'a>],
+    arena: &'a DataArena,
+) -> Result<&'a DataValue<'a>> {
+    DateTimeOp::Diff.arity().check("date_diff", args.len())?;
+
+    let end = datetime_argument("date_diff", args, 0, arena)?;
+    let start = datetime_argument("date_diff", args, 1, arena)?;
+
+    if args.len() == 2 {
+        return Ok(arena.alloc(DataValue::Duration(end - start)));
+    }
+
+    let unit = unit_argument("date_diff", args, 2, arena)?;
+    let difference = match unit.milliseconds() {
+        Some(length) => (end - start).num_milliseconds() / length,
+        None => {
+            let months = months_between(
+                end.naive_local(),
+                start.with_timezone(end.offset()).naive_local(),
+            );
+            if unit == Unit::Years {
+                months / 12
+            } else {
+                months
+            }
+        }
+    };
+
+    Ok(arena.alloc(DataValue::integer(difference)))
+}
+
+/// Evaluates the addition of a duration, or of an amount of a unit, to a
+/// datetime.
+///
+/// Adding months or years keeps the day of the month, or uses the last day
+/// of the month if it is shorter. Amounts may be negative.
+pub fn eval_date_add<'a>(
+    args: &'a [&'a Token<'a>],
+    arena: &'a DataArena,
+) -> Result<&'a DataValue<'a>> {
+    DateTimeOp::Add.arity().check("date_add", args.len())?;
+
+    let datetime = datetime_argument("date_add", args, 0, arena)?;
+    let amount = evaluate(args[1], arena)?;
+
+    let result = if args.len() == 2 {
+        let duration = as_duration(amount)
+            .ok_or_else(|| LogicError::invalid_argument("date_add", 1, "duration", amount))?;
+        datetime.checked_add_signed(duration)
+    } else {
+        let number = amount
+            .coerce_to_number()
+            .ok_or_else(|| LogicError::nan("date_add", 1, amount))?
+            .as_f64();
+        match unit_argument("date_add", args, 2, arena)? {
+            unit @ (Unit::Years | Unit::Months) => {
+                if number.fract() != 0.0 {
+                    return Err(LogicError::invalid_argument(
+                        "date_add",
+                        1,
+                        "whole number of months or years",
+                        amount,
+                    ));
+                }
+                let months = if unit == Unit::Years {
+                    number * 12.0
+                } else {
+                    number
+                };
+                add_months(datetime, months)
+            }
+            unit => {
+                let length = unit.milliseconds().unwrap_or(1) as f64;
+                milliseconds((number * length).round())
+                    .and_then(|duration| datetime.checked_add_signed(duration))
+            }
+        }
+    };
+
+    let result = result.ok_or_else(|| {
+        LogicError::invalid_argument(
+            "date_add",
+            1,
+            "amount within the range of datetimes",
+            amount,
+        )
+    })?;
+    Ok(arena.alloc(DataValue::DateTime(result)))
+}
+
+/// Evaluates the formatting of a datetime with a `strftime` format.
+pub fn eval_format_date<'a>(
+    args: &'a [&'a Token<'a>],
+    arena: &'a DataArena,
+) -> Result<&'a DataValue<'a>> {
+    DateTimeOp::Format
+        .arity()
+        .check("format_date", args.len())?;
+
+    let datetime = datetime_argument("format_date", args, 0, arena)?;
+    let format = format_argument("format_date", args, 1, arena)?;
+
+    let formatted = datetime
+        .format_with_items(StrftimeItems::new(format))
+        .to_string();
+    Ok(arena.alloc(DataValue::String(arena.alloc_str(&formatted))))
+}
+
+/// Evaluates the extraction of a component of a datetime, in its own offset.
+///
+/// The components are `year`, `month`, `day`, `hour`, `minute`, `second`,
+/// `millisecond`, `weekday`, from 1 for Monday to 7 for Sunday, `day_of_year`
+/// and `week`, the ISO 8601 week number.
+pub fn eval_date_part<'a>(
+    args: &'a [&'a Token<'a>],
+    arena: &'a DataArena,
+) -> Result<&'a DataValue<'a>> {
+    DateTimeOp::Part.arity().check("date_part", args.len())?;
+
+    let datetime = datetime_argument("date_part", args, 0, arena)?;
+    let part = evaluate(args[1], arena)?;
+
+    let value = match part.as_str() {
+        Some("year") => datetime.year() as i64,
+        Some("month") => datetime.month() as i64,
+        Some("day") => datetime.day() as i64,
+        Some("hour") => datetime.hour() as i64,
+        Some("minute") => datetime.minute() as i64,
+        Some("second") => datetime.second() as i64,
+        Some("millisecond") => (datetime.nanosecond() / 1_000_000) as i64,
+        Some("weekday") => datetime.weekday().number_from_monday() as i64,
+        Some("day_of_year") => datetime.ordinal() as i64,
+        Some("week") => datetime.iso_week().week() as i64,
+        _ => {
+            return Err(LogicError::invalid_argument(
+                "date_part",
+                1,
+                "datetime component",
+                part,
+            ));
+        }
+    };
+
+    Ok(arena.alloc(DataValue::integer(value)))
+}
+
+/// Returns a value as a datetime, parsing strings.
+pub(crate) fn as_datetime(value: &DataValue) -> Option<DateTime<FixedOffset>> {
+    match value {
+        DataValue::DateTime(datetime) => Some(*datetime),
+        DataValue::String(s) => parse_datetime(s),
+        _ => None,
+    }
+}
+
+/// Returns a value as a duration, parsing strings.
+pub(crate) fn as_duration(value: &DataValue) -> Option<TimeDelta> {
+    match value {
+        DataValue::Duration(duration) => Some(*duration),
+        DataValue::String(s) => parse_duration(s),
+        _ => None,
+    }
+}
+
+/// Evaluates the argument at `index` as a datetime.
+fn datetime_argument<'a>(
+    operator: &str,
+    args: &'a [&'a Token<'a>],
+    index: usize,
+    arena: &'a DataArena,
+) -> Result<DateTime<FixedOffset>> {
+    let value = evaluate(args[index], arena)?;
+    as_datetime(value)
+        .ok_or_else(|| LogicError::invalid_argument(operator, index, "datetime", value))
+}
+
+/// Evaluates the argument at `index` as a unit of time.
+fn unit_argument<'a>(
+    operator: &str,
+    args: &'a [&'a Token<'a>],
+    index: usize,
+    arena: &'a DataArena,
+) -> Result<Unit> {
+    let value = evaluate(args[index], arena)?;
+    value
+        .as_str()
+        .and_then(Unit::parse)
+        .ok_or_else(|| LogicError::invalid_argument(operator, index, "unit of time", value))
+}
+
+/// Evaluates the argument at `index` as a valid `strftime` format.
+fn format_argument<'a>(
+    operator: &str,
+    args: &'a [&'a Token<'a>],
+    index: usize,
+    arena: &'a DataArena,
+) -> Result<&'a str> {
+    let value = evaluate(args[index], arena)?;
+    match value {
+        DataValue::String(format) if StrftimeItems::new(format).parse().is_ok() => Ok(format),
+        _ => Err(LogicError::invalid_argument(
+            operator,
+            index,
+            "strftime format",
+            value,
+        )),
+    }
+}
+
+/// Parses a datetime with a `strftime` format, in UTC unless the format
+/// has an offset.
+fn parse_with_format(s: &str, format: &str) -> Option<DateTime<FixedOffset>> {
+    if let Ok(datetime) = DateTime::parse_from_str(s, format) {
+        return Some(datetime);
+    }
+
+    let naive = NaiveDateTime::parse_from_str(s, format)
+        .or_else(|_| NaiveDate::parse_from_str(s, format).map(|date| date.into()))
+        .ok()?;
+    Some(naive.and_utc().fixed_offset())
+}
+
+/// Converts seconds since the Unix epoch to a datetime in UTC.
+fn from_timestamp(seconds: f64) -> Option<DateTime<FixedOffset>> {
+    let duration = milliseconds((seconds * 1000.0).round())?;
+    DateTime::UNIX_EPOCH
+        .checked_add_signed(duration)
+        .map(|datetime| datetime.fixed_offset())
+}
+
+/// Converts a whole number of milliseconds to a duration.
+fn milliseconds(milliseconds: f64) -> Option<TimeDelta> {
+    if !milliseconds.is_finite() || milliseconds.abs() >= i64::MAX as f64 {
+        return None;
+    }
+    TimeDelta::try_milliseconds(milliseconds as i64)
+}
+
+/// Adds a whole number of months, which may be negative, to a datetime.
+fn add_months(datetime: DateTime<FixedOffset>, months: f64) -> Option<DateTime<FixedOffset>> {
+    if months.abs() > u32::MAX as f64 {
+        return None;
+    }
+    let count = Months::new(months.abs() as u32);
+    if months < 0.0 {
+        datetime.checked_sub_months(count)
+    } else {
+        datetime.checked_add_months(count)
+    }
+}
+
+/// Counts the whole calendar months from `start` to `end`.
+///
+/// A month is complete once the same day of the month and time of day is
+/// reached, so there is one month from January 15th to February 15th.
+fn months_between(end: NaiveDateTime, start: NaiveDateTime) -> i64 {
+    let mut months =
+        (end.year() as i64 - start.year() as i64) * 12 + end.month() as i64 - start.month() as i64;
+
+    let end_rest = (end.day(), end.time());
+    let start_rest = (start.day(), start.time());
+    if months > 0 && end_rest < start_rest {
+        months -= 1;
+    } else if months < 0 && end_rest > start_rest {
+        months += 1;
+    }
+    months
+}
+
+#[cfg(test)]
+mod tests {
+    use crate::logic::options::{EvaluateOptions, FixedClock};
+    use crate::value::parse_datetime;
+    use crate::{DataLogic, LogicError, ToJson};
+    use serde_json::{Value as JsonValue, json};
+
+    fn evaluate(rule: &str, data: JsonValue) -> JsonValue {
+        DataLogic::new()
+            .evaluate_str(rule, &data.to_string(), None)
+            .unwrap()
+    }
+
+    #[test]
+    fn test_datetime() {
+        assert_eq!(
+            evaluate(r#"{"datetime": "2024-05-17T09:30:00+02:00"}"#, json!(null)),
+            json!("2024-05-17T09:30:00+02:00")
+        );
+        assert_eq!(
+            evaluate(r#"{"datetime": {"var": "d"}}"#, json!({"d": "2024-05-17"})),
+            json!("2024-05-17T00:00:00Z")
+        );
+        assert_eq!(
+            evaluate(
+                r#"{"datetime": ["17/05/2024 09:30", "%d/%m/%Y %H:%M"]}"#,
+                json!(null)
+            ),
+            json!("2024-05-17T09:30:00Z")
+        );
+        assert_eq!(
+            evaluate(r#"{"datetime": 1715938200}"#, json!(null)),
+            json!("2024-05-17T09:30:00Z")
+        );
+
+        let dl = DataLogic::new();
+        let error = dl
+            .evaluate_str(
+                r#"{"datetime": {"var": "d"}}"#,
+                r#"{"d": "tomorrow"}"#,
+                None,
+            )
+            .unwrap_err();
+        assert!(matches!(error, LogicError::InvalidArgumentsError { .. }));
+    }
+
+    #[test]
+    fn test_date_diff() {
+        let rule = r#"{"date_diff": [{"var": "end"}, {"var": "start"}, {"var": "unit"}]}"#;
+        let cases = [
+            ("2024-05-17", "1990-05-18", "years", 33),
+            ("2024-05-17", "1990-05-17", "years", 34),
+            ("2024-03-14", "2024-01-15", "months", 1),
+            ("2024-01-15", "2024-03-14", "months", -1),
+            ("2024-03-01", "2024-02-01", "days", 29),
+            (
+                "2024-05-17T10:00:00Z",
+                "2024-05-17T12:00:00+02:00",
+                "hours",
+                0,
+            ),
+            (
+                "2024-05-17T09:30:00Z",
+                "2024-05-17T08:00:00Z",
+                "minutes",
+                90,
+            ),
+        ];
+        for (end, start, unit, expected) in cases {
+            let data = json!({"end": end, "start": start, "unit": unit});
+            assert_eq!(
+                evaluate(rule, data),
+                json!(expected),
+                "{} - {} in {}",
+                end,
+                start,
+                unit
+            );
+        }
+
+        assert_eq!(
+            evaluate(
+                r#"{"date_diff": ["2024-05-18T12:00:00Z", "2024-05-17"]}"#,
+                json!(null)
+            ),
+            json!("P1DT12H")
+        );
+    }
+
+    #[test]
+    fn test_date_add() {
+        let cases = [
+            (
+                r#"{"date_add": ["2024-01-31", 1, "month"]}"#,
+                "2024-02-29T00:00:00Z",
+            ),
+            (
+                r#"{"date_add": ["2024-02-29", -1, "years"]}"#,
+                "2023-02-28T00:00:00Z",
+            ),
+            (
+                r#"{"date_add": ["2024-05-17", 1.5, "days"]}"#,
+                "2024-05-18T12:00:00Z",
+            ),
+            (
+                r#"{"date_add": ["2024-05-17T09:30:00+02:00", "PT45M"]}"#,
+                "2024-05-17T10:15:00+02:00",
+            ),
+            (
+                r#"{"date_add": ["2024-05-17", {"date_diff": ["2024-05-18", "2024-05-17"]}]}"#,
+                "2024-05-18T00:00:00Z",
+            ),
+        ];
+        for (rule, expected) in cases {
+            assert_eq!(evaluate(rule, json!(null)), json!(expected), "{}", rule);
+        }
+    }
+
+    #[test]
+    fn test_format_and_parts() {
+        let data = json!({"d": "2024-05-17T09:30:05.250+02:00"});
+        assert_eq!(
+            evaluate(
+                r#"{"format_date": [{"var": "d"}, "%A %-d %B %Y, %H:%M"]}"#,
+                data.clone()
+            ),
+            json!("Friday 17 May 2024, 09:30")
+        );
+        for (part, expected) in [
+            ("year", 2024),
+            ("month", 5),
+            ("day", 17),
+            ("hour", 9),
+            ("second", 5),
+            ("millisecond", 250),
+            ("weekday", 5),
+            ("day_of_year", 138),
+            ("week", 20),
+        ] {
+            let rule = format!(r#"{{"date_part": [{{"var": "d"}}, "{}"]}}"#, part);
+            assert_eq!(evaluate(&rule, data.clone()), json!(expected), "{}", part);
+        }
+
+        let dl = DataLogic::new();
+        for rule in [
+            r#"{"format_date": ["2024-05-17", "%Q"]}"#,
+            r#"{"date_part": ["2024-05-17", "era"]}"#,
+        ] {
+            let error = dl.evaluate_str(rule, "null", None).unwrap_err();
+            assert!(matches!(error, LogicError::InvalidArgumentsError { .. }));
+        }
+    }
+
+    #[test]
+    fn test_now_uses_the_clock() {
+        let dl = DataLogic::new();
+        let rule = dl
+            .parse_logic(
+                r#"{"and": [
+                    {"<=": [{"date_diff": [{"now": []}, {"var": "start"}, "days"]}, 30]},
+                    {"==": [{"now": []}, {"now": []}]}
+                ]}"#,
+                None,
+            )
+            .unwrap();
+        let data = dl.parse_data(r#"{"start": "2024-05-01"}"#).unwrap();
+
+        let clock = |now: &str| FixedClock(parse_datetime(now).unwrap());
+        let options = EvaluateOptions::new().with_clock(clock("2024-05-17T12:00:00Z"));
+        let result = dl.evaluate_with_options(&rule, &data, &options).unwrap();
+        assert_eq!(result.to_json(), json!(true));
+
+        let options = EvaluateOptions::new().with_clock(clock("2024-06-17T12:00:00Z"));
+        let result = dl.evaluate_with_options(&rule, &data, &options).unwrap();
+        assert_eq!(result.to_json(), json!(false));
+
+        // Without a clock, now is the system time
+        let result = dl.evaluate_str(r#"{"date_part": [{"now": []}, "year"]}"#, "null", None);
+        assert!(result.unwrap().as_i64().unwrap() >= 2024);
+    }
+}
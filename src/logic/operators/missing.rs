@@ -4,12 +4,19 @@
 //! such as missing and missing_some.
 
 use crate::arena::DataArena;
-use crate::logic::error::{LogicError, Result};
+use crate::logic::operators::Arity;
+use crate::logic::error::Result;
 use crate::logic::evaluator::evaluate;
 use crate::logic::operators::variable;
 use crate::logic::token::Token;
 use crate::value::DataValue;
 
+/// The number of arguments `missing` accepts.
+pub(crate) const MISSING_ARITY: Arity = Arity::ANY;
+
+/// The number of arguments `missing_some` accepts.
+pub(crate) const MISSING_SOME_ARITY: Arity = Arity::exactly(2);
+
 /// Evaluates a missing operation.
 /// Checks whether the specified variables are missing from the data.
 pub fn eval_missing<'a>(
@@ -43,10 +50,10 @@ pub fn eval_missing<'a>(
                 if let DataValue::String(name) = name_value {
                     // Check if the variable exists
                     let none_ref: Option<&Token> = None;
-                    if let Ok(var_value) = variable::evaluate_variable(name, &none_ref, arena) {
-                        if var_value != arena.null_value() {
-                            continue;
-                        }
+                    if let Ok(var_value) = variable::evaluate_variable(name, &none_ref, arena)
+                        && var_value != arena.null_value()
+                    {
+                        continue;
                     }
 
                     // Variable is missing
@@ -68,9 +75,7 @@ pub fn eval_missing_some<'a>(
     args: &'a [&'a Token<'a>],
     arena: &'a DataArena,
 ) -> Result<&'a DataValue<'a>> {
-    if args.len() != 2 {
-        return Err(LogicError::InvalidArgumentsError);
-    }
+    MISSING_SOME_ARITY.check("missing_some", args.len())?;
 
     // Evaluate the first argument (minimum number of required fields)
     let min_required = evaluate(args[0], arena)?;
@@ -91,11 +96,11 @@ pub fn eval_missing_some<'a>(
             if let DataValue::String(name) = name_value {
                 // Check if the variable exists
                 let none_ref: Option<&Token> = None;
-                if let Ok(var_value) = variable::evaluate_variable(name, &none_ref, arena) {
-                    if var_value != arena.null_value() {
-                        found_count += 1;
-                        continue;
-                    }
+                if let Ok(var_value) = variable::evaluate_variable(name, &none_ref, arena)
+                    && var_value != arena.null_value()
+                {
+                    found_count += 1;
+                    continue;
                 }
 
                 // Variable is missing
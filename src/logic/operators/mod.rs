@@ -6,7 +6,10 @@ pub mod arithmetic;
 pub mod array;
 pub mod comparison;
 pub mod control;
+pub mod custom;
+pub mod datetime;
 pub mod missing;
+pub mod regex;
 pub mod string;
 pub mod throw;
 pub mod r#try;
@@ -18,4 +21,82 @@ pub use arithmetic::ArithmeticOp;
 pub use array::ArrayOp;
 pub use comparison::ComparisonOp;
 pub use control::ControlOp;
+pub use datetime::DateTimeOp;
+pub use regex::RegexOp;
 pub use string::StringOp;
+
+use super::error::{LogicError, Result};
+
+/// The number of arguments an operator accepts.
+///
+/// Each operator module declares the arity of its operators, which the
+/// operators check when they are evaluated and the validator checks ahead
+/// of time.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub(crate) struct Arity {
+    min: usize,
+    max: Option<usize>,
+}
+
+impl Arity {
+    /// Any number of arguments.
+    pub(crate) const ANY: Arity = Arity::at_least(0);
+
+    /// Exactly `count` arguments.
+    pub(crate) const fn exactly(count: usize) -> Self {
+        Self {
+            min: count,
+            max: Some(count),
+        }
+    }
+
+    /// At least `min` arguments.
+    pub(crate) const fn at_least(min: usize) -> Self {
+        Self { min, max: None }
+    }
+
+    /// Between `min` and `max` arguments, inclusive.
+    pub(crate) const fn between(min: usize, max: usize) -> Self {
+        Self {
+            min,
+            max: Some(max),
+        }
+    }
+
+    /// Returns true if the operator accepts `count` arguments.
+    #[inline]
+    pub(crate) const fn accepts(self, count: usize) -> bool {
+        count >= self.min
+            && match self.max {
+                Some(max) => count <= max,
+                None => true,
+            }
+    }
+
+    /// Returns an error for `operator` if it does not accept `count` arguments.
+    #[inline]
+    pub(crate) fn check(self, operator: &str, count: usize) -> Result<()> {
+        if self.accepts(count) {
+            Ok(())
+        } else {
+            Err(self.error(operator, count))
+        }
+    }
+
+    /// Returns the error for `operator` called with `count` arguments.
+    #[cold]
+    pub(crate) fn error(self, operator: &str, count: usize) -> LogicError {
+        LogicError::argument_count(operator, self.describe(), count)
+    }
+
+    /// Describes the accepted counts, such as `"2 or 3 arguments"`.
+    pub(crate) fn describe(self) -> String {
+        let noun = |count: usize| if count == 1 { "argument" } else { "arguments" };
+        match self.max {
+            Some(max) if max == self.min => format!("{} {}", max, noun(max)),
+            Some(max) if max == self.min + 1 => format!("{} or {} arguments", self.min, max),
+            Some(max) => format!("{} to {} arguments", self.min, max),
+            None => format!("at least {} {}", self.min, noun(self.min)),
+        }
+    }
+}
@@ -0,0 +1,354 @@
+//! Regular expression operators for logic expressions.
+//!
+//! This module provides implementations for the `match`, `regex_extract`
+//! and `regex_replace` operators. The types are always available, but
+//! patterns can only be compiled and matched with the `regex` feature;
+//! without it, the operator names are not recognized by the parser.
+//!
+//! A pattern given as a literal string is compiled once, when the rule is
+//! parsed or optimized, and stored in the token tree as a
+//! [`Token::Pattern`]. Other patterns are compiled each time they are
+//! evaluated.
+
+#[cfg(feature = "regex")]
+use std::borrow::Cow;
+
+#[cfg(feature = "regex")]
+use regex::Regex;
+
+use crate::arena::DataArena;
+use crate::logic::error::{LogicError, Result};
+#[cfg(feature = "regex")]
+use crate::logic::evaluator::evaluate;
+use crate::logic::operators::Arity;
+#[cfg(feature = "regex")]
+use crate::logic::operators::string::{bool_value, coerce_to_str, string_argument, string_value};
+use crate::logic::token::Token;
+use crate::value::DataValue;
+
+/// The position of the pattern in the arguments of every regex operator.
+const PATTERN_INDEX: usize = 1;
+
+/// Enumeration of regular expression operators.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum RegexOp {
+    /// Test whether a string matches a pattern
+    Match,
+    /// Extraction of the first match or one of its groups
+    Extract,
+    /// Replacement of all matches
+    Replace,
+}
+
+impl RegexOp {
+    /// Returns the number of arguments the operator accepts.
+    pub(crate) const fn arity(self) -> Arity {
+        match self {
+            RegexOp::Match => Arity::exactly(2),
+            RegexOp::Extract => Arity::between(2, 3),
+            RegexOp::Replace => Arity::exactly(3),
+        }
+    }
+}
+
+/// A compiled regular expression.
+///
+/// Patterns compare equal when they were compiled from the same source.
+#[derive(Debug, Clone)]
+pub struct Pattern {
+    #[cfg(feature = "regex")]
+    regex: Regex,
+    #[cfg(not(feature = "regex"))]
+    source: Box<str>,
+}
+
+impl Pattern {
+    /// Compiles a pattern, returning the reason if it is not a valid
+    /// regular expression.
+    ///
+    /// Without the `regex` feature, no pattern can be compiled.
+    pub fn new(source: &str) -> std::result::Result<Self, String> {
+        #[cfg(feature = "regex")]
+        return Regex::new(source)
+            .map(|regex| Self { regex })
+            .map_err(|e| e.to_string());
+
+        #[cfg(not(feature = "regex"))]
+        return Err(format!(
+            "cannot compile '{}' without the `regex` feature",
+            source
+        ));
+    }
+
+    /// Returns the source of the pattern.
+    pub fn as_str(&self) -> &str {
+        #[cfg(feature = "regex")]
+        return self.regex.as_str();
+
+        #[cfg(not(feature = "regex"))]
+        return &self.source;
+    }
+
+    /// Returns the compiled regular expression.
+    #[cfg(feature = "regex")]
+    pub(crate) fn regex(&self) -> &Regex {
+        &self.regex
+    }
+}
+
+impl PartialEq for Pattern {
+    fn eq(&self, other: &Self) -> bool {
+        self.as_str() == other.as_str()
+    }
+}
+
+/// Replaces a literal pattern in the arguments of a regex operator with
+/// its compiled form.
+///
+/// Returns `None` if there is no literal pattern to compile, and a parse
+/// error if the pattern is not a valid regular expression.
+pub(crate) fn compile_pattern<'a>(
+    args: &'a Token<'a>,
+    arena: &'a DataArena,
+) -> Option<Result<&'a Token<'a>>> {
+    let Token::ArrayLiteral(items) = args else {
+        return None;
+    };
+    let Some(Token::Literal(DataValue::String(source))) = items.get(PATTERN_INDEX) else {
+        return None;
+    };
+
+    let pattern = match Pattern::new(source) {
+        Ok(pattern) => pattern,
+        Err(reason) => {
+            return Some(Err(LogicError::parse_error(format!(
+                "Invalid regular expression '{}': {}",
+                source, reason
+            ))));
+        }
+    };
+
+    let mut items = items.to_vec();
+    items[PATTERN_INDEX] = arena.alloc(Token::Pattern(arena.alloc_pattern(pattern)));
+    Some(Ok(
+        arena.alloc(Token::ArrayLiteral(arena.vec_into_slice(items)))
+    ))
+}
+
+/// Evaluates whether a string matches a pattern anywhere.
+///
+/// Use `^` and `$` to match the whole string.
+#[cfg(feature = "regex")]
+pub fn eval_match<'a>(
+    args: &'a [&'a Token<'a>],
+    arena: &'a DataArena,
+) -> Result<&'a DataValue<'a>> {
+    RegexOp::Match.arity().check("match", args.len())?;
+
+    let string = string_argument("match", args, 0, arena)?;
+    let regex = pattern_argument("match", args, arena)?;
+    Ok(bool_value(arena, regex.is_match(string)))
+}
+
+/// Evaluates the extraction of the first match of a pattern.
+///
+/// The optional third argument selects a capture group by number or name.
+/// Returns null if the pattern does not match or the group did not
+/// take part in the match.
+#[cfg(feature = "regex")]
+pub fn eval_extract<'a>(
+    args: &'a [&'a Token<'a>],
+    arena: &'a DataArena,
+) -> Result<&'a DataValue<'a>> {
+    RegexOp::Extract
+        .arity()
+        .check("regex_extract", args.len())?;
+
+    let string = string_argument("regex_extract", args, 0, arena)?;
+    let regex = pattern_argument("regex_extract", args, arena)?;
+
+    let Some(captures) = regex.captures(string) else {
+        return Ok(arena.null_value());
+    };
+
+    let group = if args.len() == 3 {
+        match evaluate(args[2], arena)? {
+            DataValue::String(name) => captures.name(name),
+            value => match value.as_i64() {
+                Some(index) if index >= 0 => captures.get(index as usize),
+                _ => {
+                    return Err(LogicError::invalid_argument(
+                        "regex_extract",
+                        2,
+                        "group number or name",
+                        value,
+                    ));
+                }
+            },
+        }
+    } else {
+        captures.get(0)
+    };
+
+    // The match borrows from the string, so nothing is copied
+    match group {
+        Some(group) => Ok(arena.alloc(DataValue::String(&string[group.range()]))),
+        None => Ok(arena.null_value()),
+    }
+}
+
+/// Evaluates the replacement of all matches of a pattern.
+///
+/// The replacement can refer to capture groups as `$1` or `${name}`.
+#[cfg(feature = "regex")]
+pub fn eval_replace<'a>(
+    args: &'a [&'a Token<'a>],
+    arena: &'a DataArena,
+) -> Result<&'a DataValue<'a>> {
+    RegexOp::Replace
+        .arity()
+        .check("regex_replace", args.len())?;
+
+    let string = string_argument("regex_replace", args, 0, arena)?;
+    let regex = pattern_argument("regex_replace", args, arena)?;
+    let replacement = string_argument("regex_replace", args, 2, arena)?;
+
+    match regex.replace_all(string, replacement) {
+        Cow::Borrowed(unchanged) => Ok(arena.alloc(DataValue::String(unchanged))),
+        Cow::Owned(replaced) => Ok(string_value(arena, &replaced)),
+    }
+}
+
+/// Returns the compiled pattern of a regex operator, compiling it now if
+/// it was not a literal.
+#[cfg(feature = "regex")]
+fn pattern_argument<'a>(
+    operator: &str,
+    args: &'a [&'a Token<'a>],
+    arena: &'a DataArena,
+) -> Result<Cow<'a, Regex>> {
+    if let Token::Pattern(pattern) = args[PATTERN_INDEX] {
+        return Ok(Cow::Borrowed(pattern.regex()));
+    }
+
+    let value = evaluate(args[PATTERN_INDEX], arena)?;
+    let source = coerce_to_str(operator, PATTERN_INDEX, value, arena)?;
+    Regex::new(source).map(Cow::Owned).map_err(|_| {
+        LogicError::invalid_argument(operator, PATTERN_INDEX, "regular expression", value)
+    })
+}
+
+#[cfg(all(test, feature = "regex"))]
+mod tests {
+    use crate::DataLogic;
+    use crate::arena::DataArena;
+    use crate::logic::error::LogicError;
+    use crate::logic::token::Token;
+    use crate::parser::jsonlogic::parse_json;
+    use serde_json::json;
+
+    #[test]
+    fn test_match() {
+        let dl = DataLogic::new();
+        let rule =
+            r#"{"match": [{"var": "postcode"}, "^[A-Z]{1,2}[0-9][A-Z0-9]? [0-9][A-Z]{2}$"]}"#;
+
+        let result = dl.evaluate_str(rule, r#"{"postcode": "SW1A 1AA"}"#, None);
+        assert_eq!(result.unwrap(), json!(true));
+        let result = dl.evaluate_str(rule, r#"{"postcode": "12345"}"#, None);
+        assert_eq!(result.unwrap(), json!(false));
+    }
+
+    #[test]
+    fn test_literal_pattern_is_compiled_when_parsed() {
+        let arena = DataArena::new();
+        let token = parse_json(
+            &json!({"match": [{"var": "sku"}, "^[A-Z]{3}-\\d+$"]}),
+            &arena,
+        )
+        .unwrap();
+
+        let (_, args) = token.as_operator().unwrap();
+        match args.as_array_literal().unwrap()[1] {
+            Token::Pattern(pattern) => assert_eq!(pattern.as_str(), "^[A-Z]{3}-\\d+$"),
+            other => panic!("expected a compiled pattern, got {:?}", other),
+        }
+    }
+
+    #[test]
+    fn test_invalid_literal_pattern_is_a_parse_error() {
+        let arena = DataArena::new();
+        let error = parse_json(
+            &json!({"and": [true, {"match": [{"var": "sku"}, "([A-Z]"]}]}),
+            &arena,
+        )
+        .unwrap_err();
+
+        assert_eq!(error.code(), "parse_error");
+        assert_eq!(error.location(), Some("/and/1/match/1"));
+
+        // The position of the pattern is reported for a source string
+        let dl = DataLogic::new();
+        let error = dl
+            .parse_logic(
+                "{\"match\": [\n  {\"var\": \"sku\"},\n  \"([A-Z]\"\n]}",
+                None,
+            )
+            .unwrap_err();
+        let position = error.position().unwrap();
+        assert_eq!((position.line, position.column), (3, 3));
+    }
+
+    #[test]
+    fn test_dynamic_pattern() {
+        let dl = DataLogic::new();
+        let rule = r##"{"regex_replace": [{"var": "text"}, {"var": "pattern"}, "#"]}"##;
+
+        let result = dl.evaluate_str(rule, r#"{"text": "a1b22", "pattern": "[0-9]+"}"#, None);
+        assert_eq!(result.unwrap(), json!("a#b#"));
+
+        // An invalid pattern that is not a literal fails when it is evaluated
+        let result = dl.evaluate_str(rule, r#"{"text": "a1b22", "pattern": "[0-9"}"#, None);
+        assert!(matches!(
+            result,
+            Err(LogicError::InvalidArgumentsError { .. })
+        ));
+    }
+
+    #[test]
+    fn test_extract() {
+        let dl = DataLogic::new();
+        let data = r#"{"email": "jane.doe@example.com"}"#;
+
+        let cases = [
+            (
+                r#"{"regex_extract": [{"var": "email"}, "@(.+)$"]}"#,
+                json!("@example.com"),
+            ),
+            (
+                r#"{"regex_extract": [{"var": "email"}, "@(.+)$", 1]}"#,
+                json!("example.com"),
+            ),
+            (
+                r#"{"regex_extract": [{"var": "email"}, "^(?P<user>[^@]+)@", "user"]}"#,
+                json!("jane.doe"),
+            ),
+            (
+                r#"{"regex_extract": [{"var": "email"}, "^[0-9]+"]}"#,
+                json!(null),
+            ),
+            (
+                r#"{"regex_extract": [{"var": "email"}, "(x)?@", 1]}"#,
+                json!(null),
+            ),
+        ];
+        for (rule, expected) in cases {
+            assert_eq!(
+                dl.evaluate_str(rule, data, None).unwrap(),
+                expected,
+                "{}",
+                rule
+            );
+        }
+    }
+}
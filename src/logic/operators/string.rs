@@ -1,14 +1,24 @@
 //! String operators for logic expressions.
 //!
 //! This module provides implementations for string operators
-//! such as cat, substr, etc.
+//! such as cat, substr, upper, split, etc.
+//!
+//! Positions and lengths count characters rather than bytes, so strings
+//! with multi-byte characters are handled the same as ASCII strings.
 
 use crate::arena::DataArena;
+use crate::logic::operators::Arity;
 use crate::logic::error::{LogicError, Result};
 use crate::logic::evaluator::evaluate;
 use crate::logic::token::Token;
 use crate::value::DataValue;
 
+/// The longest string `pad` produces, in characters.
+///
+/// The padding is built outside the arena, so a length from the data must
+/// not be able to exhaust memory before the memory limit is checked.
+const MAX_PAD_LENGTH: usize = 1 << 20;
+
 /// Enumeration of string operators.
 #[derive(Debug, Clone, Copy, PartialEq, Eq)]
 pub enum StringOp {
@@ -16,6 +26,47 @@ pub enum StringOp {
     Cat,
     /// Substring extraction
     Substr,
+    /// Conversion to upper case
+    Upper,
+    /// Conversion to lower case
+    Lower,
+    /// Removal of leading and trailing whitespace
+    Trim,
+    /// Prefix test
+    StartsWith,
+    /// Suffix test
+    EndsWith,
+    /// Substring test
+    Contains,
+    /// Splitting at a separator
+    Split,
+    /// Joining array items with a separator
+    Join,
+    /// Replacement of all occurrences of a substring
+    Replace,
+    /// Length in characters
+    Length,
+    /// Padding to a minimum length
+    Pad,
+}
+
+impl StringOp {
+    /// Returns the number of arguments the operator accepts.
+    pub(crate) const fn arity(self) -> Arity {
+        match self {
+            StringOp::Cat => Arity::ANY,
+            StringOp::Substr | StringOp::Pad => Arity::between(2, 3),
+            StringOp::Upper | StringOp::Lower | StringOp::Trim | StringOp::Length => {
+                Arity::exactly(1)
+            }
+            StringOp::StartsWith
+            | StringOp::EndsWith
+            | StringOp::Contains
+            | StringOp::Split
+            | StringOp::Join => Arity::exactly(2),
+            StringOp::Replace => Arity::exactly(3),
+        }
+    }
 }
 
 /// Evaluates a string concatenation operation.
@@ -91,9 +142,7 @@ pub fn eval_substr<'a>(
     args: &'a [&'a Token<'a>],
     arena: &'a DataArena,
 ) -> Result<&'a DataValue<'a>> {
-    if args.len() < 2 || args.len() > 3 {
-        return Err(LogicError::InvalidArgumentsError);
-    }
+    StringOp::Substr.arity().check("substr", args.len())?;
 
     let string = evaluate(args[0], arena)?;
     let string_str = match string {
@@ -152,9 +201,257 @@ pub fn eval_substr<'a>(
     Ok(arena.alloc(DataValue::String(arena.alloc_str(&result))))
 }
 
+/// Evaluates a conversion to upper case.
+pub fn eval_upper<'a>(
+    args: &'a [&'a Token<'a>],
+    arena: &'a DataArena,
+) -> Result<&'a DataValue<'a>> {
+    StringOp::Upper.arity().check("upper", args.len())?;
+
+    let string = string_argument("upper", args, 0, arena)?;
+    Ok(string_value(arena, &string.to_uppercase()))
+}
+
+/// Evaluates a conversion to lower case.
+pub fn eval_lower<'a>(
+    args: &'a [&'a Token<'a>],
+    arena: &'a DataArena,
+) -> Result<&'a DataValue<'a>> {
+    StringOp::Lower.arity().check("lower", args.len())?;
+
+    let string = string_argument("lower", args, 0, arena)?;
+    Ok(string_value(arena, &string.to_lowercase()))
+}
+
+/// Evaluates the removal of leading and trailing whitespace.
+pub fn eval_trim<'a>(
+    args: &'a [&'a Token<'a>],
+    arena: &'a DataArena,
+) -> Result<&'a DataValue<'a>> {
+    StringOp::Trim.arity().check("trim", args.len())?;
+
+    let string = string_argument("trim", args, 0, arena)?;
+    Ok(arena.alloc(DataValue::String(string.trim())))
+}
+
+/// Evaluates whether a string starts with a prefix.
+pub fn eval_starts_with<'a>(
+    args: &'a [&'a Token<'a>],
+    arena: &'a DataArena,
+) -> Result<&'a DataValue<'a>> {
+    StringOp::StartsWith.arity().check("starts_with", args.len())?;
+
+    let string = string_argument("starts_with", args, 0, arena)?;
+    let prefix = string_argument("starts_with", args, 1, arena)?;
+    Ok(bool_value(arena, string.starts_with(prefix)))
+}
+
+/// Evaluates whether a string ends with a suffix.
+pub fn eval_ends_with<'a>(
+    args: &'a [&'a Token<'a>],
+    arena: &'a DataArena,
+) -> Result<&'a DataValue<'a>> {
+    StringOp::EndsWith.arity().check("ends_with", args.len())?;
+
+    let string = string_argument("ends_with", args, 0, arena)?;
+    let suffix = string_argument("ends_with", args, 1, arena)?;
+    Ok(bool_value(arena, string.ends_with(suffix)))
+}
+
+/// Evaluates whether a string contains a substring.
+pub fn eval_contains<'a>(
+    args: &'a [&'a Token<'a>],
+    arena: &'a DataArena,
+) -> Result<&'a DataValue<'a>> {
+    StringOp::Contains.arity().check("contains", args.len())?;
+
+    let string = string_argument("contains", args, 0, arena)?;
+    let needle = string_argument("contains", args, 1, arena)?;
+    Ok(bool_value(arena, string.contains(needle)))
+}
+
+/// Evaluates the splitting of a string at a separator.
+///
+/// An empty separator splits the string into its characters.
+pub fn eval_split<'a>(
+    args: &'a [&'a Token<'a>],
+    arena: &'a DataArena,
+) -> Result<&'a DataValue<'a>> {
+    StringOp::Split.arity().check("split", args.len())?;
+
+    let string = string_argument("split", args, 0, arena)?;
+    let separator = string_argument("split", args, 1, arena)?;
+
+    // The parts borrow from the string, so only the array is allocated
+    let mut parts = arena.get_data_value_vec();
+    if separator.is_empty() {
+        for (start, c) in string.char_indices() {
+            parts.push(DataValue::String(&string[start..start + c.len_utf8()]));
+        }
+    } else {
+        parts.extend(string.split(separator).map(DataValue::String));
+    }
+
+    Ok(arena.alloc(DataValue::Array(arena.bump_vec_into_slice(parts))))
+}
+
+/// Evaluates the joining of array items with a separator.
+///
+/// Items that are not strings are converted to strings as in `cat`.
+pub fn eval_join<'a>(
+    args: &'a [&'a Token<'a>],
+    arena: &'a DataArena,
+) -> Result<&'a DataValue<'a>> {
+    StringOp::Join.arity().check("join", args.len())?;
+
+    let array = evaluate(args[0], arena)?;
+    let DataValue::Array(items) = array else {
+        return Err(LogicError::invalid_argument("join", 0, "array", array));
+    };
+    let separator = string_argument("join", args, 1, arena)?;
+
+    let mut result = String::new();
+    for (i, item) in items.iter().enumerate() {
+        if i > 0 {
+            result.push_str(separator);
+        }
+        match item {
+            DataValue::String(s) => result.push_str(s),
+            _ => result.push_str(&item.to_string()),
+        }
+    }
+
+    Ok(string_value(arena, &result))
+}
+
+/// Evaluates the replacement of all occurrences of a substring.
+pub fn eval_replace<'a>(
+    args: &'a [&'a Token<'a>],
+    arena: &'a DataArena,
+) -> Result<&'a DataValue<'a>> {
+    StringOp::Replace.arity().check("replace", args.len())?;
+
+    let string = string_argument("replace", args, 0, arena)?;
+    let pattern = string_argument("replace", args, 1, arena)?;
+    let replacement = string_argument("replace", args, 2, arena)?;
+    Ok(string_value(arena, &string.replace(pattern, replacement)))
+}
+
+/// Evaluates the length of a string in characters, or of an array in items.
+pub fn eval_length<'a>(
+    args: &'a [&'a Token<'a>],
+    arena: &'a DataArena,
+) -> Result<&'a DataValue<'a>> {
+    StringOp::Length.arity().check("length", args.len())?;
+
+    let length = match evaluate(args[0], arena)? {
+        DataValue::Array(items) => items.len(),
+        value => coerce_to_str("length", 0, value, arena)?.chars().count(),
+    };
+    Ok(arena.alloc(DataValue::integer(length as i64)))
+}
+
+/// Evaluates the padding of a string to a minimum length.
+///
+/// A positive length pads at the start and a negative length at the end,
+/// with a space or the given fill string, which is repeated as needed.
+/// Lengths longer than `MAX_PAD_LENGTH` are rejected.
+pub fn eval_pad<'a>(
+    args: &'a [&'a Token<'a>],
+    arena: &'a DataArena,
+) -> Result<&'a DataValue<'a>> {
+    StringOp::Pad.arity().check("pad", args.len())?;
+
+    let string = string_argument("pad", args, 0, arena)?;
+
+    let length_value = evaluate(args[1], arena)?;
+    let length = length_value
+        .coerce_to_number()
+        .ok_or_else(|| LogicError::nan("pad", 1, length_value))?
+        .as_f64() as i64;
+    if length.unsigned_abs() > MAX_PAD_LENGTH as u64 {
+        return Err(LogicError::invalid_argument(
+            "pad",
+            1,
+            format!("length of at most {} characters", MAX_PAD_LENGTH),
+            length_value,
+        ));
+    }
+
+    let fill = if args.len() == 3 {
+        string_argument("pad", args, 2, arena)?
+    } else {
+        " "
+    };
+
+    let char_count = string.chars().count();
+    let target = length.unsigned_abs() as usize;
+    if char_count >= target || fill.is_empty() {
+        return Ok(arena.alloc(DataValue::String(string)));
+    }
+
+    let padding: String = fill.chars().cycle().take(target - char_count).collect();
+    let result = if length < 0 {
+        format!("{}{}", string, padding)
+    } else {
+        format!("{}{}", padding, string)
+    };
+    Ok(string_value(arena, &result))
+}
+
+/// Evaluates the argument at `index` as a string.
+pub(crate) fn string_argument<'a>(
+    operator: &str,
+    args: &'a [&'a Token<'a>],
+    index: usize,
+    arena: &'a DataArena,
+) -> Result<&'a str> {
+    let value = evaluate(args[index], arena)?;
+    coerce_to_str(operator, index, value, arena)
+}
+
+/// Returns the string form of the argument at `index`.
+///
+/// Numbers and booleans are converted to strings. Other values are rejected
+/// rather than converted, so a missing value does not turn into `"null"`.
+pub(crate) fn coerce_to_str<'a>(
+    operator: &str,
+    index: usize,
+    value: &'a DataValue<'a>,
+    arena: &'a DataArena,
+) -> Result<&'a str> {
+    match value {
+        DataValue::String(s) => Ok(s),
+        DataValue::Number(_)
+        | DataValue::Bool(_)
+        | DataValue::DateTime(_)
+        | DataValue::Duration(_) => Ok(arena.alloc_str(&value.to_string())),
+        _ => Err(LogicError::invalid_argument(
+            operator, index, "string", value,
+        )),
+    }
+}
+
+/// Allocates a string value in the arena.
+pub(crate) fn string_value<'a>(arena: &'a DataArena, string: &str) -> &'a DataValue<'a> {
+    arena.alloc(DataValue::String(arena.alloc_str(string)))
+}
+
+/// Returns the preallocated value for a boolean.
+pub(crate) fn bool_value(arena: &DataArena, value: bool) -> &DataValue<'_> {
+    if value {
+        arena.true_value()
+    } else {
+        arena.false_value()
+    }
+}
+
 #[cfg(test)]
 mod tests {
+    use crate::DataLogic;
     use crate::logic::datalogic_core::DataLogicCore;
+    use crate::logic::error::LogicError;
+    use crate::value::DataValue;
     use serde_json::json;
 
     #[test]
@@ -253,4 +550,178 @@ mod tests {
         let result = core.apply(&rule, &data_json).unwrap();
         assert_eq!(result, json!(""));
     }
+
+    #[test]
+    fn test_case_and_trim() {
+        let core = DataLogicCore::new();
+        let builder = core.builder();
+
+        let data_json = json!({"name": "  Ünïcödé Straße  "});
+
+        let rule = builder.string_ops().upper_op().var("name").build();
+        let result = core.apply(&rule, &data_json).unwrap();
+        assert_eq!(result, json!("  ÜNÏCÖDÉ STRASSE  "));
+
+        let rule = builder.string_ops().lower_op().var("name").build();
+        let result = core.apply(&rule, &data_json).unwrap();
+        assert_eq!(result, json!("  ünïcödé straße  "));
+
+        let rule = builder.string_ops().trim_op().var("name").build();
+        let result = core.apply(&rule, &data_json).unwrap();
+        assert_eq!(result, json!("Ünïcödé Straße"));
+
+        // Numbers and booleans are converted to strings
+        let rule = builder.string_ops().upper_op().bool(true).build();
+        let result = core.apply(&rule, &data_json).unwrap();
+        assert_eq!(result, json!("TRUE"));
+    }
+
+    #[test]
+    fn test_substring_tests() {
+        let core = DataLogicCore::new();
+        let builder = core.builder();
+
+        let data_json = json!({"email": "jane@example.com"});
+
+        let rule = builder
+            .string_ops()
+            .starts_with_op()
+            .var("email")
+            .string("jane")
+            .build();
+        assert_eq!(core.apply(&rule, &data_json).unwrap(), json!(true));
+
+        let rule = builder
+            .string_ops()
+            .ends_with_op()
+            .var("email")
+            .string(".org")
+            .build();
+        assert_eq!(core.apply(&rule, &data_json).unwrap(), json!(false));
+
+        let rule = builder
+            .string_ops()
+            .contains_op()
+            .var("email")
+            .string("@")
+            .build();
+        assert_eq!(core.apply(&rule, &data_json).unwrap(), json!(true));
+    }
+
+    #[test]
+    fn test_split_and_join() {
+        let core = DataLogicCore::new();
+        let builder = core.builder();
+
+        let data_json = json!({"tags": "a,b,,c", "word": "héllo", "items": ["x", 1, true]});
+
+        let rule = builder
+            .string_ops()
+            .split_op()
+            .var("tags")
+            .string(",")
+            .build();
+        assert_eq!(
+            core.apply(&rule, &data_json).unwrap(),
+            json!(["a", "b", "", "c"])
+        );
+
+        // An empty separator splits into characters
+        let rule = builder
+            .string_ops()
+            .split_op()
+            .var("word")
+            .string("")
+            .build();
+        assert_eq!(
+            core.apply(&rule, &data_json).unwrap(),
+            json!(["h", "é", "l", "l", "o"])
+        );
+
+        let rule = builder
+            .string_ops()
+            .join_op()
+            .var("items")
+            .string("-")
+            .build();
+        assert_eq!(core.apply(&rule, &data_json).unwrap(), json!("x-1-true"));
+    }
+
+    #[test]
+    fn test_replace_length_and_pad() {
+        let core = DataLogicCore::new();
+        let builder = core.builder();
+
+        let data_json = json!({"text": "a-b-c", "word": "héllo", "id": 42});
+
+        let rule = builder
+            .string_ops()
+            .replace_op()
+            .var("text")
+            .string("-")
+            .string("+")
+            .build();
+        assert_eq!(core.apply(&rule, &data_json).unwrap(), json!("a+b+c"));
+
+        let rule = builder.string_ops().length_op().var("word").build();
+        assert_eq!(core.apply(&rule, &data_json).unwrap(), json!(5));
+
+        let rule = builder
+            .string_ops()
+            .pad_op()
+            .var("id")
+            .int(5)
+            .string("0")
+            .build();
+        assert_eq!(core.apply(&rule, &data_json).unwrap(), json!("00042"));
+
+        // A negative length pads at the end, counting characters
+        let rule = builder
+            .string_ops()
+            .pad_op()
+            .var("word")
+            .int(-7)
+            .string("xy")
+            .build();
+        assert_eq!(core.apply(&rule, &data_json).unwrap(), json!("hélloxy"));
+
+        // Lengths that would exhaust memory are rejected, also when folded at parse time
+        let dl = DataLogic::new();
+        for rule in [
+            r#"{"pad": ["a", 1000000000000]}"#,
+            r#"{"pad": ["a", {"var": "n"}]}"#,
+        ] {
+            let err = dl.evaluate_str(rule, r#"{"n": -1000000000000}"#, None).unwrap_err();
+            assert!(
+                matches!(err, LogicError::InvalidArgumentsError { index: Some(1), .. }),
+                "{rule}"
+            );
+        }
+    }
+
+    #[test]
+    fn test_invalid_string_arguments() {
+        let core = DataLogicCore::new();
+        let builder = core.builder();
+
+        let data_json = json!({"missing": null, "text": "abc"});
+
+        let rule = builder.string_ops().upper_op().var("missing").build();
+        let err = core.apply(&rule, &data_json).unwrap_err();
+        assert_eq!(
+            err.without_location(),
+            LogicError::invalid_argument("upper", 0, "string", &DataValue::Null)
+        );
+
+        let rule = builder
+            .string_ops()
+            .join_op()
+            .var("text")
+            .string(",")
+            .build();
+        assert!(core.apply(&rule, &data_json).is_err());
+
+        let rule = builder.string_ops().trim_op().build();
+        assert!(core.apply(&rule, &data_json).is_err());
+    }
 }
@@ -3,11 +3,15 @@
 //! This module provides the implementation of the throw operator.
 
 use crate::arena::DataArena;
+use crate::logic::operators::Arity;
 use crate::logic::error::{LogicError, Result};
 use crate::logic::evaluator::evaluate;
 use crate::logic::token::Token;
 use crate::value::DataValue;
 
+/// The number of arguments `throw` accepts.
+pub(crate) const THROW_ARITY: Arity = Arity::at_least(1);
+
 /// Evaluates a throw operation.
 /// The throw operator throws an error with the provided value.
 #[inline]
@@ -16,9 +20,7 @@ pub fn eval_throw<'a>(
     arena: &'a DataArena,
 ) -> Result<&'a DataValue<'a>> {
     // Check if we have the right number of arguments
-    if args.is_empty() {
-        return Err(LogicError::InvalidArgumentsError);
-    }
+    THROW_ARITY.check("throw", args.len())?;
 
     // Evaluate the first argument to get the error value/type
     let error_value = evaluate(args[0], arena)?;
@@ -31,10 +33,10 @@ pub fn eval_throw<'a>(
     // Handle object values with a "type" field
     if let Some(obj) = error_value.as_object() {
         for (key, value) in obj {
-            if *key == "type" {
-                if let Some(type_str) = value.as_str() {
-                    return Err(LogicError::thrown_error(type_str));
-                }
+            if *key == "type"
+                && let Some(type_str) = value.as_str()
+            {
+                return Err(LogicError::thrown_error(type_str));
             }
         }
     }
@@ -74,7 +76,7 @@ mod tests {
         let rule = builder.throw_op(builder.string_value("hello"));
         let result = core.apply(&rule, &data_json);
         assert!(result.is_err());
-        if let Err(LogicError::ThrownError { r#type: error_type }) = result {
+        if let Err(LogicError::ThrownError { r#type: error_type, .. }) = result {
             assert_eq!(error_type, "hello");
         } else {
             panic!("Expected ThrownError, got: {:?}", result);
@@ -95,7 +97,7 @@ mod tests {
         let rule = builder.throw_op(builder.val_str("x"));
         let result = core.apply(&rule, &data_json);
         assert!(result.is_err());
-        if let Err(LogicError::ThrownError { r#type: error_type }) = result {
+        if let Err(LogicError::ThrownError { r#type: error_type, .. }) = result {
             assert_eq!(error_type, "Some error");
         } else {
             panic!("Expected ThrownError, got: {:?}", result);
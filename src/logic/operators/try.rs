@@ -3,11 +3,15 @@
 //! This module provides the implementation of the try operator for error handling.
 
 use crate::arena::DataArena;
+use crate::logic::operators::Arity;
 use crate::logic::error::{LogicError, Result};
 use crate::logic::evaluator::evaluate;
 use crate::logic::token::Token;
 use crate::value::DataValue;
 
+/// The number of arguments `try` accepts.
+pub(crate) const TRY_ARITY: Arity = Arity::at_least(1);
+
 /// Evaluates a try operation.
 /// The try operator attempts to evaluate a sequence of expressions, returning
 /// the result of the first one that succeeds without an error.
@@ -21,9 +25,7 @@ pub fn eval_try<'a>(
     arena: &'a DataArena,
 ) -> Result<&'a DataValue<'a>> {
     // Check if we have arguments
-    if args.is_empty() {
-        return Err(LogicError::InvalidArgumentsError);
-    }
+    TRY_ARITY.check("try", args.len())?;
 
     // Special case for a single argument - just evaluate it
     if args.len() == 1 {
@@ -46,6 +48,9 @@ pub fn eval_try<'a>(
                 }
             }
         } else {
+            // The previous attempt failed, so its error no longer needs a location
+            arena.take_failure();
+
             // For subsequent expressions, we need to create an error context
             // that includes the error details from the previous attempt
             
@@ -53,7 +58,7 @@ pub fn eval_try<'a>(
             let current_chain_len = arena.path_chain_len();
             
             let error_context = match &last_error {
-                Some(LogicError::ThrownError { r#type: error_type }) => {
+                Some(LogicError::ThrownError { r#type: error_type, .. }) => {
                     // Create a context with the error type
                     let entries = arena.vec_into_slice(vec![(
                         arena.intern_str("type"),
@@ -61,7 +66,7 @@ pub fn eval_try<'a>(
                     )]);
                     arena.alloc(DataValue::Object(entries))
                 }
-                Some(LogicError::NaNError) => {
+                Some(LogicError::NaNError { .. }) => {
                     // Create a context for NaN errors
                     let entries = arena.vec_into_slice(vec![(
                         arena.intern_str("type"),
@@ -85,7 +90,7 @@ pub fn eval_try<'a>(
             
             // Set the error context as current but restore the original root context
             let key = DataValue::Number(crate::value::NumberValue::from_f64(i as f64));
-            arena.set_current_context(&error_context, &key);
+            arena.set_current_context(error_context, &key);
             
             // Make sure the root context is still available for scope jumps
             if let Some(root) = original_root {
@@ -108,7 +113,7 @@ pub fn eval_try<'a>(
     }
 
     // If we get here, all expressions failed; propagate the last error
-    Err(last_error.unwrap_or(LogicError::InvalidArgumentsError))
+    Err(last_error.unwrap_or_else(|| TRY_ARITY.error("try", 0)))
 }
 
 #[cfg(test)]
@@ -149,7 +154,7 @@ mod tests {
         ]);
         let result = core.apply(&rule, &data_json);
         assert!(result.is_err());
-        if let Err(LogicError::ThrownError { r#type: error_type }) = result {
+        if let Err(LogicError::ThrownError { r#type: error_type, .. }) = result {
             assert_eq!(error_type, "Another error");
         } else {
             panic!("Expected ThrownError, got: {:?}", result);
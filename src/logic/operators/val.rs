@@ -4,11 +4,18 @@
 //! which is a replacement for the var operator.
 
 use crate::arena::DataArena;
-use crate::logic::error::{LogicError, Result};
+use crate::logic::operators::Arity;
+use crate::logic::error::Result;
 use crate::logic::evaluator::evaluate;
 use crate::logic::token::Token;
 use crate::value::DataValue;
 
+/// The number of arguments `val` accepts.
+pub(crate) const VAL_ARITY: Arity = Arity::at_least(1);
+
+/// The number of arguments `exists` accepts.
+pub(crate) const EXISTS_ARITY: Arity = Arity::at_least(1);
+
 /// The val operator is used to access properties from the data context
 /// Examples: {"val": "a"}, {"val": ["a", "b", "c"]}, {"val": 0}
 #[inline]
@@ -17,9 +24,7 @@ pub fn eval_val<'a>(
     arena: &'a DataArena,
 ) -> Result<&'a DataValue<'a>> {
     // Check if we have the right number of arguments
-    if args.is_empty() {
-        return Err(LogicError::InvalidArgumentsError);
-    }
+    VAL_ARITY.check("val", args.len())?;
 
     // Evaluate the first argument to get the path
     let path_value = evaluate(args[0], arena)?;
@@ -49,13 +54,12 @@ pub fn eval_val<'a>(
 #[inline]
 fn get_current_index<'a>(arena: &'a DataArena) -> Result<&'a DataValue<'a>> {
     // Check if we're in a scope with an array index
-    if let Some(last_path) = arena.last_path_component() {
-        if let DataValue::Number(n) = last_path {
-            if let Some(idx) = n.as_i64() {
-                // Return the array index as a DataValue
-                return Ok(arena.alloc(DataValue::integer(idx)));
-            }
-        }
+    if let Some(last_path) = arena.last_path_component()
+        && let DataValue::Number(n) = last_path
+        && let Some(idx) = n.as_i64()
+    {
+        // Return the array index as a DataValue
+        return Ok(arena.alloc(DataValue::integer(idx)));
     }
     // If we can't find a valid index, return 0
     Ok(arena.alloc(DataValue::integer(0)))
@@ -79,28 +83,28 @@ fn process_complex_path<'a>(
 
         // Case 3: Array path for nested access
         DataValue::Array(path_components) => {
-            if let DataValue::Array(jumps) = path_components[0] {
-                if jumps.len() == 1 {
-                    let jump = jumps[0].as_i64().unwrap_or(0);
-                    
-                    // Get the context after jumping up the scope chain
-                    let jumped_context = arena.current_context(jump.abs() as usize)
-                        .unwrap_or_else(|| arena.null_value());
-                    
-                    // If there are additional path components beyond the jump, navigate them
-                    if path_components.len() > 1 {
-                        // Special case for accessing the index after a scope jump
-                        if path_components.len() == 2 && 
-                           matches!(path_components[1], DataValue::String(key) if key == "index") {
-                            return handle_index_with_jump(jump, arena);
-                        }
-                        
-                        return navigate_nested_path(jumped_context, &path_components[1..], arena);
+            if let DataValue::Array(jumps) = path_components[0]
+                && jumps.len() == 1
+            {
+                let jump = jumps[0].as_i64().unwrap_or(0);
+                
+                // Get the context after jumping up the scope chain
+                let jumped_context = arena.current_context(jump.unsigned_abs() as usize)
+                    .unwrap_or_else(|| arena.null_value());
+                
+                // If there are additional path components beyond the jump, navigate them
+                if path_components.len() > 1 {
+                    // Special case for accessing the index after a scope jump
+                    if path_components.len() == 2 && 
+                       matches!(path_components[1], DataValue::String(key) if key == "index") {
+                        return handle_index_with_jump(jump, arena);
                     }
                     
-                    return Ok(jumped_context);
+                    return navigate_nested_path(jumped_context, &path_components[1..], arena);
                 }
-            } 
+                
+                return Ok(jumped_context);
+            }
 
             navigate_nested_path(current_context, path_components, arena)
         },
@@ -141,7 +145,7 @@ fn handle_index_with_jump<'a>(
     }
     
     // For negative jump, check if we're looking at the current array index
-    let jump_level = jump.abs() as usize;
+    let jump_level = jump.unsigned_abs() as usize;
     if jump == -1 {
         // For -1 jump, prioritize checking the current array index
         return get_current_index(arena);
@@ -152,12 +156,11 @@ fn handle_index_with_jump<'a>(
         return arena.with_path_chain(|path_components| {
             let idx_position = path_len - jump_level;
             
-            if idx_position > 0 && idx_position <= path_components.len() {
-                if let DataValue::Number(n) = path_components[idx_position - 1] {
-                    if let Some(idx) = n.as_i64() {
-                        return Ok(arena.alloc(DataValue::integer(idx)));
-                    }
-                }
+            if idx_position > 0 && idx_position <= path_components.len()
+                && let DataValue::Number(n) = path_components[idx_position - 1]
+                && let Some(idx) = n.as_i64()
+            {
+                return Ok(arena.alloc(DataValue::integer(idx)));
             }
             
             // If we can't find a valid index, return 0
@@ -189,10 +192,10 @@ fn access_property<'a>(
         }
         DataValue::Array(items) => {
             // Try to parse the key as an array index
-            if let Ok(index) = key.parse::<usize>() {
-                if index < items.len() {
-                    return Ok(&items[index]);
-                }
+            if let Ok(index) = key.parse::<usize>()
+                && index < items.len()
+            {
+                return Ok(&items[index]);
             }
             // Invalid index or out of bounds
             Ok(arena.null_value())
@@ -209,10 +212,10 @@ fn access_array_index<'a>(
     index: usize,
     arena: &'a DataArena,
 ) -> Result<&'a DataValue<'a>> {
-    if let DataValue::Array(items) = data {
-        if index < items.len() {
-            return Ok(&items[index]);
-        }
+    if let DataValue::Array(items) = data
+        && index < items.len()
+    {
+        return Ok(&items[index]);
     }
     // Not an array or index out of bounds
     Ok(arena.null_value())
@@ -314,9 +317,7 @@ pub fn eval_exists<'a>(
     args: &'a [DataValue<'a>],
     arena: &'a DataArena,
 ) -> Result<&'a DataValue<'a>> {
-    if args.is_empty() {
-        return Err(LogicError::InvalidArgumentsError);
-    }
+    EXISTS_ARITY.check("exists", args.len())?;
 
     let current_context = arena.current_context(0).unwrap();
     
@@ -343,7 +344,7 @@ pub fn eval_exists<'a>(
     }
     
     // Multiple arguments case - treat as a nested path
-    return check_nested_path_exists(args, current_context, arena);
+    check_nested_path_exists(args, current_context, arena)
 }
 
 /// Checks if a nested path exists in the data
@@ -384,16 +385,11 @@ fn check_nested_path_exists<'a>(
                     if idx >= 0 {
                         let idx_usize = idx as usize;
                         match current {
-                            DataValue::Array(arr) => {
-                                if idx_usize < arr.len() {
-                                    current = &arr[idx_usize];
-                                } else {
-                                    // Index out of bounds
-                                    return Ok(arena.alloc(DataValue::Bool(false)));
-                                }
+                            DataValue::Array(arr) if idx_usize < arr.len() => {
+                                current = &arr[idx_usize];
                             },
                             _ => {
-                                // Not an array, so path doesn't exist
+                                // Not an array or index out of bounds
                                 return Ok(arena.alloc(DataValue::Bool(false)));
                             }
                         }
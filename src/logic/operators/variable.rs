@@ -93,10 +93,10 @@ fn evaluate_simple_path<'a>(
 ) -> Result<&'a DataValue<'a>> {
     // Special case for numeric indices - direct array access
     if let Ok(index) = path.parse::<usize>() {
-        if let DataValue::Array(items) = data {
-            if index < items.len() {
-                return Ok(&items[index]);
-            }
+        if let DataValue::Array(items) = data
+            && index < items.len()
+        {
+            return Ok(&items[index]);
         }
 
         // Not found, use default
@@ -142,10 +142,10 @@ fn find_in_object<'a>(obj: &'a DataValue<'a>, key: &str) -> Option<&'a DataValue
 /// Helper function to get an index from an array
 #[inline]
 fn get_array_index<'a>(arr: &'a DataValue<'a>, index: usize) -> Option<&'a DataValue<'a>> {
-    if let DataValue::Array(items) = arr {
-        if index < items.len() {
-            return Some(&items[index]);
-        }
+    if let DataValue::Array(items) = arr
+        && index < items.len()
+    {
+        return Some(&items[index]);
     }
     None
 }
@@ -3,147 +3,563 @@
 //! This module provides functions for optimizing logic expressions by
 //! precomputing static parts of the expression at compile time.
 
+use super::cse::share_subexpressions;
 use super::error::Result;
+use super::location::SourceMap;
+use super::operators::array::{ArrayOp, set_order};
+use super::operators::control::ControlOp;
+use super::operators::datetime::DateTimeOp;
+use super::operators::regex;
 use super::token::{OperatorType, Token};
 use crate::arena::DataArena;
 use crate::logic::evaluator::evaluate;
 use crate::value::DataValue;
 
 /// Optimizes a token by evaluating static parts of the expression.
+///
+/// Expressions that occur more than once are then shared, so each of them is
+/// evaluated once per evaluation.
 pub fn optimize<'a>(token: &'a Token<'a>, arena: &'a DataArena) -> Result<&'a Token<'a>> {
+    optimize_mapped(token, arena, &mut SourceMap::default())
+}
+
+/// Optimizes a token, giving each token it replaces the pointer of the
+/// original in the source map.
+pub(crate) fn optimize_mapped<'a>(
+    token: &'a Token<'a>,
+    arena: &'a DataArena,
+    source_map: &mut SourceMap,
+) -> Result<&'a Token<'a>> {
+    let token = optimize_token(token, arena, source_map)?;
+    Ok(share_subexpressions(token, arena, source_map))
+}
+
+fn optimize_token<'a>(
+    token: &'a Token<'a>,
+    arena: &'a DataArena,
+    source_map: &mut SourceMap,
+) -> Result<&'a Token<'a>> {
+    let optimized = optimize_node(token, arena, source_map)?;
+    source_map.replace(token, optimized);
+    Ok(optimized)
+}
+
+fn optimize_node<'a>(
+    token: &'a Token<'a>,
+    arena: &'a DataArena,
+    source_map: &mut SourceMap,
+) -> Result<&'a Token<'a>> {
     match token {
         // Literals are already optimized
         Token::Literal(_) => Ok(token),
 
+        // Patterns are compiled already
+        Token::Pattern(_) => Ok(token),
+
         // Variables can't be optimized without data
         Token::Variable { .. } => Ok(token),
 
         // Dynamic variables can't be optimized without data
         Token::DynamicVariable { .. } => Ok(token),
 
-        // For now, just return the original token for array literals
-        // This needs to be fixed with a proper lifetime-respecting implementation
-        Token::ArrayLiteral(_) => Ok(token),
+        // An array of expressions is a literal once all of them are
+        Token::ArrayLiteral(items) => {
+            let items = optimize_items(items, arena, source_map)?;
+            if !items.iter().all(|item| item.is_literal()) {
+                return Ok(arena.alloc(Token::ArrayLiteral(items)));
+            }
+
+            let values: Vec<DataValue> = items
+                .iter()
+                .filter_map(|item| item.as_literal().cloned())
+                .collect();
+            let array = DataValue::Array(arena.vec_into_slice(values));
+            Ok(arena.alloc(Token::literal(array)))
+        }
 
-        // Operators might be optimizable if their arguments are static
+        // Operators are simplified once their arguments are optimized
         Token::Operator { op_type, args } => {
-            // Special case: missing and missing_some operators always need data
-            if *op_type == OperatorType::Missing
-                || *op_type == OperatorType::MissingSome
-                || *op_type == OperatorType::Exists
-                || *op_type == OperatorType::Val
-            {
-                // Just optimize the arguments
-                let optimized_args = optimize(args, arena)?;
-                return Ok(arena.alloc(Token::operator(*op_type, optimized_args)));
-            }
+            let optimized_args = optimize_arguments(args, arena, source_map)?;
+            Ok(simplify(*op_type, optimized_args, arena))
+        }
 
+        // Custom operators can't be optimized, but their arguments can
+        Token::CustomOperator { name, args } => {
             // Optimize the arguments
-            let optimized_args = optimize(args, arena)?;
-
-            // Check if all arguments are literals or static expressions
-            let is_static = match optimized_args {
-                Token::ArrayLiteral(items) => {
-                    items.iter().all(|item| matches!(item, Token::Literal(_)))
-                }
-                Token::Literal(_) => true,
-                _ => false,
+            let optimized_args = optimize_arguments(args, arena, source_map)?;
+
+            // Return the optimized custom operator
+            Ok(arena.alloc(Token::custom_operator(name, optimized_args)))
+        }
+
+        // Shared expressions have been optimized already
+        Token::Shared { .. } => Ok(token),
+    }
+}
+
+/// Optimizes the arguments of an operator.
+///
+/// An array of arguments stays an array of tokens even if all of them are
+/// literals, as each of them is a separate argument.
+fn optimize_arguments<'a>(
+    args: &'a Token<'a>,
+    arena: &'a DataArena,
+    source_map: &mut SourceMap,
+) -> Result<&'a Token<'a>> {
+    match args {
+        Token::ArrayLiteral(items) => Ok(arena.alloc(Token::ArrayLiteral(optimize_items(
+            items, arena, source_map,
+        )?))),
+        _ => optimize_token(args, arena, source_map),
+    }
+}
+
+fn optimize_items<'a>(
+    items: &[&'a Token<'a>],
+    arena: &'a DataArena,
+    source_map: &mut SourceMap,
+) -> Result<&'a [&'a Token<'a>]> {
+    let items = items
+        .iter()
+        .map(|item| optimize_token(item, arena, source_map))
+        .collect::<Result<Vec<_>>>()?;
+    Ok(arena.vec_into_slice(items))
+}
+
+/// Applies an operator to its optimized arguments, simplifying the result.
+///
+/// Besides evaluating operators whose arguments are all literals, this
+/// removes the arguments of `and`, `or` and `if` that cannot change the
+/// result, flattens nested `and` and `or`, folds double negations and turns
+/// `in` against a literal array into a set lookup.
+pub(crate) fn simplify<'a>(
+    op_type: OperatorType,
+    args: &'a Token<'a>,
+    arena: &'a DataArena,
+) -> &'a Token<'a> {
+    match (op_type, args) {
+        (
+            OperatorType::Control(op @ (ControlOp::And | ControlOp::Or)),
+            Token::ArrayLiteral(items),
+        ) if !items.is_empty() => simplify_and_or(op, items, arena),
+        (OperatorType::Control(ControlOp::If), Token::ArrayLiteral(items)) if !items.is_empty() => {
+            simplify_if(items, arena)
+        }
+        (OperatorType::Control(op @ (ControlOp::Not | ControlOp::DoubleNegation)), _) => {
+            simplify_negation(op, args, arena)
+        }
+        (OperatorType::Array(ArrayOp::In), Token::ArrayLiteral(items)) => match items {
+            [needle, Token::Literal(DataValue::Array(members))] if !needle.is_literal() => {
+                in_set(needle, members, arena)
+            }
+            _ => fold(op_type, args, arena),
+        },
+        // A pattern that has become a literal is compiled now, unless it is
+        // invalid, which is reported when the operator is evaluated
+        (OperatorType::Regex(_), _) => {
+            let args = match regex::compile_pattern(args, arena) {
+                Some(Ok(compiled)) => compiled,
+                _ => args,
             };
+            fold(op_type, args, arena)
+        }
+        _ => fold(op_type, args, arena),
+    }
+}
 
-            // If all arguments are static, evaluate the expression
-            if is_static {
-                // Create a dummy data value for evaluation
-                let dummy_data = arena.alloc(DataValue::Null);
-                arena.set_current_context(&dummy_data, &DataValue::String("$"));
-
-                // Create the operator token in the arena
-                let op_token = arena.alloc(Token::operator(*op_type, optimized_args));
-
-                // Try to evaluate the expression
-                match evaluate(op_token, arena) {
-                    Ok(result) => {
-                        // Return the result as a literal
-                        return Ok(arena.alloc(Token::literal(result.clone())));
-                    }
-                    Err(_) => {
-                        // If evaluation fails, just return the optimized operator
-                        return Ok(op_token);
-                    }
-                }
+/// Flattens nested `and` and `or`, drops the arguments that cannot decide
+/// the result and those after an argument that always decides it.
+///
+/// `and` returns its first falsy argument and `or` its first truthy one, or
+/// otherwise their last argument, so a literal argument that does not
+/// decide the result only matters in the last position.
+fn simplify_and_or<'a>(
+    op: ControlOp,
+    items: &[&'a Token<'a>],
+    arena: &'a DataArena,
+) -> &'a Token<'a> {
+    let mut flattened = Vec::with_capacity(items.len());
+    for item in items {
+        match item {
+            Token::Operator {
+                op_type: OperatorType::Control(nested),
+                args: Token::ArrayLiteral(nested_items),
+            } if *nested == op && !nested_items.is_empty() => flattened.extend(*nested_items),
+            _ => flattened.push(*item),
+        }
+    }
+
+    // A falsy argument decides an `and`, a truthy one decides an `or`
+    let decides = |value: &DataValue| value.coerce_to_bool() == (op == ControlOp::Or);
+
+    let mut kept = Vec::with_capacity(flattened.len());
+    for (index, item) in flattened.iter().enumerate() {
+        if let Token::Literal(value) = item {
+            if decides(value) {
+                kept.push(*item);
+                break;
             }
+            if index + 1 < flattened.len() {
+                continue;
+            }
+        }
+        kept.push(*item);
+    }
 
-            // If not all arguments are static, check if we can optimize nested expressions
-            if let Token::ArrayLiteral(items) = optimized_args {
-                let mut all_optimized_items = Vec::with_capacity(items.len());
-                let mut any_changed = false;
-
-                // Try to optimize each item
-                for item in items.iter() {
-                    if let Token::Operator {
-                        op_type: _nested_op_type,
-                        args: _nested_args,
-                    } = *item
-                    {
-                        // Recursively optimize the nested operator
-                        let optimized_item = optimize(item, arena)?;
-                        all_optimized_items.push(optimized_item);
-
-                        // Check if the item was optimized
-                        if !std::ptr::eq(optimized_item, *item) {
-                            any_changed = true;
-                        }
-                    } else {
-                        // Keep non-operator items as is
-                        all_optimized_items.push(*item);
-                    }
-                }
-
-                // If any items were optimized, create a new array literal
-                if any_changed {
-                    // Check if all items are literals
-                    let all_literals = all_optimized_items
-                        .iter()
-                        .all(|item| matches!(item, Token::Literal(_)));
-
-                    // Create a new array literal
-                    let new_array_literal = Token::ArrayLiteral(all_optimized_items);
-                    let new_array_token = arena.alloc(new_array_literal);
-
-                    if all_literals {
-                        // Create a dummy data value for evaluation
-                        let dummy_data = arena.alloc(DataValue::Null);
-                        arena.set_current_context(&dummy_data, &DataValue::String("$"));
-                        // Create the operator token in the arena
-                        let op_token = arena.alloc(Token::operator(*op_type, new_array_token));
-
-                        // Try to evaluate the expression
-                        match evaluate(op_token, arena) {
-                            Ok(result) => {
-                                // Return the result as a literal
-                                return Ok(arena.alloc(Token::literal(result.clone())));
-                            }
-                            Err(_) => {
-                                // If evaluation fails, just return the optimized operator
-                                return Ok(op_token);
-                            }
-                        }
-                    }
-
-                    return Ok(arena.alloc(Token::operator(*op_type, new_array_token)));
-                }
+    match kept.as_slice() {
+        [single] => single,
+        _ => fold(
+            OperatorType::Control(op),
+            arena.alloc(Token::ArrayLiteral(arena.vec_into_slice(kept))),
+            arena,
+        ),
+    }
+}
+
+/// Drops the branches of `if` whose condition is always false, and those
+/// after a condition that is always true, which becomes the else branch.
+fn simplify_if<'a>(items: &[&'a Token<'a>], arena: &'a DataArena) -> &'a Token<'a> {
+    let mut kept = Vec::with_capacity(items.len());
+    let mut index = 0;
+    while index + 1 < items.len() {
+        match items[index] {
+            Token::Literal(condition) if !condition.coerce_to_bool() => {}
+            Token::Literal(_) => {
+                kept.push(items[index + 1]);
+                break;
+            }
+            condition => {
+                kept.push(condition);
+                kept.push(items[index + 1]);
             }
+        }
+        index += 2;
+    }
 
-            // If nothing was optimized, just return the optimized operator
-            Ok(arena.alloc(Token::operator(*op_type, optimized_args)))
+    // The else branch, unless a condition that always holds came first
+    if index + 1 == items.len() {
+        kept.push(items[index]);
+    }
+
+    match kept.as_slice() {
+        [] => arena.alloc(Token::literal(DataValue::Null)),
+        [branch] => branch,
+        _ => fold(
+            OperatorType::Control(ControlOp::If),
+            arena.alloc(Token::ArrayLiteral(arena.vec_into_slice(kept))),
+            arena,
+        ),
+    }
+}
+
+/// Folds `!` and `!!` applied to negations and other boolean expressions.
+fn simplify_negation<'a>(
+    op: ControlOp,
+    args: &'a Token<'a>,
+    arena: &'a DataArena,
+) -> &'a Token<'a> {
+    match (op, single_operand(args)) {
+        // `!!` of a boolean is the boolean itself
+        (ControlOp::DoubleNegation, Some(operand)) if is_boolean(operand) => operand,
+        // `!` of a negation undoes one of its negations
+        (
+            ControlOp::Not,
+            Some(Token::Operator {
+                op_type: OperatorType::Control(inner @ (ControlOp::Not | ControlOp::DoubleNegation)),
+                args: inner_args,
+            }),
+        ) if single_operand(inner_args).is_some() => {
+            let op = match inner {
+                ControlOp::Not => ControlOp::DoubleNegation,
+                _ => ControlOp::Not,
+            };
+            simplify_negation(op, inner_args, arena)
         }
+        _ => fold(OperatorType::Control(op), args, arena),
+    }
+}
 
-        // Custom operators can't be optimized, but their arguments can
-        Token::CustomOperator { name, args } => {
-            // Optimize the arguments
-            let optimized_args = optimize(args, arena)?;
+/// Returns the only argument of an operator that is not known yet.
+fn single_operand<'a>(args: &'a Token<'a>) -> Option<&'a Token<'a>> {
+    match args {
+        Token::ArrayLiteral(items) if items.len() == 1 => Some(items[0]),
+        Token::ArrayLiteral(_) | Token::Literal(_) => None,
+        token => Some(token),
+    }
+}
 
-            // Return the optimized custom operator
-            Ok(arena.alloc(Token::custom_operator(name, optimized_args)))
+/// Returns true if a token always evaluates to a boolean.
+fn is_boolean(token: &Token) -> bool {
+    match token {
+        Token::Literal(value) => value.is_bool(),
+        Token::Operator { op_type, .. } => matches!(
+            op_type,
+            OperatorType::Comparison(_)
+                | OperatorType::Control(ControlOp::Not | ControlOp::DoubleNegation)
+                | OperatorType::Array(
+                    ArrayOp::All | ArrayOp::Some | ArrayOp::None | ArrayOp::In | ArrayOp::InSet
+                )
+                | OperatorType::Exists
+        ),
+        _ => false,
+    }
+}
+
+/// Turns `in` against a literal array into a binary search of its sorted
+/// members.
+fn in_set<'a>(
+    needle: &'a Token<'a>,
+    members: &[DataValue<'a>],
+    arena: &'a DataArena,
+) -> &'a Token<'a> {
+    // Only booleans, numbers and strings are ever matched
+    let mut set: Vec<DataValue> = members
+        .iter()
+        .filter(|member| {
+            matches!(
+                member,
+                DataValue::Bool(_) | DataValue::Number(_) | DataValue::String(_)
+            )
+        })
+        .cloned()
+        .collect();
+    set.sort_by(set_order);
+    set.dedup_by(|a, b| a == b);
+
+    let set = arena.alloc(Token::literal(DataValue::Array(arena.vec_into_slice(set))));
+    arena.alloc(Token::operator(
+        OperatorType::Array(ArrayOp::InSet),
+        arena.alloc(Token::ArrayLiteral(arena.alloc_slice_copy(&[needle, set]))),
+    ))
+}
+
+/// Returns true for the operators that read the data or the clock
+/// themselves, which can never be evaluated ahead of time.
+fn reads_data(op_type: OperatorType) -> bool {
+    matches!(
+        op_type,
+        OperatorType::Missing
+            | OperatorType::MissingSome
+            | OperatorType::Exists
+            | OperatorType::Val
+            | OperatorType::DateTime(DateTimeOp::Now)
+    )
+}
+
+/// Returns true if a token is a literal or a compiled pattern.
+fn is_constant(token: &Token) -> bool {
+    matches!(token, Token::Literal(_) | Token::Pattern(_))
+}
+
+/// Applies an operator to its arguments, evaluating it right away if all
+/// the arguments are literals.
+pub(crate) fn fold<'a>(
+    op_type: OperatorType,
+    args: &'a Token<'a>,
+    arena: &'a DataArena,
+) -> &'a Token<'a> {
+    let op_token = arena.alloc(Token::operator(op_type, args));
+    let is_static = match args {
+        Token::ArrayLiteral(items) => items.iter().all(|item| is_constant(item)),
+        Token::Literal(_) => true,
+        _ => false,
+    };
+
+    if is_static && !reads_data(op_type) {
+        evaluate_static(op_token, arena)
+    } else {
+        op_token
+    }
+}
+
+/// Evaluates an operator that does not depend on the data into a literal.
+///
+/// If the evaluation fails, the operator is returned unchanged, so the
+/// error is raised when the rule is evaluated.
+pub(crate) fn evaluate_static<'a>(op_token: &'a Token<'a>, arena: &'a DataArena) -> &'a Token<'a> {
+    // Create a dummy data value for evaluation
+    let dummy_data = arena.alloc(DataValue::Null);
+    arena.set_current_context(dummy_data, &DataValue::String("$"));
+
+    match evaluate(op_token, arena) {
+        Ok(result) => arena.alloc(Token::literal(result.clone())),
+        Err(_) => op_token,
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use crate::{DataLogic, ToJson};
+    use serde_json::{Value as JsonValue, json};
+
+    fn optimized(rule: JsonValue) -> JsonValue {
+        let dl = DataLogic::new();
+        dl.parse_logic(&rule.to_string(), None).unwrap().to_json()
+    }
+
+    fn evaluate(rule: JsonValue, data: JsonValue) -> JsonValue {
+        DataLogic::new()
+            .evaluate_str(&rule.to_string(), &data.to_string(), None)
+            .unwrap()
+    }
+
+    #[test]
+    fn test_and_or() {
+        assert_eq!(
+            optimized(json!({"and": [true, {"var": "a"}, {"and": [{"var": "b"}, 1]}]})),
+            json!({"and": [{"var": "a"}, {"var": "b"}, 1]})
+        );
+        assert_eq!(
+            optimized(json!({"and": [{"var": "a"}, 0, {"var": "b"}]})),
+            json!({"and": [{"var": "a"}, 0]})
+        );
+        assert_eq!(
+            optimized(json!({"or": [false, {"var": "a"}]})),
+            json!({"var": "a"})
+        );
+        assert_eq!(
+            optimized(json!({"or": [0, "yes", {"var": "a"}]})),
+            json!("yes")
+        );
+
+        // The last value is the result, so it is kept even when it decides nothing
+        assert_eq!(
+            optimized(json!({"or": [{"var": "a"}, false]})),
+            json!({"or": [{"var": "a"}, false]})
+        );
+    }
+
+    #[test]
+    fn test_if() {
+        assert_eq!(
+            optimized(json!({"if": [false, 1, {"var": "a"}, 2, 3]})),
+            json!({"if": [{"var": "a"}, 2, 3]})
+        );
+        assert_eq!(
+            optimized(json!({"if": [{"var": "a"}, 1, "x", {"var": "b"}, 3]})),
+            json!({"if": [{"var": "a"}, 1, {"var": "b"}]})
+        );
+        assert_eq!(optimized(json!({"if": [0, 1]})), json!(null));
+        assert_eq!(
+            optimized(json!({"?:": [true, {"var": "a"}, 2]})),
+            json!({"var": "a"})
+        );
+    }
+
+    #[test]
+    fn test_math_functions() {
+        assert_eq!(
+            optimized(json!({"+": [{"pow": [2, 10]}, {"sqrt": 16}, {"abs": -1.5}]})),
+            json!(1029.5)
+        );
+        assert_eq!(
+            optimized(json!({"floor": {"/": [{"var": "a"}, {"ceil": 1.2}]}})),
+            json!({"floor": {"/": [{"var": "a"}, 2]}})
+        );
+
+        // Errors are left for evaluation to report
+        assert_eq!(optimized(json!({"log": 0})), json!({"log": 0}));
+    }
+
+    #[test]
+    fn test_negations() {
+        assert_eq!(
+            optimized(json!({"!!": [{"==": [{"var": "a"}, 1]}]})),
+            json!({"==": [{"var": "a"}, 1]})
+        );
+        assert_eq!(
+            optimized(json!({"!": [{"!": [{"var": "a"}]}]})),
+            json!({"!!": [{"var": "a"}]})
+        );
+        assert_eq!(
+            optimized(json!({"!": {"!!": {"var": "a"}}})),
+            json!({"!": {"var": "a"}})
+        );
+
+        // A value that is not a boolean still needs its conversion
+        assert_eq!(
+            optimized(json!({"!!": [{"var": "a"}]})),
+            json!({"!!": [{"var": "a"}]})
+        );
+    }
+
+    #[test]
+    fn test_in_literal_array() {
+        let rule = json!({"in": [{"var": "x"}, ["b", 3, "a", true, 1.5, "b", null, [1]]]});
+        assert_eq!(
+            optimized(rule.clone()),
+            json!({"in": [{"var": "x"}, [true, 1.5, 3, "a", "b"]]})
+        );
+
+        for (x, expected) in [
+            (json!("a"), true),
+            (json!(3.0), true),
+            (json!(1.5), true),
+            (json!(true), true),
+            (json!("c"), false),
+            (json!(1), false),
+            (json!(null), false),
+            (json!([1]), false),
+        ] {
+            assert_eq!(
+                evaluate(rule.clone(), json!({"x": x})),
+                json!(expected),
+                "{}",
+                x
+            );
         }
+
+        // A string haystack is still searched for a substring
+        let rule = json!({"in": [{"var": "x"}, "abc"]});
+        assert_eq!(optimized(rule.clone()), rule);
+    }
+
+    #[test]
+    fn test_string_operators() {
+        assert_eq!(
+            optimized(json!({"pad": [{"upper": {"trim": " ab "}}, 4, "-"]})),
+            json!("--AB")
+        );
+        assert_eq!(
+            optimized(json!({"join": [{"split": ["a,b", ","]}, "; "]})),
+            json!("a; b")
+        );
+        assert_eq!(
+            optimized(json!({"starts_with": [{"var": "s"}, {"lower": "AB"}]})),
+            json!({"starts_with": [{"var": "s"}, "ab"]})
+        );
+
+        // Errors are left to be raised when the rule is evaluated
+        let rule = json!({"length": null});
+        assert_eq!(optimized(rule.clone()), rule);
+    }
+
+    #[cfg(feature = "regex")]
+    #[test]
+    fn test_regex_operators() {
+        assert_eq!(
+            optimized(json!({"match": ["SKU-12", "^SKU-\\d+$"]})),
+            json!(true)
+        );
+        assert_eq!(
+            optimized(json!({"regex_replace": [{"upper": "a-b"}, "-", "_"]})),
+            json!("A_B")
+        );
+
+        // A pattern that becomes a literal is compiled
+        let dl = DataLogic::new();
+        let rule = dl
+            .parse_logic(r#"{"match": [{"var": "s"}, {"cat": ["^", "a"]}]}"#, None)
+            .unwrap();
+        let (_, args) = rule.root().as_operator().unwrap();
+        assert!(matches!(
+            args.as_array_literal().unwrap()[1],
+            crate::logic::Token::Pattern(_)
+        ));
+        assert_eq!(rule.to_json(), json!({"match": [{"var": "s"}, "^a"]}));
+
+        // An invalid one is left to be reported when the rule is evaluated
+        let rule = json!({"match": [{"var": "s"}, {"cat": ["(", "a"]}]});
+        assert_eq!(optimized(rule), json!({"match": [{"var": "s"}, "(a"]}));
     }
 }
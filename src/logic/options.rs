@@ -0,0 +1,229 @@
+//! Per-evaluation options.
+//!
+//! This module provides `EvaluateOptions`, which carries a cancellation token,
+//! a deadline and a clock for a single evaluation, the `CancellationToken`
+//! used to abort an evaluation from another thread, and the `Clock` the
+//! `now` operator reads.
+
+use chrono::{DateTime, FixedOffset, Utc};
+use std::fmt;
+use std::sync::atomic::{AtomicBool, Ordering};
+use std::sync::Arc;
+use std::time::{Duration, Instant};
+
+/// A token for cooperatively cancelling an evaluation.
+///
+/// Clones share the same state, so a clone can be handed to another thread
+/// and cancelled from there while the evaluation is running.
+#[derive(Debug, Clone, Default)]
+pub struct CancellationToken {
+    cancelled: Arc<AtomicBool>,
+}
+
+impl CancellationToken {
+    /// Creates a new token that is not cancelled.
+    pub fn new() -> Self {
+        Self::default()
+    }
+
+    /// Requests cancellation of every evaluation using this token.
+    pub fn cancel(&self) {
+        self.cancelled.store(true, Ordering::Relaxed);
+    }
+
+    /// Returns true if cancellation has been requested.
+    #[inline]
+    pub fn is_cancelled(&self) -> bool {
+        self.cancelled.load(Ordering::Relaxed)
+    }
+}
+
+/// A source of the current time for the `now` operator.
+///
+/// The clock is read at most once per evaluation, so every `now` in a rule
+/// returns the same instant.
+pub trait Clock: fmt::Debug + Send + Sync {
+    /// Returns the current time.
+    fn now(&self) -> DateTime<FixedOffset>;
+}
+
+/// The clock of the system, in UTC. This is the default clock.
+#[derive(Debug, Clone, Copy, Default)]
+pub struct SystemClock;
+
+impl Clock for SystemClock {
+    fn now(&self) -> DateTime<FixedOffset> {
+        Utc::now().fixed_offset()
+    }
+}
+
+/// A clock that is stopped at a given instant, for tests and for evaluating
+/// rules as of another time.
+#[derive(Debug, Clone, Copy)]
+pub struct FixedClock(pub DateTime<FixedOffset>);
+
+impl Clock for FixedClock {
+    fn now(&self) -> DateTime<FixedOffset> {
+        self.0
+    }
+}
+
+/// Options for a single call to `DataLogic::evaluate_with_options`.
+///
+/// # Examples
+///
+/// ```
+/// use datalogic_rs::{CancellationToken, DataLogic, EvaluateOptions, LogicError};
+/// use std::time::Duration;
+///
+/// let dl = DataLogic::new();
+/// let rule = dl.parse_logic(r#"{"map": [{"var": "items"}, {"*": [{"var": ""}, 2]}]}"#, None).unwrap();
+/// let data = dl.parse_data(r#"{"items": [1, 2, 3]}"#).unwrap();
+///
+/// let token = CancellationToken::new();
+/// let options = EvaluateOptions::new()
+///     .with_cancellation(token.clone())
+///     .with_timeout(Duration::from_secs(1));
+/// assert!(dl.evaluate_with_options(&rule, &data, &options).is_ok());
+///
+/// token.cancel();
+/// let result = dl.evaluate_with_options(&rule, &data, &options);
+/// assert_eq!(result.unwrap_err(), LogicError::Cancelled);
+/// ```
+#[derive(Debug, Clone, Default)]
+pub struct EvaluateOptions {
+    /// Token that aborts the evaluation when cancelled.
+    pub cancellation: Option<CancellationToken>,
+    /// Point in time after which the evaluation is aborted.
+    pub deadline: Option<Instant>,
+    /// Clock read by the `now` operator, instead of the system clock.
+    pub clock: Option<Arc<dyn Clock>>,
+}
+
+impl EvaluateOptions {
+    /// Creates options without cancellation, deadline or clock.
+    pub fn new() -> Self {
+        Self::default()
+    }
+
+    /// Sets the cancellation token.
+    pub fn with_cancellation(mut self, token: CancellationToken) -> Self {
+        self.cancellation = Some(token);
+        self
+    }
+
+    /// Sets the deadline.
+    pub fn with_deadline(mut self, deadline: Instant) -> Self {
+        self.deadline = Some(deadline);
+        self
+    }
+
+    /// Sets the deadline to the given duration from now.
+    pub fn with_timeout(self, timeout: Duration) -> Self {
+        self.with_deadline(Instant::now() + timeout)
+    }
+
+    /// Sets the clock read by the `now` operator.
+    pub fn with_clock(mut self, clock: impl Clock + 'static) -> Self {
+        self.clock = Some(Arc::new(clock));
+        self
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::logic::error::LogicError;
+    use crate::DataLogic;
+    use std::thread;
+
+    #[test]
+    fn test_cancellation_token() {
+        let token = CancellationToken::new();
+        let clone = token.clone();
+        assert!(!token.is_cancelled());
+
+        clone.cancel();
+        assert!(token.is_cancelled());
+    }
+
+    #[test]
+    fn test_no_options() {
+        let dl = DataLogic::new();
+        let rule = dl.parse_logic(r#"{"+": [{"var": "a"}, 1]}"#, None).unwrap();
+        let data = dl.parse_data(r#"{"a": 1}"#).unwrap();
+
+        let result = dl
+            .evaluate_with_options(&rule, &data, &EvaluateOptions::new())
+            .unwrap();
+        assert_eq!(result.as_i64(), Some(2));
+    }
+
+    #[test]
+    fn test_deadline_exceeded() {
+        let dl = DataLogic::new();
+        let rule = dl.parse_logic(r#"{"+": [{"var": "a"}, 1]}"#, None).unwrap();
+        let data = dl.parse_data(r#"{"a": 1}"#).unwrap();
+
+        let options = EvaluateOptions::new().with_deadline(Instant::now());
+        let result = dl.evaluate_with_options(&rule, &data, &options);
+        assert_eq!(result, Err(LogicError::DeadlineExceeded));
+
+        let options = EvaluateOptions::new().with_timeout(Duration::from_secs(60));
+        let result = dl.evaluate_with_options(&rule, &data, &options).unwrap();
+        assert_eq!(result.as_i64(), Some(2));
+    }
+
+    #[test]
+    fn test_iterators_check_cancellation() {
+        let dl = DataLogic::new();
+        let data = dl.parse_data(r#"{"items": [1, 2, 3]}"#).unwrap();
+        let token = CancellationToken::new();
+        token.cancel();
+        let options = EvaluateOptions::new().with_cancellation(token);
+
+        for rule in [
+            r#"{"map": [{"var": "items"}, 1]}"#,
+            r#"{"filter": [{"var": "items"}, true]}"#,
+            r#"{"all": [{"var": "items"}, true]}"#,
+            r#"{"some": [{"var": "items"}, false]}"#,
+            r#"{"none": [{"var": "items"}, false]}"#,
+            r#"{"reduce": [{"var": "items"}, {"+": [{"var": "current"}, {"var": "accumulator"}]}, 0]}"#,
+        ] {
+            let rule = dl.parse_logic(rule, None).unwrap();
+            let result = dl.evaluate_with_options(&rule, &data, &options);
+            assert_eq!(result, Err(LogicError::Cancelled));
+        }
+    }
+
+    #[test]
+    fn test_cancel_from_another_thread() {
+        let dl = DataLogic::new();
+        let row: Vec<u32> = (0..1000).collect();
+        let rows = vec![row; 500];
+        let data = dl
+            .parse_data(&serde_json::json!({ "rows": rows }).to_string())
+            .unwrap();
+        // Nested iteration that runs long enough to be cancelled
+        let rule = dl
+            .parse_logic(
+                r#"{"map": [{"var": "rows"}, {"reduce": [{"var": ""}, {"+": [{"var": "current"}, {"var": "accumulator"}, 1]}, 0]}]}"#,
+                None,
+            )
+            .unwrap();
+
+        let token = CancellationToken::new();
+        let canceller = {
+            let token = token.clone();
+            thread::spawn(move || {
+                thread::sleep(Duration::from_millis(1));
+                token.cancel();
+            })
+        };
+
+        let options = EvaluateOptions::new().with_cancellation(token);
+        let result = dl.evaluate_with_options(&rule, &data, &options);
+        canceller.join().unwrap();
+        assert_eq!(result, Err(LogicError::Cancelled));
+    }
+}
@@ -0,0 +1,413 @@
+//! Partial evaluation of rules.
+//!
+//! This module substitutes the part of the data that is already known into
+//! a rule and simplifies it, leaving a residual rule that only depends on
+//! the data that is still missing.
+
+use super::operators::array::ArrayOp;
+use super::operators::datetime::DateTimeOp;
+use super::optimizer::{evaluate_static, simplify};
+use super::token::{OperatorType, Token};
+use crate::arena::DataArena;
+use crate::value::DataValue;
+
+/// Returns the residual of a rule once the known data is substituted.
+///
+/// Variables whose path is in the known data are replaced by their values
+/// and the operators around them are simplified by the optimizer, which
+/// evaluates those whose arguments become literals and cuts `and`, `or` and
+/// `if` down to the arguments that can still make a difference.
+/// A path that is not in the known data may still be provided later, so the
+/// variables reading it are kept as they are.
+pub(crate) fn partial_evaluate<'a>(
+    token: &'a Token<'a>,
+    known: &DataValue,
+    arena: &'a DataArena,
+) -> &'a Token<'a> {
+    PartialEvaluator { known, arena }.residual(token)
+}
+
+struct PartialEvaluator<'k, 'a> {
+    known: &'k DataValue<'k>,
+    arena: &'a DataArena,
+}
+
+impl<'a> PartialEvaluator<'_, 'a> {
+    fn residual(&self, token: &'a Token<'a>) -> &'a Token<'a> {
+        match token {
+            Token::Literal(_) => token,
+            Token::Pattern(_) => token,
+            Token::ArrayLiteral(items) => match self.residual_items(items) {
+                Some(items) => self
+                    .arena
+                    .alloc(Token::ArrayLiteral(self.arena.vec_into_slice(items))),
+                None => token,
+            },
+            Token::Variable { path, default } => {
+                if let Some(value) = self.lookup_path(path) {
+                    return value;
+                }
+                match default {
+                    Some(default) => {
+                        let residual = self.residual(default);
+                        if std::ptr::eq(residual, *default) {
+                            token
+                        } else {
+                            self.arena.alloc(Token::variable(path, Some(residual)))
+                        }
+                    }
+                    None => token,
+                }
+            }
+            Token::DynamicVariable { path_expr, default } => {
+                let path_expr = self.residual(path_expr);
+                let default = default.map(|default| self.residual(default));
+                match path_expr {
+                    // The path is known now, so it is an ordinary variable
+                    Token::Literal(DataValue::String(path)) => {
+                        self.residual(self.arena.alloc(Token::variable(path, default)))
+                    }
+                    _ => self
+                        .arena
+                        .alloc(Token::dynamic_variable(path_expr, default)),
+                }
+            }
+            Token::Operator { op_type, args } => self.residual_operator(*op_type, args),
+            Token::CustomOperator { name, args } => {
+                let residual = self.residual(args);
+                if std::ptr::eq(residual, *args) {
+                    token
+                } else {
+                    self.arena.alloc(Token::custom_operator(name, residual))
+                }
+            }
+            Token::Shared { expr, .. } => {
+                let residual = self.residual(expr);
+                if std::ptr::eq(residual, *expr) {
+                    token
+                } else {
+                    residual
+                }
+            }
+        }
+    }
+
+    /// Returns the residuals of a list of tokens, or `None` if none of them
+    /// changed.
+    fn residual_items(&self, items: &[&'a Token<'a>]) -> Option<Vec<&'a Token<'a>>> {
+        let residuals: Vec<_> = items.iter().map(|item| self.residual(item)).collect();
+        let changed = residuals
+            .iter()
+            .zip(items)
+            .any(|(residual, item)| !std::ptr::eq(*residual, *item));
+        changed.then_some(residuals)
+    }
+
+    fn residual_operator(&self, op_type: OperatorType, args: &'a Token<'a>) -> &'a Token<'a> {
+        let items = match args {
+            Token::ArrayLiteral(items) => Some(*items),
+            _ => None,
+        };
+
+        match (op_type, items) {
+            (
+                OperatorType::Array(
+                    ArrayOp::Map
+                    | ArrayOp::Filter
+                    | ArrayOp::Reduce
+                    | ArrayOp::All
+                    | ArrayOp::Some
+                    | ArrayOp::None,
+                ),
+                Some([array, function, rest @ ..]),
+            ) => return self.residual_iterator(op_type, array, function, rest),
+            (OperatorType::Val, _) => {
+                if let Some(value) = val_segments(args).and_then(|path| self.lookup(&path)) {
+                    return value;
+                }
+            }
+            _ => {}
+        }
+
+        // missing, missing_some and exists check for data that may still
+        // arrive, so they are kept even if their arguments are known
+        let residual = self.residual(args);
+        simplify(op_type, residual, self.arena)
+    }
+
+    /// Substitutes into the array of an iterator and the initial value of
+    /// `reduce`, and evaluates the iterator once they are known.
+    ///
+    /// The function reads from the elements of the array rather than the
+    /// data, so it is kept as it is.
+    fn residual_iterator(
+        &self,
+        op_type: OperatorType,
+        array: &'a Token<'a>,
+        function: &'a Token<'a>,
+        rest: &[&'a Token<'a>],
+    ) -> &'a Token<'a> {
+        let mut items = Vec::with_capacity(rest.len() + 2);
+        items.push(self.residual(array));
+        items.push(function);
+        items.extend(rest.iter().map(|item| self.residual(item)));
+
+        let known = items[0].is_literal() && items[2..].iter().all(|item| item.is_literal());
+        let op_token = self.arena.alloc(Token::operator(
+            op_type,
+            self.arena
+                .alloc(Token::ArrayLiteral(self.arena.vec_into_slice(items))),
+        ));
+        if known && stays_in_scope(function) {
+            evaluate_static(op_token, self.arena)
+        } else {
+            op_token
+        }
+    }
+
+    /// Looks up a `var` path in the known data, following the rules of `var`.
+    fn lookup_path(&self, path: &str) -> Option<&'a Token<'a>> {
+        // The whole data is only known once all of it has arrived
+        if path.is_empty() {
+            return None;
+        }
+        // A path without dots that is a number only indexes arrays
+        if !path.contains('.')
+            && path.parse::<usize>().is_ok()
+            && !matches!(self.known, DataValue::Array(_))
+        {
+            return None;
+        }
+        self.lookup(&path.split('.').collect::<Vec<_>>())
+    }
+
+    /// Looks up a path in the known data and returns its value as a literal.
+    fn lookup<S: AsRef<str>>(&self, segments: &[S]) -> Option<&'a Token<'a>> {
+        let mut current = self.known;
+        for segment in segments {
+            let segment = segment.as_ref();
+            current = match current {
+                DataValue::Object(entries) => entries
+                    .iter()
+                    .find(|(key, _)| *key == segment)
+                    .map(|(_, value)| value)?,
+                DataValue::Array(items) => items.get(segment.parse::<usize>().ok()?)?,
+                _ => return None,
+            };
+        }
+
+        let value = current.to_owned_value().to_data_value(self.arena);
+        Some(self.arena.alloc(Token::literal(value)))
+    }
+}
+
+/// Returns the path read by a `val` expression if it is a literal path into
+/// the current context, without a scope jump.
+fn val_segments(args: &Token) -> Option<Vec<String>> {
+    let path = match args {
+        Token::ArrayLiteral(items) => *items.first()?,
+        token => token,
+    };
+    match path.as_literal()? {
+        // index is the position in the enclosing iterator, not a key
+        DataValue::String("index") => None,
+        DataValue::String(key) => Some(vec![key.to_string()]),
+        DataValue::Array(components) if !components.is_empty() => components
+            .iter()
+            .map(|component| match component {
+                DataValue::String(key) => Some(key.to_string()),
+                DataValue::Number(n) => n.as_i64().map(|index| index.to_string()),
+                _ => None,
+            })
+            .collect(),
+        _ => None,
+    }
+}
+
+/// Returns true if the function of an iterator only reads from the elements
+/// it is applied to, so it can be evaluated without the rest of the data.
+fn stays_in_scope(token: &Token) -> bool {
+    match token {
+        // The clock is read when the rule is evaluated
+        Token::Operator {
+            op_type: OperatorType::DateTime(DateTimeOp::Now),
+            ..
+        } => false,
+        // A scope jump reads from an enclosing context
+        Token::Operator {
+            op_type: OperatorType::Val,
+            args,
+        } => {
+            let path = match args {
+                Token::ArrayLiteral(items) => items.first().copied(),
+                token => Some(*token),
+            };
+            match path.and_then(|path| path.as_literal()) {
+                Some(DataValue::Array([DataValue::Array(_), ..])) => false,
+                Some(_) => true,
+                None => path.is_none(),
+            }
+        }
+        Token::Literal(_) => true,
+        Token::Pattern(_) => true,
+        Token::Variable { default, .. } => default.is_none_or(stays_in_scope),
+        Token::DynamicVariable { path_expr, default } => {
+            stays_in_scope(path_expr) && default.is_none_or(stays_in_scope)
+        }
+        Token::ArrayLiteral(items) => items.iter().all(|item| stays_in_scope(item)),
+        Token::Operator { args, .. } | Token::CustomOperator { args, .. } => stays_in_scope(args),
+        Token::Shared { expr, .. } => stays_in_scope(expr),
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use crate::{DataLogic, ToJson};
+    use serde_json::{Value as JsonValue, json};
+
+    fn residual(rule: JsonValue, known: JsonValue) -> JsonValue {
+        let dl = DataLogic::new();
+        let rule = dl.parse_logic(&rule.to_string(), None).unwrap();
+        let known = dl.parse_data(&known.to_string()).unwrap();
+        dl.partial_evaluate(&rule, &known).to_json()
+    }
+
+    #[test]
+    fn test_substitutes_known_variables() {
+        assert_eq!(
+            residual(
+                json!({"+": [{"var": "a"}, {"var": "b.c"}, {"var": ["d", {"var": "a"}]}]}),
+                json!({"a": 1})
+            ),
+            json!({"+": [1, {"var": "b.c"}, {"var": ["d", 1]}]})
+        );
+        assert_eq!(
+            residual(
+                json!({"*": [{"var": "a"}, {"val": ["b", "c"]}]}),
+                json!({"a": 2, "b": {"c": 3}})
+            ),
+            json!(6)
+        );
+
+        // A known null is substituted, an unknown path is kept
+        assert_eq!(
+            residual(
+                json!({"==": [{"var": "a"}, {"var": "b"}]}),
+                json!({"a": null})
+            ),
+            json!({"==": [null, {"var": "b"}]})
+        );
+        assert_eq!(
+            residual(
+                json!({"var": {"cat": ["user.", {"var": "field"}]}}),
+                json!({"field": "id", "user": {}})
+            ),
+            json!({"var": "user.id"})
+        );
+    }
+
+    #[test]
+    fn test_simplifies_and_or() {
+        let rule = json!({"and": [
+            {"==": [{"var": "country"}, "US"]},
+            {">": [{"var": "cart.total"}, 100]}
+        ]});
+        assert_eq!(
+            residual(rule.clone(), json!({"country": "US"})),
+            json!({">": [{"var": "cart.total"}, 100]})
+        );
+        assert_eq!(
+            residual(rule.clone(), json!({"country": "FR"})),
+            json!(false)
+        );
+        assert_eq!(
+            residual(rule, json!({"cart": {"total": 150}})),
+            json!({"and": [{"==": [{"var": "country"}, "US"]}, true]})
+        );
+
+        assert_eq!(
+            residual(
+                json!({"or": [{"var": "a"}, {"var": "b"}, {"var": "c"}, {"var": "d"}]}),
+                json!({"b": 0, "c": "yes"})
+            ),
+            json!({"or": [{"var": "a"}, "yes"]})
+        );
+    }
+
+    #[test]
+    fn test_simplifies_if() {
+        let rule = json!({"if": [
+            {"var": "vip"}, "gold",
+            {">": [{"var": "spent"}, 1000]}, "silver",
+            {"var": "fallback"}
+        ]});
+        assert_eq!(residual(rule.clone(), json!({"vip": true})), json!("gold"));
+        assert_eq!(
+            residual(rule.clone(), json!({"vip": false})),
+            json!({"if": [{">": [{"var": "spent"}, 1000]}, "silver", {"var": "fallback"}]})
+        );
+        assert_eq!(
+            residual(rule.clone(), json!({"spent": 5000})),
+            json!({"if": [{"var": "vip"}, "gold", "silver"]})
+        );
+        assert_eq!(
+            residual(rule, json!({"vip": 0, "spent": 10, "fallback": "none"})),
+            json!("none")
+        );
+        assert_eq!(
+            residual(json!({"if": [{"var": "a"}, 1]}), json!({"a": false})),
+            json!(null)
+        );
+    }
+
+    #[test]
+    fn test_iterators() {
+        let rule = json!({"some": [{"var": "cart"}, {">": [{"var": "qty"}, {"var": "limit"}]}]});
+        assert_eq!(
+            residual(
+                rule.clone(),
+                json!({"cart": [{"qty": 1, "limit": 5}, {"qty": 9, "limit": 5}]})
+            ),
+            json!(true)
+        );
+        assert_eq!(residual(rule.clone(), json!({"limit": 5})), rule);
+
+        // Functions that jump out of their scope wait for the rest of the data
+        let rule =
+            json!({"map": [{"var": "items"}, {"+": [{"var": ""}, {"val": [[-1], "offset"]}]}]});
+        assert_eq!(
+            residual(rule, json!({"items": [1, 2]})),
+            json!({"map": [[1, 2], {"+": [{"var": ""}, {"val": [[-1], "offset"]}]}]})
+        );
+    }
+
+    #[test]
+    fn test_residual_evaluates_like_the_rule() {
+        let dl = DataLogic::new();
+        let rule = json!({"if": [
+            {"and": [{"==": [{"var": "country"}, "US"]}, {"missing": ["cart"]}]}, "empty",
+            {">": [
+                {"reduce": [{"var": "cart"}, {"+": [{"var": "current"}, {"var": "accumulator"}]}, 0]},
+                {"var": "limit"}
+            ]}, "over",
+            "ok"
+        ]});
+        let rule = dl.parse_logic(&rule.to_string(), None).unwrap();
+        let known = json!({"country": "US", "limit": 10});
+        let residual = dl.partial_evaluate(&rule, &dl.parse_data(&known.to_string()).unwrap());
+
+        for rest in [json!({}), json!({"cart": [4, 5]}), json!({"cart": [8, 9]})] {
+            let mut data = known.clone();
+            data.as_object_mut()
+                .unwrap()
+                .extend(rest.as_object().unwrap().clone());
+
+            let data = dl.parse_data(&data.to_string()).unwrap();
+            let rest = dl.parse_data(&rest.to_string()).unwrap();
+            assert_eq!(
+                dl.evaluate(&rule, &data).unwrap(),
+                dl.evaluate(&residual, &rest).unwrap()
+            );
+        }
+    }
+}
@@ -0,0 +1,276 @@
+//! Operator policies for restricting which operators a rule may use.
+//!
+//! This module provides the `OperatorPolicy` type, which is checked against
+//! every operator in a parsed rule before the rule is accepted.
+
+use std::collections::HashSet;
+
+use super::error::{LogicError, Result};
+use super::token::Token;
+
+/// How the operator set of a policy is interpreted.
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Default)]
+enum PolicyMode {
+    /// Every operator is allowed.
+    #[default]
+    AllowAll,
+    /// Every operator is allowed except the listed ones.
+    Deny,
+    /// Only the listed operators are allowed.
+    AllowOnly,
+}
+
+/// A policy that decides which operators a rule may use.
+///
+/// Operators are identified by their canonical name, such as `"if"`, `"=="`,
+/// `"reduce"` or `"var"`, or by the name a custom operator was registered
+/// under. Aliases are checked under their canonical name, so `"?:"` is
+/// checked as `"if"`.
+///
+/// # Examples
+///
+/// ```
+/// use datalogic_rs::{DataLogic, LogicError, OperatorPolicy};
+///
+/// let mut dl = DataLogic::new();
+/// dl.set_operator_policy(OperatorPolicy::deny(["throw", "try"]));
+///
+/// let err = dl
+///     .parse_logic(r#"{"and": [true, {"throw": "boom"}]}"#, None)
+///     .unwrap_err();
+/// assert_eq!(
+///     err,
+///     LogicError::OperatorNotAllowedError {
+///         operator: "throw".to_string(),
+///         path: "/and/1".to_string(),
+///     }
+/// );
+/// ```
+#[derive(Debug, Clone, Default, PartialEq, Eq)]
+pub struct OperatorPolicy {
+    mode: PolicyMode,
+    operators: HashSet<String>,
+}
+
+impl OperatorPolicy {
+    /// Creates a policy that allows every operator.
+    pub fn allow_all() -> Self {
+        Self::default()
+    }
+
+    /// Creates a policy that allows every operator except the given ones.
+    pub fn deny<I, S>(operators: I) -> Self
+    where
+        I: IntoIterator<Item = S>,
+        S: Into<String>,
+    {
+        Self {
+            mode: PolicyMode::Deny,
+            operators: operators.into_iter().map(Into::into).collect(),
+        }
+    }
+
+    /// Creates a policy that allows only the given operators.
+    pub fn allow_only<I, S>(operators: I) -> Self
+    where
+        I: IntoIterator<Item = S>,
+        S: Into<String>,
+    {
+        Self {
+            mode: PolicyMode::AllowOnly,
+            operators: operators.into_iter().map(Into::into).collect(),
+        }
+    }
+
+    /// Returns true if the policy allows the operator with the given name.
+    pub fn is_allowed(&self, operator: &str) -> bool {
+        match self.mode {
+            PolicyMode::AllowAll => true,
+            PolicyMode::Deny => !self.operators.contains(operator),
+            PolicyMode::AllowOnly => self.operators.contains(operator),
+        }
+    }
+
+    /// Checks every operator in the token tree against the policy.
+    ///
+    /// Returns an `OperatorNotAllowedError` for the first rejected operator,
+    /// with its location in the rule as a JSON pointer.
+    pub fn check(&self, token: &Token) -> Result<()> {
+        if self.mode == PolicyMode::AllowAll {
+            return Ok(());
+        }
+        let mut path = String::new();
+        self.check_token(token, &mut path)
+    }
+
+    fn check_token(&self, token: &Token, path: &mut String) -> Result<()> {
+        match token {
+            Token::Literal(_) => Ok(()),
+            Token::Pattern(_) => Ok(()),
+            Token::ArrayLiteral(items) => self.check_items(items, path),
+            Token::Variable { default, .. } => {
+                self.check_operator("var", path)?;
+                match default {
+                    Some(default) => self.check_child(default, path, &["var", "1"]),
+                    None => Ok(()),
+                }
+            }
+            Token::DynamicVariable { path_expr, default } => {
+                self.check_operator("var", path)?;
+                self.check_child(path_expr, path, &["var", "0"])?;
+                match default {
+                    Some(default) => self.check_child(default, path, &["var", "1"]),
+                    None => Ok(()),
+                }
+            }
+            Token::Operator { op_type, args } => {
+                self.check_operator(op_type.as_str(), path)?;
+                self.check_args(op_type.as_str(), args, path)
+            }
+            Token::CustomOperator { name, args } => {
+                self.check_operator(name, path)?;
+                self.check_args(name, args, path)
+            }
+            Token::Shared { expr, .. } => self.check_token(expr, path),
+        }
+    }
+
+    fn check_operator(&self, operator: &str, path: &str) -> Result<()> {
+        if self.is_allowed(operator) {
+            Ok(())
+        } else {
+            Err(LogicError::OperatorNotAllowedError {
+                operator: operator.to_string(),
+                path: path.to_string(),
+            })
+        }
+    }
+
+    fn check_args(&self, operator: &str, args: &Token, path: &mut String) -> Result<()> {
+        let len = path.len();
+        push_segment(path, operator);
+        let result = match args {
+            Token::ArrayLiteral(items) => self.check_items(items, path),
+            _ => self.check_token(args, path),
+        };
+        path.truncate(len);
+        result
+    }
+
+    fn check_items(&self, items: &[&Token], path: &mut String) -> Result<()> {
+        for (index, item) in items.iter().enumerate() {
+            let len = path.len();
+            push_segment(path, &index.to_string());
+            let result = self.check_token(item, path);
+            path.truncate(len);
+            result?;
+        }
+        Ok(())
+    }
+
+    fn check_child(&self, token: &Token, path: &mut String, segments: &[&str]) -> Result<()> {
+        let len = path.len();
+        for segment in segments {
+            push_segment(path, segment);
+        }
+        let result = self.check_token(token, path);
+        path.truncate(len);
+        result
+    }
+}
+
+/// Appends a JSON pointer segment, escaping `~` and `/`.
+pub(crate) fn push_segment(path: &mut String, segment: &str) {
+    path.push('/');
+    for c in segment.chars() {
+        match c {
+            '~' => path.push_str("~0"),
+            '/' => path.push_str("~1"),
+            _ => path.push(c),
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::arena::DataArena;
+    use crate::parser::jsonlogic::parse_json;
+    use serde_json::json;
+
+    fn check(policy: &OperatorPolicy, rule: serde_json::Value) -> Result<()> {
+        let arena = DataArena::new();
+        let token = parse_json(&rule, &arena).unwrap();
+        policy.check(token)
+    }
+
+    fn not_allowed(operator: &str, path: &str) -> Result<()> {
+        Err(LogicError::OperatorNotAllowedError {
+            operator: operator.to_string(),
+            path: path.to_string(),
+        })
+    }
+
+    #[test]
+    fn test_allow_all() {
+        let policy = OperatorPolicy::allow_all();
+        assert!(policy.is_allowed("throw"));
+        assert_eq!(check(&policy, json!({"throw": "boom"})), Ok(()));
+    }
+
+    #[test]
+    fn test_deny() {
+        let policy = OperatorPolicy::deny(["throw", "reduce", "my_op"]);
+        assert!(!policy.is_allowed("throw"));
+        assert!(policy.is_allowed("if"));
+
+        assert_eq!(check(&policy, json!({"throw": "boom"})), not_allowed("throw", ""));
+        assert_eq!(
+            check(&policy, json!({"and": [true, {"throw": "boom"}]})),
+            not_allowed("throw", "/and/1")
+        );
+        assert_eq!(
+            check(&policy, json!({"!": {"my_op": [1]}})),
+            not_allowed("my_op", "/!")
+        );
+        assert_eq!(
+            check(
+                &policy,
+                json!({"map": [{"var": "a"}, {"reduce": [{"var": ""}, 1, 0]}]})
+            ),
+            not_allowed("reduce", "/map/1")
+        );
+        assert_eq!(
+            check(&policy, json!({"var": ["a", {"throw": "boom"}]})),
+            not_allowed("throw", "/var/1")
+        );
+        assert_eq!(check(&policy, json!({"if": [true, 1, 2]})), Ok(()));
+    }
+
+    #[test]
+    fn test_allow_only() {
+        let policy = OperatorPolicy::allow_only(["==", "var", "and"]);
+
+        assert_eq!(
+            check(&policy, json!({"and": [{"==": [{"var": "a"}, 1]}]})),
+            Ok(())
+        );
+        assert_eq!(
+            check(&policy, json!({"and": [{"==": [{"+": [1, 2]}, 3]}]})),
+            not_allowed("+", "/and/0/==/0")
+        );
+        assert_eq!(
+            check(&policy, json!({"==": [{"val": "a"}, 1]})),
+            not_allowed("val", "/==/0")
+        );
+    }
+
+    #[test]
+    fn test_pointer_escaping() {
+        let policy = OperatorPolicy::allow_only(["/", "throw"]);
+        assert_eq!(
+            check(&policy, json!({"/": [{"*": [1, 2]}, 2]})),
+            not_allowed("*", "/~1/0")
+        );
+    }
+}
@@ -0,0 +1,436 @@
+//! Data paths read by rules.
+//!
+//! This module walks a rule without evaluating it and collects the paths of
+//! the data it reads, so callers can fetch only those fields of the data.
+
+use std::collections::BTreeSet;
+
+use serde_json::{Value as JsonValue, json};
+
+use super::location::for_each_child;
+use super::operators::array::ArrayOp;
+use super::token::{OperatorType, Token};
+use crate::value::{DataValue, ToJson};
+
+/// A path of the data that a rule reads.
+///
+/// Segments are separated by dots, as in `var`, and the empty path is the
+/// whole data. A `*` segment stands for each element of an array, so the
+/// price of each item in `{"map": [{"var": "items"}, {"var": "price"}]}` is
+/// read from `items.*.price`.
+#[derive(Debug, Clone, PartialEq, Eq, PartialOrd, Ord, Hash)]
+pub enum ReferencedPath {
+    /// A path that is known without evaluating the rule.
+    Static(String),
+    /// A path that is computed while the rule is evaluated, such as the path
+    /// of `{"var": {"cat": ["user.", {"var": "field"}]}}`. It may be any path
+    /// below the given prefix, or anywhere in the data if the prefix is empty.
+    Dynamic(String),
+}
+
+impl ReferencedPath {
+    /// Returns the path, or the prefix of a dynamic path.
+    pub fn path(&self) -> &str {
+        match self {
+            ReferencedPath::Static(path) | ReferencedPath::Dynamic(path) => path,
+        }
+    }
+
+    /// Returns true if the path is only known while the rule is evaluated.
+    pub fn is_dynamic(&self) -> bool {
+        matches!(self, ReferencedPath::Dynamic(_))
+    }
+}
+
+impl ToJson for ReferencedPath {
+    fn to_json(&self) -> JsonValue {
+        json!({
+            "path": self.path(),
+            "dynamic": self.is_dynamic(),
+        })
+    }
+}
+
+/// Collects the data paths read by a rule.
+///
+/// The paths are sorted and free of duplicates, with the static paths first.
+pub(crate) fn referenced_paths(root: &Token) -> Vec<ReferencedPath> {
+    let mut collector = Collector {
+        scopes: vec![Scope::Data(Some(String::new()))],
+        paths: BTreeSet::new(),
+    };
+    collector.visit(root);
+    collector.paths.into_iter().collect()
+}
+
+/// The context that relative references are resolved against.
+///
+/// The path is `None` if the context is computed, such as the items of a
+/// `map` over the result of `merge`. References into a computed context are
+/// left out, as the values they read are already covered by the paths of
+/// the expression that computed it.
+enum Scope {
+    /// The root of the data, or an element of an array iterated over.
+    Data(Option<String>),
+    /// The `current` and `accumulator` object of `reduce`, with the path of
+    /// the elements of the array.
+    Reduce(Option<String>),
+}
+
+/// What a `val` expression reads.
+enum ValPath {
+    /// The path below the context `jump` scopes up from the current one.
+    Static { jump: usize, segments: Vec<String> },
+    /// A path that is computed during evaluation.
+    Dynamic,
+}
+
+struct Collector {
+    /// The enclosing scopes, innermost last.
+    scopes: Vec<Scope>,
+    paths: BTreeSet<ReferencedPath>,
+}
+
+impl Collector {
+    fn visit(&mut self, token: &Token) {
+        match token {
+            Token::Variable { path, .. } => self.add(0, &split_path(path)),
+            Token::DynamicVariable { .. } => self.add_dynamic(),
+            Token::Operator { op_type, args } => match op_type {
+                OperatorType::Array(
+                    op @ (ArrayOp::Map
+                    | ArrayOp::Filter
+                    | ArrayOp::Reduce
+                    | ArrayOp::All
+                    | ArrayOp::Some
+                    | ArrayOp::None),
+                ) => {
+                    if let Token::ArrayLiteral(items) = args {
+                        self.visit_iterator(*op, items);
+                        return;
+                    }
+                }
+                OperatorType::Val => match val_path(args) {
+                    Some(ValPath::Static { jump, segments }) => self.add(jump, &segments),
+                    Some(ValPath::Dynamic) => self.add_dynamic(),
+                    None => {}
+                },
+                OperatorType::Exists => self.add_exists(args),
+                OperatorType::Missing => {
+                    for arg in arguments(args) {
+                        self.add_names(arg);
+                    }
+                }
+                OperatorType::MissingSome => {
+                    if let Some(names) = arguments(args).get(1) {
+                        self.add_names(names);
+                    }
+                }
+                _ => {}
+            },
+            _ => {}
+        }
+
+        for_each_child(token, &mut |_, child| {
+            self.visit(child);
+            false
+        });
+    }
+
+    /// Visits an iterator, whose function reads relative to each element.
+    fn visit_iterator(&mut self, op: ArrayOp, items: &[&Token]) {
+        let Some((array, rest)) = items.split_first() else {
+            return;
+        };
+        self.visit(array);
+
+        let elements = self.elements(array);
+        let scope = match op {
+            ArrayOp::Reduce => Scope::Reduce(elements),
+            _ => Scope::Data(elements),
+        };
+
+        if let Some((function, rest)) = rest.split_first() {
+            self.scopes.push(scope);
+            self.visit(function);
+            self.scopes.pop();
+
+            // The initial value of reduce is evaluated in the enclosing scope
+            for arg in rest {
+                self.visit(arg);
+            }
+        }
+    }
+
+    /// Returns the path of the elements of the array an expression evaluates
+    /// to, if it is known.
+    fn elements(&self, token: &Token) -> Option<String> {
+        let path = match token.unshared() {
+            Token::Variable { path, .. } => self.resolve(0, &split_path(path))?,
+            Token::Operator {
+                op_type: OperatorType::Val,
+                args,
+            } => match val_path(args)? {
+                ValPath::Static { jump, segments } => self.resolve(jump, &segments)?,
+                ValPath::Dynamic => return None,
+            },
+            // filter returns some of the elements of its array
+            Token::Operator {
+                op_type: OperatorType::Array(ArrayOp::Filter),
+                args: Token::ArrayLiteral(items),
+            } => return self.elements(items.first()?),
+            _ => return None,
+        };
+        Some(join(path, "*"))
+    }
+
+    /// Resolves a path relative to the context `jump` scopes up.
+    ///
+    /// Returns `None` if the path does not read from the data, or if the
+    /// context is computed.
+    fn resolve<S: AsRef<str>>(&self, jump: usize, segments: &[S]) -> Option<String> {
+        let index = self.scopes.len().checked_sub(jump + 1)?;
+        let (mut path, segments) = match &self.scopes[index] {
+            Scope::Data(path) => (path.clone()?, segments),
+            Scope::Reduce(elements) => match segments.split_first() {
+                None => (elements.clone()?, segments),
+                Some((first, rest)) if first.as_ref() == "current" => (elements.clone()?, rest),
+                // The accumulator is computed from values read elsewhere
+                Some(_) => return None,
+            },
+        };
+        for segment in segments {
+            path = join(path, segment.as_ref());
+        }
+        Some(path)
+    }
+
+    fn add<S: AsRef<str>>(&mut self, jump: usize, segments: &[S]) {
+        if let Some(path) = self.resolve(jump, segments) {
+            self.paths.insert(ReferencedPath::Static(path));
+        }
+    }
+
+    /// Adds a path computed during evaluation relative to the current context.
+    fn add_dynamic(&mut self) {
+        if let Some(prefix) = self.resolve::<&str>(0, &[]) {
+            self.paths.insert(ReferencedPath::Dynamic(prefix));
+        }
+    }
+
+    /// Adds the path checked by `exists`, whose arguments are the keys of a
+    /// nested path.
+    fn add_exists(&mut self, args: &Token) {
+        let values: Option<Vec<&DataValue>> = match args {
+            Token::ArrayLiteral(items) => items.iter().map(|item| item.as_literal()).collect(),
+            Token::Literal(DataValue::Array(items)) => Some(items.iter().collect()),
+            Token::Literal(value) => Some(vec![value]),
+            _ => None,
+        };
+        let Some(values) = values else {
+            self.add_dynamic();
+            return;
+        };
+
+        let keys = match values.as_slice() {
+            [DataValue::Array(items)] => items.iter().collect(),
+            _ => values,
+        };
+        let segments: Option<Vec<&str>> = keys.iter().map(|key| key.as_str()).collect();
+        if let Some(segments) = segments {
+            self.add(0, &segments);
+        }
+    }
+
+    /// Adds the paths named by an argument of `missing` or `missing_some`.
+    fn add_names(&mut self, token: &Token) {
+        match token {
+            Token::Literal(DataValue::String(name)) => self.add(0, &split_path(name)),
+            Token::Literal(DataValue::Array(names)) => {
+                for name in names.iter().filter_map(DataValue::as_str) {
+                    self.add(0, &split_path(name));
+                }
+            }
+            Token::Literal(_) => {}
+            _ => self.add_dynamic(),
+        }
+    }
+}
+
+/// Returns the arguments of an operator, in the way lazy operators see them.
+fn arguments<'t>(args: &'t Token<'t>) -> Vec<&'t Token<'t>> {
+    match args {
+        Token::ArrayLiteral(items) => items.to_vec(),
+        token => vec![token],
+    }
+}
+
+/// Returns what the `val` expression with the given arguments reads.
+///
+/// Returns `None` if it does not read from the data, as for `index`.
+fn val_path(args: &Token) -> Option<ValPath> {
+    let path = match args {
+        Token::ArrayLiteral(items) => *items.first()?,
+        token => token,
+    };
+    let Token::Literal(value) = path else {
+        return Some(ValPath::Dynamic);
+    };
+
+    let (jump, components) = match value {
+        DataValue::String("index") => return None,
+        DataValue::String(key) => return Some(static_path(0, vec![key.to_string()])),
+        DataValue::Number(n) => {
+            let index = n.as_i64().filter(|index| *index >= 0)?;
+            return Some(static_path(0, vec![index.to_string()]));
+        }
+        DataValue::Array(components) => match components.split_first() {
+            Some((DataValue::Array([jump]), rest)) => {
+                (jump.as_i64().unwrap_or(0).unsigned_abs() as usize, rest)
+            }
+            _ => (0, *components),
+        },
+        _ => return None,
+    };
+
+    if jump > 0 && matches!(components, [DataValue::String("index")]) {
+        return None;
+    }
+
+    let mut segments = Vec::with_capacity(components.len());
+    for component in components {
+        match component {
+            DataValue::String(key) => segments.push(key.to_string()),
+            DataValue::Number(n) => segments.push(n.as_i64()?.to_string()),
+            _ => return Some(ValPath::Dynamic),
+        }
+    }
+    Some(static_path(jump, segments))
+}
+
+fn static_path(jump: usize, segments: Vec<String>) -> ValPath {
+    ValPath::Static { jump, segments }
+}
+
+/// Splits a `var` path into its segments.
+fn split_path(path: &str) -> Vec<&str> {
+    if path.is_empty() {
+        Vec::new()
+    } else {
+        path.split('.').collect()
+    }
+}
+
+fn join(mut path: String, segment: &str) -> String {
+    if !path.is_empty() {
+        path.push('.');
+    }
+    path.push_str(segment);
+    path
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::DataLogic;
+    use serde_json::json;
+
+    fn paths(rule: JsonValue) -> Vec<String> {
+        let dl = DataLogic::new();
+        let logic = dl.parse_logic(&rule.to_string(), None).unwrap();
+        logic
+            .referenced_paths()
+            .iter()
+            .map(|path| match path {
+                ReferencedPath::Static(path) => path.clone(),
+                ReferencedPath::Dynamic(prefix) => format!("{}?", prefix),
+            })
+            .collect()
+    }
+
+    #[test]
+    fn test_variables() {
+        assert_eq!(
+            paths(json!({"and": [
+                {"==": [{"var": "user.age"}, 18]},
+                {"var": ["user.name", {"var": "fallback"}]},
+                {"==": [{"var": "user.age"}, {"var": ""}]}
+            ]})),
+            vec!["", "fallback", "user.age", "user.name"]
+        );
+        assert_eq!(
+            paths(json!({"+": [{"val": "a.b"}, {"val": ["c", "d", 0]}, {"val": "index"}]})),
+            vec!["a.b", "c.d.0"]
+        );
+        assert!(paths(json!({"==": [1, 1]})).is_empty());
+    }
+
+    #[test]
+    fn test_existence_checks() {
+        assert_eq!(
+            paths(json!({"if": [
+                {"missing": ["a", "b.c"]},
+                {"missing_some": [1, ["d", "e"]]},
+                {"exists": ["f", "g"]}
+            ]})),
+            vec!["a", "b.c", "d", "e", "f.g"]
+        );
+        assert_eq!(paths(json!({"exists": "h"})), vec!["h"]);
+    }
+
+    #[test]
+    fn test_iterator_scopes() {
+        assert_eq!(
+            paths(json!({"map": [
+                {"filter": [{"var": "orders"}, {">": [{"var": "total"}, {"val": [[-1], "min"]}]}]},
+                {"*": [{"var": "total"}, {"var": "rate"}]}
+            ]})),
+            vec!["min", "orders", "orders.*.rate", "orders.*.total"]
+        );
+        assert_eq!(
+            paths(json!({"reduce": [
+                {"var": "items"},
+                {"+": [{"var": "accumulator"}, {"var": "current.price"}]},
+                {"var": "base"}
+            ]})),
+            vec!["base", "items", "items.*.price"]
+        );
+        assert_eq!(
+            paths(
+                json!({"all": [{"var": "groups"}, {"some": [{"var": "members"}, {"var": "active"}]}]})
+            ),
+            vec!["groups", "groups.*.members", "groups.*.members.*.active"]
+        );
+
+        // Elements of computed arrays are covered by the paths they come from
+        assert_eq!(
+            paths(json!({"map": [{"merge": [{"var": "a"}, {"var": "b"}]}, {"var": "x"}]})),
+            vec!["a", "b"]
+        );
+    }
+
+    #[test]
+    fn test_dynamic_paths() {
+        assert_eq!(
+            paths(json!({"var": {"cat": ["user.", {"var": "field"}]}})),
+            vec!["field", "?"]
+        );
+        assert_eq!(
+            paths(json!({"map": [{"var": "rows"}, {"val": {"var": "column"}}]})),
+            vec!["rows", "rows.*.column", "rows.*?"]
+        );
+        assert_eq!(
+            paths(json!({"missing": {"merge": [["a"], {"var": "b"}]}})),
+            vec!["b", "?"]
+        );
+
+        let dl = DataLogic::new();
+        let logic = dl.parse_logic(r#"{"var": {"var": "key"}}"#, None).unwrap();
+        let referenced = logic.referenced_paths();
+        assert!(referenced[1].is_dynamic());
+        assert_eq!(
+            referenced[1].to_json(),
+            json!({"path": "", "dynamic": true})
+        );
+    }
+}
@@ -3,21 +3,30 @@
 //! This module provides a compact token representation for logic expressions,
 //! optimized for memory efficiency and evaluation performance.
 
-use super::operators::{ArithmeticOp, ArrayOp, ComparisonOp, ControlOp, StringOp};
-use crate::value::DataValue;
+use super::operators::{
+    ArithmeticOp, Arity, ArrayOp, ComparisonOp, ControlOp, DateTimeOp, StringOp, missing, throw,
+    r#try, val,
+};
+use super::operators::{RegexOp, regex::Pattern};
+use crate::value::{DataValue, ToJson};
+use serde_json::{Value as JsonValue, json};
 use std::str::FromStr;
 
 /// A token in a logic expression.
 ///
 /// This is a compact representation of a logic expression node, optimized
 /// for memory efficiency and evaluation performance.
+///
+/// Variants may be added in future releases, so matches outside this
+/// crate need a wildcard arm.
 #[derive(Debug, Clone, PartialEq)]
+#[non_exhaustive]
 pub enum Token<'a> {
     /// A literal value.
     Literal(DataValue<'a>),
 
     /// An array literal.
-    ArrayLiteral(Vec<&'a Token<'a>>),
+    ArrayLiteral(&'a [&'a Token<'a>]),
 
     /// A variable reference.
     Variable {
@@ -50,10 +59,32 @@ pub enum Token<'a> {
         /// The arguments to the operator.
         args: &'a Token<'a>,
     },
+
+    /// An expression that occurs more than once in the rule.
+    ///
+    /// The optimizer puts a shared token in place of each occurrence, all with
+    /// the same slot and expression. The result is memoized in the arena, so
+    /// the expression is evaluated once per evaluation and current context.
+    Shared {
+        /// The index of the memoized result in the arena.
+        slot: usize,
+        /// The shared expression.
+        expr: &'a Token<'a>,
+    },
+
+    /// A regular expression pattern, compiled from a literal string when
+    /// the rule was parsed or optimized.
+    ///
+    /// It evaluates to its source string.
+    Pattern(&'a Pattern),
 }
 
 /// The type of operator.
+///
+/// Variants may be added in future releases, so matches outside this
+/// crate need a wildcard arm.
 #[derive(Debug, Clone, Copy, PartialEq, Eq)]
+#[non_exhaustive]
 pub enum OperatorType {
     /// Comparison operator
     Comparison(ComparisonOp),
@@ -65,6 +96,12 @@ pub enum OperatorType {
     String(StringOp),
     /// Array operator
     Array(ArrayOp),
+    /// Datetime operator
+    DateTime(DateTimeOp),
+    /// Regular expression operator
+    ///
+    /// Evaluating it requires the `regex` feature.
+    Regex(RegexOp),
     /// Missing operator
     Missing,
     /// Missing Some operator
@@ -166,8 +203,16 @@ impl<'a> Token<'a> {
         }
     }
 
+    /// Returns the expression of a shared token, or the token itself.
+    pub(crate) fn unshared(&self) -> &Token<'a> {
+        match self {
+            Token::Shared { expr, .. } => expr,
+            token => token,
+        }
+    }
+
     /// Returns the array tokens if this token is an array literal.
-    pub fn as_array_literal(&self) -> Option<&Vec<&'a Token<'a>>> {
+    pub fn as_array_literal(&self) -> Option<&'a [&'a Token<'a>]> {
         match self {
             Token::ArrayLiteral(tokens) => Some(tokens),
             _ => None,
@@ -175,6 +220,109 @@ impl<'a> Token<'a> {
     }
 }
 
+/// Converts a token tree back into JSONLogic.
+///
+/// The output parses back into an equivalent token tree. Literals that
+/// contain objects are wrapped in `preserve`, so they are not mistaken for
+/// operators.
+impl ToJson for Token<'_> {
+    fn to_json(&self) -> JsonValue {
+        match self {
+            Token::Literal(value) => literal_to_json(value),
+            Token::ArrayLiteral(items) => {
+                JsonValue::Array(items.iter().map(|item| item.to_json()).collect())
+            }
+            Token::Variable { path, default } => match default {
+                Some(default) => json!({"var": [path, default.to_json()]}),
+                None => json!({"var": path}),
+            },
+            Token::DynamicVariable { path_expr, default } => {
+                let path_json = path_expr.to_json();
+                match default {
+                    Some(default) => json!({"var": [path_json, default.to_json()]}),
+                    // An object is parsed as a dynamic path as is
+                    None if path_json.is_object() => json!({"var": path_json}),
+                    None => json!({"var": [path_json]}),
+                }
+            }
+            Token::Operator {
+                op_type: OperatorType::ArrayLiteral,
+                args,
+            } => match args {
+                Token::ArrayLiteral(_) => args.to_json(),
+                _ => JsonValue::Array(vec![args.to_json()]),
+            },
+            Token::Operator {
+                op_type: OperatorType::Val,
+                args,
+            } => {
+                // The arguments of val are parsed as a single value
+                json!({"val": args.to_json()})
+            }
+            Token::Operator { op_type, args } => operator_to_json(op_type.as_str(), args),
+            Token::CustomOperator { name, args } => operator_to_json(name, args),
+            Token::Shared { expr, .. } => expr.to_json(),
+            Token::Pattern(pattern) => json!(pattern.as_str()),
+        }
+    }
+}
+
+/// Converts an operator application to JSON.
+fn operator_to_json(name: &str, args: &Token) -> JsonValue {
+    let args_json = match args {
+        Token::ArrayLiteral(items) => {
+            JsonValue::Array(items.iter().map(|item| item.to_json()).collect())
+        }
+        // A single literal array argument would otherwise be read as an argument list
+        Token::Literal(value @ DataValue::Array(_)) => json!({"preserve": value.to_json()}),
+        _ => args.to_json(),
+    };
+
+    let mut map = serde_json::Map::with_capacity(1);
+    map.insert(name.to_string(), args_json);
+    JsonValue::Object(map)
+}
+
+/// Converts a literal to JSON, wrapping it in `preserve` if it contains an object.
+fn literal_to_json(value: &DataValue) -> JsonValue {
+    if contains_object(value) {
+        json!({"preserve": value.to_json()})
+    } else {
+        temporal_to_json(value)
+    }
+}
+
+/// Converts a literal to JSON, writing datetimes and durations as the
+/// operators that parse them back.
+fn temporal_to_json(value: &DataValue) -> JsonValue {
+    match value {
+        DataValue::DateTime(_) => json!({"datetime": value.to_json()}),
+        DataValue::Duration(_) => json!({"duration": value.to_json()}),
+        DataValue::Array(items) if items.iter().any(is_temporal) => {
+            JsonValue::Array(items.iter().map(temporal_to_json).collect())
+        }
+        _ => value.to_json(),
+    }
+}
+
+/// Returns true if the value is or contains a datetime or a duration.
+fn is_temporal(value: &DataValue) -> bool {
+    match value {
+        DataValue::DateTime(_) | DataValue::Duration(_) => true,
+        DataValue::Array(items) => items.iter().any(is_temporal),
+        _ => false,
+    }
+}
+
+/// Returns true if the value is or contains an object.
+fn contains_object(value: &DataValue) -> bool {
+    match value {
+        DataValue::Object(_) => true,
+        DataValue::Array(items) => items.iter().any(contains_object),
+        _ => false,
+    }
+}
+
 impl OperatorType {
     /// Returns the string representation of this operator type.
     pub fn as_str(&self) -> &'static str {
@@ -197,6 +345,13 @@ impl OperatorType {
                 ArithmeticOp::Modulo => "%",
                 ArithmeticOp::Min => "min",
                 ArithmeticOp::Max => "max",
+                ArithmeticOp::Round => "round",
+                ArithmeticOp::Abs => "abs",
+                ArithmeticOp::Floor => "floor",
+                ArithmeticOp::Ceil => "ceil",
+                ArithmeticOp::Pow => "pow",
+                ArithmeticOp::Sqrt => "sqrt",
+                ArithmeticOp::Log => "log",
             },
             OperatorType::Control(op) => match op {
                 ControlOp::If => "if",
@@ -208,6 +363,17 @@ impl OperatorType {
             OperatorType::String(op) => match op {
                 StringOp::Cat => "cat",
                 StringOp::Substr => "substr",
+                StringOp::Upper => "upper",
+                StringOp::Lower => "lower",
+                StringOp::Trim => "trim",
+                StringOp::StartsWith => "starts_with",
+                StringOp::EndsWith => "ends_with",
+                StringOp::Contains => "contains",
+                StringOp::Split => "split",
+                StringOp::Join => "join",
+                StringOp::Replace => "replace",
+                StringOp::Length => "length",
+                StringOp::Pad => "pad",
             },
             OperatorType::Array(op) => match op {
                 ArrayOp::Map => "map",
@@ -217,7 +383,21 @@ impl OperatorType {
                 ArrayOp::Some => "some",
                 ArrayOp::None => "none",
                 ArrayOp::Merge => "merge",
-                ArrayOp::In => "in",
+                ArrayOp::In | ArrayOp::InSet => "in",
+            },
+            OperatorType::DateTime(op) => match op {
+                DateTimeOp::Now => "now",
+                DateTimeOp::DateTime => "datetime",
+                DateTimeOp::Duration => "duration",
+                DateTimeOp::Diff => "date_diff",
+                DateTimeOp::Add => "date_add",
+                DateTimeOp::Format => "format_date",
+                DateTimeOp::Part => "date_part",
+            },
+            OperatorType::Regex(op) => match op {
+                RegexOp::Match => "match",
+                RegexOp::Extract => "regex_extract",
+                RegexOp::Replace => "regex_replace",
             },
             OperatorType::Missing => "missing",
             OperatorType::MissingSome => "missing_some",
@@ -229,6 +409,26 @@ impl OperatorType {
             OperatorType::ArrayLiteral => "array",
         }
     }
+
+    /// Returns the number of arguments the operator accepts.
+    pub(crate) const fn arity(&self) -> Arity {
+        match self {
+            OperatorType::Comparison(op) => op.arity(),
+            OperatorType::Arithmetic(op) => op.arity(),
+            OperatorType::Control(op) => op.arity(),
+            OperatorType::String(op) => op.arity(),
+            OperatorType::Array(op) => op.arity(),
+            OperatorType::DateTime(op) => op.arity(),
+            OperatorType::Regex(op) => op.arity(),
+            OperatorType::Missing => missing::MISSING_ARITY,
+            OperatorType::MissingSome => missing::MISSING_SOME_ARITY,
+            OperatorType::Exists => val::EXISTS_ARITY,
+            OperatorType::Coalesce | OperatorType::ArrayLiteral => Arity::ANY,
+            OperatorType::Val => val::VAL_ARITY,
+            OperatorType::Throw => throw::THROW_ARITY,
+            OperatorType::Try => r#try::TRY_ARITY,
+        }
+    }
 }
 
 impl FromStr for OperatorType {
@@ -251,6 +451,13 @@ impl FromStr for OperatorType {
             "%" => Ok(OperatorType::Arithmetic(ArithmeticOp::Modulo)),
             "min" => Ok(OperatorType::Arithmetic(ArithmeticOp::Min)),
             "max" => Ok(OperatorType::Arithmetic(ArithmeticOp::Max)),
+            "round" => Ok(OperatorType::Arithmetic(ArithmeticOp::Round)),
+            "abs" => Ok(OperatorType::Arithmetic(ArithmeticOp::Abs)),
+            "floor" => Ok(OperatorType::Arithmetic(ArithmeticOp::Floor)),
+            "ceil" => Ok(OperatorType::Arithmetic(ArithmeticOp::Ceil)),
+            "pow" => Ok(OperatorType::Arithmetic(ArithmeticOp::Pow)),
+            "sqrt" => Ok(OperatorType::Arithmetic(ArithmeticOp::Sqrt)),
+            "log" => Ok(OperatorType::Arithmetic(ArithmeticOp::Log)),
             "and" => Ok(OperatorType::Control(ControlOp::And)),
             "or" => Ok(OperatorType::Control(ControlOp::Or)),
             "!" => Ok(OperatorType::Control(ControlOp::Not)),
@@ -259,6 +466,17 @@ impl FromStr for OperatorType {
             "?:" => Ok(OperatorType::Control(ControlOp::If)),
             "cat" => Ok(OperatorType::String(StringOp::Cat)),
             "substr" => Ok(OperatorType::String(StringOp::Substr)),
+            "upper" => Ok(OperatorType::String(StringOp::Upper)),
+            "lower" => Ok(OperatorType::String(StringOp::Lower)),
+            "trim" => Ok(OperatorType::String(StringOp::Trim)),
+            "starts_with" => Ok(OperatorType::String(StringOp::StartsWith)),
+            "ends_with" => Ok(OperatorType::String(StringOp::EndsWith)),
+            "contains" => Ok(OperatorType::String(StringOp::Contains)),
+            "split" => Ok(OperatorType::String(StringOp::Split)),
+            "join" => Ok(OperatorType::String(StringOp::Join)),
+            "replace" => Ok(OperatorType::String(StringOp::Replace)),
+            "length" => Ok(OperatorType::String(StringOp::Length)),
+            "pad" => Ok(OperatorType::String(StringOp::Pad)),
             "map" => Ok(OperatorType::Array(ArrayOp::Map)),
             "filter" => Ok(OperatorType::Array(ArrayOp::Filter)),
             "reduce" => Ok(OperatorType::Array(ArrayOp::Reduce)),
@@ -267,6 +485,19 @@ impl FromStr for OperatorType {
             "none" => Ok(OperatorType::Array(ArrayOp::None)),
             "merge" => Ok(OperatorType::Array(ArrayOp::Merge)),
             "in" => Ok(OperatorType::Array(ArrayOp::In)),
+            "now" => Ok(OperatorType::DateTime(DateTimeOp::Now)),
+            "datetime" => Ok(OperatorType::DateTime(DateTimeOp::DateTime)),
+            "duration" => Ok(OperatorType::DateTime(DateTimeOp::Duration)),
+            "date_diff" => Ok(OperatorType::DateTime(DateTimeOp::Diff)),
+            "date_add" => Ok(OperatorType::DateTime(DateTimeOp::Add)),
+            "format_date" => Ok(OperatorType::DateTime(DateTimeOp::Format)),
+            "date_part" => Ok(OperatorType::DateTime(DateTimeOp::Part)),
+            #[cfg(feature = "regex")]
+            "match" => Ok(OperatorType::Regex(RegexOp::Match)),
+            #[cfg(feature = "regex")]
+            "regex_extract" => Ok(OperatorType::Regex(RegexOp::Extract)),
+            #[cfg(feature = "regex")]
+            "regex_replace" => Ok(OperatorType::Regex(RegexOp::Replace)),
             "missing" => Ok(OperatorType::Missing),
             "missing_some" => Ok(OperatorType::MissingSome),
             "exists" => Ok(OperatorType::Exists),
@@ -282,6 +513,8 @@ impl FromStr for OperatorType {
 #[cfg(test)]
 mod tests {
     use super::*;
+    use crate::arena::DataArena;
+    use crate::parser::jsonlogic::parse_json;
 
     #[test]
     fn test_operator_type_conversion() {
@@ -305,4 +538,74 @@ mod tests {
 
         assert_eq!(OperatorType::from_str("unknown"), Err("unknown operator"));
     }
+
+    #[test]
+    fn test_to_json_round_trip() {
+        let rules = [
+            json!(null),
+            json!(42),
+            json!(3.5),
+            json!("hello"),
+            json!([1, "a", [true, null]]),
+            json!({"var": "a.b"}),
+            json!({"var": ""}),
+            json!({"var": ["a", 0]}),
+            json!({"var": ["a", {"var": "b"}]}),
+            json!({"var": {"cat": ["a", "b"]}}),
+            json!({"var": [{"cat": ["a", "b"]}, "default"]}),
+            json!({"val": "a"}),
+            json!({"val": [[-1], "index"]}),
+            json!({"val": [{"var": "key"}]}),
+            json!({"exists": ["a", "b"]}),
+            json!({"==": [{"var": "a"}, 10]}),
+            json!({"!": {"var": "a"}}),
+            json!({"!": [[]]}),
+            json!({"if": [{"<": [{"var": "t"}, 0]}, "cold", {"<": [{"var": "t"}, 100]}, "mild", "hot"]}),
+            json!({"?:": [true, 1, 2]}),
+            json!({"and": [true, {"or": [false, {"var": "x"}]}]}),
+            json!({"+": [1, {"*": [2, 3]}, {"-": [4]}, {"/": [8, 2]}, {"%": [5, 2]}]}),
+            json!({"min": [1, {"max": [2, 3]}]}),
+            json!({"cat": ["a", {"substr": ["hello", 1, 2]}]}),
+            json!({"map": [{"var": "items"}, {"*": [{"var": ""}, 2]}]}),
+            json!({"filter": [{"var": "items"}, {">": [{"var": ""}, 2]}]}),
+            json!({"reduce": [{"var": "items"}, {"+": [{"var": "current"}, {"var": "accumulator"}]}, 0]}),
+            json!({"all": [[1, 2], {">": [{"var": ""}, 0]}]}),
+            json!({"some": [{"var": "a"}, true]}),
+            json!({"none": [{"var": "a"}, false]}),
+            json!({"merge": [[1, 2], {"var": "a"}, [[3]]]}),
+            json!({"in": ["a", ["a", "b"]]}),
+            json!({"missing": ["a", "b"]}),
+            json!({"missing_some": [1, ["a", "b"]]}),
+            json!({"??": [null, {"var": "a"}]}),
+            json!({"try": [{"throw": "Oops"}, {"var": "type"}]}),
+            json!({"my_op": [1, {"var": "a"}]}),
+            json!({"my_op": {"var": "a"}}),
+            json!({"preserve": {"a": 1}}),
+            json!({"preserve": [{"a": 1}, 2]}),
+            json!({}),
+            json!([{"var": "a"}, [1, {"var": "b"}]]),
+        ];
+
+        for rule in rules {
+            let arena = DataArena::new();
+            let token = parse_json(&rule, &arena).unwrap();
+            let serialized = token.to_json();
+            let reparsed = parse_json(&serialized, &arena).unwrap();
+            assert_eq!(reparsed, token, "round trip of {} via {}", rule, serialized);
+            assert_eq!(reparsed.to_json(), serialized);
+        }
+    }
+
+    #[test]
+    fn test_to_json_canonical_names() {
+        let arena = DataArena::new();
+        let token = parse_json(&json!({"?:": [true, 1, 2]}), &arena).unwrap();
+        assert_eq!(token.to_json(), json!({"if": [true, 1, 2]}));
+
+        let token = parse_json(&json!({"var": ["a", "b", "c"]}), &arena).unwrap();
+        assert_eq!(token.to_json(), json!({"var": "a.b.c"}));
+
+        let token = parse_json(&json!({"preserve": {"==": [1, 1]}}), &arena).unwrap();
+        assert_eq!(token.to_json(), json!({"preserve": {"==": [1, 1]}}));
+    }
 }
@@ -0,0 +1,314 @@
+//! Evaluation traces for explaining results.
+//!
+//! This module provides the `TraceNode` type, which records how a rule was
+//! evaluated, and the recorder that builds the trace during evaluation.
+
+use serde_json::{Value as JsonValue, json};
+
+use super::error::{LogicError, Result};
+use super::operators::control::ControlOp;
+use super::token::{OperatorType, Token};
+use crate::value::{DataValue, OwnedDataValue, ToJson};
+
+/// A node in an evaluation trace.
+///
+/// Every token that was evaluated gets a node, including literals. The
+/// children are the nodes of the tokens evaluated while evaluating this one,
+/// in evaluation order, so an iterator has one child per evaluation of its
+/// body.
+#[derive(Debug, Clone, PartialEq)]
+pub struct TraceNode {
+    /// The operator name, `"var"` for variables, `"literal"` for literals and
+    /// `"array"` for arrays of expressions.
+    pub operator: String,
+    /// The evaluated expression as JSONLogic.
+    pub expression: JsonValue,
+    /// The resolved value of each argument, or `None` if the argument was
+    /// not evaluated.
+    ///
+    /// For an argument that was evaluated several times, such as the body
+    /// of `map`, this is the value of the first evaluation.
+    pub arguments: Vec<Option<OwnedDataValue>>,
+    /// The result of the evaluation.
+    pub result: std::result::Result<OwnedDataValue, LogicError>,
+    /// For `and` and `or`, the index of the argument that short-circuited the
+    /// evaluation. For `if`, the index of the branch whose value was returned.
+    pub branch: Option<usize>,
+    /// The nodes of the tokens evaluated while evaluating this one.
+    pub children: Vec<TraceNode>,
+}
+
+impl ToJson for TraceNode {
+    fn to_json(&self) -> JsonValue {
+        let mut node = json!({
+            "operator": self.operator,
+            "expression": self.expression,
+        });
+        let map = node.as_object_mut().unwrap();
+
+        if !self.arguments.is_empty() {
+            let arguments = self
+                .arguments
+                .iter()
+                .map(|arg| arg.as_ref().map_or(JsonValue::Null, ToJson::to_json))
+                .collect();
+            map.insert("arguments".to_string(), JsonValue::Array(arguments));
+        }
+        match &self.result {
+            Ok(value) => map.insert("result".to_string(), value.to_json()),
+            Err(error) => map.insert("error".to_string(), json!(error.to_string())),
+        };
+        if let Some(branch) = self.branch {
+            map.insert("branch".to_string(), json!(branch));
+        }
+        if !self.children.is_empty() {
+            let children = self.children.iter().map(ToJson::to_json).collect();
+            map.insert("children".to_string(), JsonValue::Array(children));
+        }
+        node
+    }
+}
+
+/// A token whose evaluation is in progress.
+struct Frame {
+    /// Address of the token, used to match children to arguments.
+    token: usize,
+    /// Nodes of the tokens evaluated so far, with their addresses.
+    children: Vec<(usize, TraceNode)>,
+}
+
+/// Records trace nodes while a rule is evaluated.
+#[derive(Default)]
+pub(crate) struct TraceRecorder {
+    stack: Vec<Frame>,
+    root: Option<TraceNode>,
+}
+
+impl TraceRecorder {
+    /// Records the start of the evaluation of a token.
+    pub(crate) fn enter(&mut self, token: &Token) {
+        self.stack.push(Frame {
+            token: token_address(token),
+            children: Vec::new(),
+        });
+    }
+
+    /// Records the end of the evaluation of the token passed to the matching `enter`.
+    pub(crate) fn exit(&mut self, token: &Token, result: &Result<&DataValue>) {
+        let Some(frame) = self.stack.pop() else {
+            return;
+        };
+        debug_assert_eq!(frame.token, token_address(token));
+
+        let args = argument_tokens(token);
+        let arguments: Vec<Option<OwnedDataValue>> = args
+            .iter()
+            .map(|arg| {
+                let address = token_address(arg);
+                frame
+                    .children
+                    .iter()
+                    .find(|(child, _)| *child == address)
+                    .and_then(|(_, node)| node.result.as_ref().ok().cloned())
+            })
+            .collect();
+        let branch = match token {
+            Token::Operator { op_type, .. } => branch_index(*op_type, &args, &frame.children),
+            _ => None,
+        };
+
+        let node = TraceNode {
+            operator: operator_name(token).to_string(),
+            expression: token.to_json(),
+            arguments,
+            result: match result {
+                Ok(value) => Ok(value.to_owned_value()),
+                Err(error) => Err(error.clone()),
+            },
+            branch,
+            children: frame.children.into_iter().map(|(_, node)| node).collect(),
+        };
+
+        match self.stack.last_mut() {
+            Some(parent) => parent.children.push((frame.token, node)),
+            None => self.root = Some(node),
+        }
+    }
+
+    /// Returns the node of the outermost evaluated token.
+    pub(crate) fn finish(self) -> Option<TraceNode> {
+        self.root
+    }
+}
+
+fn token_address(token: &Token) -> usize {
+    // A shared expression is evaluated and traced as the expression itself
+    let token = match token {
+        Token::Shared { expr, .. } => expr,
+        token => token,
+    };
+    token as *const Token as usize
+}
+
+/// Returns the argument tokens of an operator, in the order the operator receives them.
+fn argument_tokens<'t>(token: &'t Token<'t>) -> Vec<&'t Token<'t>> {
+    match token {
+        Token::Operator { args, .. } | Token::CustomOperator { args, .. } => match args {
+            Token::ArrayLiteral(items) => items.to_vec(),
+            _ => vec![*args],
+        },
+        _ => Vec::new(),
+    }
+}
+
+/// Returns the trace name of a token.
+fn operator_name<'t>(token: &Token<'t>) -> &'t str {
+    match token {
+        Token::Literal(_) => "literal",
+        Token::Pattern(_) => "literal",
+        Token::ArrayLiteral(_) => "array",
+        Token::Variable { .. } | Token::DynamicVariable { .. } => "var",
+        Token::Operator { op_type, .. } => op_type.as_str(),
+        Token::CustomOperator { name, .. } => name,
+        Token::Shared { expr, .. } => operator_name(expr),
+    }
+}
+
+/// Determines the short-circuiting argument of `and`/`or` or the chosen branch of `if`.
+fn branch_index(
+    op_type: OperatorType,
+    args: &[&Token],
+    children: &[(usize, TraceNode)],
+) -> Option<usize> {
+    // The arguments are evaluated in order, so the last evaluated one decides
+    let last = args.iter().rposition(|arg| {
+        let address = token_address(arg);
+        children.iter().any(|(child, _)| *child == address)
+    })?;
+
+    match op_type {
+        OperatorType::Control(ControlOp::And | ControlOp::Or) => {
+            (last + 1 < args.len()).then_some(last)
+        }
+        OperatorType::Control(ControlOp::If) => {
+            // Then branches are at odd indices, the else branch is the last of an odd count
+            let is_else = last + 1 == args.len() && args.len() % 2 == 1;
+            (last % 2 == 1 || is_else).then_some(last)
+        }
+        _ => None,
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::DataLogic;
+
+    fn trace(rule: &str, data: &str) -> TraceNode {
+        let dl = DataLogic::new();
+        let rule = dl.parse_logic(rule, None).unwrap();
+        let data = dl.parse_data(data).unwrap();
+        dl.evaluate_traced(&rule, &data).1
+    }
+
+    fn int(n: i64) -> Option<OwnedDataValue> {
+        Some(OwnedDataValue::Number(crate::value::NumberValue::Integer(
+            n,
+        )))
+    }
+
+    #[test]
+    fn test_trace_tree() {
+        let root = trace(
+            r#"{"+": [{"var": "a"}, {"*": [{"var": "b"}, 3]}]}"#,
+            r#"{"a": 1, "b": 2}"#,
+        );
+
+        assert_eq!(root.operator, "+");
+        assert_eq!(root.arguments, vec![int(1), int(6)]);
+        assert_eq!(root.result.as_ref().unwrap().as_i64(), Some(7));
+        assert_eq!(root.children.len(), 2);
+        assert_eq!(root.children[0].operator, "var");
+        assert_eq!(root.children[0].expression, json!({"var": "a"}));
+
+        let multiply = &root.children[1];
+        assert_eq!(multiply.operator, "*");
+        assert_eq!(multiply.arguments, vec![int(2), int(3)]);
+        assert_eq!(multiply.children[1].operator, "literal");
+    }
+
+    #[test]
+    fn test_trace_short_circuit() {
+        let root = trace(
+            r#"{"and": [{"var": "a"}, {"var": "b"}, {"var": "c"}]}"#,
+            r#"{"a": true, "b": false, "c": true}"#,
+        );
+        assert_eq!(root.branch, Some(1));
+        assert_eq!(root.arguments[2], None);
+        assert_eq!(root.children.len(), 2);
+
+        let root = trace(
+            r#"{"or": [{"var": "a"}, {"var": "b"}]}"#,
+            r#"{"a": 0, "b": 0}"#,
+        );
+        assert_eq!(root.branch, None);
+
+        let root = trace(
+            r#"{"if": [{"var": "a"}, "one", {"var": "b"}, "two", "three"]}"#,
+            r#"{"a": false, "b": true}"#,
+        );
+        assert_eq!(root.branch, Some(3));
+        assert_eq!(root.arguments[1], None);
+
+        let root = trace(
+            r#"{"if": [{"var": "a"}, "one", "other"]}"#,
+            r#"{"a": false}"#,
+        );
+        assert_eq!(root.branch, Some(2));
+
+        let root = trace(r#"{"if": [{"var": "a"}, "one"]}"#, r#"{"a": false}"#);
+        assert_eq!(root.branch, None);
+    }
+
+    #[test]
+    fn test_trace_iteration_and_errors() {
+        let root = trace(
+            r#"{"map": [{"var": "items"}, {"+": [{"var": ""}, 1]}]}"#,
+            r#"{"items": [1, 2]}"#,
+        );
+        // The array, then the body once per item
+        assert_eq!(root.children.len(), 3);
+        assert_eq!(root.arguments[1], int(2));
+
+        let dl = DataLogic::new();
+        let rule = dl.parse_logic(r#"{"!": {"throw": "Oops"}}"#, None).unwrap();
+        let data = dl.parse_data("{}").unwrap();
+        let (result, root) = dl.evaluate_traced(&rule, &data);
+        assert!(result.is_err());
+        assert_eq!(
+            root.result,
+            result
+                .map(|v| v.to_owned_value())
+                .map_err(LogicError::without_location)
+        );
+        assert!(root.children[0].result.is_err());
+    }
+
+    #[test]
+    fn test_trace_to_json() {
+        let root = trace(r#"{"or": [{"var": "a"}, "fallback"]}"#, r#"{"a": "x"}"#);
+        assert_eq!(
+            root.to_json(),
+            json!({
+                "operator": "or",
+                "expression": {"or": [{"var": "a"}, "fallback"]},
+                "arguments": ["x", null],
+                "result": "x",
+                "branch": 0,
+                "children": [
+                    {"operator": "var", "expression": {"var": "a"}, "result": "x"}
+                ]
+            })
+        );
+    }
+}
@@ -0,0 +1,506 @@
+//! Static checks of rules.
+//!
+//! This module provides the `Diagnostic` type and the pass that looks for
+//! problems in a rule without evaluating it, such as operators called with
+//! the wrong number of arguments or branches that can never be taken.
+
+use std::fmt;
+
+use serde_json::{Value as JsonValue, json};
+
+use super::location::{for_each_child, push_segments};
+use super::operators::array::ArrayOp;
+use super::operators::control::ControlOp;
+use super::operators::custom::CustomOperatorRegistry;
+use super::token::{OperatorType, Token};
+use crate::value::ToJson;
+
+/// How serious a problem found by the validator is.
+#[derive(Debug, Clone, Copy, PartialEq, Eq, PartialOrd, Ord, Hash)]
+pub enum Severity {
+    /// The rule fails whenever the expression is evaluated.
+    Error,
+    /// The rule runs, but probably does not do what was intended.
+    Warning,
+    /// Something worth a second look, such as a possible typo.
+    Info,
+}
+
+impl Severity {
+    /// Returns the lowercase name of the severity.
+    pub fn as_str(&self) -> &'static str {
+        match self {
+            Severity::Error => "error",
+            Severity::Warning => "warning",
+            Severity::Info => "info",
+        }
+    }
+}
+
+impl fmt::Display for Severity {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        f.write_str(self.as_str())
+    }
+}
+
+/// A problem found in a rule by the validator.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct Diagnostic {
+    /// How serious the problem is.
+    pub severity: Severity,
+    /// A stable, machine-readable code for the kind of problem, such as
+    /// `argument_count`.
+    pub code: &'static str,
+    /// A description of the problem.
+    pub message: String,
+    /// JSON pointer to the expression in the rule, such as `/if/2`. The
+    /// pointer is empty for the root of the rule.
+    pub path: String,
+}
+
+impl fmt::Display for Diagnostic {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        if self.path.is_empty() {
+            write!(f, "{}: {} at the rule root", self.severity, self.message)
+        } else {
+            write!(f, "{}: {} at '{}'", self.severity, self.message, self.path)
+        }
+    }
+}
+
+impl ToJson for Diagnostic {
+    fn to_json(&self) -> JsonValue {
+        json!({
+            "severity": self.severity.as_str(),
+            "code": self.code,
+            "message": self.message,
+            "path": self.path,
+        })
+    }
+}
+
+/// Checks a rule for problems without evaluating it.
+///
+/// The diagnostics are in the order of the expressions in the rule.
+pub(crate) fn validate(root: &Token, custom_operators: &CustomOperatorRegistry) -> Vec<Diagnostic> {
+    let mut validator = Validator {
+        custom_operators,
+        diagnostics: Vec::new(),
+        variables: Vec::new(),
+    };
+    let mut path = String::new();
+    validator.visit(root, &mut path);
+    validator.check_similar_paths();
+    validator.diagnostics
+}
+
+struct Validator<'r> {
+    custom_operators: &'r CustomOperatorRegistry,
+    diagnostics: Vec<Diagnostic>,
+    /// Variable paths read by the rule, with the pointer of their first use.
+    variables: Vec<(String, String)>,
+}
+
+impl Validator<'_> {
+    fn visit(&mut self, token: &Token, path: &mut String) {
+        match token {
+            Token::Variable { path: variable, .. } => self.check_variable(variable, path),
+            Token::Operator { op_type, args } => self.check_operator(*op_type, args, path),
+            Token::CustomOperator { name, .. } if !self.custom_operators.contains(name) => {
+                self.report(
+                    Severity::Error,
+                    "unknown_operator",
+                    format!("Operator '{}' is not registered", name),
+                    path,
+                );
+            }
+            _ => {}
+        }
+
+        for_each_child(token, &mut |segments, child| {
+            let len = path.len();
+            push_segments(path, segments);
+            self.visit(child, path);
+            path.truncate(len);
+            false
+        });
+    }
+
+    fn report(&mut self, severity: Severity, code: &'static str, message: String, path: &str) {
+        self.diagnostics.push(Diagnostic {
+            severity,
+            code,
+            message,
+            path: path.to_string(),
+        });
+    }
+
+    fn check_operator(&mut self, op_type: OperatorType, args: &Token, path: &str) {
+        let name = op_type.as_str();
+        let items = match args {
+            Token::ArrayLiteral(items) => Some(*items),
+            _ => None,
+        };
+
+        if let OperatorType::Control(ControlOp::If | ControlOp::And | ControlOp::Or) = op_type
+            && items.is_none()
+        {
+            self.report(
+                Severity::Error,
+                "invalid_arguments",
+                format!("'{}' expects an array of arguments", name),
+                path,
+            );
+            return;
+        }
+
+        if let Some(count) = argument_count(op_type, args) {
+            let arity = op_type.arity();
+            if !arity.accepts(count) {
+                let actual = if count == 1 {
+                    "1 argument".to_string()
+                } else {
+                    format!("{} arguments", count)
+                };
+                self.report(
+                    Severity::Error,
+                    "argument_count",
+                    format!("'{}' expects {}, got {}", name, arity.describe(), actual),
+                    path,
+                );
+                return;
+            }
+        }
+
+        match (op_type, items) {
+            (OperatorType::Control(ControlOp::If), Some(items)) => self.check_if(items, path),
+            (OperatorType::Array(ArrayOp::Reduce), Some([_, reducer, initial])) => {
+                self.check_reduce(reducer, initial, path)
+            }
+            _ => {}
+        }
+    }
+
+    /// Looks for `if` branches that are never taken and a missing else branch.
+    fn check_if(&mut self, items: &[&Token], path: &str) {
+        let mut index = 0;
+        while index + 1 < items.len() {
+            if let Token::Literal(condition) = items[index] {
+                if condition.coerce_to_bool() {
+                    // Nothing after an always true condition is evaluated
+                    if index + 2 < items.len() {
+                        self.report(
+                            Severity::Warning,
+                            "unreachable_branch",
+                            format!(
+                                "The branches after the condition at argument {} are never evaluated, as it is always true",
+                                index
+                            ),
+                            &format!("{}/if/{}", path, index + 2),
+                        );
+                    }
+                    return;
+                }
+                self.report(
+                    Severity::Warning,
+                    "unreachable_branch",
+                    format!(
+                        "This branch is never evaluated, as the condition at argument {} is always false",
+                        index
+                    ),
+                    &format!("{}/if/{}", path, index + 1),
+                );
+            }
+            index += 2;
+        }
+
+        if !items.is_empty() && items.len().is_multiple_of(2) {
+            self.report(
+                Severity::Warning,
+                "missing_else",
+                "'if' has no else branch and returns null when no condition holds".to_string(),
+                path,
+            );
+        }
+    }
+
+    /// Checks that the initial value of an arithmetic `reduce` is a number.
+    fn check_reduce(&mut self, reducer: &Token, initial: &Token, path: &str) {
+        let (
+            Token::Operator {
+                op_type: OperatorType::Arithmetic(op),
+                ..
+            },
+            Token::Literal(value),
+        ) = (reducer.unshared(), initial)
+        else {
+            return;
+        };
+
+        if value.coerce_to_number().is_none() {
+            let name = OperatorType::Arithmetic(*op).as_str();
+            self.report(
+                Severity::Error,
+                "invalid_initial_value",
+                format!(
+                    "The initial value of 'reduce' is {} {}, but the reducer '{}' expects a number",
+                    article(value.type_name()),
+                    value.type_name(),
+                    name
+                ),
+                &format!("{}/reduce/2", path),
+            );
+        }
+    }
+
+    /// Looks for variable paths that are malformed.
+    fn check_variable(&mut self, variable: &str, path: &str) {
+        if !self.variables.iter().any(|(known, _)| known == variable) {
+            self.variables
+                .push((variable.to_string(), path.to_string()));
+        }
+        if variable.is_empty() {
+            return;
+        }
+
+        let problem = if variable.split('.').any(str::is_empty) {
+            Some("has an empty segment")
+        } else if variable.split('.').any(|segment| segment.trim() != segment) {
+            Some("has a segment with leading or trailing whitespace")
+        } else {
+            None
+        };
+        if let Some(problem) = problem {
+            self.report(
+                Severity::Warning,
+                "suspicious_path",
+                format!("Variable path '{}' {}", variable, problem),
+                path,
+            );
+        }
+    }
+
+    /// Reports variable paths that differ from another path of the rule by a
+    /// single typo in one segment.
+    fn check_similar_paths(&mut self) {
+        let variables = std::mem::take(&mut self.variables);
+        for (index, (variable, path)) in variables.iter().enumerate() {
+            if let Some((similar, _)) = variables[..index]
+                .iter()
+                .find(|(earlier, _)| is_similar_path(earlier, variable))
+            {
+                self.report(
+                    Severity::Info,
+                    "similar_path",
+                    format!(
+                        "Variable path '{}' is similar to '{}', which is also used",
+                        variable, similar
+                    ),
+                    path,
+                );
+            }
+        }
+    }
+}
+
+/// Returns the number of arguments an operator is called with, if it is
+/// known without evaluating the rule.
+fn argument_count(op_type: OperatorType, args: &Token) -> Option<usize> {
+    match args {
+        Token::ArrayLiteral(items) => Some(items.len()),
+        // These operators spread an argument that evaluates to an array
+        _ if matches!(op_type, OperatorType::Arithmetic(_) | OperatorType::Exists) => match args {
+            Token::Literal(value) => Some(value.as_array().map_or(1, <[_]>::len)),
+            _ => None,
+        },
+        _ => Some(1),
+    }
+}
+
+fn article(type_name: &str) -> &'static str {
+    if type_name.starts_with(['a', 'e', 'i', 'o', 'u']) {
+        "an"
+    } else {
+        "a"
+    }
+}
+
+/// Returns true if two paths differ by one typo in a single segment.
+///
+/// Only segments of at least four characters are compared, and segments
+/// that differ only in their digits, such as `line1` and `line2`, are not
+/// similar.
+fn is_similar_path(a: &str, b: &str) -> bool {
+    let a: Vec<&str> = a.split('.').collect();
+    let b: Vec<&str> = b.split('.').collect();
+    if a.len() != b.len() {
+        return false;
+    }
+
+    let mut differing = a.iter().zip(&b).filter(|(x, y)| x != y);
+    let (Some((x, y)), None) = (differing.next(), differing.next()) else {
+        return false;
+    };
+    let without_digits = |s: &str| {
+        s.chars()
+            .filter(|c| !c.is_ascii_digit())
+            .collect::<String>()
+    };
+
+    x.chars().count() >= 4
+        && y.chars().count() >= 4
+        && without_digits(x) != without_digits(y)
+        && edit_distance(x, y) == 1
+}
+
+/// Returns the number of insertions, deletions, substitutions and swaps of
+/// adjacent characters that turn `a` into `b`.
+fn edit_distance(a: &str, b: &str) -> usize {
+    let a: Vec<char> = a.chars().collect();
+    let b: Vec<char> = b.chars().collect();
+    let mut rows = vec![vec![0; b.len() + 1]; a.len() + 1];
+    for (i, row) in rows.iter_mut().enumerate() {
+        row[0] = i;
+    }
+    for (j, cell) in rows[0].iter_mut().enumerate() {
+        *cell = j;
+    }
+
+    for i in 1..=a.len() {
+        for j in 1..=b.len() {
+            let cost = usize::from(a[i - 1] != b[j - 1]);
+            let mut distance = (rows[i - 1][j] + 1)
+                .min(rows[i][j - 1] + 1)
+                .min(rows[i - 1][j - 1] + cost);
+            if i > 1 && j > 1 && a[i - 1] == b[j - 2] && a[i - 2] == b[j - 1] {
+                distance = distance.min(rows[i - 2][j - 2] + 1);
+            }
+            rows[i][j] = distance;
+        }
+    }
+    rows[a.len()][b.len()]
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::DataLogic;
+
+    fn validate(rule: &str) -> Vec<Diagnostic> {
+        let dl = DataLogic::new();
+        let rule = dl.parse_logic(rule, None).unwrap();
+        dl.validate(&rule)
+    }
+
+    fn codes(rule: &str) -> Vec<(Severity, &'static str, String)> {
+        validate(rule)
+            .into_iter()
+            .map(|d| (d.severity, d.code, d.path))
+            .collect()
+    }
+
+    #[test]
+    fn test_argument_count() {
+        let diagnostics = validate(r#"{"cat": [{"substr": [{"var": "s"}, 1, 2, 3, 4]}, "x"]}"#);
+        assert_eq!(diagnostics.len(), 1);
+        assert_eq!(diagnostics[0].severity, Severity::Error);
+        assert_eq!(diagnostics[0].code, "argument_count");
+        assert_eq!(diagnostics[0].path, "/cat/0");
+        assert_eq!(
+            diagnostics[0].message,
+            "'substr' expects 2 or 3 arguments, got 5 arguments"
+        );
+
+        assert_eq!(
+            codes(r#"{"!": [{"var": "a"}, {"var": "b"}]}"#),
+            vec![(Severity::Error, "argument_count", String::new())]
+        );
+        assert_eq!(
+            codes(r#"{"and": {"var": "a"}}"#),
+            vec![(Severity::Error, "invalid_arguments", String::new())]
+        );
+        // The arguments of a spreading operator are only known for literals
+        assert!(validate(r#"{"%": {"var": "pair"}}"#).is_empty());
+        assert!(validate(r#"{"map": [{"var": "items"}, {"var": ""}]}"#).is_empty());
+    }
+
+    #[test]
+    fn test_if_branches() {
+        assert_eq!(
+            codes(r#"{"if": [{"var": "a"}, 1, {"var": "b"}, 2]}"#),
+            vec![(Severity::Warning, "missing_else", String::new())]
+        );
+        assert_eq!(
+            codes(r#"{"if": [{"var": "a"}, 1, true, 2, 3]}"#),
+            vec![(Severity::Warning, "unreachable_branch", "/if/4".to_string())]
+        );
+        assert_eq!(
+            codes(r#"{"if": [{"var": "a"}, 1, 0, {"var": "b"}, 3]}"#),
+            vec![(Severity::Warning, "unreachable_branch", "/if/3".to_string())]
+        );
+        assert!(validate(r#"{"if": [{"var": "a"}, 1, 2]}"#).is_empty());
+    }
+
+    #[test]
+    fn test_reduce_initial_value() {
+        let diagnostics = validate(
+            r#"{"reduce": [{"var": "items"}, {"+": [{"var": "current"}, {"var": "accumulator"}]}, "total"]}"#,
+        );
+        assert_eq!(diagnostics.len(), 1);
+        assert_eq!(diagnostics[0].code, "invalid_initial_value");
+        assert_eq!(diagnostics[0].path, "/reduce/2");
+        assert_eq!(
+            diagnostics[0].message,
+            "The initial value of 'reduce' is a string, but the reducer '+' expects a number"
+        );
+
+        assert!(
+            validate(r#"{"reduce": [{"var": "items"}, {"cat": [{"var": "accumulator"}, {"var": "current"}]}, "x"]}"#)
+                .is_empty()
+        );
+    }
+
+    #[test]
+    fn test_unknown_operator() {
+        assert_eq!(
+            codes(r#"{"or": [{"var": "a"}, {"double": 2}]}"#),
+            vec![(Severity::Error, "unknown_operator", "/or/1".to_string())]
+        );
+    }
+
+    #[test]
+    fn test_variable_paths() {
+        assert_eq!(
+            codes(r#"{"and": [{"var": "user..name"}, {"var": "user.name "}]}"#),
+            vec![
+                (Severity::Warning, "suspicious_path", "/and/0".to_string()),
+                (Severity::Warning, "suspicious_path", "/and/1".to_string()),
+            ]
+        );
+
+        let diagnostics = validate(
+            r#"{"and": [{"var": "user.name"}, {"var": "user.line1"}, {"var": "user.line2"}, {"==": [{"var": "user.nmae"}, "x"]}]}"#,
+        );
+        assert_eq!(diagnostics.len(), 1);
+        assert_eq!(diagnostics[0].severity, Severity::Info);
+        assert_eq!(diagnostics[0].path, "/and/3/==/0");
+        assert_eq!(
+            diagnostics[0].to_string(),
+            "info: Variable path 'user.nmae' is similar to 'user.name', which is also used at '/and/3/==/0'"
+        );
+    }
+
+    #[test]
+    fn test_diagnostic_to_json() {
+        let diagnostic = &validate(r#"{"in": ["a"]}"#)[0];
+        assert_eq!(
+            diagnostic.to_json(),
+            json!({
+                "severity": "error",
+                "code": "argument_count",
+                "message": "'in' expects 2 arguments, got 1 argument",
+                "path": ""
+            })
+        );
+    }
+}
@@ -5,8 +5,9 @@
 use std::str::FromStr;
 
 use crate::arena::DataArena;
+use crate::logic::compile_pattern;
 use crate::logic::{LogicError, OperatorType, Result, Token};
-use crate::parser::ExpressionParser;
+use crate::parser::{ExpressionParser, source};
 use crate::value::{DataValue, FromJson};
 use serde_json::{Map as JsonMap, Value as JsonValue};
 
@@ -16,12 +17,10 @@ pub struct JsonLogicParser;
 impl ExpressionParser for JsonLogicParser {
     fn parse<'a>(&self, input: &str, arena: &'a DataArena) -> Result<&'a Token<'a>> {
         // Parse the input string as JSON
-        let json: JsonValue = serde_json::from_str(input).map_err(|e| LogicError::ParseError {
-            reason: format!("Invalid JSON: {}", e),
-        })?;
+        let json = source::parse_json(input)?;
 
-        // Use the JSONLogic parsing logic
-        parse_json(&json, arena)
+        // Use the JSONLogic parsing logic, pointing errors to the source
+        parse_json(&json, arena).map_err(|e| source::locate(input, e))
     }
 
     fn format_name(&self) -> &'static str {
@@ -32,7 +31,9 @@ impl ExpressionParser for JsonLogicParser {
 /// Checks if a JSON value is a literal.
 fn is_json_literal(value: &JsonValue) -> bool {
     match value {
-        JsonValue::Null | JsonValue::Bool(_) | JsonValue::Number(_) | JsonValue::String(_) => true,
+        JsonValue::Null | JsonValue::Bool(_) | JsonValue::String(_) => true,
+        // Numbers out of the range of floats are rejected as tokens
+        JsonValue::Number(n) => n.as_f64().is_some(),
         JsonValue::Array(arr) => {
             // Nested arrays are allowed if they only contain literals
             arr.iter().all(is_json_literal)
@@ -42,8 +43,12 @@ fn is_json_literal(value: &JsonValue) -> bool {
 }
 
 /// Parses a logic expression from a JSON value.
+///
+/// Errors have the JSON pointer of the value that caused them as their
+/// location.
 pub fn parse_json<'a>(json: &JsonValue, arena: &'a DataArena) -> Result<&'a Token<'a>> {
-    let token = parse_json_internal(json, arena)?;
+    // Errors without a location were raised by the root value itself
+    let token = parse_json_internal(json, arena).map_err(|error| error.located(String::new()))?;
     Ok(arena.alloc(token))
 }
 
@@ -53,17 +58,11 @@ fn parse_json_internal<'a>(json: &JsonValue, arena: &'a DataArena) -> Result<Tok
         // Simple literals
         JsonValue::Null => Ok(Token::literal(DataValue::null())),
         JsonValue::Bool(b) => Ok(Token::literal(DataValue::bool(*b))),
-        JsonValue::Number(n) => {
-            if let Some(i) = n.as_i64() {
-                Ok(Token::literal(DataValue::integer(i)))
-            } else if let Some(f) = n.as_f64() {
-                Ok(Token::literal(DataValue::float(f)))
-            } else {
-                Err(LogicError::ParseError {
-                    reason: format!("Invalid number: {}", n),
-                })
-            }
+        // Numbers are read like data, so decimal arithmetic applies to literals
+        JsonValue::Number(n) if n.as_f64().is_some() => {
+            Ok(Token::literal(DataValue::from_json(json, arena)))
         }
+        JsonValue::Number(n) => Err(LogicError::parse_error(format!("Invalid number: {}", n))),
         JsonValue::String(s) => Ok(Token::literal(DataValue::string(arena, s))),
 
         // Arrays could be literal arrays or token arrays
@@ -89,12 +88,13 @@ fn parse_json_internal<'a>(json: &JsonValue, arena: &'a DataArena) -> Result<Tok
             } else {
                 // Otherwise, create an array of tokens and allocate them in the arena
                 let mut tokens = Vec::with_capacity(arr.len());
-                for item in arr {
-                    let token = parse_json_internal(item, arena)?;
+                for (index, item) in arr.iter().enumerate() {
+                    let token = parse_json_internal(item, arena)
+                        .map_err(|e| e.within(&index.to_string()))?;
                     let token_ref = arena.alloc(token);
                     tokens.push(token_ref);
                 }
-                Ok(Token::ArrayLiteral(tokens))
+                Ok(Token::ArrayLiteral(arena.vec_into_slice(tokens)))
             }
         }
 
@@ -109,13 +109,12 @@ fn parse_object<'a>(obj: &JsonMap<String, JsonValue>, arena: &'a DataArena) -> R
     if obj.len() == 1 {
         let (key, value) = obj.iter().next().unwrap();
 
-        match key.as_str() {
+        let token = match key.as_str() {
             "var" => parse_variable(value, arena),
-            "val" => {
-                let token = parse_json_internal(value, arena)?;
+            "val" => parse_json_internal(value, arena).map(|token| {
                 let args_token = arena.alloc(token);
-                Ok(Token::operator(OperatorType::Val, args_token))
-            }
+                Token::operator(OperatorType::Val, args_token)
+            }),
             "exists" => parse_exists_operator(value, arena),
             "preserve" => {
                 // The preserve operator returns its argument as-is without parsing it as an operator
@@ -123,15 +122,14 @@ fn parse_object<'a>(obj: &JsonMap<String, JsonValue>, arena: &'a DataArena) -> R
                 Ok(Token::literal(preserved_value))
             }
             _ => {
-                // Check if it's a standard operator
-                if let Ok(op_type) = OperatorType::from_str(key) {
-                    return parse_operator(op_type, value, arena);
+                // Check if it's a standard operator, otherwise treat it as a custom operator
+                match OperatorType::from_str(key) {
+                    Ok(op_type) => parse_operator(op_type, value, arena),
+                    Err(_) => parse_custom_operator(key, value, arena),
                 }
-
-                // Otherwise, treat it as a custom operator
-                parse_custom_operator(key, value, arena)
             }
-        }
+        };
+        token.map_err(|e| e.within(key))
     } else if obj.is_empty() {
         // Empty object literal
         Ok(Token::literal(DataValue::Object(
@@ -144,9 +142,7 @@ fn parse_object<'a>(obj: &JsonMap<String, JsonValue>, arena: &'a DataArena) -> R
         let (key, _) = obj.iter().next().unwrap();
 
         // Return an OperatorNotFoundError instead of a ParseError
-        Err(LogicError::OperatorNotFoundError {
-            operator: key.clone(),
-        })
+        Err(LogicError::operator_not_found(key.clone()))
     }
 }
 
@@ -186,12 +182,13 @@ fn parse_variable<'a>(var_json: &JsonValue, arena: &'a DataArena) -> Result<Toke
                 && !arr[0].is_null()
             {
                 // Parse the path expression
-                let path_expr = parse_json_internal(&arr[0], arena)?;
+                let path_expr = parse_json_internal(&arr[0], arena).map_err(|e| e.within("0"))?;
                 let path_token = arena.alloc(path_expr);
 
                 // If there's a default value, parse it
                 let default = if arr.len() >= 2 {
-                    let default_token = parse_json_internal(&arr[1], arena)?;
+                    let default_token =
+                        parse_json_internal(&arr[1], arena).map_err(|e| e.within("1"))?;
                     Some(arena.alloc(default_token))
                 } else {
                     None
@@ -217,7 +214,8 @@ fn parse_variable<'a>(var_json: &JsonValue, arena: &'a DataArena) -> Result<Toke
                 };
 
                 // Parse the default value
-                let default_token = parse_json_internal(&arr[1], arena)?;
+                let default_token =
+                    parse_json_internal(&arr[1], arena).map_err(|e| e.within("1"))?;
                 let default = arena.alloc(default_token);
 
                 return Ok(Token::variable(path, Some(default)));
@@ -237,12 +235,10 @@ fn parse_variable<'a>(var_json: &JsonValue, arena: &'a DataArena) -> Result<Toke
                         JsonValue::Bool(b) => b.to_string(),
                         JsonValue::Null => "".to_string(),
                         _ => {
-                            return Err(LogicError::ParseError {
-                                reason: format!(
-                                    "Variable path component must be a scalar value, found: {:?}",
-                                    item
-                                ),
-                            })
+                            return Err(LogicError::parse_error(format!(
+                                "Variable path component must be a scalar value, found: {:?}",
+                                item
+                            )));
                         }
                     };
                     path_parts.push(part);
@@ -259,12 +255,10 @@ fn parse_variable<'a>(var_json: &JsonValue, arena: &'a DataArena) -> Result<Toke
                 JsonValue::Bool(b) => arena.intern_str(&b.to_string()),
                 JsonValue::Null => arena.intern_str(""),
                 _ => {
-                    return Err(LogicError::ParseError {
-                        reason: format!(
-                            "Variable path must be a scalar value, found: {:?}",
-                            arr[0]
-                        ),
-                    })
+                    return Err(LogicError::parse_error(format!(
+                        "Variable path must be a scalar value, found: {:?}",
+                        arr[0]
+                    )));
                 }
             };
 
@@ -275,7 +269,7 @@ fn parse_variable<'a>(var_json: &JsonValue, arena: &'a DataArena) -> Result<Toke
 
             // If there are two or more elements, the second is the default
             // Parse the default value
-            let default_token = parse_json_internal(&arr[1], arena)?;
+            let default_token = parse_json_internal(&arr[1], arena).map_err(|e| e.within("1"))?;
             let default = arena.alloc(default_token);
 
             Ok(Token::variable(path, Some(default)))
@@ -314,9 +308,10 @@ fn parse_variable<'a>(var_json: &JsonValue, arena: &'a DataArena) -> Result<Toke
         }
 
         // Invalid variable reference
-        _ => Err(LogicError::ParseError {
-            reason: format!("Invalid variable reference: {:?}", var_json),
-        }),
+        _ => Err(LogicError::parse_error(format!(
+            "Invalid variable reference: {:?}",
+            var_json
+        ))),
     }
 }
 
@@ -329,6 +324,15 @@ fn parse_operator<'a>(
     // Parse the arguments
     let args = parse_arguments(args_json, arena)?;
 
+    // Compile a literal pattern once, rather than on every evaluation
+    let args = match op_type {
+        OperatorType::Regex(_) => match compile_pattern(args, arena) {
+            Some(compiled) => compiled.map_err(|e| e.within("1"))?,
+            None => args,
+        },
+        _ => args,
+    };
+
     // Create the operator token
     Ok(Token::operator(op_type, args))
 }
@@ -357,7 +361,7 @@ fn parse_arguments<'a>(args_json: &JsonValue, arena: &'a DataArena) -> Result<&'
 
         // Empty array - create an empty ArrayLiteral
         JsonValue::Array(arr) if arr.is_empty() => {
-            let empty_array_token = Token::ArrayLiteral(Vec::new());
+            let empty_array_token = Token::ArrayLiteral(&[]);
             Ok(arena.alloc(empty_array_token))
         }
 
@@ -366,14 +370,15 @@ fn parse_arguments<'a>(args_json: &JsonValue, arena: &'a DataArena) -> Result<&'
             let mut tokens = Vec::with_capacity(arr.len());
 
             // Parse each argument
-            for arg_json in arr {
-                let arg = parse_json_internal(arg_json, arena)?;
+            for (index, arg_json) in arr.iter().enumerate() {
+                let arg =
+                    parse_json_internal(arg_json, arena).map_err(|e| e.within(&index.to_string()))?;
                 let arg_ref = arena.alloc(arg);
                 tokens.push(arg_ref);
             }
 
             // Create an array literal token
-            let array_token = Token::ArrayLiteral(tokens);
+            let array_token = Token::ArrayLiteral(arena.vec_into_slice(tokens));
             Ok(arena.alloc(array_token))
         }
 
@@ -418,9 +423,9 @@ mod tests {
         assert_eq!(token.as_literal().unwrap().as_i64(), Some(42));
 
         // Parse float
-        let token = parse_json(&json!(3.14), &arena).unwrap();
+        let token = parse_json(&json!(3.5), &arena).unwrap();
         assert!(token.is_literal());
-        assert_eq!(token.as_literal().unwrap().as_f64(), Some(3.14));
+        assert_eq!(token.as_literal().unwrap().as_f64(), Some(3.5));
 
         // Parse string
         let token = parse_json(&json!("hello"), &arena).unwrap();
@@ -496,8 +501,15 @@ mod tests {
         let token = parse_json(&json!({"my_op": [1, 2, 3]}), &arena).unwrap();
         assert!(token.is_custom_operator());
 
-        let (name, _args) = token.as_custom_operator().unwrap();
+        let (name, args) = token.as_custom_operator().unwrap();
+        assert_eq!(name, "my_op");
+        assert_eq!(args.as_array_literal().unwrap().len(), 3);
+
+        // A single non-array argument is kept as is
+        let token = parse_json(&json!({"my_op": {"var": "a"}}), &arena).unwrap();
+        let (name, args) = token.as_custom_operator().unwrap();
         assert_eq!(name, "my_op");
+        assert!(args.is_variable());
     }
 
     #[test]
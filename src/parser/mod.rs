@@ -1,8 +1,9 @@
 use crate::arena::DataArena;
-use crate::logic::{LogicError, Result, Token};
+use crate::logic::{LogicError, OperatorPolicy, Result, Token};
 use std::collections::HashMap;
 
 pub mod jsonlogic;
+pub(crate) mod source;
 #[cfg(test)]
 mod tests;
 
@@ -19,6 +20,7 @@ pub trait ExpressionParser {
 pub struct ParserRegistry {
     parsers: HashMap<String, Box<dyn ExpressionParser>>,
     default_parser: String,
+    policy: OperatorPolicy,
 }
 
 impl Default for ParserRegistry {
@@ -33,6 +35,7 @@ impl ParserRegistry {
         let mut registry = Self {
             parsers: HashMap::new(),
             default_parser: "jsonlogic".to_string(),
+            policy: OperatorPolicy::allow_all(),
         };
 
         // Register the default JSONLogic parser
@@ -53,13 +56,27 @@ impl ParserRegistry {
             self.default_parser = format_name.to_string();
             Ok(())
         } else {
-            Err(LogicError::ParseError {
-                reason: format!("Unknown parser format: {}", format_name),
-            })
+            Err(LogicError::parse_error(format!(
+                "Unknown parser format: {}",
+                format_name
+            )))
         }
     }
 
+    /// Set the operator policy that parsed expressions are checked against
+    pub fn set_policy(&mut self, policy: OperatorPolicy) {
+        self.policy = policy;
+    }
+
+    /// Get the operator policy that parsed expressions are checked against
+    pub fn policy(&self) -> &OperatorPolicy {
+        &self.policy
+    }
+
     /// Parse an expression using the specified parser, or default if none specified
+    ///
+    /// The parsed expression is rejected if it uses an operator that the
+    /// operator policy does not allow.
     pub fn parse<'a>(
         &self,
         input: &str,
@@ -69,11 +86,14 @@ impl ParserRegistry {
         let format = format.unwrap_or(&self.default_parser);
 
         if let Some(parser) = self.parsers.get(format) {
-            parser.parse(input, arena)
+            let token = parser.parse(input, arena)?;
+            self.policy.check(token)?;
+            Ok(token)
         } else {
-            Err(LogicError::ParseError {
-                reason: format!("Unknown parser format: {}", format),
-            })
+            Err(LogicError::parse_error(format!(
+                "Unknown parser format: {}",
+                format
+            )))
         }
     }
 }
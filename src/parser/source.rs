@@ -0,0 +1,381 @@
+//! Positions of parse errors in the source string of a rule.
+//!
+//! The parser reports an invalid value with its JSON pointer. This module
+//! finds that value in the source string again, so parse errors can report
+//! the line, column and fragment of the source they refer to.
+
+use std::ops::Range;
+
+use serde_json::Value as JsonValue;
+
+#[cfg(feature = "decimal")]
+use crate::logic::push_segment;
+use crate::logic::{LogicError, Result, SourcePosition};
+
+/// Parses a JSON source string, reporting syntax errors as `ParseError`.
+///
+/// A number out of the range of floats is a syntax error, also with the
+/// `decimal` feature, which lets `serde_json` read it.
+pub(crate) fn parse_json(source: &str) -> Result<JsonValue> {
+    let json = serde_json::from_str(source).map_err(|e| syntax_error(source, &e))?;
+
+    #[cfg(feature = "decimal")]
+    if let Some(pointer) = out_of_range_number(&json) {
+        return Err(LogicError::ParseError {
+            reason: "Invalid JSON: number out of range".to_string(),
+            position: value_span(source, &pointer).map(|span| position(source, span)),
+            location: None,
+        });
+    }
+
+    Ok(json)
+}
+
+/// Returns the JSON pointer of the first number out of the range of floats.
+#[cfg(feature = "decimal")]
+fn out_of_range_number(json: &JsonValue) -> Option<String> {
+    let (segment, child) = match json {
+        JsonValue::Number(n) => return n.as_f64().is_none().then(String::new),
+        JsonValue::Array(items) => items
+            .iter()
+            .enumerate()
+            .find_map(|(i, item)| Some((i.to_string(), out_of_range_number(item)?)))?,
+        JsonValue::Object(entries) => entries
+            .iter()
+            .find_map(|(key, value)| Some((key.clone(), out_of_range_number(value)?)))?,
+        _ => return None,
+    };
+    let mut pointer = String::new();
+    push_segment(&mut pointer, &segment);
+    Some(pointer + &child)
+}
+
+/// Converts a JSON syntax error into a `ParseError` with its source position.
+pub(crate) fn syntax_error(source: &str, error: &serde_json::Error) -> LogicError {
+    // serde_json appends the position to the message
+    let message = error.to_string();
+    let suffix = format!(" at line {} column {}", error.line(), error.column());
+    let message = message.strip_suffix(&suffix).unwrap_or(&message);
+
+    let position = (error.line() > 0).then(|| {
+        let line_start = line_start(source, error.line());
+        // The column counts bytes and points at the offending byte
+        let offset = floor_char_boundary(source, line_start + error.column().saturating_sub(1));
+        position(source, offset..token_end(source, offset))
+    });
+
+    LogicError::ParseError {
+        reason: format!("Invalid JSON: {}", message),
+        position,
+        location: None,
+    }
+}
+
+/// Adds the position in the source to an error located by the parser.
+///
+/// Parse errors have their JSON pointer replaced with the position, while
+/// other errors, such as unknown operators, keep it alongside the position.
+pub(crate) fn locate(source: &str, mut error: LogicError) -> LogicError {
+    let Some(Some(location)) = error.location_slot() else {
+        return error;
+    };
+    if location.position.is_some() {
+        return error;
+    }
+    let Some(span) = value_span(source, &location.path) else {
+        return error;
+    };
+
+    match error {
+        LogicError::ParseError {
+            reason,
+            position: None,
+            ..
+        } => LogicError::ParseError {
+            reason,
+            position: Some(position(source, span)),
+            location: None,
+        },
+        mut error => {
+            if let Some(Some(location)) = error.location_slot() {
+                location.position = Some(position(source, span));
+            }
+            error
+        }
+    }
+}
+
+/// Describes the position of a span of the source.
+fn position(source: &str, span: Range<usize>) -> SourcePosition {
+    let start = span.start;
+    let line_start = source[..start].rfind('\n').map_or(0, |i| i + 1);
+    let line_end = source[start..]
+        .find('\n')
+        .map_or(source.len(), |i| start + i);
+
+    SourcePosition {
+        line: source[..start].matches('\n').count() + 1,
+        column: source[line_start..start].chars().count() + 1,
+        fragment: source[span].to_string(),
+        source_line: source[line_start..line_end]
+            .trim_end_matches('\r')
+            .to_string(),
+    }
+}
+
+/// Returns the byte offset of the start of a line, counting lines from 1.
+fn line_start(source: &str, line: usize) -> usize {
+    source
+        .match_indices('\n')
+        .nth(line.saturating_sub(2))
+        .filter(|_| line > 1)
+        .map_or(0, |(i, _)| i + 1)
+}
+
+fn floor_char_boundary(source: &str, mut offset: usize) -> usize {
+    offset = offset.min(source.len());
+    while !source.is_char_boundary(offset) {
+        offset -= 1;
+    }
+    offset
+}
+
+/// Returns the end of the word or the single character starting at `start`.
+fn token_end(source: &str, start: usize) -> usize {
+    let rest = &source[start..];
+    let word = rest
+        .find(|c: char| !(c.is_alphanumeric() || c == '_' || c == '-' || c == '.'))
+        .unwrap_or(rest.len());
+    match word {
+        0 => rest.chars().next().map_or(start, |c| start + c.len_utf8()),
+        len => start + len,
+    }
+}
+
+/// Finds the span of the value at a JSON pointer in a valid JSON document.
+fn value_span(source: &str, pointer: &str) -> Option<Range<usize>> {
+    let bytes = source.as_bytes();
+    let mut start = skip_whitespace(bytes, 0);
+
+    for segment in pointer.split('/').skip(1) {
+        let segment = segment.replace("~1", "/").replace("~0", "~");
+        start = child_start(source, start, &segment)?;
+    }
+
+    Some(start..value_end(bytes, start)?)
+}
+
+/// Returns the start of the member `segment` of the object or array at `start`.
+///
+/// For an object with duplicate keys, the last member wins, as in `serde_json`.
+fn child_start(source: &str, start: usize, segment: &str) -> Option<usize> {
+    let bytes = source.as_bytes();
+    let (close, is_object) = match bytes.get(start)? {
+        b'{' => (b'}', true),
+        b'[' => (b']', false),
+        _ => return None,
+    };
+    let target = if is_object {
+        None
+    } else {
+        Some(segment.parse::<usize>().ok()?)
+    };
+
+    let mut found = None;
+    let mut index = 0;
+    let mut pos = skip_whitespace(bytes, start + 1);
+    while *bytes.get(pos)? != close {
+        let matches = if is_object {
+            let key_end = string_end(bytes, pos)?;
+            let key: String = serde_json::from_str(&source[pos..key_end]).ok()?;
+            pos = skip_whitespace(bytes, key_end);
+            if *bytes.get(pos)? != b':' {
+                return None;
+            }
+            pos = skip_whitespace(bytes, pos + 1);
+            key == segment
+        } else {
+            target == Some(index)
+        };
+
+        if matches {
+            found = Some(pos);
+            if !is_object {
+                break;
+            }
+        }
+
+        index += 1;
+        pos = skip_whitespace(bytes, value_end(bytes, pos)?);
+        if bytes.get(pos) == Some(&b',') {
+            pos = skip_whitespace(bytes, pos + 1);
+        }
+    }
+    found
+}
+
+/// Returns the end of the value starting at `start`.
+fn value_end(bytes: &[u8], start: usize) -> Option<usize> {
+    match bytes.get(start)? {
+        b'"' => string_end(bytes, start),
+        b'{' | b'[' => {
+            let mut depth = 0;
+            let mut pos = start;
+            while pos < bytes.len() {
+                match bytes[pos] {
+                    b'"' => {
+                        pos = string_end(bytes, pos)?;
+                        continue;
+                    }
+                    b'{' | b'[' => depth += 1,
+                    b'}' | b']' => {
+                        depth -= 1;
+                        if depth == 0 {
+                            return Some(pos + 1);
+                        }
+                    }
+                    _ => {}
+                }
+                pos += 1;
+            }
+            None
+        }
+        _ => {
+            let len = bytes[start..]
+                .iter()
+                .position(|b| b.is_ascii_whitespace() || b",:]}".contains(b))
+                .unwrap_or(bytes.len() - start);
+            Some(start + len)
+        }
+    }
+}
+
+/// Returns the end of the string starting at `start`, after the closing quote.
+fn string_end(bytes: &[u8], start: usize) -> Option<usize> {
+    let mut pos = start + 1;
+    while pos < bytes.len() {
+        match bytes[pos] {
+            b'\\' => pos += 2,
+            b'"' => return Some(pos + 1),
+            _ => pos += 1,
+        }
+    }
+    None
+}
+
+fn skip_whitespace(bytes: &[u8], mut pos: usize) -> usize {
+    while bytes.get(pos).is_some_and(u8::is_ascii_whitespace) {
+        pos += 1;
+    }
+    pos
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::DataLogic;
+
+    fn parse_error(source: &str) -> LogicError {
+        DataLogic::new().parse_logic(source, None).unwrap_err()
+    }
+
+    #[test]
+    fn test_value_span() {
+        let source = r#"{"and": [ {"var": "a"}, {"x": "a\"]"} , 3 ], "and": [true, {"b": [1]}]}"#;
+        let span = |pointer| value_span(source, pointer).map(|span| &source[span]);
+
+        assert_eq!(span(""), Some(source));
+        assert_eq!(span("/and/1/b"), Some("[1]"));
+        assert_eq!(span("/and/0"), Some("true"));
+        assert_eq!(span("/and/2"), None);
+        assert_eq!(span("/or"), None);
+    }
+
+    #[test]
+    fn test_syntax_error_position() {
+        let err = parse_error("{\n  \"and\": [true,\n    false,]\n}");
+        let position = err.position().unwrap();
+        assert_eq!((position.line, position.column), (3, 11));
+        assert_eq!(position.fragment, "]");
+        assert_eq!(position.source_line, "    false,]");
+        assert!(err.to_string().ends_with("at line 3, column 11"));
+        assert!(!err.to_string().contains("column 11 at"));
+
+        let err = parse_error(r#"{"==": [1, @]}"#);
+        assert_eq!(err.position().unwrap().fragment, "@");
+        let err = parse_error(r#"{"==": [1, 2] "x"}"#);
+        assert_eq!(err.position().unwrap().fragment, "\"");
+
+        let err = parse_error("{\"==\": [1,");
+        assert_eq!(err.position().unwrap().line, 1);
+    }
+
+    #[test]
+    fn test_number_out_of_range() {
+        let err = parse_error("{\"+\": [1,\n  1e400]}");
+        assert!(matches!(err, LogicError::ParseError { .. }), "{err:?}");
+        assert!(err.to_string().contains("number out of range"), "{err}");
+        assert_eq!(err.position().unwrap().line, 2);
+
+        let dl = DataLogic::new();
+        let err = dl.parse_data(r#"{"a": 1.5e400}"#).unwrap_err();
+        assert!(err.to_string().contains("number out of range"), "{err}");
+        let err = dl.evaluate_str(r#"{"var": "a"}"#, r#"{"a": [-1e400]}"#, None);
+        assert!(matches!(err, Err(LogicError::ParseError { .. })));
+    }
+
+    #[test]
+    fn test_invalid_value_position() {
+        let source = "{\"if\": [\n  {\"var\": true},\n  1\n]}";
+        let err = parse_error(source);
+        let position = err.position().unwrap();
+        assert_eq!((position.line, position.column), (2, 11));
+        assert_eq!(position.fragment, "true");
+        assert_eq!(
+            err.diagnostic(),
+            "Parse error: Invalid variable reference: Bool(true)\n \
+             --> line 2, column 11\n  |\n2 |   {\"var\": true},\n  |           ^^^^"
+        );
+
+        // Rules parsed from JSON values report the JSON pointer instead
+        let arena = crate::arena::DataArena::new();
+        let rule = serde_json::json!({"if": [{"var": true}, 1]});
+        let err = crate::parser::jsonlogic::parse_json(&rule, &arena).unwrap_err();
+        assert_eq!(err.location(), Some("/if/0/var"));
+    }
+
+    #[test]
+    fn test_operator_error_position() {
+        let err = parse_error(r#"{"if":[true,{"bad op":1,"x":2}]}"#);
+        assert_eq!(err.location(), Some("/if/1"));
+        assert_eq!(err.code(), "operator_not_found");
+        let position = err.position().unwrap();
+        assert_eq!((position.line, position.column), (1, 13));
+        assert_eq!(position.fragment, r#"{"bad op":1,"x":2}"#);
+        assert_eq!(
+            err.diagnostic(),
+            "Operator 'bad op' not found\n \
+             --> line 1, column 13\n  |\n1 | {\"if\":[true,{\"bad op\":1,\"x\":2}]}\n  \
+             |             ^^^^^^^^^^^^^^^^^^"
+        );
+
+        let err = parse_error(r#"  {"a":1,"b":2}"#);
+        assert_eq!(err.location(), Some(""));
+        assert_eq!(err.position().unwrap().column, 3);
+
+        let err = parse_error(r#"{"preserve":1,"x":1}"#);
+        assert_eq!(err.position().unwrap().column, 1);
+        let json = serde_json::to_value(&err).unwrap();
+        assert_eq!(json["line"], 1);
+        assert_eq!(json["column"], 1);
+    }
+
+    #[test]
+    fn test_data_syntax_error_position() {
+        let dl = DataLogic::new();
+        let err = dl.parse_data("{\"a\": 1 \"b\": 2}").unwrap_err();
+        let position = err.position().unwrap();
+        assert_eq!((position.line, position.column), (1, 9));
+        assert_eq!(position.fragment, "\"");
+    }
+}
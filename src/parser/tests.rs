@@ -3,9 +3,10 @@
 //! This module contains tests for the parser registry and the included parsers.
 
 #[cfg(test)]
+#[allow(clippy::module_inception)]
 mod tests {
     use crate::arena::DataArena;
-    use crate::logic::{ComparisonOp, OperatorType, Token};
+    use crate::logic::{ComparisonOp, LogicError, OperatorPolicy, OperatorType, Token};
     use crate::parser::{ExpressionParser, ParserRegistry};
 
     #[test]
@@ -51,6 +52,29 @@ mod tests {
         assert!(result.is_err());
     }
 
+    #[test]
+    fn test_parser_registry_with_policy() {
+        let mut registry = ParserRegistry::new();
+        let arena = DataArena::new();
+        registry.set_policy(OperatorPolicy::allow_only(["==", "var"]));
+
+        // Allowed operators parse as usual
+        let token = registry
+            .parse(r#"{"==": [{"var": "a"}, 42]}"#, None, &arena)
+            .unwrap();
+        assert!(token.is_operator());
+
+        // Forbidden operators are rejected with their location
+        let result = registry.parse(r#"{"==": [{"var": "a"}, {"my_op": 42}]}"#, None, &arena);
+        assert_eq!(
+            result.unwrap_err(),
+            LogicError::OperatorNotAllowedError {
+                operator: "my_op".to_string(),
+                path: "/==/1".to_string(),
+            }
+        );
+    }
+
     // This is a mock parser for testing purposes
     struct MockParser;
 
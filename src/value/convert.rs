@@ -4,10 +4,13 @@
 //! such as JSON.
 
 use super::data_value::DataValue;
+use super::datetime::{format_datetime, format_duration};
 use super::number::NumberValue;
 use crate::arena::DataArena;
 use serde_json::{Map as JsonMap, Number as JsonNumber, Value as JsonValue};
+use rust_decimal::Decimal;
 use std::collections::HashMap;
+use std::str::FromStr;
 
 /// A trait for converting from JSON to DataValue.
 pub trait FromJson<'a> {
@@ -29,10 +32,16 @@ impl<'a> FromJson<'a> for DataValue<'a> {
             JsonValue::Number(n) => {
                 if let Some(i) = n.as_i64() {
                     DataValue::integer(i)
+                } else if arena.decimal_arithmetic().is_some()
+                    && let Some(d) = parse_decimal(n)
+                {
+                    // With decimal arithmetic, fractions are read exactly as written
+                    DataValue::decimal(d)
                 } else if let Some(f) = n.as_f64() {
                     DataValue::float(f)
                 } else {
-                    // This should never happen with valid JSON
+                    // Only out of the range of floats, which serde_json reads
+                    // with the `decimal` feature
                     DataValue::null()
                 }
             }
@@ -85,9 +94,15 @@ impl ToJson for DataValue<'_> {
                             JsonValue::Null
                         }
                     }
+                    NumberValue::Decimal(d) => match n.as_i64() {
+                        Some(i) => JsonValue::Number(i.into()),
+                        None => decimal_to_json(d),
+                    },
                 }
             }
             DataValue::String(s) => JsonValue::String(s.to_string()),
+            DataValue::DateTime(datetime) => JsonValue::String(format_datetime(datetime)),
+            DataValue::Duration(duration) => JsonValue::String(format_duration(duration)),
             DataValue::Array(arr) => {
                 let json_arr: Vec<JsonValue> = arr.iter().map(|item| item.to_json()).collect();
                 JsonValue::Array(json_arr)
@@ -103,6 +118,43 @@ impl ToJson for DataValue<'_> {
     }
 }
 
+/// Parses a JSON number as a decimal from the text it was written as.
+///
+/// Returns `None` if it is out of the range of decimals. Digits beyond the
+/// 28 decimal places a decimal holds are rounded.
+#[cfg(feature = "decimal")]
+fn parse_decimal(number: &JsonNumber) -> Option<Decimal> {
+    let text = number.as_str();
+    Decimal::from_str(text)
+        .or_else(|_| Decimal::from_scientific(text))
+        .ok()
+}
+
+/// Writes a decimal to JSON digit for digit rather than through a float.
+#[cfg(feature = "decimal")]
+fn decimal_to_json(decimal: &Decimal) -> JsonValue {
+    JsonNumber::from_str(&decimal.to_string()).map_or(JsonValue::Null, JsonValue::Number)
+}
+
+/// Writes a decimal to JSON as the float closest to it.
+#[cfg(not(feature = "decimal"))]
+fn decimal_to_json(decimal: &Decimal) -> JsonValue {
+    f64::from_str(&decimal.to_string())
+        .ok()
+        .and_then(JsonNumber::from_f64)
+        .map_or(JsonValue::Null, JsonValue::Number)
+}
+
+/// Parses a JSON number as a decimal through the float it was read as.
+///
+/// Returns `None` if it is out of the range of decimals. The float is
+/// converted to the shortest decimal that reads back as the same float, so
+/// numbers with up to 15 significant digits are exact.
+#[cfg(not(feature = "decimal"))]
+fn parse_decimal(number: &JsonNumber) -> Option<Decimal> {
+    NumberValue::Float(number.as_f64()?).as_decimal()
+}
+
 /// Converts a JSON value to a DataValue.
 pub fn json_to_data_value<'a>(json: &JsonValue, arena: &'a DataArena) -> DataValue<'a> {
     DataValue::from_json(json, arena)
@@ -146,7 +198,7 @@ mod tests {
             "null": null,
             "bool": true,
             "integer": 42,
-            "float": 3.14,
+            "float": 3.5,
             "string": "hello",
             "array": [1, 2, 3],
             "object": {
@@ -165,6 +217,38 @@ mod tests {
         assert_eq!(json, json2);
     }
 
+    #[test]
+    fn test_decimal_json_conversion() {
+        let arena = DataArena::new();
+        arena.set_decimal_arithmetic(Some(crate::DecimalArithmetic::new()));
+
+        // With the decimal feature, more significant digits than a float
+        // holds are read and written exactly
+        #[cfg(feature = "decimal")]
+        let texts = [
+            "1234567890.12345678",
+            "0.3333333333333333333333333333",
+            "-98765432109876543.21",
+            "12345678901234567890",
+        ];
+        // Otherwise numbers are read through floats, which hold 15 digits
+        #[cfg(not(feature = "decimal"))]
+        let texts = ["1234567890.12345", "0.333333333333333", "-98765432109.8765"];
+        for text in texts {
+            let json: JsonValue = serde_json::from_str(text).unwrap();
+            let data_value = DataValue::from_json(&json, &arena);
+            assert!(matches!(data_value, DataValue::Number(NumberValue::Decimal(_))), "{text}");
+            assert_eq!(data_value.to_json().to_string(), text);
+        }
+
+        // Exponents are read too
+        let json: JsonValue = serde_json::from_str("1.5e-20").unwrap();
+        let data_value = DataValue::from_json(&json, &arena);
+        assert_eq!(data_value, DataValue::decimal(Decimal::new(15, 21)));
+        #[cfg(feature = "decimal")]
+        assert_eq!(data_value.to_json().to_string(), "0.000000000000000000015");
+    }
+
     #[test]
     fn test_hash_map_conversion() {
         let arena = DataArena::new();
@@ -4,6 +4,9 @@
 //! representation of data values that leverages arena allocation.
 
 use super::number::NumberValue;
+use super::datetime::{format_datetime, format_duration, parse_datetime, parse_duration};
+use chrono::{DateTime, FixedOffset, TimeDelta};
+use rust_decimal::Decimal;
 use crate::arena::DataArena;
 use std::cmp::Ordering;
 use std::fmt;
@@ -31,6 +34,12 @@ pub enum DataValue<'a> {
 
     /// Represents an object with key-value pairs (arena-allocated)
     Object(&'a [(&'a str, DataValue<'a>)]),
+
+    /// Represents a point in time with a UTC offset
+    DateTime(DateTime<FixedOffset>),
+
+    /// Represents a length of time
+    Duration(TimeDelta),
 }
 
 impl<'a> DataValue<'a> {
@@ -54,6 +63,11 @@ impl<'a> DataValue<'a> {
         DataValue::Number(NumberValue::from_f64(value))
     }
 
+    /// Creates an exact decimal value.
+    pub fn decimal(value: Decimal) -> Self {
+        DataValue::Number(NumberValue::from_decimal(value))
+    }
+
     /// Creates a string value.
     ///
     /// If the string is empty, returns a string value with the preallocated empty string.
@@ -200,6 +214,10 @@ impl<'a> DataValue<'a> {
 
             // Object case - only empty object is false
             DataValue::Object(items) => !items.is_empty(),
+
+            // Datetimes are always true, durations unless they are zero
+            DataValue::DateTime(_) => true,
+            DataValue::Duration(duration) => !duration.is_zero(),
         }
     }
 
@@ -268,6 +286,8 @@ impl<'a> DataValue<'a> {
             DataValue::Array(_) => None,
 
             DataValue::Object(_) => None,
+
+            DataValue::DateTime(_) | DataValue::Duration(_) => None,
         }
     }
 
@@ -293,6 +313,12 @@ impl<'a> DataValue<'a> {
                 DataValue::String(arena.alloc_str(&result))
             }
             DataValue::Object(_) => DataValue::String(arena.alloc_str("[object Object]")),
+            DataValue::DateTime(datetime) => {
+                DataValue::String(arena.alloc_str(&format_datetime(datetime)))
+            }
+            DataValue::Duration(duration) => {
+                DataValue::String(arena.alloc_str(&format_duration(duration)))
+            }
         }
     }
 
@@ -315,6 +341,29 @@ impl<'a> DataValue<'a> {
         }
     }
 
+    /// Copies this value, with its strings, arrays and objects, into another arena.
+    pub(crate) fn copy_to<'b>(&self, arena: &'b DataArena) -> DataValue<'b> {
+        match self {
+            DataValue::Null => DataValue::Null,
+            DataValue::Bool(b) => DataValue::Bool(*b),
+            DataValue::Number(n) => DataValue::Number(*n),
+            DataValue::String(s) => DataValue::String(arena.alloc_str(s)),
+            DataValue::Array(items) => {
+                let items: Vec<_> = items.iter().map(|item| item.copy_to(arena)).collect();
+                DataValue::array(arena, &items)
+            }
+            DataValue::Object(entries) => {
+                let entries: Vec<_> = entries
+                    .iter()
+                    .map(|(key, value)| (arena.intern_str(key), value.copy_to(arena)))
+                    .collect();
+                DataValue::object(arena, &entries)
+            }
+            DataValue::DateTime(datetime) => DataValue::DateTime(*datetime),
+            DataValue::Duration(duration) => DataValue::Duration(*duration),
+        }
+    }
+
     /// Returns a string representation of the type of this value.
     pub fn type_name(&self) -> &'static str {
         match self {
@@ -324,6 +373,8 @@ impl<'a> DataValue<'a> {
             DataValue::String(_) => "string",
             DataValue::Array(_) => "array",
             DataValue::Object(_) => "object",
+            DataValue::DateTime(_) => "datetime",
+            DataValue::Duration(_) => "duration",
         }
     }
 
@@ -351,6 +402,18 @@ impl<'a> DataValue<'a> {
                 a == b
             }
 
+            // Datetimes are equal if they are the same instant
+            (DataValue::DateTime(a), DataValue::DateTime(b)) => a == b,
+            (DataValue::Duration(a), DataValue::Duration(b)) => a == b,
+            (DataValue::DateTime(a), DataValue::String(b))
+            | (DataValue::String(b), DataValue::DateTime(a)) => {
+                parse_datetime(b).is_some_and(|b| *a == b)
+            }
+            (DataValue::Duration(a), DataValue::String(b))
+            | (DataValue::String(b), DataValue::Duration(a)) => {
+                parse_duration(b).is_some_and(|b| *a == b)
+            }
+
             // Different types with coercion
             (DataValue::Null, DataValue::Bool(b)) => !b,
             (DataValue::Bool(a), DataValue::Null) => !a,
@@ -423,6 +486,8 @@ impl<'a> DataValue<'a> {
             (DataValue::Bool(a), DataValue::Bool(b)) => a == b,
             (DataValue::Number(a), DataValue::Number(b)) => a == b,
             (DataValue::String(a), DataValue::String(b)) => a == b,
+            (DataValue::DateTime(a), DataValue::DateTime(b)) => a == b,
+            (DataValue::Duration(a), DataValue::Duration(b)) => a == b,
             (DataValue::Array(a), DataValue::Array(b)) => {
                 if a.len() != b.len() {
                     return false;
@@ -476,6 +541,20 @@ impl PartialOrd for DataValue<'_> {
             }
             (DataValue::Bool(a), DataValue::Bool(b)) => a.partial_cmp(b),
             (DataValue::Null, DataValue::Null) => Some(Ordering::Equal),
+            (DataValue::DateTime(a), DataValue::DateTime(b)) => a.partial_cmp(b),
+            (DataValue::Duration(a), DataValue::Duration(b)) => a.partial_cmp(b),
+            (DataValue::DateTime(a), DataValue::String(b)) => {
+                parse_datetime(b).and_then(|b| a.partial_cmp(&b))
+            }
+            (DataValue::String(a), DataValue::DateTime(b)) => {
+                parse_datetime(a).and_then(|a| a.partial_cmp(b))
+            }
+            (DataValue::Duration(a), DataValue::String(b)) => {
+                parse_duration(b).and_then(|b| a.partial_cmp(&b))
+            }
+            (DataValue::String(a), DataValue::Duration(b)) => {
+                parse_duration(a).and_then(|a| a.partial_cmp(b))
+            }
 
             (DataValue::Array(a), DataValue::Array(b)) => {
                 // Fast path for empty arrays
@@ -501,10 +580,7 @@ impl PartialOrd for DataValue<'_> {
             // Mixed types: convert to common type for comparison
             (DataValue::Number(a), DataValue::String(b)) => {
                 if let Ok(b_num) = b.parse::<f64>() {
-                    let a_f64 = match a {
-                        NumberValue::Integer(i) => *i as f64,
-                        NumberValue::Float(f) => *f,
-                    };
+                    let a_f64 = a.as_f64();
 
                     if a_f64 > b_num {
                         Some(Ordering::Greater)
@@ -519,10 +595,7 @@ impl PartialOrd for DataValue<'_> {
             }
             (DataValue::String(a), DataValue::Number(b)) => {
                 if let Ok(a_num) = a.parse::<f64>() {
-                    let b_f64 = match b {
-                        NumberValue::Integer(i) => *i as f64,
-                        NumberValue::Float(f) => *f,
-                    };
+                    let b_f64 = b.as_f64();
 
                     if a_num > b_f64 {
                         Some(Ordering::Greater)
@@ -569,6 +642,8 @@ impl fmt::Display for DataValue<'_> {
                 }
                 write!(f, "}}")
             }
+            DataValue::DateTime(datetime) => write!(f, "{}", format_datetime(datetime)),
+            DataValue::Duration(duration) => write!(f, "{}", format_duration(duration)),
         }
     }
 }
@@ -585,7 +660,7 @@ mod tests {
         let null = DataValue::null();
         let boolean = DataValue::bool(true);
         let integer = DataValue::integer(42);
-        let float = DataValue::float(3.14);
+        let float = DataValue::float(3.5);
         let string = DataValue::string(&arena, "hello");
 
         assert!(null.is_null());
@@ -596,7 +671,7 @@ mod tests {
 
         assert_eq!(boolean.as_bool(), Some(true));
         assert_eq!(integer.as_i64(), Some(42));
-        assert_eq!(float.as_f64(), Some(3.14));
+        assert_eq!(float.as_f64(), Some(3.5));
         assert_eq!(string.as_str(), Some("hello"));
     }
 
@@ -658,8 +733,8 @@ mod tests {
             Some(NumberValue::Integer(42))
         );
         assert_eq!(
-            DataValue::string(&arena, "3.14").coerce_to_number(),
-            Some(NumberValue::Float(3.14))
+            DataValue::string(&arena, "3.5").coerce_to_number(),
+            Some(NumberValue::Float(3.5))
         );
 
         // String coercion
@@ -685,7 +760,7 @@ mod tests {
         assert!(DataValue::null() == DataValue::null());
         assert!(DataValue::bool(true) > DataValue::bool(false));
         assert!(DataValue::integer(5) > DataValue::integer(3));
-        assert!(DataValue::float(3.14) > DataValue::float(2.71));
+        assert!(DataValue::float(3.5) > DataValue::float(2.71));
         assert!(DataValue::string(&arena, "hello") == DataValue::string(&arena, "hello"));
         assert!(DataValue::string(&arena, "world") > DataValue::string(&arena, "hello"));
 
@@ -0,0 +1,171 @@
+//! Text forms of datetime and duration values.
+//!
+//! Datetimes are written as RFC 3339 strings, such as
+//! `2024-05-17T09:30:00Z`, and durations as ISO 8601 durations in weeks,
+//! days, hours, minutes and seconds, such as `P1DT2H30M`. Months and years
+//! do not have a fixed length, so they are not part of a duration.
+
+use chrono::{DateTime, FixedOffset, NaiveDate, NaiveDateTime, SecondsFormat, TimeDelta};
+
+/// Formats a datetime as an RFC 3339 string.
+///
+/// Fractional seconds are only written when they are not zero, and a zero
+/// offset is written as `Z`.
+pub fn format_datetime(datetime: &DateTime<FixedOffset>) -> String {
+    datetime.to_rfc3339_opts(SecondsFormat::AutoSi, true)
+}
+
+/// Parses a datetime from an RFC 3339 string, or from a date or a datetime
+/// without an offset, which are taken to be in UTC.
+///
+/// ```
+/// use datalogic_rs::value::parse_datetime;
+///
+/// let datetime = parse_datetime("2024-05-17T09:30:00+02:00").unwrap();
+/// assert_eq!(datetime.to_rfc3339(), "2024-05-17T09:30:00+02:00");
+/// assert_eq!(
+///     parse_datetime("2024-05-17").unwrap(),
+///     parse_datetime("2024-05-17T00:00:00Z").unwrap()
+/// );
+/// assert!(parse_datetime("17/05/2024").is_none());
+/// ```
+pub fn parse_datetime(s: &str) -> Option<DateTime<FixedOffset>> {
+    if let Ok(datetime) = DateTime::parse_from_rfc3339(s) {
+        return Some(datetime);
+    }
+
+    let naive = NaiveDateTime::parse_from_str(s, "%Y-%m-%dT%H:%M:%S%.f")
+        .or_else(|_| NaiveDateTime::parse_from_str(s, "%Y-%m-%d %H:%M:%S%.f"))
+        .or_else(|_| NaiveDate::parse_from_str(s, "%Y-%m-%d").map(|date| date.into()))
+        .ok()?;
+    Some(naive.and_utc().fixed_offset())
+}
+
+/// Formats a duration as an ISO 8601 duration.
+///
+/// ```
+/// use chrono::TimeDelta;
+/// use datalogic_rs::value::format_duration;
+///
+/// assert_eq!(format_duration(&TimeDelta::days(30)), "P30D");
+/// assert_eq!(format_duration(&TimeDelta::minutes(-90)), "-PT1H30M");
+/// assert_eq!(format_duration(&TimeDelta::milliseconds(1500)), "PT1.5S");
+/// assert_eq!(format_duration(&TimeDelta::zero()), "PT0S");
+/// ```
+pub fn format_duration(duration: &TimeDelta) -> String {
+    let mut result = String::new();
+    if *duration < TimeDelta::zero() {
+        result.push('-');
+    }
+    result.push('P');
+
+    let duration = duration.abs();
+    let days = duration.num_days();
+    let hours = duration.num_hours() % 24;
+    let minutes = duration.num_minutes() % 60;
+    let seconds = duration.num_seconds() % 60;
+    let nanos = duration.subsec_nanos();
+
+    if days > 0 {
+        result.push_str(&format!("{}D", days));
+    }
+    if hours > 0 || minutes > 0 || seconds > 0 || nanos > 0 || days == 0 {
+        result.push('T');
+    }
+    if hours > 0 {
+        result.push_str(&format!("{}H", hours));
+    }
+    if minutes > 0 {
+        result.push_str(&format!("{}M", minutes));
+    }
+    if nanos > 0 {
+        let fraction = format!("{:09}", nanos);
+        result.push_str(&format!("{}.{}S", seconds, fraction.trim_end_matches('0')));
+    } else if seconds > 0 || (days == 0 && hours == 0 && minutes == 0) {
+        result.push_str(&format!("{}S", seconds));
+    }
+    result
+}
+
+/// Parses an ISO 8601 duration in weeks, days, hours, minutes and seconds,
+/// with an optional sign.
+///
+/// ```
+/// use chrono::TimeDelta;
+/// use datalogic_rs::value::parse_duration;
+///
+/// assert_eq!(parse_duration("P2W"), Some(TimeDelta::days(14)));
+/// assert_eq!(parse_duration("-PT1H30M"), Some(TimeDelta::minutes(-90)));
+/// assert_eq!(parse_duration("PT0.25S"), Some(TimeDelta::milliseconds(250)));
+/// assert!(parse_duration("P1M").is_none());
+/// ```
+pub fn parse_duration(s: &str) -> Option<TimeDelta> {
+    let (negative, rest) = match s.strip_prefix('-') {
+        Some(rest) => (true, rest),
+        None => (false, s.strip_prefix('+').unwrap_or(s)),
+    };
+    let rest = rest.strip_prefix('P')?;
+    let (date, time) = match rest.split_once('T') {
+        Some((_, "")) => return None,
+        Some((date, time)) => (date, Some(time)),
+        None => (rest, None),
+    };
+    if date.is_empty() && time.is_none() {
+        return None;
+    }
+
+    let mut total = TimeDelta::zero();
+    for (amount, unit) in components(date)? {
+        let days = match unit {
+            'W' => 7,
+            'D' => 1,
+            _ => return None,
+        };
+        let amount: i64 = amount.parse().ok()?;
+        total = total.checked_add(&TimeDelta::try_days(amount.checked_mul(days)?)?)?;
+    }
+    if let Some(time) = time {
+        for (amount, unit) in components(time)? {
+            let part = match unit {
+                'H' => TimeDelta::try_hours(amount.parse().ok()?)?,
+                'M' => TimeDelta::try_minutes(amount.parse().ok()?)?,
+                'S' => seconds(amount)?,
+                _ => return None,
+            };
+            total = total.checked_add(&part)?;
+        }
+    }
+
+    Some(if negative { -total } else { total })
+}
+
+/// Splits the designators of a duration into their amounts and units.
+fn components(s: &str) -> Option<Vec<(&str, char)>> {
+    let mut parts = Vec::new();
+    let mut start = 0;
+    for (index, c) in s.char_indices() {
+        if c.is_ascii_alphabetic() {
+            let amount = &s[start..index];
+            if amount.is_empty() || !amount.bytes().all(|b| b.is_ascii_digit() || b == b'.') {
+                return None;
+            }
+            parts.push((amount, c));
+            start = index + 1;
+        }
+    }
+    (start == s.len()).then_some(parts)
+}
+
+/// Parses a number of seconds with an optional fraction.
+fn seconds(amount: &str) -> Option<TimeDelta> {
+    let (whole, fraction) = amount.split_once('.').unwrap_or((amount, ""));
+    if fraction.len() > 9 || !fraction.bytes().all(|b| b.is_ascii_digit()) {
+        return None;
+    }
+    let nanos = if fraction.is_empty() {
+        0
+    } else {
+        format!("{:0<9}", fraction).parse().ok()?
+    };
+    TimeDelta::try_seconds(whole.parse().ok()?)?.checked_add(&TimeDelta::nanoseconds(nanos))
+}
@@ -7,14 +7,18 @@
 mod access;
 mod convert;
 mod data_value;
+mod datetime;
 mod number;
+mod owned;
 
 pub use access::{parse_path, PathSegment, ValueAccess};
 pub use convert::{
     data_value_to_json, hash_map_to_data_value, json_to_data_value, FromJson, ToJson,
 };
 pub use data_value::DataValue;
+pub use datetime::{format_datetime, format_duration, parse_datetime, parse_duration};
 pub use number::NumberValue;
+pub use owned::OwnedDataValue;
 
 use crate::arena::DataArena;
 
@@ -5,6 +5,10 @@
 
 use std::cmp::Ordering;
 use std::fmt;
+use std::str::FromStr;
+
+use rust_decimal::Decimal;
+use rust_decimal::prelude::ToPrimitive;
 
 /// Specialized representation for numeric values to optimize memory usage.
 ///
@@ -17,6 +21,9 @@ pub enum NumberValue {
 
     /// Floating point value
     Float(f64),
+
+    /// Exact decimal value, used by decimal arithmetic
+    Decimal(Decimal),
 }
 
 impl NumberValue {
@@ -27,14 +34,26 @@ impl NumberValue {
 
     /// Creates a new NumberValue from an f64.
     pub fn from_f64(value: f64) -> Self {
-        // Store integers as integers when possible
-        if value.fract() == 0.0 && value >= i64::MIN as f64 && value <= i64::MAX as f64 {
+        // Store integers as integers when possible. `i64::MAX as f64` is 2^63,
+        // which is out of range itself.
+        if value.fract() == 0.0 && value >= i64::MIN as f64 && value < i64::MAX as f64 {
             NumberValue::Integer(value as i64)
         } else {
             NumberValue::Float(value)
         }
     }
 
+    /// Creates a new NumberValue from a decimal.
+    pub fn from_decimal(value: Decimal) -> Self {
+        // Store integers as integers when possible
+        if value.is_integer()
+            && let Some(i) = value.to_i64()
+        {
+            return NumberValue::Integer(i);
+        }
+        NumberValue::Decimal(value.normalize())
+    }
+
     /// Returns true if the value is an integer.
     pub fn is_integer(&self) -> bool {
         matches!(self, NumberValue::Integer(_))
@@ -45,6 +64,11 @@ impl NumberValue {
         matches!(self, NumberValue::Float(_))
     }
 
+    /// Returns true if the value is a decimal.
+    pub fn is_decimal(&self) -> bool {
+        matches!(self, NumberValue::Decimal(_))
+    }
+
     /// Returns the value as an i64, if possible.
     pub fn as_i64(&self) -> Option<i64> {
         match *self {
@@ -56,6 +80,13 @@ impl NumberValue {
                     None
                 }
             }
+            NumberValue::Decimal(d) => {
+                if d.is_integer() {
+                    d.to_i64()
+                } else {
+                    None
+                }
+            }
         }
     }
 
@@ -64,11 +95,42 @@ impl NumberValue {
         match *self {
             NumberValue::Integer(i) => i as f64,
             NumberValue::Float(f) => f,
+            NumberValue::Decimal(d) => d.to_f64().unwrap_or(f64::NAN),
+        }
+    }
+
+    /// Returns the value as a decimal, if it is in the range of decimals.
+    ///
+    /// A floating point value is converted to the shortest decimal that
+    /// parses back to it, so `0.1` becomes exactly one tenth.
+    pub fn as_decimal(&self) -> Option<Decimal> {
+        match *self {
+            NumberValue::Integer(i) => Some(Decimal::from(i)),
+            NumberValue::Float(f) if f.is_finite() => Decimal::from_str(&f.to_string()).ok(),
+            NumberValue::Float(_) => None,
+            NumberValue::Decimal(d) => Some(d),
+        }
+    }
+
+    /// Applies an operation to two numbers as decimals, if either of them is
+    /// a decimal and the result is in the range of decimals.
+    fn decimal_operation(
+        &self,
+        other: &NumberValue,
+        operation: impl FnOnce(Decimal, Decimal) -> Option<Decimal>,
+    ) -> Option<NumberValue> {
+        if !self.is_decimal() && !other.is_decimal() {
+            return None;
         }
+        operation(self.as_decimal()?, other.as_decimal()?).map(NumberValue::from_decimal)
     }
 
     /// Adds another NumberValue to this one.
     pub fn add(&self, other: &NumberValue) -> NumberValue {
+        if let Some(result) = self.decimal_operation(other, |a, b| a.checked_add(b)) {
+            return result;
+        }
+
         match (*self, *other) {
             (NumberValue::Integer(a), NumberValue::Integer(b)) => {
                 // Check for overflow
@@ -83,6 +145,10 @@ impl NumberValue {
 
     /// Subtracts another NumberValue from this one.
     pub fn subtract(&self, other: &NumberValue) -> NumberValue {
+        if let Some(result) = self.decimal_operation(other, |a, b| a.checked_sub(b)) {
+            return result;
+        }
+
         match (*self, *other) {
             (NumberValue::Integer(a), NumberValue::Integer(b)) => {
                 // Check for overflow
@@ -97,6 +163,10 @@ impl NumberValue {
 
     /// Multiplies this NumberValue by another.
     pub fn multiply(&self, other: &NumberValue) -> NumberValue {
+        if let Some(result) = self.decimal_operation(other, |a, b| a.checked_mul(b)) {
+            return result;
+        }
+
         match (*self, *other) {
             (NumberValue::Integer(a), NumberValue::Integer(b)) => {
                 // Check for overflow
@@ -115,6 +185,9 @@ impl NumberValue {
         if divisor == 0.0 {
             return None;
         }
+        if let Some(result) = self.decimal_operation(other, |a, b| a.checked_div(b)) {
+            return Some(result);
+        }
 
         match (*self, *other) {
             (NumberValue::Integer(a), NumberValue::Integer(b)) => {
@@ -134,6 +207,9 @@ impl NumberValue {
         if divisor == 0.0 {
             return None;
         }
+        if let Some(result) = self.decimal_operation(other, |a, b| a.checked_rem(b)) {
+            return Some(result);
+        }
 
         match (*self, *other) {
             (NumberValue::Integer(a), NumberValue::Integer(b)) => Some(NumberValue::Integer(a % b)),
@@ -149,6 +225,11 @@ impl PartialEq for NumberValue {
             (NumberValue::Float(a), NumberValue::Float(b)) => a == b,
             (NumberValue::Integer(a), NumberValue::Float(b)) => (a as f64) == b,
             (NumberValue::Float(a), NumberValue::Integer(b)) => a == (b as f64),
+            // Decimals are compared exactly
+            _ => match (self.as_decimal(), other.as_decimal()) {
+                (Some(a), Some(b)) => a == b,
+                _ => self.as_f64() == other.as_f64(),
+            },
         }
     }
 }
@@ -162,6 +243,10 @@ impl PartialOrd for NumberValue {
             (NumberValue::Float(a), NumberValue::Float(b)) => a.partial_cmp(&b),
             (NumberValue::Integer(a), NumberValue::Float(b)) => (a as f64).partial_cmp(&b),
             (NumberValue::Float(a), NumberValue::Integer(b)) => a.partial_cmp(&(b as f64)),
+            _ => match (self.as_decimal(), other.as_decimal()) {
+                (Some(a), Some(b)) => a.partial_cmp(&b),
+                _ => self.as_f64().partial_cmp(&other.as_f64()),
+            },
         }
     }
 }
@@ -171,6 +256,7 @@ impl fmt::Display for NumberValue {
         match *self {
             NumberValue::Integer(i) => write!(f, "{}", i),
             NumberValue::Float(fl) => write!(f, "{}", fl),
+            NumberValue::Decimal(d) => write!(f, "{}", d),
         }
     }
 }
@@ -182,7 +268,7 @@ mod tests {
     #[test]
     fn test_number_creation() {
         let int = NumberValue::from_i64(42);
-        let float = NumberValue::from_f64(3.14);
+        let float = NumberValue::from_f64(3.5);
         let int_from_float = NumberValue::from_f64(42.0);
 
         assert!(int.is_integer());
@@ -194,7 +280,11 @@ mod tests {
         assert_eq!(int_from_float.as_i64(), Some(42));
 
         assert_eq!(int.as_f64(), 42.0);
-        assert_eq!(float.as_f64(), 3.14);
+        assert_eq!(float.as_f64(), 3.5);
+
+        // Integers out of the range of i64 stay floats rather than saturating
+        assert!(NumberValue::from_f64(2f64.powi(63)).is_float());
+        assert!(NumberValue::from_f64(-(2f64.powi(63))).is_integer());
     }
 
     #[test]
@@ -226,4 +316,26 @@ mod tests {
         assert!(a > d);
         assert!(d > b);
     }
+
+    #[test]
+    fn test_decimal_numbers() {
+        let tenth = NumberValue::from_decimal(Decimal::new(1, 1));
+        let two = NumberValue::from_decimal(Decimal::new(200, 2));
+
+        assert!(tenth.is_decimal());
+        assert!(two.is_integer());
+        assert_eq!(tenth.as_f64(), 0.1);
+
+        // Decimal operations stay exact
+        let sum = tenth.add(&NumberValue::from_f64(0.2));
+        assert_eq!(sum, NumberValue::from_decimal(Decimal::new(3, 1)));
+        assert_eq!(sum.to_string(), "0.3");
+        assert_eq!(
+            NumberValue::from_i64(1).divide(&NumberValue::from_decimal(Decimal::new(4, 0))),
+            Some(NumberValue::from_f64(0.25))
+        );
+
+        assert!(tenth < NumberValue::from_f64(0.1000000001));
+        assert!(tenth == NumberValue::from_f64(0.1));
+    }
 }
@@ -0,0 +1,318 @@
+//! Owned values that outlive the arena.
+//!
+//! This module provides the OwnedDataValue enum, a heap-allocated
+//! counterpart of DataValue that can be returned from functions, cached and
+//! sent across threads.
+
+use super::convert::ToJson;
+use super::data_value::DataValue;
+use super::number::NumberValue;
+use super::IntoDataValue;
+use crate::arena::DataArena;
+use chrono::{DateTime, FixedOffset, TimeDelta};
+use serde_json::Value as JsonValue;
+use std::fmt;
+use std::sync::Arc;
+
+/// A value that owns its data instead of borrowing it from an arena.
+///
+/// Strings are stored as `Arc<str>`, so cloning a string value is cheap.
+///
+/// # Examples
+///
+/// ```
+/// use datalogic_rs::{DataLogic, OwnedDataValue};
+///
+/// fn is_hot(temp: i64) -> OwnedDataValue {
+///     let dl = DataLogic::new();
+///     let rule = dl.parse_logic(r#"{">": [{"var": "temp"}, 100]}"#, None).unwrap();
+///     let data = dl.parse_data(&format!(r#"{{"temp": {}}}"#, temp)).unwrap();
+///     dl.evaluate_owned(&rule, &data).unwrap()
+/// }
+///
+/// assert_eq!(is_hot(110), OwnedDataValue::Bool(true));
+/// ```
+#[derive(Debug, Clone, PartialEq)]
+pub enum OwnedDataValue {
+    /// Represents a null value
+    Null,
+
+    /// Represents a boolean value
+    Bool(bool),
+
+    /// Represents a numeric value (integer or floating point)
+    Number(NumberValue),
+
+    /// Represents a string value
+    String(Arc<str>),
+
+    /// Represents an array of values
+    Array(Vec<OwnedDataValue>),
+
+    /// Represents an object with key-value pairs
+    Object(Vec<(Arc<str>, OwnedDataValue)>),
+
+    /// Represents a point in time with a UTC offset
+    DateTime(DateTime<FixedOffset>),
+
+    /// Represents a length of time
+    Duration(TimeDelta),
+}
+
+impl OwnedDataValue {
+    /// Creates an owned copy of a DataValue.
+    pub fn from_data_value(value: &DataValue<'_>) -> Self {
+        match value {
+            DataValue::Null => OwnedDataValue::Null,
+            DataValue::Bool(b) => OwnedDataValue::Bool(*b),
+            DataValue::Number(n) => OwnedDataValue::Number(*n),
+            DataValue::String(s) => OwnedDataValue::String(Arc::from(*s)),
+            DataValue::Array(items) => {
+                OwnedDataValue::Array(items.iter().map(Self::from_data_value).collect())
+            }
+            DataValue::Object(entries) => OwnedDataValue::Object(
+                entries
+                    .iter()
+                    .map(|(key, value)| (Arc::from(*key), Self::from_data_value(value)))
+                    .collect(),
+            ),
+            DataValue::DateTime(datetime) => OwnedDataValue::DateTime(*datetime),
+            DataValue::Duration(duration) => OwnedDataValue::Duration(*duration),
+        }
+    }
+
+    /// Converts the value to a DataValue, allocating in the given arena.
+    pub fn to_data_value<'a>(&self, arena: &'a DataArena) -> DataValue<'a> {
+        match self {
+            OwnedDataValue::Null => DataValue::null(),
+            OwnedDataValue::Bool(b) => DataValue::bool(*b),
+            OwnedDataValue::Number(n) => DataValue::Number(*n),
+            OwnedDataValue::String(s) => DataValue::string(arena, s),
+            OwnedDataValue::Array(items) => {
+                let values: Vec<DataValue<'a>> =
+                    items.iter().map(|item| item.to_data_value(arena)).collect();
+                DataValue::array(arena, &values)
+            }
+            OwnedDataValue::Object(entries) => {
+                let entries: Vec<(&'a str, DataValue<'a>)> = entries
+                    .iter()
+                    .map(|(key, value)| (arena.intern_str(key), value.to_data_value(arena)))
+                    .collect();
+                DataValue::object(arena, &entries)
+            }
+            OwnedDataValue::DateTime(datetime) => DataValue::DateTime(*datetime),
+            OwnedDataValue::Duration(duration) => DataValue::Duration(*duration),
+        }
+    }
+
+    /// Converts to a DataValue that borrows the strings of this value.
+    ///
+    /// Only arrays and objects are allocated in the arena.
+    pub(crate) fn borrow_in<'a>(&'a self, arena: &'a DataArena) -> DataValue<'a> {
+        match self {
+            OwnedDataValue::Null => DataValue::null(),
+            OwnedDataValue::Bool(b) => DataValue::bool(*b),
+            OwnedDataValue::Number(n) => DataValue::Number(*n),
+            OwnedDataValue::String(s) => DataValue::String(s),
+            OwnedDataValue::Array(items) => DataValue::Array(
+                arena.alloc_slice_fill_with(items.len(), |i| items[i].borrow_in(arena)),
+            ),
+            OwnedDataValue::Object(entries) => {
+                DataValue::Object(arena.alloc_slice_fill_with(entries.len(), |i| {
+                    let (key, value) = &entries[i];
+                    (&**key, value.borrow_in(arena))
+                }))
+            }
+            OwnedDataValue::DateTime(datetime) => DataValue::DateTime(*datetime),
+            OwnedDataValue::Duration(duration) => DataValue::Duration(*duration),
+        }
+    }
+
+    /// Returns true if the value is null.
+    pub fn is_null(&self) -> bool {
+        matches!(self, OwnedDataValue::Null)
+    }
+
+    /// Returns the value as a boolean, if it is one.
+    pub fn as_bool(&self) -> Option<bool> {
+        match self {
+            OwnedDataValue::Bool(b) => Some(*b),
+            _ => None,
+        }
+    }
+
+    /// Returns the value as an i64, if it is an integer.
+    pub fn as_i64(&self) -> Option<i64> {
+        match self {
+            OwnedDataValue::Number(n) => n.as_i64(),
+            _ => None,
+        }
+    }
+
+    /// Returns the value as an f64, if it is a number.
+    pub fn as_f64(&self) -> Option<f64> {
+        match self {
+            OwnedDataValue::Number(n) => Some(n.as_f64()),
+            _ => None,
+        }
+    }
+
+    /// Returns the value as a string slice, if it is a string.
+    pub fn as_str(&self) -> Option<&str> {
+        match self {
+            OwnedDataValue::String(s) => Some(s),
+            _ => None,
+        }
+    }
+
+    /// Returns the value as a slice of values, if it is an array.
+    pub fn as_array(&self) -> Option<&[OwnedDataValue]> {
+        match self {
+            OwnedDataValue::Array(items) => Some(items),
+            _ => None,
+        }
+    }
+
+    /// Returns the value as a slice of key-value pairs, if it is an object.
+    pub fn as_object(&self) -> Option<&[(Arc<str>, OwnedDataValue)]> {
+        match self {
+            OwnedDataValue::Object(entries) => Some(entries),
+            _ => None,
+        }
+    }
+
+    /// Gets a value from an object by key.
+    pub fn get(&self, key: &str) -> Option<&OwnedDataValue> {
+        self.as_object()?
+            .iter()
+            .find(|(k, _)| k.as_ref() == key)
+            .map(|(_, value)| value)
+    }
+}
+
+impl DataValue<'_> {
+    /// Creates an owned copy of this value that does not borrow the arena.
+    pub fn to_owned_value(&self) -> OwnedDataValue {
+        OwnedDataValue::from_data_value(self)
+    }
+}
+
+impl From<&DataValue<'_>> for OwnedDataValue {
+    fn from(value: &DataValue<'_>) -> Self {
+        OwnedDataValue::from_data_value(value)
+    }
+}
+
+impl<'a> IntoDataValue<'a> for &OwnedDataValue {
+    fn into_data_value(self, arena: &'a DataArena) -> DataValue<'a> {
+        self.to_data_value(arena)
+    }
+}
+
+impl ToJson for OwnedDataValue {
+    fn to_json(&self) -> JsonValue {
+        match self {
+            OwnedDataValue::Null => JsonValue::Null,
+            OwnedDataValue::Bool(b) => JsonValue::Bool(*b),
+            OwnedDataValue::Number(n) => DataValue::Number(*n).to_json(),
+            OwnedDataValue::String(s) => JsonValue::String(s.to_string()),
+            OwnedDataValue::Array(items) => {
+                JsonValue::Array(items.iter().map(|item| item.to_json()).collect())
+            }
+            OwnedDataValue::Object(entries) => JsonValue::Object(
+                entries
+                    .iter()
+                    .map(|(key, value)| (key.to_string(), value.to_json()))
+                    .collect(),
+            ),
+            OwnedDataValue::DateTime(datetime) => DataValue::DateTime(*datetime).to_json(),
+            OwnedDataValue::Duration(duration) => DataValue::Duration(*duration).to_json(),
+        }
+    }
+}
+
+impl fmt::Display for OwnedDataValue {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        let arena = DataArena::new();
+        write!(f, "{}", self.to_data_value(&arena))
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::logic::DataLogicCore;
+    use crate::value::FromJson;
+    use crate::DataLogic;
+    use serde_json::json;
+    use std::thread;
+
+    #[test]
+    fn test_round_trip() {
+        let json = json!({
+            "name": "Alice",
+            "age": 30,
+            "score": 9.5,
+            "tags": ["a", "b"],
+            "address": {"city": "Paris", "zip": null},
+            "active": true
+        });
+
+        let owned = {
+            let arena = DataArena::new();
+            DataValue::from_json(&json, &arena).to_owned_value()
+        };
+        assert_eq!(owned.to_json(), json);
+        assert_eq!(owned.get("name").and_then(|v| v.as_str()), Some("Alice"));
+        assert_eq!(owned.get("age").and_then(|v| v.as_i64()), Some(30));
+        assert_eq!(owned.get("score").and_then(|v| v.as_f64()), Some(9.5));
+        assert_eq!(owned.get("tags").and_then(|v| v.as_array()).map(|a| a.len()), Some(2));
+        assert!(owned.get("address").and_then(|v| v.get("zip")).unwrap().is_null());
+
+        let arena = DataArena::new();
+        let value = owned.to_data_value(&arena);
+        assert_eq!(value.to_json(), json);
+        assert_eq!(OwnedDataValue::from(&value), owned);
+        assert_eq!((&owned).into_data_value(&arena), value);
+    }
+
+    #[test]
+    fn test_send_across_threads() {
+        let owned = {
+            let arena = DataArena::new();
+            DataValue::from_json(&json!(["x", 1, {"y": false}]), &arena).to_owned_value()
+        };
+
+        let handle = thread::spawn(move || owned.to_json());
+        assert_eq!(handle.join().unwrap(), json!(["x", 1, {"y": false}]));
+    }
+
+    #[test]
+    fn test_evaluate_owned() {
+        let result = {
+            let dl = DataLogic::new();
+            let rule = dl
+                .parse_logic(r#"{"map": [{"var": "items"}, {"cat": ["n", {"var": ""}]}]}"#, None)
+                .unwrap();
+            let data = dl.parse_data(r#"{"items": [1, 2]}"#).unwrap();
+            dl.evaluate_owned(&rule, &data).unwrap()
+        };
+        assert_eq!(result.to_json(), json!(["n1", "n2"]));
+
+        let result = {
+            let core = DataLogicCore::new();
+            let rule = core.builder().compare().greater_than_op().var("n").int(1).build();
+            core.evaluate_owned(&rule, &json!({"n": 2})).unwrap()
+        };
+        assert_eq!(result, OwnedDataValue::Bool(true));
+    }
+
+    #[test]
+    fn test_display() {
+        let owned = OwnedDataValue::Array(vec![
+            OwnedDataValue::String(Arc::from("a")),
+            OwnedDataValue::Number(NumberValue::Integer(1)),
+        ]);
+        assert_eq!(owned.to_string(), r#"["a", 1]"#);
+    }
+}